@@ -70,7 +70,7 @@
 //! </details>
 
 // x-release-please-start-version
-#![doc(html_root_url = "https://docs.rs/toad-msg/0.19.0")]
+#![doc(html_root_url = "https://docs.rs/toad-msg/0.20.0")]
 // x-release-please-end
 #![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(not(test), forbid(missing_debug_implementations, unreachable_pub))]
@@ -90,6 +90,14 @@ pub mod cache_key;
 /// Message structs
 pub mod msg;
 
+/// CoRE Link Format ([RFC6690](https://www.rfc-editor.org/rfc/rfc6690))
+pub mod link_format;
+
+/// CoAP over TCP, TLS & WebSockets ([RFC8323](https://www.rfc-editor.org/rfc/rfc8323))
+///
+/// Message framing for reliable transports & signaling messages
+pub mod tcp;
+
 #[doc(hidden)]
 pub mod to_bytes;
 
@@ -133,7 +141,7 @@ pub(crate) fn test_msg() -> (alloc::Message, Vec<u8>) {
   let msg = alloc::Message { id: Id(1),
                              ty: Type::Con,
                              ver: Version(1),
-                             token: Token(tinyvec::array_vec!([u8; 8] => 254)),
+                             token: Token::new(&[254]).unwrap(),
                              opts: BTreeMap::from([(OptNumber(12),
                                                     vec![OptValue(content_format.to_vec())])]),
                              code: Code { class: 2,
@@ -0,0 +1,427 @@
+use core::fmt::{self, Display, Write};
+
+/// Target attributes defined by [RFC6690](https://www.rfc-editor.org/rfc/rfc6690#section-3)
+/// and [RFC7641](https://www.rfc-editor.org/rfc/rfc7641#section-6)
+pub mod attr {
+  /// Resource type; a space-separated list of application-specific names
+  pub const RT: &str = "rt";
+  /// Interface description; a space-separated list of interface names
+  pub const IF: &str = "if";
+  /// [Content-Format](crate::ContentFormat) code
+  pub const CT: &str = "ct";
+  /// Maximum size estimate of the resource representation, in bytes
+  pub const SZ: &str = "sz";
+  /// The resource is observable
+  pub const OBS: &str = "obs";
+  /// Human-readable title
+  pub const TITLE: &str = "title";
+  /// Relation type
+  pub const REL: &str = "rel";
+  /// Context URI, when not the resource's origin
+  pub const ANCHOR: &str = "anchor";
+  /// Not an attribute, but a query parameter that filters on link targets
+  pub const HREF: &str = "href";
+}
+
+/// Errors encountered while parsing a link-format document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+  /// A link did not start with `<`
+  ExpectedTarget,
+  /// A link target was missing the closing `>`
+  UnterminatedTarget,
+  /// An attribute value was missing the closing `"`
+  UnterminatedQuote,
+  /// Expected a `;` or `,` after a link target, found this instead
+  UnexpectedChar(char),
+}
+
+/// A target attribute of a link, e.g. `rt="temperature"` or `obs`
+///
+/// Quoted values are yielded without the quotes, but escape sequences
+/// are left as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Attr<'a> {
+  /// The name of the attribute
+  pub name: &'a str,
+  /// The value of the attribute, if there is one
+  pub value: Option<&'a str>,
+}
+
+impl<'a> Attr<'a> {
+  /// Create an attribute with a value, e.g. `rt="temperature"`
+  pub fn new(name: &'a str, value: &'a str) -> Self {
+    Self { name,
+           value: Some(value) }
+  }
+
+  /// Create an attribute without a value, e.g. `obs`
+  pub fn flag(name: &'a str) -> Self {
+    Self { name, value: None }
+  }
+
+  /// The space-separated values of this attribute
+  ///
+  /// `rt`, `if` and `rel` may have many values, e.g. `rt="temperature-c sensor"`
+  pub fn values(&self) -> impl Iterator<Item = &'a str> {
+    self.value.unwrap_or("").split_whitespace()
+  }
+
+  /// Does this attribute satisfy the query filter value `value`?
+  ///
+  /// Per [RFC6690 section 4.1](https://www.rfc-editor.org/rfc/rfc6690#section-4.1),
+  /// the filter matches if it equals any of the attribute's [`values`](Attr::values),
+  /// or if it ends with `*` and is a prefix of any of them.
+  ///
+  /// ```
+  /// use toad_msg::link_format::Attr;
+  ///
+  /// let rt = Attr::new("rt", "temperature-c sensor");
+  /// assert!(rt.matches("sensor"));
+  /// assert!(rt.matches("temp*"));
+  /// assert!(!rt.matches("temperature"));
+  /// ```
+  pub fn matches(&self, value: &str) -> bool {
+    let value_matches = |v: &str| match value.strip_suffix('*') {
+      | Some(prefix) => v.starts_with(prefix),
+      | None => v == value,
+    };
+
+    if value.is_empty() || value == "*" {
+      return true;
+    }
+
+    value_matches(self.value.unwrap_or("")) || self.values().any(value_matches)
+  }
+}
+
+impl<'a> Display for Attr<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name)?;
+
+    match self.value {
+      | None => Ok(()),
+      | Some(v) if !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()) => write!(f, "={}", v),
+      | Some(v) => {
+        f.write_str("=\"")?;
+        v.chars().try_for_each(|c| match c {
+                   | '"' | '\\' => write!(f, "\\{}", c),
+                   | c => f.write_char(c),
+                 })?;
+        f.write_char('"')
+      },
+    }
+  }
+}
+
+/// Find the first occurrence of `delim` in `s` that isn't in a quoted string
+fn find_unquoted(s: &str, delim: char) -> Result<Option<usize>, ParseError> {
+  let mut quoted = false;
+  let mut escaped = false;
+
+  for (ix, c) in s.char_indices() {
+    match c {
+      | _ if escaped => escaped = false,
+      | '\\' if quoted => escaped = true,
+      | '"' => quoted = !quoted,
+      | c if c == delim && !quoted => return Ok(Some(ix)),
+      | _ => (),
+    }
+  }
+
+  if quoted {
+    Err(ParseError::UnterminatedQuote)
+  } else {
+    Ok(None)
+  }
+}
+
+/// A link in a link-format document
+///
+/// ```
+/// use toad_msg::link_format::{attr, Attr, Link};
+///
+/// let link = Link::parse(r#"</sensors/temp>;rt="temperature-c";obs"#).unwrap();
+/// assert_eq!(link.target(), "/sensors/temp");
+/// assert_eq!(link.attr(attr::RT), Some(Attr::new("rt", "temperature-c")));
+/// assert!(link.attr(attr::OBS).is_some());
+/// assert!(link.matches("rt=temperature*"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Link<'a> {
+  target: &'a str,
+  params: &'a str,
+}
+
+impl<'a> Link<'a> {
+  /// Parse a single link
+  ///
+  /// To parse a document containing many links, see [`parse`].
+  pub fn parse(s: &'a str) -> Result<Self, ParseError> {
+    match parse(s).next() {
+      | Some(Ok(link)) => Ok(link),
+      | Some(Err(e)) => Err(e),
+      | None => Err(ParseError::ExpectedTarget),
+    }
+  }
+
+  /// The URI of the linked resource, e.g. `/sensors/temp`
+  pub fn target(&self) -> &'a str {
+    self.target
+  }
+
+  /// Iterate over the target attributes of this link
+  pub fn attrs(&self) -> Attrs<'a> {
+    Attrs { rest: self.params }
+  }
+
+  /// Get the first target attribute named `name`
+  pub fn attr(&self, name: &str) -> Option<Attr<'a>> {
+    self.attrs().find(|a| a.name == name)
+  }
+
+  /// Does this link satisfy the query filter `query`?
+  ///
+  /// The filter is a single query parameter like `rt=temperature`
+  /// or `href=/sensors/*`, which matches as described in
+  /// [RFC6690 section 4.1](https://www.rfc-editor.org/rfc/rfc6690#section-4.1)
+  /// (see [`Attr::matches`]).
+  pub fn matches(&self, query: &str) -> bool {
+    let (name, value) = query.split_once('=').unwrap_or((query, ""));
+
+    if name == attr::HREF {
+      Attr::new(name, self.target).matches(value)
+    } else {
+      self.attrs()
+          .any(|a| a.name == name && a.matches(value))
+    }
+  }
+}
+
+impl<'a> Display for Link<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "<{}>{}", self.target, self.params)
+  }
+}
+
+/// Parse a link-format document into its [`Link`]s
+///
+/// ```
+/// use toad_msg::link_format::parse;
+///
+/// let doc = r#"</sensors/temp>;rt="temperature-c";if="sensor",
+///              </sensors/light>;rt="light-lux";if="sensor""#;
+///
+/// let targets = parse(doc).map(|l| l.unwrap().target())
+///                         .collect::<Vec<_>>();
+/// assert_eq!(targets, vec!["/sensors/temp", "/sensors/light"]);
+///
+/// let temp = parse(doc).map(Result::unwrap)
+///                      .find(|l| l.matches("rt=temperature-c"));
+/// assert_eq!(temp.map(|l| l.target()), Some("/sensors/temp"));
+/// ```
+pub fn parse(s: &str) -> Links<'_> {
+  Links { rest: s }
+}
+
+/// Iterator over the links in a link-format document, created by [`parse`]
+///
+/// Once an error is yielded, the iterator will not yield any more links.
+#[derive(Debug, Clone)]
+pub struct Links<'a> {
+  rest: &'a str,
+}
+
+impl<'a> Links<'a> {
+  fn next_link(&mut self) -> Result<Link<'a>, ParseError> {
+    let s = self.rest.trim_start();
+    let s = s.strip_prefix('<').ok_or(ParseError::ExpectedTarget)?;
+    let (target, s) = s.split_once('>')
+                       .ok_or(ParseError::UnterminatedTarget)?;
+
+    let end = find_unquoted(s, ',')?.unwrap_or(s.len());
+    let params = s[..end].trim();
+
+    match params.chars().next() {
+      | Some(c) if c != ';' => return Err(ParseError::UnexpectedChar(c)),
+      | _ => (),
+    }
+
+    self.rest = s.get(end + 1..).unwrap_or("");
+    Ok(Link { target, params })
+  }
+}
+
+impl<'a> Iterator for Links<'a> {
+  type Item = Result<Link<'a>, ParseError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.rest.trim().is_empty() {
+      return None;
+    }
+
+    let link = self.next_link();
+    if link.is_err() {
+      self.rest = "";
+    }
+
+    Some(link)
+  }
+}
+
+/// Iterator over the target attributes of a [`Link`]
+#[derive(Debug, Clone)]
+pub struct Attrs<'a> {
+  rest: &'a str,
+}
+
+impl<'a> Iterator for Attrs<'a> {
+  type Item = Attr<'a>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let s = self.rest.trim_start().strip_prefix(';')?;
+
+    // quotes were checked when the link was parsed
+    let end = find_unquoted(s, ';').ok().flatten().unwrap_or(s.len());
+    self.rest = &s[end..];
+
+    let param = s[..end].trim();
+    let attr = match param.split_once('=') {
+      | Some((name, value)) => {
+        let value = value.trim();
+        let value = value.strip_prefix('"')
+                         .and_then(|v| v.strip_suffix('"'))
+                         .unwrap_or(value);
+        Attr::new(name.trim(), value)
+      },
+      | None => Attr::flag(param),
+    };
+
+    Some(attr)
+  }
+}
+
+/// Writes links in link-format to a [`fmt::Write`]
+///
+/// ```
+/// use toad_msg::link_format::{attr, Attr, Writer};
+///
+/// let mut doc = Writer::new(String::new());
+/// doc.link("/sensors/temp",
+///          [Attr::new(attr::RT, "temperature-c"),
+///           Attr::new(attr::CT, "0"),
+///           Attr::flag(attr::OBS)])
+///    .unwrap();
+/// doc.link("/sensors/light", [Attr::new(attr::IF, "sensor")])
+///    .unwrap();
+///
+/// assert_eq!(doc.into_inner(),
+///            r#"</sensors/temp>;rt="temperature-c";ct=0;obs,</sensors/light>;if="sensor""#);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Writer<W> {
+  w: W,
+  empty: bool,
+}
+
+impl<W> Writer<W> where W: Write
+{
+  /// Create a writer that will write links to `w`
+  pub fn new(w: W) -> Self {
+    Self { w, empty: true }
+  }
+
+  /// Write a link to `target` with target attributes `attrs`
+  pub fn link<'a, I>(&mut self, target: &str, attrs: I) -> fmt::Result
+    where I: IntoIterator<Item = Attr<'a>>
+  {
+    if !self.empty {
+      self.w.write_char(',')?;
+    }
+
+    self.empty = false;
+    write!(self.w, "<{}>", target)?;
+    attrs.into_iter()
+         .try_for_each(|a| write!(self.w, ";{}", a))
+  }
+
+  /// Get the wrapped [`fmt::Write`]
+  pub fn into_inner(self) -> W {
+    self.w
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_rfc6690_example() {
+    let doc = r#"</sensors>;ct=40;title="Sensor Index",
+                 </sensors/temp>;rt="temperature-c";if="sensor",
+                 </sensors/light>;rt="light-lux";if="sensor",
+                 <http://www.example.com/sensors/t123>;anchor="/sensors/temp";rel="describedby",
+                 </t>;anchor="/sensors/temp";rel="alternate""#;
+
+    let links = parse(doc).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(links.len(), 5);
+    assert_eq!(links[0].target(), "/sensors");
+    assert_eq!(links[0].attrs().collect::<Vec<_>>(),
+               vec![Attr::new("ct", "40"), Attr::new("title", "Sensor Index")]);
+    assert_eq!(links[3].target(), "http://www.example.com/sensors/t123");
+    assert_eq!(links[3].attr(attr::ANCHOR),
+               Some(Attr::new("anchor", "/sensors/temp")));
+  }
+
+  #[test]
+  fn parse_should_respect_quoted_delimiters() {
+    let link = Link::parse(r#"</a>;title="one, two; \"three\"";obs"#).unwrap();
+    assert_eq!(link.attrs().collect::<Vec<_>>(),
+               vec![Attr::new("title", r#"one, two; \"three\""#),
+                    Attr::flag("obs")]);
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert_eq!(Link::parse("/a>;obs"), Err(ParseError::ExpectedTarget));
+    assert_eq!(Link::parse("</a;obs"), Err(ParseError::UnterminatedTarget));
+    assert_eq!(Link::parse(r#"</a>;title="oops"#),
+               Err(ParseError::UnterminatedQuote));
+    assert_eq!(Link::parse("</a>obs"), Err(ParseError::UnexpectedChar('o')));
+
+    let mut links = parse("</a>,b,</c>");
+    assert!(links.next().unwrap().is_ok());
+    assert_eq!(links.next(), Some(Err(ParseError::ExpectedTarget)));
+    assert_eq!(links.next(), None);
+  }
+
+  #[test]
+  fn link_matches_query_filters() {
+    let link = Link::parse(r#"</sensors/temp>;rt="temperature-c sensor";ct=0;obs"#).unwrap();
+
+    assert!(link.matches("rt=sensor"));
+    assert!(link.matches("rt=temperature*"));
+    assert!(link.matches("ct=0"));
+    assert!(link.matches("obs"));
+    assert!(link.matches("href=/sensors/temp"));
+    assert!(link.matches("href=/sensors*"));
+
+    assert!(!link.matches("rt=temperature"));
+    assert!(!link.matches("ct=40"));
+    assert!(!link.matches("if=sensor"));
+    assert!(!link.matches("href=/sensors"));
+  }
+
+  #[test]
+  fn written_links_should_parse() {
+    let mut doc = Writer::new(String::new());
+    doc.link("/a", [Attr::new(attr::TITLE, r#"say "hi""#)])
+       .unwrap();
+    doc.link("/b", []).unwrap();
+    let doc = doc.into_inner();
+
+    let links = parse(&doc).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(links.iter().map(|l| l.to_string()).collect::<Vec<_>>(),
+               vec![r#"</a>;title="say \"hi\"""#, "</b>"]);
+  }
+}
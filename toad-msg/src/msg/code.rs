@@ -43,6 +43,9 @@ pub enum CodeKind {
   Request,
   /// A response code ([2-5].xx)
   Response,
+  /// A signaling code (7.xx), only sent over reliable transports
+  /// (see [`crate::tcp::Signal`])
+  Signaling,
   /// EMPTY (0.00)
   Empty,
 }
@@ -97,11 +100,14 @@ impl Code {
   ///
   /// let resp = Code::new(2, 5); // OK CONTENT
   /// assert_eq!(resp.kind(), CodeKind::Response);
+  ///
+  /// assert_eq!(Code::PING.kind(), CodeKind::Signaling);
   /// ```
   pub fn kind(&self) -> CodeKind {
     match (self.class, self.detail) {
       | (0, 0) => CodeKind::Empty,
       | (0, _) => CodeKind::Request,
+      | (7, _) => CodeKind::Signaling,
       | _ => CodeKind::Response,
     }
   }
@@ -120,6 +126,21 @@ impl Code {
 
   #[doc = rfc_7252_doc!("5.8.4")]
   pub const DELETE: Self = Self::new(0, 4);
+
+  /// Capabilities and Settings Message ([RFC8323 Section 5.3](https://www.rfc-editor.org/rfc/rfc8323#section-5.3))
+  pub const CSM: Self = Self::new(7, 1);
+
+  /// Ping ([RFC8323 Section 5.4](https://www.rfc-editor.org/rfc/rfc8323#section-5.4))
+  pub const PING: Self = Self::new(7, 2);
+
+  /// Pong ([RFC8323 Section 5.4](https://www.rfc-editor.org/rfc/rfc8323#section-5.4))
+  pub const PONG: Self = Self::new(7, 3);
+
+  /// Release ([RFC8323 Section 5.5](https://www.rfc-editor.org/rfc/rfc8323#section-5.5))
+  pub const RELEASE: Self = Self::new(7, 4);
+
+  /// Abort ([RFC8323 Section 5.6](https://www.rfc-editor.org/rfc/rfc8323#section-5.6))
+  pub const ABORT: Self = Self::new(7, 5);
 }
 
 #[cfg(feature = "alloc")]
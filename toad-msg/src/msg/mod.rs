@@ -125,7 +125,7 @@ impl<PayloadBytes: Array<Item = u8>, Options: OptionMap> Len for Message<Payload
     let header_size = 4;
     let payload_marker_size = 1;
     let payload_size = self.payload.0.len();
-    let token_size = self.token.0.len();
+    let token_size = self.token.0.len() + self.token.tkl().1.len();
     let opts_size: usize = self.opts.opt_refs().map(|o| o.len()).sum();
 
     header_size + payload_marker_size + payload_size + token_size + opts_size
@@ -583,6 +583,60 @@ pub trait MessageOptions {
         .and_then(observe::Action::from_byte)
   }
 
+  /// Set the value for the [No-Response](opt::known::no_repeat::NO_RESPONSE) option,
+  /// discarding any existing values.
+  ///
+  /// ```
+  /// use toad_msg::alloc::Message;
+  /// use toad_msg::{Code, Id, MessageOptions, NoResponse, Token, Type};
+  ///
+  /// let mut msg = Message::new(Type::Non, Code::POST, Id(1), Token(Default::default()));
+  /// assert_eq!(msg.no_response(), None);
+  ///
+  /// msg.set_no_response(NoResponse::all()).unwrap();
+  /// assert_eq!(msg.no_response(), Some(NoResponse::all()));
+  /// ```
+  fn set_no_response(&mut self, n: NoResponse) -> Result<(), Self::SetError> {
+    let n = u8::from(n);
+    self.set(opt::known::no_repeat::NO_RESPONSE,
+             core::iter::once(n).filter(|n| *n != 0).collect())
+        .map(|_| ())
+  }
+
+  /// Get the value for the [No-Response](opt::known::no_repeat::NO_RESPONSE) option
+  fn no_response(&self) -> Option<NoResponse> {
+    self.get_first(opt::known::no_repeat::NO_RESPONSE)
+        .map(|v| v.0.iter().last().copied().unwrap_or(0))
+        .map(NoResponse::from)
+  }
+
+  /// Set the value for the [Echo](opt::known::no_repeat::ECHO) option,
+  /// discarding any existing values.
+  ///
+  /// ```
+  /// use toad_msg::alloc::Message;
+  /// use toad_msg::{Code, Id, MessageOptions, Token, Type};
+  ///
+  /// let mut msg = Message::new(Type::Con, Code::GET, Id(1), Token(Default::default()));
+  /// assert_eq!(msg.echo(), None);
+  ///
+  /// msg.set_echo([1, 2, 3, 4]).unwrap();
+  /// assert_eq!(msg.echo().map(|v| v.as_bytes()),
+  ///            Some([1u8, 2, 3, 4].as_ref()));
+  /// ```
+  fn set_echo<B>(&mut self, value: B) -> Result<(), Self::SetError>
+    where B: AsRef<[u8]>
+  {
+    self.set(opt::known::no_repeat::ECHO,
+             value.as_ref().iter().copied().collect())
+        .map(|_| ())
+  }
+
+  /// Get the value for the [Echo](opt::known::no_repeat::ECHO) option
+  fn echo(&self) -> Option<&OptValue<Self::OptValueBytes>> {
+    self.get_first(opt::known::no_repeat::ECHO)
+  }
+
   /// Update the value for the [Accept](opt::known::no_repeat::ACCEPT) option,
   /// discarding any existing values.
   #[doc = rfc_7252_doc!("5.10.4")]
@@ -704,6 +758,30 @@ pub trait MessageOptions {
     self.get_str(opt::known::no_repeat::PROXY_SCHEME)
   }
 
+  /// Update the value for the [Hop-Limit](opt::known::no_repeat::HOP_LIMIT) option,
+  /// discarding any existing values.
+  ///
+  /// ```
+  /// use toad_msg::alloc::Message;
+  /// use toad_msg::{Code, Id, MessageOptions, Token, Type};
+  ///
+  /// let mut msg = Message::new(Type::Con, Code::GET, Id(1), Token(Default::default()));
+  /// assert_eq!(msg.hop_limit(), None);
+  ///
+  /// msg.set_hop_limit(16).unwrap();
+  /// assert_eq!(msg.hop_limit(), Some(16));
+  /// ```
+  fn set_hop_limit(&mut self, hops: u8) -> Result<(), Self::SetError> {
+    self.set(opt::known::no_repeat::HOP_LIMIT,
+             core::iter::once(hops).collect())
+        .map(|_| ())
+  }
+
+  /// Get the value for the [Hop-Limit](opt::known::no_repeat::HOP_LIMIT) option
+  fn hop_limit(&self) -> Option<u8> {
+    self.get_u8(opt::known::no_repeat::HOP_LIMIT)
+  }
+
   /// Insert a new value for the [If-Match](opt::known::repeat::IF_MATCH) option,
   /// alongside any existing values.
   #[doc = rfc_7252_doc!("5.10.8.1")]
@@ -774,6 +852,31 @@ pub trait MessageOptions {
   fn etags(&self) -> Option<&Self::OptValues> {
     self.get(opt::known::repeat::ETAG)
   }
+
+  /// Insert a new value for the [Request-Tag](opt::known::repeat::REQUEST_TAG) option,
+  /// alongside any existing values.
+  ///
+  /// ```
+  /// use toad_msg::alloc::Message;
+  /// use toad_msg::{Code, Id, MessageOptions, Token, Type};
+  ///
+  /// let mut msg = Message::new(Type::Con, Code::PUT, Id(1), Token(Default::default()));
+  /// assert!(msg.request_tags().is_none());
+  ///
+  /// msg.add_request_tag([0xAB]).unwrap();
+  /// assert_eq!(msg.request_tags().map(|tags| tags.len()), Some(1));
+  /// ```
+  fn add_request_tag<B>(&mut self, tag: B) -> Result<(), Self::SetError>
+    where B: AsRef<[u8]>
+  {
+    self.add(opt::known::repeat::REQUEST_TAG,
+             tag.as_ref().iter().copied().collect())
+  }
+
+  /// Get all values for the [Request-Tag](opt::known::repeat::REQUEST_TAG) option
+  fn request_tags(&self) -> Option<&Self::OptValues> {
+    self.get(opt::known::repeat::REQUEST_TAG)
+  }
 }
 
 impl<PayloadBytes: Array<Item = u8> + AppendCopy<u8>, Options: OptionMap>
@@ -835,7 +938,7 @@ impl<PayloadBytes: Array<Item = u8> + AppendCopy<u8>, Options: OptionMap>
   ///   #                     id: Id(1),
   ///   #                     ty: Type::Con,
   ///   #                     ver: Version(1),
-  ///   #                     token: Token(tinyvec::array_vec!([u8; 8] => 254)),
+  ///   #                     token: Token::new(&[254]).unwrap(),
   ///   #                     opts: Default::default(),
   ///   #                     payload: Payload(vec![]) };
   ///   # Some((addr, msg))
@@ -958,17 +1061,9 @@ impl<Bytes: AsRef<[u8]>, PayloadBytes: Array<Item = u8> + AppendCopy<u8>, Option
                                       .ok_or_else(MessageParseError::eof)?
                                       .try_into()?;
 
-    if tkl > 8 {
-      return Err(Self::Error::InvalidTokenLength(tkl));
-    }
-
     let code: Code = bytes.next().ok_or_else(MessageParseError::eof)?.into();
     let id: Id = Id::try_consume_bytes(&mut bytes)?;
-
-    let token = bytes.take_exact(tkl as usize)
-                     .ok_or_else(MessageParseError::eof)?;
-    let token = tinyvec::ArrayVec::<[u8; 8]>::try_from(token).expect("tkl was checked to be <= 8");
-    let token = Token(token);
+    let token = Token::try_consume_bytes(tkl, &mut bytes)?;
 
     let opts = Options::try_consume_bytes(&mut bytes).map_err(Self::Error::OptParseError)?;
 
@@ -997,6 +1092,32 @@ mod tests {
     assert_eq!(alloc::Message::try_from_bytes(&msg).unwrap(), expect)
   }
 
+  #[test]
+  fn parse_invalid_token_length() {
+    #[cfg(not(feature = "extended_token"))]
+    assert_eq!(alloc::Message::try_from_bytes([0b_01_00_1001u8, 1, 0, 0]),
+               Err(MessageParseError::InvalidTokenLength(9)));
+    assert_eq!(alloc::Message::try_from_bytes([0b_01_00_1110u8, 1, 0, 0, 0, 0]),
+               Err(MessageParseError::InvalidTokenLength(14)));
+    assert_eq!(alloc::Message::try_from_bytes([0b_01_00_1111u8, 1, 0, 0]),
+               Err(MessageParseError::InvalidTokenLength(15)));
+  }
+
+  #[cfg(feature = "extended_token")]
+  #[test]
+  fn extended_token_length() {
+    use crate::TryIntoBytes;
+
+    let (mut msg, _) = crate::test_msg();
+    msg.token = Token::new(&[1; Token::MAX_LEN]).unwrap();
+
+    let bytes: std_alloc::vec::Vec<u8> = msg.clone().try_into_bytes().unwrap();
+    assert_eq!(bytes[0] & 0b1111, 13);
+    assert_eq!(bytes[4], 19);
+    assert_eq!(bytes.len(), msg.len());
+    assert_eq!(alloc::Message::try_from_bytes(&bytes).unwrap(), msg);
+  }
+
   #[test]
   fn parse_byte1() {
     let byte = 0b_01_10_0011u8;
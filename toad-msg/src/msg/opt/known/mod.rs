@@ -10,6 +10,10 @@ pub use observe::*;
 pub mod block;
 pub use block::*;
 
+/// No-Response
+pub mod no_response;
+pub use no_response::*;
+
 macro_rules! opt {
   (rfc7252($section:literal) $name:ident = $n:literal) => {
     #[doc = ::toad_macros::rfc_7252_doc!($section)]
@@ -49,6 +53,14 @@ pub mod no_repeat {
        PROXY_SCHEME = 39);
   opt!(#[doc = concat!(toad_macros::rfc_7252_doc!("5.10.9"), include_str!("../../../../docs/Size.md"))]
        SIZE1 = 60);
+  opt!(#[doc = "<https://www.rfc-editor.org/rfc/rfc7967#section-2>"]
+       NO_RESPONSE = 258);
+  opt!(#[doc = "<https://www.rfc-editor.org/rfc/rfc9175#section-2>"]
+       ECHO = 252);
+  opt!(#[doc = "<https://www.rfc-editor.org/rfc/rfc8768#section-3>"]
+       HOP_LIMIT = 16);
+  opt!(#[doc = "<https://www.rfc-editor.org/rfc/rfc8613#section-2>"]
+       OSCORE = 9);
 }
 
 /// Repeatable options
@@ -72,4 +84,6 @@ pub mod repeat {
                 "</details>"
       )]
        ETAG = 4);
+  opt!(#[doc = "<https://www.rfc-editor.org/rfc/rfc9175#section-3>"]
+       REQUEST_TAG = 292);
 }
@@ -0,0 +1,103 @@
+use crate::Code;
+
+/// The value of the [No-Response](super::no_repeat::NO_RESPONSE) option;
+/// a bitmask of the classes of responses that the client is not interested in.
+///
+/// An empty (or absent) No-Response option means the client is interested in
+/// all responses.
+///
+/// ```
+/// use toad_msg::{Code, NoResponse};
+///
+/// let telemetry = NoResponse::new().suppress_2xx();
+/// assert!(telemetry.suppresses(Code::new(2, 4)));
+/// assert!(!telemetry.suppresses(Code::new(4, 0)));
+///
+/// assert!(NoResponse::all().suppresses_all());
+/// ```
+///
+/// See [RFC7967 Section 2.1](https://www.rfc-editor.org/rfc/rfc7967#section-2.1)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NoResponse(u8);
+
+impl NoResponse {
+  /// Bit that is set when the client is not interested in 2.xx responses
+  pub const SUPPRESS_2XX: u8 = 0b00010;
+
+  /// Bit that is set when the client is not interested in 4.xx responses
+  pub const SUPPRESS_4XX: u8 = 0b01000;
+
+  /// Bit that is set when the client is not interested in 5.xx responses
+  pub const SUPPRESS_5XX: u8 = 0b10000;
+
+  /// The client is interested in all responses
+  pub const fn new() -> Self {
+    Self(0)
+  }
+
+  /// The client is not interested in any response
+  pub const fn all() -> Self {
+    Self(Self::SUPPRESS_2XX | Self::SUPPRESS_4XX | Self::SUPPRESS_5XX)
+  }
+
+  /// Suppress 2.xx responses
+  pub const fn suppress_2xx(self) -> Self {
+    Self(self.0 | Self::SUPPRESS_2XX)
+  }
+
+  /// Suppress 4.xx responses
+  pub const fn suppress_4xx(self) -> Self {
+    Self(self.0 | Self::SUPPRESS_4XX)
+  }
+
+  /// Suppress 5.xx responses
+  pub const fn suppress_5xx(self) -> Self {
+    Self(self.0 | Self::SUPPRESS_5XX)
+  }
+
+  /// Should a response with code `code` be suppressed?
+  pub fn suppresses(&self, code: Code) -> bool {
+    match code.class {
+      | 2 => self.0 & Self::SUPPRESS_2XX != 0,
+      | 4 => self.0 & Self::SUPPRESS_4XX != 0,
+      | 5 => self.0 & Self::SUPPRESS_5XX != 0,
+      | _ => false,
+    }
+  }
+
+  /// Is the client uninterested in all responses?
+  pub fn suppresses_all(&self) -> bool {
+    *self == Self::all()
+  }
+}
+
+impl From<NoResponse> for u8 {
+  fn from(n: NoResponse) -> Self {
+    n.0
+  }
+}
+
+impl From<u8> for NoResponse {
+  fn from(n: u8) -> Self {
+    NoResponse(n)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn no_response() {
+    assert_eq!(u8::from(NoResponse::all()), 26);
+    assert_eq!(NoResponse::from(26), NoResponse::all());
+    assert!(!NoResponse::new().suppresses(Code::new(2, 5)));
+
+    let n = NoResponse::from(NoResponse::SUPPRESS_4XX | NoResponse::SUPPRESS_5XX);
+    assert!(!n.suppresses(Code::new(2, 5)));
+    assert!(n.suppresses(Code::new(4, 4)));
+    assert!(n.suppresses(Code::new(5, 0)));
+    assert!(!n.suppresses_all());
+    assert!(n.suppress_2xx().suppresses_all());
+  }
+}
@@ -7,7 +7,7 @@ pub enum MessageParseError {
   /// Reached end of stream before parsing was finished
   UnexpectedEndOfStream,
 
-  /// Token length was > 8
+  /// Token length was > [`Token::MAX_LEN`](crate::Token::MAX_LEN) (8, without the `extended_token` feature), or `TKL` was 15
   InvalidTokenLength(u8),
 
   /// Error parsing option
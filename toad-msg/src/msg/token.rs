@@ -1,10 +1,67 @@
+use tinyvec::ArrayVec;
+use toad_cursor::Cursor;
 use toad_macros::rfc_7252_doc;
 
+use crate::MessageParseError;
+
+/// The bytes of a [`Token`]
+///
+/// Without the `extended_token` feature, this is an 8-byte inline array.
+/// With it, the inline array has room for [`Token::MAX_LEN`] bytes.
+#[cfg(not(feature = "extended_token"))]
+pub type TokenBytes = ArrayVec<[u8; 8]>;
+
+/// The bytes of a [`Token`]
+///
+/// Without the `extended_token` feature, this is an 8-byte inline array.
+/// With it, the inline array has room for [`Token::MAX_LEN`] bytes.
+#[cfg(feature = "extended_token")]
+pub type TokenBytes = ArrayVec<[u8; 32]>;
+
 #[doc = rfc_7252_doc!("5.3.1")]
+/// # Extended Token Length
+/// With the `extended_token` feature, tokens may be longer than 8 bytes
+/// ([RFC8974](https://www.rfc-editor.org/rfc/rfc8974)), up to [`Token::MAX_LEN`] bytes long.
+/// Messages carrying longer tokens are rejected with [`MessageParseError::InvalidTokenLength`].
 #[derive(Copy, Clone, Hash, PartialEq, PartialOrd, Debug, Eq, Ord)]
-pub struct Token(pub tinyvec::ArrayVec<[u8; 8]>);
+pub struct Token(pub TokenBytes);
 
 impl Token {
+  /// The maximum length of a token, in bytes.
+  ///
+  /// 8 ([RFC7252](https://www.rfc-editor.org/rfc/rfc7252#section-3)), or 32 with the
+  /// `extended_token` feature.
+  ///
+  /// [RFC8974](https://www.rfc-editor.org/rfc/rfc8974#section-2.1) allows tokens up to
+  /// 65804 bytes long, but tokens are stored inline so that they stay `Copy` and
+  /// allocation-free on constrained platforms. 32 bytes are enough for a stateless proxy
+  /// to encode its routing state (e.g. an IPv6 address & port) along with a MAC over it;
+  /// requests with longer tokens are rejected, as RFC8974 section 2.2.1 permits.
+  pub const MAX_LEN: usize = if cfg!(feature = "extended_token") {
+    32
+  } else {
+    8
+  };
+
+  /// Create a token from some bytes, yielding `None` if there
+  /// are more than [`Token::MAX_LEN`] of them.
+  ///
+  /// ```
+  /// use toad_msg::Token;
+  ///
+  /// assert_eq!(Token::new(&[1, 2, 3]).unwrap().as_bytes(), &[1, 2, 3]);
+  /// assert_eq!(Token::new(&[0; Token::MAX_LEN + 1]), None);
+  /// ```
+  pub fn new(bytes: &[u8]) -> Option<Token> {
+    if bytes.len() > Self::MAX_LEN {
+      return None;
+    }
+
+    let mut token = TokenBytes::default();
+    token.extend_from_slice(bytes);
+    Some(Token(token))
+  }
+
   /// Take an arbitrary-length sequence of bytes and turn it into an opaque message token
   ///
   /// Currently uses the BLAKE2 hashing algorithm, but this may change in the future.
@@ -20,11 +77,146 @@ impl Token {
 
     let mut digest = Blake2b::<U8>::new();
     digest.update(data);
-    Token(Into::<[u8; 8]>::into(digest.finalize()).into())
+    Token::new(&digest.finalize()).expect("8 <= Token::MAX_LEN")
+  }
+
+  /// Like [`Token::opaque`], but yielding a token `len` bytes long
+  /// (at most [`Token::MAX_LEN`], so never more than 8 without the `extended_token` feature).
+  ///
+  /// Longer tokens are harder for off-path attackers to guess
+  /// ([RFC9175 Section 4](https://www.rfc-editor.org/rfc/rfc9175#section-4)).
+  ///
+  /// ```
+  /// use toad_msg::Token;
+  ///
+  /// assert_eq!(Token::opaque_with_len(&[0, 1, 2], 4).as_bytes().len(), 4);
+  /// assert_eq!(Token::opaque_with_len(&[0, 1, 2], 64).as_bytes().len(),
+  ///            Token::MAX_LEN);
+  /// ```
+  pub fn opaque_with_len(data: &[u8], len: usize) -> Token {
+    use blake2::digest::consts::U32;
+    use blake2::{Blake2b, Digest};
+
+    let mut digest = Blake2b::<U32>::new();
+    digest.update(data);
+    let digest = digest.finalize();
+    Token::new(&digest[..len.min(Self::MAX_LEN)]).expect("len <= Token::MAX_LEN")
   }
 
   /// Convert a reference to a Token to a byte slice
   pub fn as_bytes(&self) -> &[u8] {
     &self.0
   }
+
+  /// Split the length of this token into the 4-bit `TKL` field & the bytes of
+  /// the `Extended Token Length` field ([RFC8974 Section 2.1](https://www.rfc-editor.org/rfc/rfc8974#section-2.1))
+  ///
+  /// Tokens shorter than 13 bytes have no `Extended Token Length`.
+  pub(crate) fn tkl(&self) -> (u8, ArrayVec<[u8; 2]>) {
+    let mut ext = ArrayVec::new();
+    match self.0.len() {
+      | n if n >= 13 => {
+        ext.push((n - 13) as u8);
+        (13, ext)
+      },
+      | n => (n as u8, ext),
+    }
+  }
+
+  /// Consume the `Extended Token Length` field (if any) & the token
+  /// following it, given the 4-bit `TKL` field.
+  ///
+  /// `TKL` 15 is invalid, as are tokens longer than [`Token::MAX_LEN`].
+  /// Without the `extended_token` feature, `TKL` values greater than 8 are invalid.
+  pub(crate) fn try_consume_bytes<B: AsRef<[u8]>>(tkl: u8,
+                                                  bytes: &mut Cursor<B>)
+                                                  -> Result<Token, MessageParseError> {
+    let extended = cfg!(feature = "extended_token");
+    let len = match tkl {
+      | 13 if extended => bytes.next().ok_or_else(MessageParseError::eof)? as usize + 13,
+      | 14 if extended => {
+        let ext = bytes.take_exact(2).ok_or_else(MessageParseError::eof)?;
+        u16::from_be_bytes([ext[0], ext[1]]) as usize + 269
+      },
+      | 13..=15 => return Err(MessageParseError::InvalidTokenLength(tkl)),
+      | n => n as usize,
+    };
+
+    if len > Self::MAX_LEN {
+      return Err(MessageParseError::InvalidTokenLength(tkl));
+    }
+
+    let token = bytes.take_exact(len).ok_or_else(MessageParseError::eof)?;
+    Ok(Token::new(token).expect("token length was checked to be <= Token::MAX_LEN"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tkl() {
+    assert_eq!(Token(Default::default()).tkl(), (0, ArrayVec::new()));
+    assert_eq!(Token::new(&[1; 8]).unwrap().tkl(), (8, ArrayVec::new()));
+  }
+
+  #[test]
+  fn try_consume_bytes_should_reject_invalid_lengths() {
+    let mut bytes = Cursor::new([1u8; 32]);
+    assert_eq!(Token::try_consume_bytes(15, &mut bytes),
+               Err(MessageParseError::InvalidTokenLength(15)));
+
+    let mut bytes = Cursor::new([20u8; 64]);
+    assert_eq!(Token::try_consume_bytes(13, &mut bytes),
+               Err(MessageParseError::InvalidTokenLength(13)));
+
+    let mut bytes = Cursor::new([0u8; 64]);
+    assert_eq!(Token::try_consume_bytes(14, &mut bytes),
+               Err(MessageParseError::InvalidTokenLength(14)));
+
+    #[cfg(not(feature = "extended_token"))]
+    for tkl in 9..=14 {
+      let mut bytes = Cursor::new([0u8; 64]);
+      assert_eq!(Token::try_consume_bytes(tkl, &mut bytes),
+                 Err(MessageParseError::InvalidTokenLength(tkl)));
+    }
+  }
+
+  #[test]
+  fn opaque_with_len_should_not_exceed_max_len() {
+    assert_eq!(Token::opaque_with_len(&[1], 8).as_bytes().len(), 8);
+    assert_eq!(Token::opaque_with_len(&[1], 16).as_bytes().len(),
+               16.min(Token::MAX_LEN));
+    assert_eq!(Token::opaque_with_len(&[1], 100).as_bytes().len(),
+               Token::MAX_LEN);
+  }
+
+  #[test]
+  fn try_consume_bytes_should_require_enough_bytes() {
+    let mut bytes = Cursor::new([1u8, 2]);
+    assert_eq!(Token::try_consume_bytes(3, &mut bytes),
+               Err(MessageParseError::UnexpectedEndOfStream));
+  }
+
+  #[cfg(feature = "extended_token")]
+  #[test]
+  fn extended_token_length_should_round_trip() {
+    use std_alloc::vec::Vec;
+
+    for (len, tkl, ext) in [(12, 12, &[][..]),
+                            (13, 13, &[0][..]),
+                            (Token::MAX_LEN, 13, &[19][..])]
+    {
+      let token = Token::new(&(0..len as u8).collect::<Vec<_>>()).unwrap();
+      assert_eq!(token.tkl(), (tkl, ArrayVec::try_from(ext).unwrap()));
+
+      let bytes = ext.iter()
+                     .chain(token.as_bytes())
+                     .copied()
+                     .collect::<Vec<_>>();
+      assert_eq!(Token::try_consume_bytes(tkl, &mut Cursor::new(bytes)),
+                 Ok(token));
+    }
+  }
 }
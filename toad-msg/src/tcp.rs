@@ -0,0 +1,549 @@
+use tinyvec::ArrayVec;
+use toad_array::{AppendCopy, Array};
+use toad_cursor::Cursor;
+use toad_len::Len;
+
+use crate::from_bytes::TryConsumeBytes;
+use crate::to_bytes::MessageToBytesError;
+use crate::*;
+
+/// A [`Message`] sent over a reliable transport (TCP, TLS or WebSockets)
+///
+/// Reliable transports take care of retransmission & deduplication, so
+/// the RFC8323 header has no Version, [`Type`] or [`Id`]; instead it starts
+/// with the length of the options & payload:
+///
+/// ```text
+///  0 1 2 3 4 5 6 7
+/// +-+-+-+-+-+-+-+-+------------+------+-------+----------+----------+
+/// |  Len  |  TKL  | Ext Length | Code | Token | Options  | Payload  |
+/// +-+-+-+-+-+-+-+-+------------+------+-------+----------+----------+
+/// ```
+///
+/// With the `extended_token` feature, the `Extended Token Length` field
+/// ([RFC8974](https://www.rfc-editor.org/rfc/rfc8974#section-2.2)) follows `Code`.
+///
+/// When serializing, the message's `ver`, `ty` & `id` are ignored.
+/// When parsing, they are set to the default [`Version`], [`Type::Non`] & `Id(0)`.
+///
+/// ```
+/// use toad_msg::alloc::Message;
+/// use toad_msg::tcp::Reliable;
+/// use toad_msg::{Code, Id, Token, TryFromBytes, TryIntoBytes, Type};
+///
+/// let get = Message::new(Type::Non, Code::GET, Id(0), Token(Default::default()));
+///
+/// let bytes: Vec<u8> = Reliable(get.clone()).try_into_bytes().unwrap();
+/// assert_eq!(bytes, vec![0b0000_0000, 0b000_00001]);
+///
+/// assert_eq!(Reliable::<Message>::try_from_bytes(&bytes).unwrap(),
+///            Reliable(get));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Reliable<M>(pub M);
+
+/// Split a length into the 4-bit `Len` field & the bytes of the
+/// `Extended Length` field ([RFC8323 Section 3.2](https://www.rfc-editor.org/rfc/rfc8323#section-3.2))
+fn len_or_extended(len: usize) -> (u8, ArrayVec<[u8; 4]>) {
+  let mut ext = ArrayVec::new();
+  match len {
+    | n if n >= 65805 => {
+      ext.extend(((n - 65805) as u32).to_be_bytes());
+      (15, ext)
+    },
+    | n if n >= 269 => {
+      ext.extend(((n - 269) as u16).to_be_bytes());
+      (14, ext)
+    },
+    | n if n >= 13 => {
+      ext.push((n - 13) as u8);
+      (13, ext)
+    },
+    | n => (n as u8, ext),
+  }
+}
+
+/// The size of the `Extended Length` field & the value it's relative to,
+/// given the 4-bit `Len` field
+fn extended_len(len: u8) -> (usize, usize) {
+  match len {
+    | 13 => (1, 13),
+    | 14 => (2, 269),
+    | 15 => (4, 65805),
+    | n => (0, n as usize),
+  }
+}
+
+/// Get the size of the options & payload of a message
+/// (the value of its `Len` field)
+fn body_len<P, O>(msg: &Message<P, O>) -> usize
+  where P: Array<Item = u8>,
+        O: OptionMap
+{
+  let opts_size: usize = msg.opts.opt_refs().map(|o| o.len()).sum();
+  let payload_size = match msg.payload.0.len() {
+    | 0 => 0,
+    | n => n + 1,
+  };
+
+  opts_size + payload_size
+}
+
+/// Get the number of bytes the first message in `bytes` occupies,
+/// once enough of its header has been received to tell.
+///
+/// Stream transports don't delimit messages, so this can be used to find
+/// where one message ends and the next begins.
+///
+/// ```
+/// use toad_msg::tcp::frame_len;
+///
+/// // Len = 13 (Extended Length follows), TKL = 1
+/// assert_eq!(frame_len(&[0b1101_0001]), None);
+/// assert_eq!(frame_len(&[0b1101_0001, 2]), Some(1 + 1 + 1 + 1 + 15));
+///
+/// // Len = 0, TKL = 0
+/// assert_eq!(frame_len(&[0b0000_0000]), Some(2));
+/// ```
+pub fn frame_len(bytes: &[u8]) -> Option<usize> {
+  let byte1 = *bytes.first()?;
+  let (ext_size, base) = extended_len(byte1 >> 4);
+  let tkl = byte1 & 0b1111;
+
+  let ext = bytes.get(1..1 + ext_size)?;
+  let len = ext.iter().fold(0usize, |len, b| (len << 8) | *b as usize) + base;
+
+  // the Extended Token Length field follows Code
+  let (ext_tkl_size, tkl) = match tkl {
+    | 13 | 14 if cfg!(feature = "extended_token") => {
+      let (ext_tkl_size, base) = extended_len(tkl);
+      let ext_tkl = bytes.get(2 + ext_size..2 + ext_size + ext_tkl_size)?;
+      (ext_tkl_size,
+       ext_tkl.iter()
+              .fold(0usize, |len, b| (len << 8) | *b as usize)
+       + base)
+    },
+    | n => (0, n as usize),
+  };
+
+  Some(1 + ext_size + 1 + ext_tkl_size + tkl + len)
+}
+
+impl<PayloadBytes: Array<Item = u8>, Options: OptionMap> TryIntoBytes
+  for Reliable<Message<PayloadBytes, Options>>
+{
+  type Error = MessageToBytesError;
+
+  fn try_into_bytes<C: Array<Item = u8>>(self) -> Result<C, Self::Error> {
+    let Reliable(msg) = self;
+
+    let (len, ext) = len_or_extended(body_len(&msg));
+    let (tkl, ext_tkl) = msg.token.tkl();
+    let size = 1 + ext.len() + 1 + ext_tkl.len() + msg.token.0.len() + body_len(&msg);
+
+    if let Some(max) = C::CAPACITY {
+      if max < size {
+        return Err(Self::Error::TooLong { capacity: max,
+                                          size });
+      }
+    }
+
+    let mut bytes = C::reserve(size);
+    bytes.extend(Some(len << 4 | tkl));
+    bytes.extend(ext);
+    bytes.extend(Some(u8::from(msg.code)));
+    bytes.extend(ext_tkl);
+    bytes.extend(msg.token.0);
+
+    for opt in msg.opts.opts() {
+      opt.extend_bytes(&mut bytes);
+    }
+
+    if !msg.payload.0.is_empty() {
+      bytes.extend(Some(0b11111111));
+      bytes.extend(msg.payload.0);
+    }
+
+    Ok(bytes)
+  }
+}
+
+impl<Bytes: AsRef<[u8]>, PayloadBytes: Array<Item = u8> + AppendCopy<u8>, Options: OptionMap>
+  TryFromBytes<Bytes> for Reliable<Message<PayloadBytes, Options>>
+{
+  type Error = MessageParseError;
+
+  fn try_from_bytes(bytes: Bytes) -> Result<Self, Self::Error> {
+    let mut bytes = Cursor::new(bytes);
+
+    let byte1 = bytes.next().ok_or_else(MessageParseError::eof)?;
+    let (ext_size, base) = extended_len(byte1 >> 4);
+    let tkl = byte1 & 0b1111;
+
+    let len = bytes.take_exact(ext_size)
+                   .ok_or_else(MessageParseError::eof)?
+                   .iter()
+                   .fold(0usize, |len, b| (len << 8) | *b as usize)
+              + base;
+
+    let code: Code = bytes.next().ok_or_else(MessageParseError::eof)?.into();
+    let token = Token::try_consume_bytes(tkl, &mut bytes)?;
+
+    let mut body = Cursor::new(bytes.take_exact(len).ok_or_else(MessageParseError::eof)?);
+
+    let opts = Options::try_consume_bytes(&mut body).map_err(Self::Error::OptParseError)?;
+
+    let mut payload = PayloadBytes::reserve(body.remaining());
+    payload.append_copy(body.take_until_end());
+
+    Ok(Reliable(Message { id: Id(0),
+                          ty: Type::Non,
+                          ver: Version::default(),
+                          code,
+                          token,
+                          opts,
+                          payload: Payload(payload) }))
+  }
+}
+
+/// Option numbers of signaling messages
+///
+/// Unlike other options, these are only meaningful alongside the
+/// signaling [`Code`] they are defined for.
+pub mod signal_opt {
+  use crate::OptNumber;
+
+  /// 7.01 CSM: the largest message the sender can receive, in bytes
+  ///
+  /// See [RFC8323 Section 5.3.1](https://www.rfc-editor.org/rfc/rfc8323#section-5.3.1)
+  pub const MAX_MESSAGE_SIZE: OptNumber = OptNumber(2);
+
+  /// 7.01 CSM: the sender supports block-wise transfer
+  ///
+  /// See [RFC8323 Section 5.3.2](https://www.rfc-editor.org/rfc/rfc8323#section-5.3.2)
+  pub const BLOCK_WISE_TRANSFER: OptNumber = OptNumber(4);
+
+  /// 7.02 Ping & 7.03 Pong: the Pong should be sent once all messages
+  /// received before the Ping have been handled
+  ///
+  /// See [RFC8323 Section 5.4.1](https://www.rfc-editor.org/rfc/rfc8323#section-5.4.1)
+  pub const CUSTODY: OptNumber = OptNumber(2);
+
+  /// 7.04 Release: an address the peer may reconnect to
+  ///
+  /// See [RFC8323 Section 5.5](https://www.rfc-editor.org/rfc/rfc8323#section-5.5)
+  pub const ALTERNATIVE_ADDRESS: OptNumber = OptNumber(2);
+
+  /// 7.04 Release: seconds the peer should wait before reconnecting
+  ///
+  /// See [RFC8323 Section 5.5](https://www.rfc-editor.org/rfc/rfc8323#section-5.5)
+  pub const HOLD_OFF: OptNumber = OptNumber(4);
+
+  /// 7.05 Abort: the number of the CSM option that caused the abort
+  ///
+  /// See [RFC8323 Section 5.6](https://www.rfc-editor.org/rfc/rfc8323#section-5.6)
+  pub const BAD_CSM_OPTION: OptNumber = OptNumber(2);
+}
+
+/// A signaling message ([RFC8323 Section 5](https://www.rfc-editor.org/rfc/rfc8323#section-5)),
+/// used to manage the connection rather than to exchange requests & responses.
+///
+/// ```
+/// use toad_msg::alloc::Message;
+/// use toad_msg::tcp::Signal;
+/// use toad_msg::{Code, Token};
+///
+/// let csm = Signal::Csm { max_message_size: Some(1152),
+///                         block_wise_transfer: true };
+/// let msg: Message = csm.to_message(Token(Default::default())).unwrap();
+/// assert_eq!(msg.code, Code::CSM);
+///
+/// assert_eq!(Signal::from_message(&msg), Some(csm));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Signal<'a> {
+  /// 7.01 Capabilities and Settings Message; must be the first message
+  /// sent by either side of a connection
+  Csm {
+    /// See [`signal_opt::MAX_MESSAGE_SIZE`]
+    max_message_size: Option<u32>,
+    /// See [`signal_opt::BLOCK_WISE_TRANSFER`]
+    block_wise_transfer: bool,
+  },
+  /// 7.02 Ping; the peer must respond with a [`Signal::Pong`]
+  Ping {
+    /// See [`signal_opt::CUSTODY`]
+    custody: bool,
+  },
+  /// 7.03 Pong
+  Pong {
+    /// See [`signal_opt::CUSTODY`]
+    custody: bool,
+  },
+  /// 7.04 Release; the sender will close the connection gracefully
+  Release {
+    /// See [`signal_opt::ALTERNATIVE_ADDRESS`]
+    alternative_address: Option<&'a str>,
+    /// See [`signal_opt::HOLD_OFF`]
+    hold_off_seconds: Option<u32>,
+  },
+  /// 7.05 Abort; the sender is closing the connection because of an error
+  Abort {
+    /// See [`signal_opt::BAD_CSM_OPTION`]
+    bad_csm_option: Option<u16>,
+  },
+}
+
+/// Encode `n` as a CoAP `uint` option value, with as few bytes as possible
+fn uint<B>(n: u32) -> OptValue<B>
+  where B: Array<Item = u8>
+{
+  OptValue(n.to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect())
+}
+
+/// Decode a CoAP `uint` option value of up to 4 bytes
+fn get_uint<B>(v: &OptValue<B>) -> Option<u32>
+  where B: Array<Item = u8>
+{
+  Some(&v.0).filter(|bytes| bytes.len() <= 4)
+            .map(|bytes| bytes.iter().fold(0u32, |n, b| (n << 8) | *b as u32))
+}
+
+impl<'a> Signal<'a> {
+  /// The signaling code of this message
+  pub fn code(&self) -> Code {
+    match self {
+      | Self::Csm { .. } => Code::CSM,
+      | Self::Ping { .. } => Code::PING,
+      | Self::Pong { .. } => Code::PONG,
+      | Self::Release { .. } => Code::RELEASE,
+      | Self::Abort { .. } => Code::ABORT,
+    }
+  }
+
+  /// Interpret a message as a signal, yielding `None` if
+  /// it doesn't have a signaling code.
+  ///
+  /// Options with invalid values are ignored.
+  pub fn from_message<P, O>(msg: &'a Message<P, O>) -> Option<Self>
+    where P: Array<Item = u8> + AppendCopy<u8>,
+          O: OptionMap
+  {
+    let has = |n: OptNumber| msg.get_first(n).is_some();
+    let uint = |n: OptNumber| msg.get_first(n).and_then(get_uint);
+
+    match msg.code {
+      | Code::CSM => Some(Self::Csm { max_message_size: uint(signal_opt::MAX_MESSAGE_SIZE),
+                                      block_wise_transfer: has(signal_opt::BLOCK_WISE_TRANSFER) }),
+      | Code::PING => Some(Self::Ping { custody: has(signal_opt::CUSTODY) }),
+      | Code::PONG => Some(Self::Pong { custody: has(signal_opt::CUSTODY) }),
+      | Code::RELEASE => Some(Self::Release { alternative_address:
+                                                msg.get_str(signal_opt::ALTERNATIVE_ADDRESS)
+                                                   .ok()
+                                                   .flatten(),
+                                              hold_off_seconds: uint(signal_opt::HOLD_OFF) }),
+      | Code::ABORT => Some(Self::Abort { bad_csm_option:
+                                            uint(signal_opt::BAD_CSM_OPTION).and_then(|n| {
+                                                                              u16::try_from(n).ok()
+                                                                            }) }),
+      | _ => None,
+    }
+  }
+
+  /// Create a message for this signal
+  ///
+  /// Since signals are only sent over reliable transports,
+  /// the message has [`Type::Non`] and `Id(0)`.
+  pub fn to_message<P, O>(&self,
+                          token: Token)
+                          -> Result<Message<P, O>, <Message<P, O> as MessageOptions>::SetError>
+    where P: Array<Item = u8> + AppendCopy<u8>,
+          O: OptionMap
+  {
+    let mut msg = Message::new(Type::Non, self.code(), Id(0), token);
+
+    match *self {
+      | Self::Csm { max_message_size,
+                    block_wise_transfer, } => {
+        if let Some(n) = max_message_size {
+          msg.set(signal_opt::MAX_MESSAGE_SIZE, uint(n))?;
+        }
+        if block_wise_transfer {
+          msg.set(signal_opt::BLOCK_WISE_TRANSFER, Default::default())?;
+        }
+      },
+      | Self::Ping { custody } | Self::Pong { custody } => {
+        if custody {
+          msg.set(signal_opt::CUSTODY, Default::default())?;
+        }
+      },
+      | Self::Release { alternative_address,
+                        hold_off_seconds, } => {
+        if let Some(addr) = alternative_address {
+          msg.set(signal_opt::ALTERNATIVE_ADDRESS,
+                  addr.as_bytes().iter().copied().collect())?;
+        }
+        if let Some(n) = hold_off_seconds {
+          msg.set(signal_opt::HOLD_OFF, uint(n))?;
+        }
+      },
+      | Self::Abort { bad_csm_option } => {
+        if let Some(n) = bad_csm_option {
+          msg.set(signal_opt::BAD_CSM_OPTION, uint(n as u32))?;
+        }
+      },
+    }
+
+    Ok(msg)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alloc;
+
+  fn msg(payload: usize) -> alloc::Message {
+    let mut msg = alloc::Message::new(Type::Con,
+                                      Code::new(2, 5),
+                                      Id(12),
+                                      Token::new(&[254]).unwrap());
+    msg.set_content_format(ContentFormat::Json).unwrap();
+    msg.payload = Payload(vec![1u8; payload]);
+    msg
+  }
+
+  #[test]
+  fn serialize() {
+    let bytes: Vec<u8> = Reliable(msg(3)).try_into_bytes().unwrap();
+
+    // option: delta 12, length 2 (json = 50); marker; 3 payload bytes
+    assert_eq!(bytes,
+               vec![0b0111_0001,
+                    0b010_00101,
+                    254,
+                    0b1100_0010,
+                    0,
+                    50,
+                    0xFF,
+                    1,
+                    1,
+                    1]);
+  }
+
+  #[test]
+  fn serialize_extended_length() {
+    // options & payload marker add 4 bytes to the length of the payload
+    let cases = [(8, 12, vec![]),
+                 (9, 13, vec![0]),
+                 (300, 14, (304u16 - 269).to_be_bytes().to_vec()),
+                 (70_000, 15, (70_004u32 - 65805).to_be_bytes().to_vec())];
+
+    for (payload, len, ext) in cases {
+      let bytes: Vec<u8> = Reliable(msg(payload)).try_into_bytes().unwrap();
+
+      assert_eq!(bytes[0] >> 4, len);
+      assert_eq!(&bytes[1..1 + ext.len()], ext.as_slice());
+      assert_eq!(frame_len(&bytes), Some(bytes.len()));
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    for payload in [0, 1, 12, 13, 268, 269, 70_000] {
+      let bytes: Vec<u8> = Reliable(msg(payload)).try_into_bytes().unwrap();
+      let Reliable(parsed) = Reliable::<alloc::Message>::try_from_bytes(&bytes).unwrap();
+
+      assert_eq!(parsed.ty, Type::Non);
+      assert_eq!(parsed.id, Id(0));
+      assert_eq!(parsed.code, Code::new(2, 5));
+      assert_eq!(parsed.token, msg(0).token);
+      assert_eq!(parsed.content_format(), Some(ContentFormat::Json));
+      assert_eq!(parsed.payload, msg(payload).payload);
+    }
+  }
+
+  #[test]
+  fn parse_should_stop_at_end_of_frame() {
+    let mut bytes: Vec<u8> = Reliable(msg(2)).try_into_bytes().unwrap();
+    let len = bytes.len();
+    bytes.extend([0b0000_0000, Code::PING.into()]);
+
+    assert_eq!(frame_len(&bytes), Some(len));
+    assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&bytes).unwrap()
+                                                                 .0
+                                                                 .payload,
+               msg(2).payload);
+    assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&bytes[len..]).unwrap()
+                                                                        .0
+                                                                        .code,
+               Code::PING);
+  }
+
+  #[cfg(feature = "extended_token")]
+  #[test]
+  fn extended_token_length() {
+    for len in [13, Token::MAX_LEN] {
+      let mut msg = msg(2);
+      msg.token = Token::new(&vec![1; len]).unwrap();
+
+      let bytes: Vec<u8> = Reliable(msg.clone()).try_into_bytes().unwrap();
+      assert_eq!(frame_len(&bytes), Some(bytes.len()));
+      assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&bytes).unwrap()
+                                                                   .0
+                                                                   .token,
+                 msg.token);
+    }
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&[]),
+               Err(MessageParseError::UnexpectedEndOfStream));
+    #[cfg(not(feature = "extended_token"))]
+    assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&[0b0000_1001, 0]),
+               Err(MessageParseError::InvalidTokenLength(9)));
+    assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&[0b0000_1110, 0, 0, 0]),
+               Err(MessageParseError::InvalidTokenLength(14)));
+    assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&[0b0000_1111, 0]),
+               Err(MessageParseError::InvalidTokenLength(15)));
+    assert_eq!(Reliable::<alloc::Message>::try_from_bytes(&[0b0010_0000, 0, 0xFF]),
+               Err(MessageParseError::UnexpectedEndOfStream));
+  }
+
+  #[test]
+  fn signals() {
+    let signals = [Signal::Csm { max_message_size: None,
+                                 block_wise_transfer: false },
+                   Signal::Csm { max_message_size: Some(1152),
+                                 block_wise_transfer: true },
+                   Signal::Ping { custody: true },
+                   Signal::Pong { custody: false },
+                   Signal::Release { alternative_address: Some("coap+tcp://[2001:db8::1]"),
+                                     hold_off_seconds: Some(30) },
+                   Signal::Abort { bad_csm_option: Some(2) }];
+
+    for signal in signals {
+      let msg: alloc::Message = signal.to_message(Token(Default::default())).unwrap();
+      let bytes: Vec<u8> = Reliable(msg).try_into_bytes().unwrap();
+      let Reliable(msg) = Reliable::<alloc::Message>::try_from_bytes(&bytes).unwrap();
+      assert_eq!(Signal::from_message(&msg), Some(signal));
+    }
+
+    let get = alloc::Message::new(Type::Non, Code::GET, Id(0), Token(Default::default()));
+    assert_eq!(Signal::from_message(&get), None);
+  }
+
+  #[test]
+  fn signal_uints_should_be_minimal() {
+    let csm: alloc::Message =
+      Signal::Csm { max_message_size: Some(1152),
+                    block_wise_transfer: false }.to_message(Token(Default::default()))
+                                                .unwrap();
+    assert_eq!(csm.get_first(signal_opt::MAX_MESSAGE_SIZE).unwrap().0,
+               1152u16.to_be_bytes().to_vec());
+  }
+}
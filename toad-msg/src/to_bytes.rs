@@ -70,18 +70,19 @@ impl<PayloadBytes: Array<Item = u8>, Options: OptionMap> TryIntoBytes
       }
     }
 
-    let byte1: u8 = Byte1 { tkl: self.token.0.len() as u8,
+    let (tkl, ext_tkl) = self.token.tkl();
+    let byte1: u8 = Byte1 { tkl,
                             ver: self.ver,
                             ty: self.ty }.into();
     let code: u8 = self.code.into();
     let id: [u8; 2] = self.id.into();
-    let token: ArrayVec<[u8; 8]> = self.token.0;
 
     bytes.extend(Some(byte1));
     bytes.extend(Some(code));
 
     bytes.extend(id);
-    bytes.extend(token);
+    bytes.extend(ext_tkl);
+    bytes.extend(self.token.0);
 
     for opt in self.opts.opts() {
       opt.extend_bytes(&mut bytes);
@@ -105,7 +106,7 @@ pub(crate) fn opt_len_or_delta(val: u16) -> (u8, Option<ArrayVec<[u8; 2]>>) {
     },
     | n if n >= 13 => {
       let mut bytes = ArrayVec::new();
-      bytes.push((n as u8) - 13);
+      bytes.push((n - 13) as u8);
       (13, Some(bytes))
     },
     | n => (n as u8, None),
@@ -202,7 +203,7 @@ mod tests {
   #[test]
   fn opt() {
     use core::iter::repeat;
-    let cases: [(u16, Vec<u8>, Vec<u8>); 4] =
+    let cases: [(u16, Vec<u8>, Vec<u8>); 5] =
       [(24,
         repeat(1).take(100).collect(),
         [[0b1101_1101u8, 24 - 13, 100 - 13].as_ref(),
@@ -213,7 +214,8 @@ mod tests {
         repeat(1).take(300).collect(),
         [[0b1101_1110, 24 - 13].as_ref(),
          (300u16 - 269).to_be_bytes().as_ref(),
-         repeat(1).take(300).collect::<Vec<u8>>().as_ref()].concat())];
+         repeat(1).take(300).collect::<Vec<u8>>().as_ref()].concat()),
+       (258, vec![1], vec![0b1101_0001, (258u16 - 13) as u8, 1])];
 
     cases.into_iter().for_each(|(delta, values, expected)| {
                        let opt = Opt::<Vec<u8>> { delta: OptDelta(delta),
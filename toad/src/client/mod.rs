@@ -0,0 +1,268 @@
+use embedded_time::duration::Milliseconds;
+use embedded_time::{Clock as _, Instant};
+use no_std_net::SocketAddr;
+use toad_msg::{Code, MessageOptions, Type};
+
+use crate::config::Config;
+use crate::net::Addrd;
+use crate::platform::{Message, Platform, PlatformError};
+use crate::req::Req;
+use crate::resp::Resp;
+use crate::step::Step;
+use crate::time::Clock;
+use crate::ToCoapValue;
+
+/// Subscribe to resources on remote servers
+///
+/// See [`Platform::observe`](crate::platform::Platform::observe)
+pub mod observe;
+
+/// Register with a Resource Directory ([RFC9176](https://www.rfc-editor.org/rfc/rfc9176))
+///
+/// See [`rd::Directory`]
+pub mod rd;
+
+/// [`BlockingClient`] errors
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+  /// No response was received within
+  /// [`Msg.request_timeout`](crate::config::Msg.request_timeout)
+  Timeout,
+  /// The request was retried as many times as allowed by
+  /// [`Msg.con`](crate::config::Msg.con) (or [`Msg.non`](crate::config::Msg.non))
+  /// without receiving a response
+  RetriesExhausted,
+  /// Error of input type `E`
+  Other(E),
+}
+
+impl<E> From<E> for Error<E> {
+  fn from(e: E) -> Self {
+    Self::Other(e)
+  }
+}
+
+/// Should we stop waiting for a response to a request sent at `sent_at`?
+pub(crate) fn gave_up<C, E>(config: Config,
+                            sent_at: Instant<C>,
+                            now: Instant<C>)
+                            -> Option<Error<E>>
+  where C: Clock
+{
+  let elapsed = now.checked_duration_since(&sent_at)
+                   .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+                   .map(|Milliseconds(ms)| ms)
+                   .unwrap_or(0);
+
+  match config.msg.request_timeout {
+    | Some(Milliseconds(timeout)) if elapsed >= timeout => Some(Error::Timeout),
+    | _ if elapsed >= config.max_transmit_wait_millis() => Some(Error::RetriesExhausted),
+    | _ => None,
+  }
+}
+
+/// The instant at which [`gave_up`] will start giving up
+/// on a request sent at `sent_at`
+pub(crate) fn give_up_at<C>(config: Config, sent_at: Instant<C>) -> Instant<C>
+  where C: Clock
+{
+  let wait = config.msg
+                   .request_timeout
+                   .map(|Milliseconds(ms)| ms)
+                   .unwrap_or(u64::MAX)
+                   .min(config.max_transmit_wait_millis());
+
+  sent_at + Milliseconds(wait)
+}
+
+/// Use a CoAP [`Platform`] as a client
+///
+/// This trait provides functions that send a request and
+/// block until the matching response is received.
+///
+/// ```no_run
+/// use toad::client::BlockingClient;
+/// use toad::net::ipv4_socketaddr;
+/// use toad::std::dtls;
+/// use toad::step::runtime::std::Runtime;
+///
+/// let client = toad::std::Platform::<dtls::N, Runtime<dtls::N>>::try_new("127.0.0.1:4444",
+///                                                                     Default::default()).unwrap();
+///
+/// let server = ipv4_socketaddr([127, 0, 0, 1], 5683);
+/// let resp = client.get(server, "hello").unwrap();
+/// println!("{:?}", resp.data().payload_string());
+/// ```
+pub trait BlockingClient<S>: Sized + Platform<S>
+  where S: Step<Self::Types, PollReq = Addrd<Req<Self::Types>>, PollResp = Addrd<Resp<Self::Types>>>
+{
+  /// Send a request and wait for the response to it
+  ///
+  /// If the request's [No-Response](toad_msg::opt::known::no_repeat::NO_RESPONSE)
+  /// option suppresses all responses, there's no response to wait for:
+  ///  * NON requests yield an empty response as soon as they're sent
+  ///  * CON requests yield the empty ACK once it's received
+  fn send_req(&self,
+              req: Addrd<Req<Self::Types>>)
+              -> Result<Addrd<Resp<Self::Types>>, Error<Self::Error>> {
+    let config = self.config();
+    let now = || {
+      self.clock()
+          .try_now()
+          .map_err(Self::Error::clock)
+          .map_err(Error::Other)
+    };
+
+    let sent_at = now()?;
+    let addr = req.addr();
+    let msg = req.map(Message::<Self::Types>::from);
+    let suppresses_all = msg.data()
+                            .no_response()
+                            .map(|n| n.suppresses_all())
+                            .unwrap_or(false);
+    let ty = msg.data().ty;
+    let (id, token) = nb::block!(self.send_msg(msg.clone())).map_err(Error::Other)?;
+
+    if suppresses_all && ty == Type::Non {
+      let empty = Message::<Self::Types>::new(Type::Non, Code::EMPTY, id, token);
+      return Ok(Addrd(Resp::from(empty), addr));
+    }
+
+    loop {
+      match self.poll_resp(token, addr) {
+        | Ok(resp) => break Ok(resp),
+        | Err(nb::Error::Other(e)) if e.is_timeout() => break Err(Error::RetriesExhausted),
+        | Err(nb::Error::Other(e)) => break Err(Error::Other(e)),
+        | Err(nb::Error::WouldBlock) => match gave_up(config, sent_at, now()?) {
+          | Some(e) => break Err(e),
+          | None => self.wait(Some(give_up_at(config, sent_at)))
+                        .map_err(Error::Other)?,
+        },
+      }
+    }
+  }
+
+  /// Send a GET request to `path` on the server at `addr`
+  fn get<P>(&self,
+            addr: SocketAddr,
+            path: P)
+            -> Result<Addrd<Resp<Self::Types>>, Error<Self::Error>>
+    where P: AsRef<str>
+  {
+    self.send_req(Addrd(Req::get(path), addr))
+  }
+
+  /// Send a POST request to `path` on the server at `addr`
+  fn post<P, B>(&self,
+                addr: SocketAddr,
+                path: P,
+                payload: B)
+                -> Result<Addrd<Resp<Self::Types>>, Error<Self::Error>>
+    where P: AsRef<str>,
+          B: ToCoapValue
+  {
+    let mut req = Req::post(path);
+    req.set_payload(payload);
+    self.send_req(Addrd(req, addr))
+  }
+
+  /// Send a PUT request to `path` on the server at `addr`
+  fn put<P, B>(&self,
+               addr: SocketAddr,
+               path: P,
+               payload: B)
+               -> Result<Addrd<Resp<Self::Types>>, Error<Self::Error>>
+    where P: AsRef<str>,
+          B: ToCoapValue
+  {
+    let mut req = Req::put(path);
+    req.set_payload(payload);
+    self.send_req(Addrd(req, addr))
+  }
+
+  /// Send a DELETE request to `path` on the server at `addr`
+  fn delete<P>(&self,
+               addr: SocketAddr,
+               path: P)
+               -> Result<Addrd<Resp<Self::Types>>, Error<Self::Error>>
+    where P: AsRef<str>
+  {
+    self.send_req(Addrd(Req::delete(path), addr))
+  }
+}
+
+impl<S, T> BlockingClient<S> for T
+  where S: Step<Self::Types, PollReq = Addrd<Req<Self::Types>>, PollResp = Addrd<Resp<Self::Types>>>,
+        T: Sized + Platform<S>
+{
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::UdpSocket;
+
+  use embedded_time::duration::Milliseconds;
+  use toad_msg::{NoResponse, Token, TryFromBytes, TryIntoBytes};
+
+  use super::*;
+  use crate::retry::{Attempts, Strategy};
+  use crate::std::{dtls, PlatformTypes as Std};
+  use crate::step::runtime::std::Runtime;
+
+  type Platform = crate::std::Platform<dtls::N, Runtime<dtls::N>>;
+
+  #[test]
+  fn send_req_should_yield_retries_exhausted_when_retry_step_gives_up() {
+    let mut config = Config::default();
+    config.msg.con.unacked_retry_strategy = Strategy::Delay { min: Milliseconds(10),
+                                                              max: Milliseconds(10) };
+    config.msg.con.max_attempts = Attempts(2);
+
+    // the retry step gives up well before `gave_up` would
+    assert!(config.max_transmit_wait_millis() > 1_000);
+
+    let client = Platform::try_new("127.0.0.1:0", config).unwrap();
+
+    // a server that never responds
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server = crate::net::ipv4_socketaddr([127, 0, 0, 1], server.local_addr().unwrap().port());
+
+    assert!(matches!(client.get(server, "hello"), Err(Error::RetriesExhausted)));
+  }
+
+  #[test]
+  fn send_req_should_not_wait_for_responses_suppressed_by_no_response() {
+    let client = Platform::try_new("127.0.0.1:0", Config::default()).unwrap();
+
+    // a server that ACKs CON requests and never responds
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server_addr =
+      crate::net::ipv4_socketaddr([127, 0, 0, 1], server.local_addr().unwrap().port());
+    std::thread::spawn(move || {
+      let mut buf = [0u8; 1152];
+      while let Ok((n, peer)) = server.recv_from(&mut buf) {
+        let req = Message::<Std<dtls::N>>::try_from_bytes(&buf[..n]).unwrap();
+        if req.ty == Type::Con {
+          let ack =
+            Message::<Std<dtls::N>>::new(Type::Ack, Code::EMPTY, req.id, Token(Default::default()));
+          server.send_to(&ack.try_into_bytes::<Vec<u8>>().unwrap(), peer)
+                .unwrap();
+        }
+      }
+    });
+
+    let req = |ty: Type| {
+      let mut req = Req::<Std<dtls::N>>::post("telemetry");
+      req.msg_mut().ty = ty;
+      req.msg_mut().set_no_response(NoResponse::all()).ok();
+      Addrd(req, server_addr)
+    };
+
+    let resp = client.send_req(req(Type::Non)).unwrap();
+    assert_eq!(resp.data().code(), Code::EMPTY);
+
+    let ack = client.send_req(req(Type::Con)).unwrap();
+    assert_eq!(ack.data().msg_type(), Type::Ack);
+    assert_eq!(ack.data().code(), Code::EMPTY);
+  }
+}
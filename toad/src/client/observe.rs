@@ -0,0 +1,326 @@
+use embedded_time::duration::Milliseconds;
+use embedded_time::{Clock as _, Instant};
+use toad_msg::opt::known::observe::Action::Deregister;
+use toad_msg::{Id, MessageOptions};
+
+use crate::net::Addrd;
+use crate::platform::{self, Platform, PlatformError, PlatformTypes};
+use crate::req::Req;
+use crate::resp::Resp;
+use crate::step::{observe, Step};
+use crate::time::Clock;
+use crate::todo::String;
+
+/// [Max-Age](toad_msg::opt::known::no_repeat::MAX_AGE) assumed
+/// for notifications that don't specify one
+pub const DEFAULT_MAX_AGE_SECONDS: u32 = 60;
+
+/// Is a notification with sequence number `v2` received at `t2`
+/// newer than a notification with sequence number `v1` received at `t1`?
+///
+/// See [RFC7641 Section 3.4](https://www.rfc-editor.org/rfc/rfc7641#section-3.4)
+///
+/// ```
+/// use toad::client::observe::is_fresher;
+/// use toad::std::Clock;
+///
+/// let t = embedded_time::Instant::<Clock>::new(0);
+///
+/// assert!(is_fresher(1, t, 2, t));
+/// assert!(!is_fresher(2, t, 1, t));
+///
+/// // sequence numbers wrap around after 2^24
+/// assert!(is_fresher(0xFF_FFFF, t, 0, t));
+/// ```
+pub fn is_fresher<C>(v1: u32, t1: Instant<C>, v2: u32, t2: Instant<C>) -> bool
+  where C: Clock
+{
+  const HALF: u32 = 1 << 23;
+
+  let long_after = t2.checked_duration_since(&t1)
+                     .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+                     .map(|Milliseconds(ms)| ms > 128_000)
+                     .unwrap_or(false);
+
+  (v1 < v2 && v2 - v1 < HALF) || (v1 > v2 && v1 - v2 > HALF) || long_after
+}
+
+/// A subscription to a resource on a remote server
+///
+/// Created by [`Platform::observe`]; see its documentation for more.
+///
+/// When cancelled with [`Sub::cancel`] or dropped, this will send
+/// the server a GET with [Observe](toad_msg::opt::known::no_repeat::OBSERVE)
+/// value of [deregister](toad_msg::opt::known::observe::Action::Deregister).
+pub struct Sub<'a, P, Steps>
+  where P: Platform<Steps>,
+        Steps: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  platform: &'a P,
+  req: Addrd<platform::Message<P::Types>>,
+  last: Option<(u32, Instant<<P::Types as PlatformTypes>::Clock>)>,
+  max_age_seconds: u32,
+  expires_at: Instant<<P::Types as PlatformTypes>::Clock>,
+  cancelled: bool,
+}
+
+impl<'a, P, Steps> core::fmt::Debug for Sub<'a, P, Steps>
+  where P: Platform<Steps>,
+        Steps: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Sub")
+     .field("req", &self.req)
+     .field("last_seq", &self.last.map(|(seq, _)| seq))
+     .field("max_age_seconds", &self.max_age_seconds)
+     .field("expires_at", &self.expires_at)
+     .field("cancelled", &self.cancelled)
+     .finish()
+  }
+}
+
+impl<'a, P, Steps> Sub<'a, P, Steps>
+  where P: Platform<Steps>,
+        Steps: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  pub(crate) fn new(platform: &'a P,
+                    req: Addrd<platform::Message<P::Types>>,
+                    now: Instant<<P::Types as PlatformTypes>::Clock>)
+                    -> Self {
+    Self { platform,
+           req,
+           last: None,
+           max_age_seconds: DEFAULT_MAX_AGE_SECONDS,
+           expires_at: now + Milliseconds(DEFAULT_MAX_AGE_SECONDS as u64 * 1000),
+           cancelled: false }
+  }
+
+  /// The registration request sent to the server
+  pub fn req(&self) -> &Addrd<platform::Message<P::Types>> {
+    &self.req
+  }
+
+  /// Sequence number of the most recent notification
+  pub fn seq(&self) -> Option<u32> {
+    self.last.map(|(seq, _)| seq)
+  }
+
+  fn now(&self) -> Result<Instant<<P::Types as PlatformTypes>::Clock>, P::Error> {
+    self.platform.clock().try_now().map_err(P::Error::clock)
+  }
+
+  fn send_copy(&self,
+               f: impl FnOnce(&mut platform::Message<P::Types>))
+               -> nb::Result<(), P::Error> {
+    let mut msg = self.req.clone();
+    msg.as_mut().id = Id(0);
+    f(msg.as_mut());
+    self.platform.send_msg(msg).map(|_| ())
+  }
+
+  /// Poll for the next notification
+  ///
+  /// Notifications older than the most recent one we've seen
+  /// (according to [`is_fresher`]) are discarded.
+  ///
+  /// If we haven't received a notification within the
+  /// [Max-Age](toad_msg::opt::known::no_repeat::MAX_AGE) of the last one,
+  /// the registration request is sent again.
+  pub fn poll(&mut self) -> nb::Result<Addrd<Resp<P::Types>>, P::Error> {
+    let now = self.now().map_err(nb::Error::Other)?;
+
+    if now >= self.expires_at {
+      self.send_copy(|_| ())?;
+      self.expires_at = now + Milliseconds(self.max_age_seconds as u64 * 1000);
+      self.platform
+          .log(log::Level::Debug,
+               String::fmt(format_args!("No notification for {:?} within {}s, re-registered",
+                                        self.req.data().token,
+                                        self.max_age_seconds)))
+          .map_err(nb::Error::Other)?;
+    }
+
+    let resp = self.platform
+                   .poll_resp(self.req.data().token, self.req.addr())?;
+    let msg = resp.data().msg();
+
+    if let Some(seq) = observe::seq::<P::Types>(msg) {
+      match self.last {
+        | Some((last, at)) if !is_fresher(last, at, seq, now) => {
+          self.platform
+              .log(log::Level::Debug,
+                   String::fmt(format_args!("Discarding stale notification {} (last was {}) for {:?}",
+                                            seq,
+                                            last,
+                                            self.req.data().token)))
+              .map_err(nb::Error::Other)?;
+          return Err(nb::Error::WouldBlock);
+        },
+        | _ => self.last = Some((seq, now)),
+      }
+    }
+
+    self.max_age_seconds = msg.max_age_seconds().unwrap_or(DEFAULT_MAX_AGE_SECONDS);
+    self.expires_at = now + Milliseconds(self.max_age_seconds as u64 * 1000);
+
+    Ok(resp)
+  }
+
+  /// Tell the server we're no longer interested in notifications
+  pub fn cancel(mut self) -> Result<(), P::Error> {
+    self.cancelled = true;
+    nb::block!(self.send_copy(|msg| {
+                     msg.set_observe(Deregister).ok();
+                   }))
+  }
+}
+
+impl<'a, P, Steps> Drop for Sub<'a, P, Steps>
+  where P: Platform<Steps>,
+        Steps: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  fn drop(&mut self) {
+    if !self.cancelled {
+      nb::block!(self.send_copy(|msg| {
+                       msg.set_observe(Deregister).ok();
+                     })).ok();
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use ::std::sync::{Arc, Mutex};
+  use ::toad_msg::opt::known::observe::Action::Register;
+
+  use super::*;
+  use crate::test::{self, ClockMock};
+
+  type Mock = test::MockStep<(), Addrd<test::Req>, Addrd<test::Resp>, ()>;
+
+  type TestPlatform = test::MockPlatform<Mock>;
+
+  /// A platform whose steps yield whatever notifications
+  /// are pushed to `notifs`
+  fn platform(notifs: Arc<Mutex<Vec<Addrd<test::Resp>>>>) -> TestPlatform {
+    let steps = Mock::default();
+    steps.set_poll_resp(move |_, _, _, _, _| {
+           let mut notifs = notifs.lock().unwrap();
+           if notifs.is_empty() {
+             Some(Err(nb::Error::WouldBlock))
+           } else {
+             Some(Ok(notifs.remove(0)))
+           }
+         });
+
+    TestPlatform::new(steps)
+  }
+
+  fn notification(seq: u32, max_age_seconds: Option<u32>) -> Addrd<test::Resp> {
+    test::msg!(CON {2 . 5} x.x.x.x:80).map(|mut msg| {
+                                        observe::set_seq::<test::Platform>(&mut msg, seq);
+                                        if let Some(s) = max_age_seconds {
+                                          msg.set_max_age(s).ok();
+                                        }
+                                        Resp::from(msg)
+                                      })
+  }
+
+  fn subscribe(platform: &TestPlatform) -> Sub<'_, TestPlatform, Mock> {
+    nb::block!(platform.observe(Addrd(Req::get("temperature"), test::x.x.x.x(80)))).unwrap()
+  }
+
+  #[test]
+  fn poll_should_discard_stale_notifications() {
+    let notifs = Arc::new(Mutex::new(vec![notification(2, None),
+                                          notification(1, None),
+                                          notification(3, None)]));
+    let platform = platform(notifs.clone());
+    let mut sub = subscribe(&platform);
+
+    assert_eq!(sub.poll()
+                  .ok()
+                  .and_then(|n| observe::seq::<test::Platform>(n.data().msg())),
+               Some(2));
+    assert!(matches!(sub.poll(), Err(nb::Error::WouldBlock)));
+    assert_eq!(sub.seq(), Some(2));
+    assert_eq!(sub.poll()
+                  .ok()
+                  .and_then(|n| observe::seq::<test::Platform>(n.data().msg())),
+               Some(3));
+    assert_eq!(sub.seq(), Some(3));
+    assert!(notifs.lock().unwrap().is_empty());
+  }
+
+  #[test]
+  fn poll_should_reregister_when_max_age_elapses() {
+    let notifs = Arc::new(Mutex::new(vec![notification(1, Some(10))]));
+    let platform = platform(notifs);
+    let mut sub = subscribe(&platform);
+
+    assert_eq!(platform.sent().len(), 1);
+    assert!(sub.poll().is_ok());
+
+    platform.clock.set(9_999_999);
+    assert!(matches!(sub.poll(), Err(nb::Error::WouldBlock)));
+    assert_eq!(platform.sent().len(), 1);
+
+    platform.clock.set(10_000_000);
+    assert!(matches!(sub.poll(), Err(nb::Error::WouldBlock)));
+
+    let sent = platform.sent();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1].data().token, sub.req().data().token);
+    assert_eq!(sent[1].data().observe(), Some(Register));
+
+    // the next re-registration is a Max-Age later
+    platform.clock.set(19_999_999);
+    assert!(matches!(sub.poll(), Err(nb::Error::WouldBlock)));
+    assert_eq!(platform.sent().len(), 2);
+  }
+
+  #[test]
+  fn cancel_should_deregister_once() {
+    let platform = platform(Default::default());
+    let sub = subscribe(&platform);
+    let token = sub.req().data().token;
+
+    sub.cancel().unwrap();
+
+    let sent = platform.sent();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1].data().token, token);
+    assert_eq!(sent[1].data().observe(), Some(Deregister));
+  }
+
+  #[test]
+  fn drop_should_deregister() {
+    let platform = platform(Default::default());
+    let sub = subscribe(&platform);
+    let token = sub.req().data().token;
+
+    drop(sub);
+
+    let sent = platform.sent();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1].data().token, token);
+    assert_eq!(sent[1].data().observe(), Some(Deregister));
+  }
+
+  #[test]
+  fn is_fresher_should_follow_rfc7641() {
+    let t = |s: u64| ClockMock::instant(s * 1_000_000);
+
+    assert!(is_fresher(1, t(0), 2, t(0)));
+    assert!(!is_fresher(2, t(0), 1, t(0)));
+    assert!(!is_fresher(1, t(0), 1, t(0)));
+
+    // wrapped around
+    assert!(is_fresher(observe::MAX_SEQ, t(0), 3, t(0)));
+    assert!(!is_fresher(3, t(0), observe::MAX_SEQ, t(0)));
+
+    // older sequence number, but received long after
+    assert!(!is_fresher(10, t(0), 1, t(128)));
+    assert!(is_fresher(10, t(0), 1, t(129)));
+  }
+}
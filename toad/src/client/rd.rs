@@ -0,0 +1,480 @@
+use core::marker::PhantomData;
+
+use embedded_time::duration::Milliseconds;
+use embedded_time::{Clock as _, Instant};
+use no_std_net::SocketAddr;
+use toad_msg::opt::known::repeat;
+use toad_msg::{link_format, Code, MessageOptions, Payload};
+use toad_writable::Writable;
+
+use super::BlockingClient;
+use crate::net::Addrd;
+use crate::platform::{Platform, PlatformError, PlatformTypes};
+use crate::req::Req;
+use crate::resp::{code, Resp};
+use crate::server::discovery::{self, Resource};
+use crate::step::Step;
+use crate::todo::String;
+
+/// Lifetime assumed by the Resource Directory for registrations
+/// that don't specify one (25 hours)
+///
+/// See [RFC9176 Section 5.3](https://www.rfc-editor.org/rfc/rfc9176#section-5.3)
+pub const DEFAULT_LIFETIME_SECONDS: u32 = 90_000;
+
+/// Resource type advertised by a Resource Directory's registration interface
+pub const RT_REGISTRATION: &str = "core.rd";
+
+/// The longest endpoint (`ep`) & sector (`d`) names allowed, in bytes
+///
+/// See [RFC9176 Section 5](https://www.rfc-editor.org/rfc/rfc9176#section-5)
+pub const MAX_NAME_LEN: usize = 63;
+
+/// The longest registration interface path a [`Directory`] can store, in bytes
+pub const MAX_PATH_LEN: usize = 64;
+
+/// Resource Directory client errors
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+  /// The server did not advertise a registration interface
+  /// (a resource with `rt="core.rd"`) at `/.well-known/core`
+  NotFound,
+  /// The Resource Directory responded with an unexpected code
+  Rejected(Code),
+  /// The Resource Directory accepted a registration without
+  /// telling us its location (Location-Path)
+  NoLocation,
+  /// The path of the registration interface is longer than [`MAX_PATH_LEN`]
+  PathTooLong,
+  /// The endpoint or sector name is longer than [`MAX_NAME_LEN`]
+  NameTooLong,
+  /// Sending a request failed
+  Client(super::Error<E>),
+}
+
+impl<E> From<super::Error<E>> for Error<E> {
+  fn from(e: super::Error<E>) -> Self {
+    Self::Client(e)
+  }
+}
+
+/// How long after registering (or refreshing) with lifetime `lt`
+/// should the registration be refreshed?
+///
+/// We refresh once 90% of the lifetime has passed, so that the
+/// refresh has time to reach the Resource Directory before the
+/// registration expires.
+///
+/// ```
+/// use toad::client::rd::refresh_after_seconds;
+///
+/// assert_eq!(refresh_after_seconds(90_000), 81_000);
+/// assert_eq!(refresh_after_seconds(60), 54);
+/// ```
+pub const fn refresh_after_seconds(lt: u32) -> u32 {
+  lt - lt / 10
+}
+
+/// Find the path of the registration interface in the link-format
+/// document served by a Resource Directory at `/.well-known/core`
+///
+/// The returned path does not include a leading `/`.
+///
+/// ```
+/// use toad::client::rd::find_registration_path;
+///
+/// let links = r#"</rd>;rt="core.rd";ct=40,</rd-lookup/ep>;rt="core.rd-lookup-ep""#;
+/// assert_eq!(find_registration_path(links), Some("rd"));
+///
+/// let links = r#"</rd-lookup/ep>;rt="core.rd-lookup-ep""#;
+/// assert_eq!(find_registration_path(links), None);
+/// ```
+pub fn find_registration_path(links: &str) -> Option<&str> {
+  // targets may be absolute URIs, e.g. `coap://[ff02::fd]/rd`
+  fn path(target: &str) -> &str {
+    let path = match target.split_once("://") {
+      | Some((_, rest)) => rest.split_once('/').map(|(_, p)| p).unwrap_or(""),
+      | None => target,
+    };
+
+    path.trim_start_matches('/')
+  }
+
+  link_format::parse(links).filter_map(Result::ok)
+                           .find(|l| l.matches("rt=core.rd"))
+                           .map(|l| path(l.target()))
+}
+
+/// Registration parameters
+///
+/// ```
+/// use toad::client::rd::Params;
+///
+/// let params = Params::new("node-1").lifetime_seconds(3600)
+///                                   .sector("warehouse");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params<'a> {
+  ep: &'a str,
+  lt: u32,
+  d: Option<&'a str>,
+}
+
+impl<'a> Params<'a> {
+  /// Register as endpoint `ep`
+  pub const fn new(ep: &'a str) -> Self {
+    Self { ep,
+           lt: DEFAULT_LIFETIME_SECONDS,
+           d: None }
+  }
+
+  /// Set the lifetime of the registration (`lt`); the Resource Directory
+  /// will forget about us if we don't refresh the registration within
+  /// this many seconds.
+  ///
+  /// Defaults to [`DEFAULT_LIFETIME_SECONDS`].
+  pub const fn lifetime_seconds(mut self, lt: u32) -> Self {
+    self.lt = lt;
+    self
+  }
+
+  /// Set the sector (`d`) to register in
+  pub const fn sector(mut self, d: &'a str) -> Self {
+    self.d = Some(d);
+    self
+  }
+
+  /// The `ep`, `lt` & `d` query parameters of the registration request,
+  /// or `None` if the endpoint or sector name is longer than [`MAX_NAME_LEN`]
+  fn queries(&self) -> Option<[Option<String<{ MAX_NAME_LEN + 3 }>>; 3]> {
+    let too_long = |name: &str| name.len() > MAX_NAME_LEN;
+    if too_long(self.ep) || self.d.map(too_long).unwrap_or(false) {
+      return None;
+    }
+
+    Some([Some(String::fmt(format_args!("ep={}", self.ep))),
+          Some(String::fmt(format_args!("lt={}", self.lt))),
+          self.d.map(|d| String::fmt(format_args!("d={}", d)))])
+  }
+}
+
+/// A Resource Directory ([RFC9176](https://www.rfc-editor.org/rfc/rfc9176))
+///
+/// Either configured with a known address & registration path ([`Directory::new`]),
+/// or discovered by asking a server for its registration interface ([`Directory::discover`]).
+///
+/// ```no_run
+/// use toad::client::rd::{Directory, Params};
+/// use toad::net::ipv4_socketaddr;
+/// use toad::server::discovery::Resource;
+/// use toad::std::dtls;
+/// use toad::step::runtime::std::Runtime;
+///
+/// let node = toad::std::Platform::<dtls::N, Runtime<dtls::N>>::try_new("0.0.0.0:5683",
+///                                                                      Default::default()).unwrap();
+///
+/// let rd = Directory::discover(&node, ipv4_socketaddr([192, 168, 0, 2], 5683)).unwrap();
+/// let mut reg = rd.register(&node,
+///                           Params::new("node-1").lifetime_seconds(3600),
+///                           &[Resource::new("sensors/temp").resource_type("temperature-c")])
+///                 .unwrap();
+///
+/// loop {
+///   // refreshes the registration before it expires
+///   match reg.poll() {
+///     | Ok(()) | Err(nb::Error::WouldBlock) => (),
+///     | Err(nb::Error::Other(e)) => panic!("{:?}", e),
+///   }
+///   # break;
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Directory {
+  addr: SocketAddr,
+  path: String<MAX_PATH_LEN>,
+}
+
+impl Directory {
+  /// A Resource Directory at `addr` with registration interface at `path`
+  ///
+  /// Yields `None` if `path` is longer than [`MAX_PATH_LEN`].
+  ///
+  /// ```
+  /// use toad::client::rd::{Directory, MAX_PATH_LEN};
+  /// use toad::net::ipv4_socketaddr;
+  ///
+  /// let addr = ipv4_socketaddr([192, 168, 0, 2], 5683);
+  /// assert_eq!(Directory::new(addr, "/rd").unwrap().path(), "rd");
+  /// assert_eq!(Directory::new(addr, &"a".repeat(MAX_PATH_LEN + 1)), None);
+  /// ```
+  pub fn new(addr: SocketAddr, path: &str) -> Option<Self> {
+    let path = path.trim_start_matches('/');
+    if path.len() > MAX_PATH_LEN {
+      return None;
+    }
+
+    Some(Self { addr,
+                path: String::from(path) })
+  }
+
+  /// Ask the server at `addr` for the path of its registration interface
+  /// by sending `GET /.well-known/core?rt=core.rd*`
+  pub fn discover<P, S>(platform: &P, addr: SocketAddr) -> Result<Self, Error<P::Error>>
+    where P: Platform<S>,
+          S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+  {
+    let mut req = Req::<P::Types>::get(".well-known/core");
+    req.msg_mut().add_query("rt=core.rd*").ok();
+
+    let resp = BlockingClient::send_req(platform, Addrd(req, addr))?;
+    match resp.data().code() {
+      | code::CONTENT => (),
+      | other => return Err(Error::Rejected(other)),
+    }
+
+    core::str::from_utf8(&resp.data().msg().payload.0).ok()
+                                                      .and_then(find_registration_path)
+                                                      .ok_or(Error::NotFound)
+                                                      .and_then(|path| {
+                                                        Self::new(addr, path).ok_or(Error::PathTooLong)
+                                                      })
+  }
+
+  /// The address of the Resource Directory
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// The path of the Resource Directory's registration interface
+  pub fn path(&self) -> &str {
+    self.path.as_str()
+  }
+
+  /// Register `resources` with the Resource Directory
+  ///
+  /// Yields [`Error::NameTooLong`] without sending anything if the endpoint
+  /// or sector name in `params` is longer than [`MAX_NAME_LEN`].
+  ///
+  /// The returned [`Registration`] must be [`poll`](Registration::poll)ed
+  /// to keep the registration alive.
+  pub fn register<'a, P, S>(&self,
+                            platform: &'a P,
+                            params: Params<'_>,
+                            resources: &[Resource<'_>])
+                            -> Result<Registration<'a, P, S>, Error<P::Error>>
+    where P: Platform<S>,
+          S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+  {
+    let mut req = Req::<P::Types>::post(self.path());
+    params.queries()
+          .ok_or(Error::NameTooLong)?
+          .into_iter()
+          .flatten()
+          .for_each(|q| {
+            req.msg_mut().add_query(q).ok();
+          });
+    set_links(&mut req, resources);
+
+    let now = now(platform)?;
+    let resp = BlockingClient::send_req(platform, Addrd(req, self.addr))?;
+    let resp = resp.data().msg();
+    match resp.code {
+      | code::CREATED => (),
+      | other => return Err(Error::Rejected(other)),
+    }
+
+    let mut location = String::<128>::default();
+    resp.get(repeat::LOCATION_PATH)
+        .into_iter()
+        .flat_map(|segs| segs.iter())
+        .filter_map(|seg| core::str::from_utf8(&seg.0).ok())
+        .filter(|seg| !seg.is_empty())
+        .enumerate()
+        .try_for_each(|(ix, seg)| {
+          use core::fmt::Write;
+          write!(location, "{}{}", if ix == 0 { "" } else { "/" }, seg)
+        })
+        .ok();
+
+    if location.as_str().is_empty() {
+      return Err(Error::NoLocation);
+    }
+
+    Ok(Registration { platform,
+                      addr: self.addr,
+                      location,
+                      lt: params.lt,
+                      refreshed_at: now,
+                      __steps: PhantomData })
+  }
+}
+
+/// A registration with a Resource Directory
+///
+/// Created by [`Directory::register`]; see its documentation for more.
+pub struct Registration<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  platform: &'a P,
+  addr: SocketAddr,
+  location: String<128>,
+  lt: u32,
+  refreshed_at: Instant<<P::Types as PlatformTypes>::Clock>,
+  __steps: PhantomData<fn() -> S>,
+}
+
+impl<'a, P, S> core::fmt::Debug for Registration<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Registration")
+     .field("addr", &self.addr)
+     .field("location", &self.location)
+     .field("lt", &self.lt)
+     .field("refreshed_at", &self.refreshed_at)
+     .finish()
+  }
+}
+
+impl<'a, P, S> Registration<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  /// The location of the registration resource, assigned by the Resource Directory
+  /// (e.g. `reg/4521`)
+  pub fn location(&self) -> &str {
+    self.location.as_str()
+  }
+
+  /// The lifetime of the registration, in seconds
+  pub fn lifetime_seconds(&self) -> u32 {
+    self.lt
+  }
+
+  /// Refresh the registration if it is about to expire
+  /// (see [`refresh_after_seconds`])
+  ///
+  /// Yields [`nb::Error::WouldBlock`] when the registration
+  /// does not need to be refreshed yet.
+  pub fn poll(&mut self) -> nb::Result<(), Error<P::Error>> {
+    let now = now(self.platform).map_err(nb::Error::Other)?;
+    let refresh_at = self.refreshed_at + Milliseconds(refresh_after_seconds(self.lt) as u64 * 1000);
+
+    if now >= refresh_at {
+      self.refresh().map_err(nb::Error::Other)
+    } else {
+      Err(nb::Error::WouldBlock)
+    }
+  }
+
+  /// Refresh the registration now, resetting its lifetime
+  pub fn refresh(&mut self) -> Result<(), Error<P::Error>> {
+    self.update(None, None)
+  }
+
+  /// Update the registration, optionally changing its lifetime
+  /// and replacing the registered resources.
+  ///
+  /// This also refreshes the registration.
+  pub fn update(&mut self,
+                lt: Option<u32>,
+                resources: Option<&[Resource<'_>]>)
+                -> Result<(), Error<P::Error>> {
+    let mut req = Req::<P::Types>::post(self.location());
+    if let Some(lt) = lt {
+      req.msg_mut()
+         .add_query(String::<16>::fmt(format_args!("lt={}", lt)))
+         .ok();
+    }
+    if let Some(resources) = resources {
+      set_links(&mut req, resources);
+    }
+
+    let now = now(self.platform)?;
+    let resp = BlockingClient::send_req(self.platform, Addrd(req, self.addr))?;
+    match resp.data().code() {
+      | code::CHANGED => (),
+      | other => return Err(Error::Rejected(other)),
+    }
+
+    self.lt = lt.unwrap_or(self.lt);
+    self.refreshed_at = now;
+    self.platform
+        .log(log::Level::Debug,
+             String::fmt(format_args!("Refreshed registration {} for {}s",
+                                      self.location(),
+                                      self.lt)))
+        .map_err(super::Error::Other)?;
+    Ok(())
+  }
+
+  /// Remove the registration from the Resource Directory
+  pub fn deregister(self) -> Result<(), Error<P::Error>> {
+    let req = Req::<P::Types>::delete(self.location());
+    let resp = BlockingClient::send_req(self.platform, Addrd(req, self.addr))?;
+    match resp.data().code() {
+      | code::DELETED => Ok(()),
+      | other => Err(Error::Rejected(other)),
+    }
+  }
+}
+
+fn now<P, S>(platform: &P) -> Result<Instant<<P::Types as PlatformTypes>::Clock>, Error<P::Error>>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  platform.clock()
+          .try_now()
+          .map_err(P::Error::clock)
+          .map_err(|e| Error::Client(super::Error::Other(e)))
+}
+
+fn set_links<P>(req: &mut Req<P>, resources: &[Resource<'_>])
+  where P: PlatformTypes
+{
+  let mut payload = Writable::from(P::MessagePayload::default());
+  discovery::write_links(&mut payload, resources).ok();
+
+  req.msg_mut()
+     .set_content_format(toad_msg::ContentFormat::LinkFormat)
+     .ok();
+  req.msg_mut().payload = Payload(payload.unwrap());
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn find_registration_path_should_find_core_rd() {
+    assert_eq!(find_registration_path(r#"</rd>;rt="core.rd""#), Some("rd"));
+    assert_eq!(find_registration_path(r#"</rd-lookup/res>;rt="core.rd-lookup-res",</resdir>;rt="core.rd core.rd-group";ct=40"#),
+               Some("resdir"));
+    assert_eq!(find_registration_path(r#"<coap://[ff02::fd]/a/rd>;rt=core.rd"#),
+               Some("a/rd"));
+    assert_eq!(find_registration_path(r#"</x>;title="a, b;rt=core.rd""#),
+               None);
+    assert_eq!(find_registration_path(""), None);
+  }
+
+  #[test]
+  fn directory_path_should_not_have_leading_slash() {
+    let dir = Directory::new(crate::test::dummy_addr(), "/rd").unwrap();
+    assert_eq!(dir.path(), "rd");
+  }
+
+  #[test]
+  fn params_should_allow_names_up_to_max_len() {
+    let name = "a".repeat(MAX_NAME_LEN);
+    let [ep, lt, d] = Params::new(&name).sector(&name).queries().unwrap();
+    assert_eq!(ep.unwrap().as_str(), format!("ep={}", name));
+    assert_eq!(lt.unwrap().as_str(), "lt=90000");
+    assert_eq!(d.unwrap().as_str(), format!("d={}", name));
+
+    let long = "a".repeat(MAX_NAME_LEN + 1);
+    assert_eq!(Params::new(&long).queries(), None);
+    assert_eq!(Params::new("a").sector(&long).queries(), None);
+  }
+}
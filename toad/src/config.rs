@@ -117,6 +117,25 @@ pub struct Msg {
   //    timestamp
   pub token_seed: u16,
 
+  /// Length of the [`Token`](toad_msg::Token)s generated for
+  /// outbound requests, in bytes.
+  ///
+  /// Tokens longer than 8 bytes are
+  /// [extended tokens](https://www.rfc-editor.org/rfc/rfc8974), which
+  /// are harder to guess but not understood by all peers, and
+  /// require the `extended_token` feature.
+  /// Values greater than [`Token::MAX_LEN`](toad_msg::Token::MAX_LEN)
+  /// (8 without `extended_token`) are treated as `Token::MAX_LEN`.
+  ///
+  /// Defaults to 8.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().token_len, 8);
+  /// ```
+  pub token_len: u8,
+
   /// Set the transmission rate that we should do our best
   /// not to exceed when waiting for:
   /// - responses to our NON requests
@@ -153,6 +172,159 @@ pub struct Msg {
   ///            Milliseconds(5000u64));
   /// ```
   pub multicast_response_leisure: Millis,
+
+  /// Whether we should refrain from sending error responses
+  /// (4.xx, 5.xx) to multicast requests.
+  ///
+  /// A multicast request may be sent to many servers that
+  /// have nothing useful to say about it, and the client
+  /// is rarely interested in all of them saying so.
+  ///
+  /// Defaults to `true`.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().suppress_multicast_error_responses, true);
+  /// ```
+  pub suppress_multicast_error_responses: bool,
+
+  /// How long the application has to respond to a CON
+  /// request before we acknowledge it with an empty ACK.
+  ///
+  /// Responses sent within this deadline are piggybacked
+  /// on the ACK, saving a datagram. Responses sent after
+  /// the empty ACK are sent separately as CON responses.
+  ///
+  /// If zero, CON requests are ACKed immediately and responses
+  /// are always sent separately.
+  ///
+  /// Defaults to 250 milliseconds.
+  ///
+  /// ```
+  /// use embedded_time::duration::Milliseconds;
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().piggyback_deadline, Milliseconds(250u64));
+  /// ```
+  pub piggyback_deadline: Millis,
+
+  /// Largest block size we should use when
+  /// sending payloads in blocks (e.g. responses
+  /// too big to fit in a single message)
+  ///
+  /// Block sizes are powers of two between 16 and 1024;
+  /// other sizes are rounded down to the nearest one (or up to 16).
+  /// If a client asks for smaller blocks, the smaller
+  /// size will be used.
+  ///
+  /// Defaults to 1024 bytes.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().block_size, 1024);
+  /// ```
+  pub block_size: u16,
+
+  /// Maximum number of bytes we are willing to buffer
+  /// for a single client while receiving request bodies
+  /// sent in blocks.
+  ///
+  /// Requests that would exceed this limit will be
+  /// responded to with `4.13 Request Entity Too Large`.
+  ///
+  /// Defaults to 64 kilobytes.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().max_request_body_bytes_per_peer, 65_536);
+  /// ```
+  pub max_request_body_bytes_per_peer: u32,
+
+  /// [Max-Age](toad_msg::opt::known::no_repeat::MAX_AGE) to
+  /// add to Observe notifications that don't specify one.
+  ///
+  /// This tells subscribers how long a notification stays fresh;
+  /// a subscriber that hears nothing for longer than this
+  /// should assume the subscription was lost and re-register.
+  ///
+  /// Defaults to 60 seconds.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().notification_max_age_seconds, 60);
+  /// ```
+  pub notification_max_age_seconds: u32,
+
+  /// How long a [`BlockingClient`](crate::client::BlockingClient) should
+  /// wait for a response before giving up with
+  /// [`Error::Timeout`](crate::client::Error::Timeout).
+  ///
+  /// If `None`, clients will wait until the request's retry
+  /// attempts are exhausted.
+  ///
+  /// Defaults to `None`.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().request_timeout, None);
+  /// ```
+  pub request_timeout: Option<Millis>,
+
+  /// How many times larger than a request a response may be before
+  /// we require the client to prove it can receive datagrams
+  /// at its claimed address ([RFC9175 section 2.4](https://www.rfc-editor.org/rfc/rfc9175#section-2.4)).
+  ///
+  /// Responses exceeding this to unverified clients are replaced with
+  /// `4.01 Unauthorized` and an [Echo](toad_msg::opt::known::no_repeat::ECHO) value
+  /// that the client must repeat in its next request.
+  ///
+  /// If `None`, clients are never challenged.
+  ///
+  /// Defaults to 3.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().amplification_factor, Some(3));
+  /// ```
+  pub amplification_factor: Option<u8>,
+
+  /// Whether POST & PATCH requests from clients that haven't proven they can
+  /// receive datagrams at their claimed address should be answered with
+  /// `4.01 Unauthorized` and an [Echo](toad_msg::opt::known::no_repeat::ECHO) value
+  /// before they are handled ([RFC9175 section 2.4](https://www.rfc-editor.org/rfc/rfc9175#section-2.4)).
+  ///
+  /// This keeps a non-idempotent handler from running twice when a response too large
+  /// for [`amplification_factor`](Msg.amplification_factor) is challenged and the client
+  /// repeats the request, at the cost of a round trip for every new client.
+  ///
+  /// Defaults to `false`.
+  ///
+  /// ```
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().challenge_non_idempotent_requests, false);
+  /// ```
+  pub challenge_non_idempotent_requests: bool,
+
+  /// How long an [Echo](toad_msg::opt::known::no_repeat::ECHO) value
+  /// we sent remains valid, and how long a client that repeated it
+  /// stays verified.
+  ///
+  /// Defaults to 60 seconds.
+  ///
+  /// ```
+  /// use embedded_time::duration::Milliseconds;
+  /// use toad::config::Msg;
+  ///
+  /// assert_eq!(Msg::default().echo_lifetime, Milliseconds(60_000u64));
+  /// ```
+  pub echo_lifetime: Millis,
 }
 
 impl Default for Con {
@@ -176,10 +348,20 @@ impl Default for Non {
 impl Default for Msg {
   fn default() -> Self {
     Msg { token_seed: 0,
+          token_len: 8,
           probing_rate: BytesPerSecond(1000),
           con: Con::default(),
           non: Non::default(),
-          multicast_response_leisure: Milliseconds(5000) }
+          multicast_response_leisure: Milliseconds(5000),
+          suppress_multicast_error_responses: true,
+          piggyback_deadline: Milliseconds(250),
+          block_size: 1024,
+          max_request_body_bytes_per_peer: 65_536,
+          notification_max_age_seconds: 60,
+          request_timeout: None,
+          amplification_factor: Some(3),
+          challenge_non_idempotent_requests: false,
+          echo_lifetime: Milliseconds(60_000) }
   }
 }
 
@@ -199,12 +381,27 @@ pub struct Config {
   /// assert_eq!(Config::default().max_concurrent_requests, 1);
   /// ```
   pub max_concurrent_requests: u8,
+  /// Whether to act as a forward proxy, forwarding requests with
+  /// a Proxy-Uri or Proxy-Scheme option to the server they're for
+  /// instead of yielding them to the application.
+  ///
+  /// See [`step::proxy`](crate::step::proxy) for more.
+  ///
+  /// Default value is `false`
+  ///
+  /// ```
+  /// use toad::config::Config;
+  ///
+  /// assert!(!Config::default().forward_proxy);
+  /// ```
+  pub forward_proxy: bool,
 }
 
 impl Default for Config {
   fn default() -> Self {
     Config { msg: Msg::default(),
-             max_concurrent_requests: 1 }
+             max_concurrent_requests: 1,
+             forward_proxy: false }
   }
 }
 
@@ -267,4 +464,11 @@ impl Config {
     + (2 * self.max_latency_millis())
     + self.expected_processing_delay_millis()
   }
+
+  /// [`Msg.block_size`](Msg.block_size) rounded to a size the
+  /// Block options can represent
+  pub(crate) fn block_size(&self) -> u16 {
+    let size = self.msg.block_size.clamp(16, 1024);
+    1 << (u16::BITS - 1 - size.leading_zeros())
+  }
 }
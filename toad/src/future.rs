@@ -0,0 +1,375 @@
+use core::future::Future;
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use embedded_time::{Clock as _, Instant};
+use toad_msg::{Code, Id, MessageOptions, Token, Type};
+
+use crate::client;
+use crate::net::{Addrd, Socket};
+use crate::platform::{Message, Platform, PlatformError, PlatformTypes};
+use crate::req::Req;
+use crate::resp::Resp;
+use crate::step::Step;
+
+type Clock<P, S> = <<P as Platform<S>>::Types as PlatformTypes>::Clock;
+type RecvReady<P, S> = <<<P as Platform<S>>::Types as PlatformTypes>::Socket as Socket>::RecvReady;
+
+/// Drive a non-blocking platform operation to completion.
+///
+/// When `f` would block, the task is registered with the platform's
+/// socket (see [`Socket::poll_recv_ready`]) to be woken when there may be
+/// something new for the platform to process, when `Steps` need to be polled
+/// (see [`Platform::poll_timeout`]) or at `until`.
+fn poll_nb<P, S, T, E>(platform: &P,
+                       ready: &mut RecvReady<P, S>,
+                       cx: &mut Context<'_>,
+                       until: Option<Instant<Clock<P, S>>>,
+                       mut f: impl FnMut() -> nb::Result<T, E>)
+                       -> Poll<Result<T, E>>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>,
+        E: From<P::Error>
+{
+  loop {
+    match f() {
+      | Ok(t) => break Poll::Ready(Ok(t)),
+      | Err(nb::Error::Other(e)) => break Poll::Ready(Err(e)),
+      | Err(nb::Error::WouldBlock) => {
+        let timeout = match platform.poll_timeout(until) {
+          | Ok(timeout) => timeout,
+          | Err(e) => break Poll::Ready(Err(e.into())),
+        };
+
+        match platform.socket().poll_recv_ready(ready, cx, timeout) {
+          | Poll::Ready(Ok(())) => continue,
+          | Poll::Ready(Err(e)) => break Poll::Ready(Err(P::Error::socket(e).into())),
+          | Poll::Pending => break Poll::Pending,
+        }
+      },
+    }
+  }
+}
+
+/// Future yielded by [`AsyncPlatform::next_req`]
+#[derive(Debug)]
+pub struct NextReq<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  platform: &'a P,
+  ready: RecvReady<P, S>,
+  __steps: PhantomData<fn() -> S>,
+}
+
+// we never project the pin, so moving our fields around is fine
+impl<'a, P, S> Unpin for NextReq<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+}
+
+impl<'a, P, S> Future for NextReq<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  type Output = Result<Addrd<Req<P::Types>>, P::Error>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let platform = this.platform;
+    poll_nb(platform, &mut this.ready, cx, None, || platform.poll_req())
+  }
+}
+
+/// [`Stream`](futures_core::Stream) of incoming requests,
+/// yielded by [`AsyncPlatform::reqs`]
+///
+/// This stream never ends.
+#[derive(Debug)]
+pub struct Reqs<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  platform: &'a P,
+  ready: RecvReady<P, S>,
+  __steps: PhantomData<fn() -> S>,
+}
+
+// we never project the pin, so moving our fields around is fine
+impl<'a, P, S> Unpin for Reqs<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+}
+
+impl<'a, P, S> Reqs<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  /// Wait for the next incoming request
+  pub fn next_req(&mut self) -> NextReq<'a, P, S> {
+    NextReq { platform: self.platform,
+              ready: Default::default(),
+              __steps: PhantomData }
+  }
+}
+
+impl<'a, P, S> futures_core::Stream for Reqs<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  type Item = Result<Addrd<Req<P::Types>>, P::Error>;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let this = self.get_mut();
+    let platform = this.platform;
+    poll_nb(platform, &mut this.ready, cx, None, || platform.poll_req()).map(Some)
+  }
+}
+
+/// Future yielded by [`AsyncPlatform::send_msg_async`]
+pub struct SendMsg<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  platform: &'a P,
+  msg: Addrd<Message<P::Types>>,
+  ready: RecvReady<P, S>,
+  __steps: PhantomData<fn() -> S>,
+}
+
+impl<'a, P, S> core::fmt::Debug for SendMsg<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("SendMsg").field("msg", &self.msg).finish()
+  }
+}
+
+// we never project the pin, so moving our fields around is fine
+impl<'a, P, S> Unpin for SendMsg<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+}
+
+impl<'a, P, S> Future for SendMsg<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  type Output = Result<(Id, Token), P::Error>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let (platform, msg) = (this.platform, &this.msg);
+    poll_nb(platform, &mut this.ready, cx, None, || {
+      platform.send_msg(msg.clone())
+    })
+  }
+}
+
+/// Future yielded by [`AsyncPlatform::send_req`]
+pub struct SendReq<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  platform: &'a P,
+  msg: Addrd<Message<P::Types>>,
+  sent: Option<(Token, Instant<Clock<P, S>>)>,
+  ready: RecvReady<P, S>,
+  __steps: PhantomData<fn() -> S>,
+}
+
+impl<'a, P, S> core::fmt::Debug for SendReq<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("SendReq")
+     .field("msg", &self.msg)
+     .field("sent", &self.sent)
+     .finish()
+  }
+}
+
+// we never project the pin, so moving our fields around is fine
+impl<'a, P, S> Unpin for SendReq<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+}
+
+impl<'a, P, S> Future for SendReq<'a, P, S>
+  where P: Platform<S>,
+        S: Step<P::Types, PollReq = Addrd<Req<P::Types>>, PollResp = Addrd<Resp<P::Types>>>
+{
+  type Output = Result<Addrd<Resp<P::Types>>, client::Error<P::Error>>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let platform = this.platform;
+    let now = || {
+      platform.clock()
+              .try_now()
+              .map_err(P::Error::clock)
+              .map_err(client::Error::Other)
+    };
+
+    let (token, sent_at) = match this.sent {
+      | Some(sent) => sent,
+      | None => {
+        let sent_at = now()?;
+        let msg = &this.msg;
+        match poll_nb(platform, &mut this.ready, cx, None, || {
+                platform.send_msg(msg.clone())
+              }) {
+          | Poll::Ready(Ok((id, token)))
+            if msg.data().ty == Type::Non
+               && msg.data()
+                     .no_response()
+                     .map(|n| n.suppresses_all())
+                     .unwrap_or(false) =>
+          {
+            let empty = Message::<P::Types>::new(Type::Non, Code::EMPTY, id, token);
+            return Poll::Ready(Ok(Addrd(Resp::from(empty), msg.addr())));
+          },
+          | Poll::Ready(Ok((_, token))) => {
+            this.sent = Some((token, sent_at));
+            (token, sent_at)
+          },
+          | Poll::Ready(Err(e)) => return Poll::Ready(Err(client::Error::Other(e))),
+          | Poll::Pending => return Poll::Pending,
+        }
+      },
+    };
+
+    let (addr, config) = (this.msg.addr(), platform.config());
+    let give_up_at = client::give_up_at(config, sent_at);
+    poll_nb(platform,
+            &mut this.ready,
+            cx,
+            Some(give_up_at),
+            || match platform.poll_resp(token, addr) {
+              | Err(nb::Error::WouldBlock) => match client::gave_up(config, sent_at, now()?) {
+                | Some(e) => Err(nb::Error::Other(e)),
+                | None => Err(nb::Error::WouldBlock),
+              },
+              | resp => resp.map_err(|e| e.map(client::Error::Other)),
+            })
+  }
+}
+
+/// Use a CoAP [`Platform`] from async code
+///
+/// This trait provides [`Future`]s and [`Stream`](futures_core::Stream)s
+/// that drive the platform, and are woken by the platform's socket
+/// (see [`Socket::poll_recv_ready`]) rather than spinning.
+///
+/// These are not tied to any executor; for a platform whose socket
+/// integrates with tokio see [`toad::std::tokio`](crate::std::tokio).
+///
+/// ```no_run
+/// use toad::future::AsyncPlatform;
+/// use toad::net::{ipv4_socketaddr, Addrd};
+/// use toad::req::Req;
+/// use toad::std::{dtls, PlatformTypes as Std};
+/// use toad::step::runtime::std::Runtime;
+///
+/// async fn get_hello() {
+///   let client =
+///     toad::std::Platform::<dtls::N, Runtime<dtls::N>>::try_new("127.0.0.1:4444",
+///                                                               Default::default()).unwrap();
+///
+///   let server = ipv4_socketaddr([127, 0, 0, 1], 5683);
+///   let req = Req::<Std<dtls::N>>::get("hello");
+///   let resp = client.send_req(Addrd(req, server)).await.unwrap();
+///   println!("{:?}", resp.data().payload_string());
+/// }
+/// ```
+pub trait AsyncPlatform<S>: Sized + Platform<S>
+  where S: Step<Self::Types, PollReq = Addrd<Req<Self::Types>>, PollResp = Addrd<Resp<Self::Types>>>
+{
+  /// Wait for the next incoming request
+  fn next_req(&self) -> NextReq<'_, Self, S> {
+    NextReq { platform: self,
+              ready: Default::default(),
+              __steps: PhantomData }
+  }
+
+  /// Get a [`Stream`](futures_core::Stream) of incoming requests
+  fn reqs(&self) -> Reqs<'_, Self, S> {
+    Reqs { platform: self,
+           ready: Default::default(),
+           __steps: PhantomData }
+  }
+
+  /// Send a message, resolving to the [`Id`] and [`Token`] it was sent with.
+  ///
+  /// See [`Platform::send_msg`]
+  fn send_msg_async(&self, msg: Addrd<Message<Self::Types>>) -> SendMsg<'_, Self, S> {
+    SendMsg { platform: self,
+              msg,
+              ready: Default::default(),
+              __steps: PhantomData }
+  }
+
+  /// Send a request, resolving to the response to it.
+  ///
+  /// Like [`BlockingClient::send_req`](crate::client::BlockingClient::send_req),
+  /// this respects [`Msg.request_timeout`](crate::config::Msg.request_timeout),
+  /// and doesn't wait for responses the request's
+  /// [No-Response](toad_msg::opt::known::no_repeat::NO_RESPONSE) option suppresses.
+  fn send_req(&self, req: Addrd<Req<Self::Types>>) -> SendReq<'_, Self, S> {
+    SendReq { platform: self,
+              msg: req.map(Message::<Self::Types>::from),
+              sent: None,
+              ready: Default::default(),
+              __steps: PhantomData }
+  }
+}
+
+impl<S, T> AsyncPlatform<S> for T
+  where S: Step<Self::Types, PollReq = Addrd<Req<Self::Types>>, PollResp = Addrd<Resp<Self::Types>>>,
+        T: Sized + Platform<S>
+{
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::platform::Platform as _;
+  use crate::resp::code;
+  use crate::std::{dtls, PlatformTypes as Std};
+  use crate::step::runtime::std::Runtime;
+
+  type Platform = crate::std::Platform<dtls::N, Runtime<dtls::N>>;
+
+  #[tokio::test]
+  async fn send_req_should_resolve_to_response() {
+    let server = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+    let client = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+    let server_addr = Socket::local_addr(server.socket());
+
+    let serve = async {
+      let req = server.next_req().await.unwrap();
+      assert_eq!(req.data().path(), Ok(Some("hello")));
+
+      let mut resp = Resp::non(req.data());
+      resp.set_code(code::CONTENT);
+      resp.set_payload("hi!".bytes());
+      server.send_msg_async(Addrd(resp.into(), req.addr()))
+            .await
+            .unwrap();
+    };
+
+    let req = Req::<Std<dtls::N>>::get("hello");
+    let (_, resp) = tokio::join!(serve, client.send_req(Addrd(req, server_addr)));
+
+    let resp = resp.unwrap();
+    assert_eq!(resp.data().code(), code::CONTENT);
+    assert_eq!(resp.data().payload_string(), Ok("hi!".to_string()));
+  }
+}
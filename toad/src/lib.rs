@@ -123,6 +123,9 @@ pub mod time;
 /// configuring runtime behavior
 pub mod config;
 
+/// end-to-end message protection with OSCORE ([RFC8613](https://www.rfc-editor.org/rfc/rfc8613))
+pub mod oscore;
+
 /// `std`-only toad stuff
 #[cfg(feature = "std")]
 #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
@@ -133,6 +136,12 @@ mod option;
 /// Server functionality
 pub mod server;
 
+/// Client functionality
+pub mod client;
+
+/// Async client & server functionality
+pub mod future;
+
 pub use option::{ContentFormat, ToCoapValue};
 
 /// Helper constants and functions for creating multicast addresses
@@ -165,6 +174,16 @@ macro_rules! code {
     #[allow(clippy::zero_prefixed_literal)]
     pub const $name: $newtype = $newtype(toad_msg::Code::new($c, $d));
   };
+  (rfc($rfc:literal, $section:literal) $name:ident = $c:literal.$d:literal) => {
+    #[doc = concat!("[RFC", $rfc, " Section ", $section, "](https://www.rfc-editor.org/rfc/rfc", $rfc, "#section-", $section, ")")]
+    #[allow(clippy::zero_prefixed_literal)]
+    pub const $name: toad_msg::Code = toad_msg::Code::new($c, $d);
+  };
+  (rfc($rfc:literal, $section:literal) $name:ident = $newtype:tt($c:literal.$d:literal)) => {
+    #[doc = concat!("[RFC", $rfc, " Section ", $section, "](https://www.rfc-editor.org/rfc/rfc", $rfc, "#section-", $section, ")")]
+    #[allow(clippy::zero_prefixed_literal)]
+    pub const $name: $newtype = $newtype(toad_msg::Code::new($c, $d));
+  };
 }
 
 pub(crate) use code;
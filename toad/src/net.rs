@@ -1,7 +1,11 @@
+use core::task::{Context, Poll};
+
 use naan::prelude::MonadOnce;
 use no_std_net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs};
 use toad_array::Array;
 
+use crate::time::Millis;
+
 /// Creates a [`SocketAddr::V4`] from an ipv4 address and port
 pub fn ipv4_socketaddr([a, b, c, d]: [u8; 4], port: u16) -> SocketAddr {
   SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(a, b, c, d), port))
@@ -82,6 +86,15 @@ pub trait Socket: Sized {
   /// manually with zero `0u8` filled in each position. (ex. `Vec::resize(_, 1024usize, 0u8)`)
   type Dgram: Array<Item = u8> + AsRef<[u8]> + Clone + core::fmt::Debug + PartialEq;
 
+  /// State owned by each task waiting in [`Socket::poll_recv_ready`]
+  ///
+  /// Every task waiting on the socket passes its own `RecvReady`,
+  /// so that sockets can wake all of them rather than only the task
+  /// that polled most recently.
+  ///
+  /// Sockets that do not override [`Socket::poll_recv_ready`] can use `()`.
+  type RecvReady: Default + core::fmt::Debug;
+
   /// Get the local address this socket was created from
   fn local_addr(&self) -> SocketAddr;
 
@@ -125,14 +138,19 @@ pub trait Socket: Sized {
     self.send(msg)
   }
 
-  /// Pull a buffered datagram from the socket, along with the address to the sender.
+  /// Pull a buffered datagram from the socket, along with the address to the sender
+  /// and the address the datagram was sent to.
+  ///
+  /// The latter may differ from [`Socket::local_addr`], e.g. when the datagram was sent
+  /// to a multicast group the socket joined, or when the socket is bound to a wildcard address.
+  /// Implementors that cannot tell should yield [`Socket::local_addr`].
   ///
   /// This clears the internal reciever queue, meaning that subsequent calls
   /// to `peek` or `recv` will block until a new datagram is received.
   ///
   /// It is expected that (like [`std::net::UdpSocket`]) if the message is larger
   /// than the buffer, those bytes are dropped and not considered an error condition.
-  fn recv(&self, buffer: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error>;
+  fn recv(&self, buffer: &mut [u8]) -> nb::Result<(Addrd<usize>, SocketAddr), Self::Error>;
 
   /// Pull a buffered datagram from the socket, along with the address to the sender.
   ///
@@ -158,13 +176,17 @@ pub trait Socket: Sized {
     self.peek(&mut []).map(|Addrd(_, addr)| addr)
   }
 
-  /// Poll the socket for a datagram from the `connect`ed host
-  fn poll(&self) -> Result<Option<Addrd<Self::Dgram>>, Self::Error> {
+  /// Poll the socket for a datagram from the `connect`ed host,
+  /// along with the address it was sent to (see [`Socket::recv`])
+  #[allow(clippy::type_complexity)]
+  fn poll(&self) -> Result<Option<(Addrd<Self::Dgram>, SocketAddr)>, Self::Error> {
     let mut buf = Self::empty_dgram();
     let recvd = self.recv(&mut buf);
 
     match recvd {
-      | Ok(Addrd(n, addr)) => Ok(Some(Addrd(buf.into_iter().take(n).collect(), addr))),
+      | Ok((Addrd(n, addr), dest)) => {
+        Ok(Some((Addrd(buf.into_iter().take(n).collect(), addr), dest)))
+      },
       | Err(nb::Error::WouldBlock) => Ok(None),
       | Err(nb::Error::Other(e)) => Err(e),
     }
@@ -172,4 +194,97 @@ pub trait Socket: Sized {
 
   /// Join a multicast group
   fn join_multicast(&self, addr: no_std_net::IpAddr) -> Result<(), Self::Error>;
+
+  /// Block the current thread until a datagram may be ready to be received,
+  /// or until `timeout` has elapsed (waiting indefinitely if `timeout` is `None`).
+  ///
+  /// Spurious wakeups are allowed; callers are expected to poll the socket
+  /// and call this again if nothing was received.
+  ///
+  /// This is used by blocking consumers of the platform
+  /// (e.g. [`BlockingServer`](crate::server::BlockingServer)) to avoid
+  /// polling the platform when nothing has changed.
+  ///
+  /// # Default Implementation
+  /// The default implementation returns immediately, meaning that
+  /// blocking code using a socket that does not override this will busy-poll.
+  fn wait_recv_ready(&self, timeout: Option<Millis>) -> Result<(), Self::Error> {
+    let _ = timeout;
+    Ok(())
+  }
+
+  /// Check if a datagram may be ready to be received,
+  /// and if not, arrange for the task in `cx` to be woken
+  /// when one may be, or when `timeout` has elapsed
+  /// (if `timeout` is `None`, the task should only be woken by a datagram).
+  ///
+  /// `ready` is owned by the waiting task (see [`Socket::RecvReady`]);
+  /// many tasks may be waiting on the same socket at once.
+  ///
+  /// This is used by [`AsyncPlatform`](crate::future::AsyncPlatform) to avoid
+  /// polling the platform when nothing has changed.
+  ///
+  /// # Default Implementation
+  /// The default implementation wakes the task immediately, meaning that
+  /// async code using a socket that does not override this will busy-poll.
+  fn poll_recv_ready(&self,
+                     ready: &mut Self::RecvReady,
+                     cx: &mut Context<'_>,
+                     timeout: Option<Millis>)
+                     -> Poll<Result<(), Self::Error>> {
+    let _ = (ready, timeout);
+    cx.waker().wake_by_ref();
+    Poll::Pending
+  }
+}
+
+/// A connection-oriented CoAP network socket, for CoAP over TCP or TLS
+/// ([RFC8323](https://www.rfc-editor.org/rfc/rfc8323))
+///
+/// Unlike a [`Socket`], a stream socket is connected to a single peer,
+/// and the bytes sent & received are not delimited into messages;
+/// each message is prefixed with its length instead (see `toad_msg::tcp`).
+///
+/// There are no message types or ids over reliable transports, since the
+/// transport takes care of retransmission & deduplication.
+pub trait StreamSocket: Sized {
+  /// The error yielded by socket operations
+  type Error: core::fmt::Debug;
+
+  /// Open a connection to a remote address, yielding a non-blocking socket
+  ///
+  /// Implementors should connect to the first address if `addr` yields multiple addresses.
+  fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, Self::Error>;
+
+  /// Get the local address of the connection
+  fn local_addr(&self) -> SocketAddr;
+
+  /// Get the address of the peer we're connected to
+  fn peer_addr(&self) -> SocketAddr;
+
+  /// Write some of `bytes` to the connection, yielding the number of bytes written.
+  ///
+  /// Callers should retry with the remaining bytes until all have been written.
+  fn send(&self, bytes: &[u8]) -> nb::Result<usize, Self::Error>;
+
+  /// Read bytes from the connection into `buffer`, yielding the number of bytes read.
+  ///
+  /// Reading 0 bytes into a non-empty buffer means the peer closed the connection.
+  fn recv(&self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error>;
+
+  /// Close the connection
+  fn shutdown(&self) -> Result<(), Self::Error>;
+
+  /// Block the current thread until bytes may be ready to be received,
+  /// or until `timeout` has elapsed (waiting indefinitely if `timeout` is `None`).
+  ///
+  /// See [`Socket::wait_recv_ready`].
+  ///
+  /// # Default Implementation
+  /// The default implementation returns immediately, meaning that
+  /// blocking code using a socket that does not override this will busy-poll.
+  fn wait_recv_ready(&self, timeout: Option<Millis>) -> Result<(), Self::Error> {
+    let _ = timeout;
+    Ok(())
+  }
 }
@@ -0,0 +1,722 @@
+use aes::Aes128;
+use ccm::aead::generic_array::GenericArray;
+use ccm::aead::{AeadInPlace, KeyInit};
+use ccm::consts::{U13, U8};
+use ccm::Ccm;
+use hkdf::Hkdf;
+use no_std_net::SocketAddr;
+use sha2::Sha256;
+use tinyvec::ArrayVec;
+
+/// COSE algorithm identifier of AES-CCM-16-64-128, the AEAD algorithm
+/// used by all security contexts
+pub const AES_CCM_16_64_128: u8 = 10;
+
+/// Length of the sender & recipient keys
+pub const KEY_LEN: usize = 16;
+
+/// Length of the AEAD nonce & common IV
+pub const NONCE_LEN: usize = 13;
+
+/// Length of the authentication tag appended to ciphertexts
+pub const TAG_LEN: usize = 8;
+
+/// Largest Sender Sequence Number that fits in a Partial IV
+pub const MAX_SSN: u64 = (1 << 40) - 1;
+
+/// A Sender or Recipient ID (at most `NONCE_LEN - 6` bytes)
+pub type Kid = ArrayVec<[u8; 7]>;
+
+/// An ID Context
+pub type IdContext = ArrayVec<[u8; 32]>;
+
+/// A Partial IV; a Sender Sequence Number encoded in at most 5 bytes
+pub type Piv = ArrayVec<[u8; 5]>;
+
+type Aead = Ccm<Aes128, U8, U13>;
+
+/// The parameters shared with a peer that a security context is derived from
+/// ([RFC8613 section 3.2](https://www.rfc-editor.org/rfc/rfc8613#section-3.2))
+///
+/// ```
+/// use toad::oscore::Params;
+///
+/// let client = Params { master_secret: (1..=16).collect(),
+///                       master_salt:
+///                         [0x9e, 0x7c, 0xa9, 0x22, 0x23, 0x78, 0x63, 0x40].into_iter()
+///                                                                         .collect(),
+///                       recipient_id: [0x01].into_iter().collect(),
+///                       ..Params::default() };
+/// ```
+///
+/// Not `Copy`, so that the master secret isn't implicitly duplicated.
+#[allow(missing_copy_implementations)]
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Params {
+  /// Secret shared with the peer
+  pub master_secret: ArrayVec<[u8; 32]>,
+  /// Salt shared with the peer (may be empty)
+  pub master_salt: ArrayVec<[u8; 32]>,
+  /// ID Context shared with the peer, if any
+  pub id_context: Option<IdContext>,
+  /// Our ID; the peer's Recipient ID
+  pub sender_id: Kid,
+  /// The peer's ID; the peer's Sender ID
+  pub recipient_id: Kid,
+}
+
+impl core::fmt::Debug for Params {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    // the master secret is deliberately left out
+    f.debug_struct("Params")
+     .field("master_salt", &self.master_salt)
+     .field("id_context", &self.id_context)
+     .field("sender_id", &self.sender_id)
+     .field("recipient_id", &self.recipient_id)
+     .finish_non_exhaustive()
+  }
+}
+
+impl Params {
+  /// Get the [`ContextId`] of the security context derived from these parameters
+  pub fn id(&self) -> ContextId {
+    ContextId { sender_id: self.sender_id,
+                recipient_id: self.recipient_id,
+                id_context: self.id_context }
+  }
+}
+
+/// The non-secret parameters that identify a security context
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextId {
+  /// See [`Params.sender_id`](Params.sender_id)
+  pub sender_id: Kid,
+  /// See [`Params.recipient_id`](Params.recipient_id)
+  pub recipient_id: Kid,
+  /// See [`Params.id_context`](Params.id_context)
+  pub id_context: Option<IdContext>,
+}
+
+/// Provides the security contexts shared with peers,
+/// and durably stores the state that must outlive the security contexts derived from them
+///
+/// Reusing a Sender Sequence Number with the same security context breaks
+/// the confidentiality of every message protected with it, and forgetting the
+/// Partial IVs already received lets old requests be replayed
+/// ([RFC8613 appendix B.1](https://www.rfc-editor.org/rfc/rfc8613#appendix-B.1)).
+///
+/// Security contexts are derived again whenever they're needed after being evicted
+/// to make room for others (or after a restart), so messages are only protected with
+/// a security context if [`Keyring::store_ssn`] is implemented, and a security context
+/// is only evicted if [`Keyring::store_replay_window`] is implemented.
+pub trait Keyring: Default + core::fmt::Debug {
+  /// How many Sender Sequence Numbers to reserve each time one is stored.
+  ///
+  /// Larger values mean fewer calls to [`Keyring::store_ssn`], and more
+  /// sequence numbers skipped after a restart.
+  const SSN_PERSIST_INTERVAL: u64 = 64;
+
+  /// Get the parameters of the security context that should be used
+  /// to protect requests sent to `addr`, if requests to `addr` should be protected.
+  fn for_peer(&self, addr: SocketAddr) -> Option<Params>;
+
+  /// Get the parameters of the security context whose Recipient ID is `kid`
+  /// (and whose ID Context is `kid_context`, if present)
+  /// to verify a protected request.
+  fn for_kid(&self, kid: &[u8], kid_context: Option<&[u8]>) -> Option<Params>;
+
+  /// Get the Sender Sequence Number last stored with [`Keyring::store_ssn`]
+  /// for the security context `context`, or `None` if one was never stored.
+  ///
+  /// # Default Implementation
+  /// The default implementation returns `None`.
+  fn load_ssn(&self, context: &ContextId) -> Option<u64> {
+    let _ = context;
+    None
+  }
+
+  /// Durably store `ssn` for the security context `context`,
+  /// returning `false` if it could not be stored.
+  ///
+  /// Sequence numbers below `ssn` may be used after this returns `true`,
+  /// and no messages will be protected with `context` if it returns `false`.
+  ///
+  /// # Default Implementation
+  /// The default implementation stores nothing and returns `false`,
+  /// so that no messages are protected.
+  fn store_ssn(&self, context: &ContextId, ssn: u64) -> bool {
+    let _ = (context, ssn);
+    false
+  }
+
+  /// Get the replay window last stored with [`Keyring::store_replay_window`]
+  /// for the security context `context`, or `None` if one was never stored.
+  ///
+  /// # Default Implementation
+  /// The default implementation returns `None`.
+  fn load_replay_window(&self, context: &ContextId) -> Option<ReplayWindow> {
+    let _ = context;
+    None
+  }
+
+  /// Durably store the replay window of the security context `context`
+  /// before it is evicted, returning `false` if it could not be stored.
+  ///
+  /// If this returns `false` the security context is kept, and messages
+  /// needing a new security context are rejected until there is room for one.
+  ///
+  /// # Default Implementation
+  /// The default implementation stores nothing and returns `false`,
+  /// so that security contexts are never evicted.
+  fn store_replay_window(&self, context: &ContextId, window: ReplayWindow) -> bool {
+    let _ = (context, window);
+    false
+  }
+}
+
+/// A [`Keyring`] with no security contexts, meaning that no messages
+/// will be protected with OSCORE
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoKeys;
+
+impl Keyring for NoKeys {
+  fn for_peer(&self, _: SocketAddr) -> Option<Params> {
+    None
+  }
+
+  fn for_kid(&self, _: &[u8], _: Option<&[u8]>) -> Option<Params> {
+    None
+  }
+}
+
+/// Sliding window of the Partial IVs received from a peer,
+/// used to detect replayed requests
+/// ([RFC8613 section 7.4](https://www.rfc-editor.org/rfc/rfc8613#section-7.4))
+///
+/// ```
+/// use toad::oscore::ReplayWindow;
+///
+/// let mut window = ReplayWindow::default();
+/// window.accept(3);
+/// window.accept(1);
+///
+/// assert!(window.is_replay(3));
+/// assert!(window.is_replay(1));
+/// assert!(!window.is_replay(2));
+/// assert!(!window.is_replay(40));
+///
+/// window.accept(40);
+/// assert!(window.is_replay(2)); // too old to tell
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayWindow {
+  highest: Option<u64>,
+  /// bit `n` is set if `highest - n` has been received
+  seen: u32,
+}
+
+impl ReplayWindow {
+  /// Has `seq` already been received, or is it too old to tell?
+  pub fn is_replay(&self, seq: u64) -> bool {
+    match self.highest {
+      | None => false,
+      | Some(highest) if seq > highest => false,
+      | Some(highest) => {
+        let age = highest - seq;
+        age >= u32::BITS as u64 || self.seen & (1 << age) != 0
+      },
+    }
+  }
+
+  /// Remember that `seq` was received
+  pub fn accept(&mut self, seq: u64) {
+    match self.highest {
+      | Some(highest) if seq <= highest => {
+        let age = highest - seq;
+        if age < u32::BITS as u64 {
+          self.seen |= 1 << age;
+        }
+      },
+      | Some(highest) => {
+        let shift = seq - highest;
+        self.seen = if shift < u32::BITS as u64 {
+          self.seen << shift | 1
+        } else {
+          1
+        };
+        self.highest = Some(seq);
+      },
+      | None => {
+        self.seen = 1;
+        self.highest = Some(seq);
+      },
+    }
+  }
+}
+
+/// A security context derived from [`Params`]
+/// ([RFC8613 section 3](https://www.rfc-editor.org/rfc/rfc8613#section-3))
+///
+/// Not `Clone`; two copies of a context would reuse sender sequence numbers.
+#[allow(missing_copy_implementations)]
+pub struct Context {
+  id: ContextId,
+  sender_key: [u8; KEY_LEN],
+  recipient_key: [u8; KEY_LEN],
+  common_iv: [u8; NONCE_LEN],
+  ssn: u64,
+  ssn_stored: u64,
+  replay: ReplayWindow,
+}
+
+impl core::fmt::Debug for Context {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    // keys are deliberately left out
+    f.debug_struct("Context")
+     .field("id", &self.id)
+     .field("ssn", &self.ssn)
+     .field("ssn_stored", &self.ssn_stored)
+     .field("replay", &self.replay)
+     .finish_non_exhaustive()
+  }
+}
+
+impl Context {
+  /// Derive a security context, starting at the Sender Sequence Number
+  /// & replay window last stored in `keys`
+  pub fn derive<K: Keyring>(params: &Params, keys: &K) -> Self {
+    let id_context = params.id_context.as_ref().map(|c| c.as_slice());
+    let derive = |id: &[u8], ty: &str, out: &mut [u8]| {
+      let info = Cbor::<64>::default().array(5)
+                                      .bstr(id)
+                                      .bstr_or_null(id_context)
+                                      .uint(AES_CCM_16_64_128 as u64)
+                                      .tstr(ty)
+                                      .uint(out.len() as u64);
+
+      // `out` is never longer than 255 * the length of a SHA-256 digest
+      Hkdf::<Sha256>::new(Some(params.master_salt.as_slice()), &params.master_secret).expand(&info.0, out)
+                                                                            .ok();
+    };
+
+    let mut ctx = Context { id: params.id(),
+                            sender_key: Default::default(),
+                            recipient_key: Default::default(),
+                            common_iv: Default::default(),
+                            ssn: 0,
+                            ssn_stored: 0,
+                            replay: Default::default() };
+
+    derive(&params.sender_id, "Key", &mut ctx.sender_key);
+    derive(&params.recipient_id, "Key", &mut ctx.recipient_key);
+    derive(&[], "IV", &mut ctx.common_iv);
+
+    ctx.ssn = keys.load_ssn(&ctx.id).unwrap_or(0);
+    ctx.ssn_stored = ctx.ssn;
+    ctx.replay = keys.load_replay_window(&ctx.id).unwrap_or_default();
+    ctx
+  }
+
+  /// Get the parameters identifying this security context
+  pub fn id(&self) -> &ContextId {
+    &self.id
+  }
+
+  /// Get the replay window of the Partial IVs received with this security context
+  pub fn replay_window(&mut self) -> &mut ReplayWindow {
+    &mut self.replay
+  }
+
+  /// Use the next Sender Sequence Number, storing a new one in `keys` when necessary.
+  ///
+  /// Yields `None` if the sequence numbers are exhausted, or the
+  /// sequence number could not be stored.
+  pub fn next_piv<K: Keyring>(&mut self, keys: &K) -> Option<Piv> {
+    if self.ssn > MAX_SSN {
+      return None;
+    }
+
+    if self.ssn >= self.ssn_stored {
+      let stored = self.ssn.saturating_add(K::SSN_PERSIST_INTERVAL);
+      if !keys.store_ssn(&self.id, stored) {
+        return None;
+      }
+
+      self.ssn_stored = stored;
+    }
+
+    let piv = piv(self.ssn);
+    self.ssn += 1;
+    Some(piv)
+  }
+
+  /// Compute the AEAD nonce for the Partial IV `piv`, generated by the endpoint with Sender ID `id_piv`
+  /// ([RFC8613 section 5.2](https://www.rfc-editor.org/rfc/rfc8613#section-5.2))
+  pub fn nonce(&self, id_piv: &[u8], piv: &[u8]) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[0] = id_piv.len() as u8;
+    nonce[NONCE_LEN - 5 - id_piv.len()..NONCE_LEN - 5].copy_from_slice(id_piv);
+    nonce[NONCE_LEN - piv.len()..].copy_from_slice(piv);
+
+    nonce.iter_mut()
+         .zip(self.common_iv.iter())
+         .for_each(|(n, iv)| *n ^= iv);
+    nonce
+  }
+
+  /// Encrypt `buf` in place with the Sender Key, yielding the authentication tag
+  pub fn encrypt(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], buf: &mut [u8]) -> [u8; TAG_LEN] {
+    let mut tag = [0u8; TAG_LEN];
+
+    // encryption only fails if `buf` is too long for the nonce size,
+    // which can't happen with the 13-byte nonces of AES-CCM-16-64-128
+    if let Ok(t) = Aead::new(GenericArray::from_slice(&self.sender_key)).encrypt_in_place_detached(GenericArray::from_slice(nonce), aad, buf) {
+      tag.copy_from_slice(&t);
+    }
+
+    tag
+  }
+
+  /// Verify & decrypt `buf` in place with the Recipient Key, yielding `false`
+  /// if it could not be verified
+  pub fn decrypt(&self,
+                 nonce: &[u8; NONCE_LEN],
+                 aad: &[u8],
+                 buf: &mut [u8],
+                 tag: &[u8; TAG_LEN])
+                 -> bool {
+    Aead::new(GenericArray::from_slice(&self.recipient_key)).decrypt_in_place_detached(GenericArray::from_slice(nonce),
+                                                                                       aad,
+                                                                                       buf,
+                                                                                       GenericArray::from_slice(tag))
+                                                            .is_ok()
+  }
+}
+
+/// Encode a Sender Sequence Number as a Partial IV
+///
+/// ```
+/// use toad::oscore::piv;
+///
+/// assert_eq!(piv(0).as_slice(), &[0x00]);
+/// assert_eq!(piv(20).as_slice(), &[0x14]);
+/// assert_eq!(piv(0x0102).as_slice(), &[0x01, 0x02]);
+/// ```
+pub fn piv(ssn: u64) -> Piv {
+  let bytes = ssn.to_be_bytes();
+  let skip = bytes.iter()
+                  .take(bytes.len() - 1)
+                  .take_while(|b| **b == 0)
+                  .count()
+                  .max(bytes.len() - 5);
+
+  bytes[skip..].iter().copied().collect()
+}
+
+/// Decode a Partial IV to a Sender Sequence Number
+pub fn ssn(piv: &[u8]) -> u64 {
+  piv.iter().fold(0, |n, b| (n << 8) | *b as u64)
+}
+
+/// Compute the Additional Authenticated Data for messages in an exchange
+/// started by a request with Sender ID `request_kid` and Partial IV `request_piv`
+/// ([RFC8613 section 5.4](https://www.rfc-editor.org/rfc/rfc8613#section-5.4))
+pub fn aad(request_kid: &[u8], request_piv: &[u8]) -> ArrayVec<[u8; 64]> {
+  let external_aad = Cbor::<32>::default().array(5)
+                                          .uint(1)
+                                          .array(1)
+                                          .uint(AES_CCM_16_64_128 as u64)
+                                          .bstr(request_kid)
+                                          .bstr(request_piv)
+                                          .bstr(&[]);
+
+  Cbor::<64>::default().array(3)
+                       .tstr("Encrypt0")
+                       .bstr(&[])
+                       .bstr(&external_aad.0)
+                       .0
+}
+
+/// The value of the [OSCORE](toad_msg::opt::known::no_repeat::OSCORE) option
+/// ([RFC8613 section 6.1](https://www.rfc-editor.org/rfc/rfc8613#section-6.1))
+///
+/// ```
+/// use toad::oscore::OptionValue;
+///
+/// let value = OptionValue::parse(&[0x09, 0x14]).unwrap();
+/// assert_eq!(value.piv.unwrap().as_slice(), &[0x14]);
+/// assert!(value.kid.unwrap().is_empty());
+/// assert_eq!(value.kid_context, None);
+///
+/// assert_eq!(value.to_bytes().as_slice(), &[0x09, 0x14]);
+/// assert!(OptionValue::default().to_bytes().is_empty());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OptionValue {
+  /// Partial IV of the message
+  pub piv: Option<Piv>,
+  /// ID Context of the security context
+  pub kid_context: Option<IdContext>,
+  /// Sender ID of the endpoint that sent the message
+  pub kid: Option<Kid>,
+}
+
+impl OptionValue {
+  const KID: u8 = 0b0000_1000;
+  const KID_CONTEXT: u8 = 0b0001_0000;
+  const PIV_LEN: u8 = 0b0000_0111;
+  const RESERVED: u8 = 0b1110_0000;
+
+  /// Parse an option value, yielding `None` if it is malformed
+  pub fn parse(bytes: &[u8]) -> Option<Self> {
+    let (flags, mut rest) = match bytes.split_first() {
+      | Some((flags, rest)) => (*flags, rest),
+      | None => return Some(Self::default()),
+    };
+
+    let piv_len = (flags & Self::PIV_LEN) as usize;
+    if flags & Self::RESERVED != 0 || piv_len > 5 || rest.len() < piv_len {
+      return None;
+    }
+
+    let mut value = Self::default();
+
+    if piv_len > 0 {
+      value.piv = Some(rest[..piv_len].iter().copied().collect());
+      rest = &rest[piv_len..];
+    }
+
+    if flags & Self::KID_CONTEXT != 0 {
+      let (len, ctx) = rest.split_first()?;
+      let len = *len as usize;
+      if ctx.len() < len || len > IdContext::default().capacity() {
+        return None;
+      }
+
+      value.kid_context = Some(ctx[..len].iter().copied().collect());
+      rest = &ctx[len..];
+    }
+
+    if flags & Self::KID != 0 {
+      if rest.len() > Kid::default().capacity() {
+        return None;
+      }
+
+      value.kid = Some(rest.iter().copied().collect());
+    } else if !rest.is_empty() {
+      return None;
+    }
+
+    Some(value)
+  }
+
+  /// Encode the option value
+  pub fn to_bytes(&self) -> ArrayVec<[u8; 48]> {
+    let mut bytes = ArrayVec::new();
+
+    let piv = self.piv.unwrap_or_default();
+    let flags = piv.len() as u8
+                | self.kid.map(|_| Self::KID).unwrap_or(0)
+                | self.kid_context.map(|_| Self::KID_CONTEXT).unwrap_or(0);
+
+    if flags == 0 {
+      return bytes;
+    }
+
+    bytes.push(flags);
+    bytes.extend(piv);
+
+    if let Some(ctx) = self.kid_context {
+      bytes.push(ctx.len() as u8);
+      bytes.extend(ctx);
+    }
+
+    if let Some(kid) = self.kid {
+      bytes.extend(kid);
+    }
+
+    bytes
+  }
+}
+
+/// Just enough CBOR to build the HKDF `info` & the AAD
+#[derive(Default)]
+struct Cbor<const N: usize>(ArrayVec<[u8; N]>);
+
+impl<const N: usize> Cbor<N> {
+  fn head(mut self, major: u8, n: u64) -> Self {
+    let major = major << 5;
+    match n {
+      | n if n < 24 => self.0.push(major | n as u8),
+      | n if n <= u8::MAX as u64 => self.0.extend([major | 24, n as u8]),
+      | n => {
+        self.0.push(major | 25);
+        self.0.extend((n as u16).to_be_bytes());
+      },
+    }
+
+    self
+  }
+
+  fn uint(self, n: u64) -> Self {
+    self.head(0, n)
+  }
+
+  fn bstr(mut self, bytes: &[u8]) -> Self {
+    self = self.head(2, bytes.len() as u64);
+    self.0.extend(bytes.iter().copied());
+    self
+  }
+
+  fn bstr_or_null(mut self, bytes: Option<&[u8]>) -> Self {
+    match bytes {
+      | Some(bytes) => self.bstr(bytes),
+      | None => {
+        self.0.push(0xF6);
+        self
+      },
+    }
+  }
+
+  fn tstr(mut self, s: &str) -> Self {
+    self = self.head(3, s.len() as u64);
+    self.0.extend(s.bytes());
+    self
+  }
+
+  fn array(self, len: u64) -> Self {
+    self.head(4, len)
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+
+  pub(crate) fn hex(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2)
+                .map(|ix| u8::from_str_radix(&s[ix..ix + 2], 16).unwrap())
+                .collect()
+  }
+
+  /// RFC8613 appendix C.1.1
+  pub(crate) fn client_params() -> Params {
+    Params { master_secret: hex("0102030405060708090a0b0c0d0e0f10").into_iter()
+                                                                   .collect(),
+             master_salt: hex("9e7ca92223786340").into_iter().collect(),
+             id_context: None,
+             sender_id: Kid::default(),
+             recipient_id: hex("01").into_iter().collect() }
+  }
+
+  /// RFC8613 appendix C.1.2
+  pub(crate) fn server_params() -> Params {
+    Params { sender_id: client_params().recipient_id,
+             recipient_id: client_params().sender_id,
+             ..client_params() }
+  }
+
+  #[test]
+  fn derive_should_match_rfc8613_test_vectors() {
+    let client = Context::derive(&client_params(), &NoKeys);
+    assert_eq!(client.sender_key.to_vec(),
+               hex("f0910ed7295e6ad4b54fc793154302ff"));
+    assert_eq!(client.recipient_key.to_vec(),
+               hex("ffb14e093c94c9cac9471648b4f98710"));
+    assert_eq!(client.common_iv.to_vec(), hex("4622d4dd6d944168eefb54987c"));
+
+    let server = Context::derive(&server_params(), &NoKeys);
+    assert_eq!(server.sender_key, client.recipient_key);
+    assert_eq!(server.recipient_key, client.sender_key);
+  }
+
+  #[test]
+  fn aad_and_nonce_should_match_rfc8613_test_vectors() {
+    let client = Context::derive(&client_params(), &NoKeys);
+
+    assert_eq!(aad(&[], &[0x14]).to_vec(),
+               hex("8368456e63727970743040488501810a40411440"));
+    assert_eq!(client.nonce(&[], &[0x14]).to_vec(),
+               hex("4622d4dd6d944168eefb549868"));
+    assert_eq!(client.nonce(&[0x01], &[0x00]).to_vec(),
+               hex("4722d4dd6d944169eefb54987c"));
+  }
+
+  #[test]
+  fn encrypt_should_round_trip() {
+    let client = Context::derive(&client_params(), &NoKeys);
+    let server = Context::derive(&server_params(), &NoKeys);
+
+    let nonce = client.nonce(&[], &[0x14]);
+    let aad = aad(&[], &[0x14]);
+    let mut buf = hex("01b3747631");
+    let tag = client.encrypt(&nonce, &aad, &mut buf);
+    assert_eq!([buf.clone(), tag.to_vec()].concat(),
+               hex("612f1092f1776f1c1668b3825e"));
+
+    assert!(server.decrypt(&nonce, &aad, &mut buf, &tag));
+    assert_eq!(buf, hex("01b3747631"));
+
+    let mut tampered = tag;
+    tampered[0] ^= 1;
+    assert!(!server.decrypt(&nonce, &aad, &mut buf, &tampered));
+  }
+
+  #[test]
+  fn next_piv_should_store_ssn_before_using_it() {
+    #[derive(Debug, Default)]
+    struct Keys(std::sync::Mutex<Vec<u64>>);
+
+    impl Keyring for Keys {
+      const SSN_PERSIST_INTERVAL: u64 = 2;
+
+      fn for_peer(&self, _: SocketAddr) -> Option<Params> {
+        None
+      }
+
+      fn for_kid(&self, _: &[u8], _: Option<&[u8]>) -> Option<Params> {
+        None
+      }
+
+      fn load_ssn(&self, _: &ContextId) -> Option<u64> {
+        Some(10)
+      }
+
+      fn store_ssn(&self, _: &ContextId, ssn: u64) -> bool {
+        self.0.lock().unwrap().push(ssn);
+        true
+      }
+    }
+
+    let keys = Keys::default();
+    let mut ctx = Context::derive(&client_params(), &keys);
+
+    let pivs = (0..3).map(|_| ctx.next_piv(&keys).unwrap())
+                     .map(|p| ssn(&p))
+                     .collect::<Vec<_>>();
+
+    assert_eq!(pivs, vec![10, 11, 12]);
+    assert_eq!(*keys.0.lock().unwrap(), vec![12, 14]);
+  }
+
+  #[test]
+  fn next_piv_should_refuse_when_ssn_cant_be_stored() {
+    let mut ctx = Context::derive(&client_params(), &NoKeys);
+    assert_eq!(ctx.next_piv(&NoKeys), None);
+  }
+
+  #[test]
+  fn option_value_should_reject_malformed() {
+    assert_eq!(OptionValue::parse(&[]), Some(OptionValue::default()));
+    assert_eq!(OptionValue::parse(&[0x06, 0, 0, 0, 0, 0, 0]), None);
+    assert_eq!(OptionValue::parse(&[0x02, 0x01]), None);
+    assert_eq!(OptionValue::parse(&[0x20]), None);
+    assert_eq!(OptionValue::parse(&[0x01, 0x14, 0xAA]), None);
+
+    let value = OptionValue { piv: Some(piv(5)),
+                              kid_context: Some([0xAB, 0xCD].into_iter().collect()),
+                              kid: Some([0x01].into_iter().collect()) };
+    assert_eq!(value.to_bytes().to_vec(), hex("190502abcd01"));
+    assert_eq!(OptionValue::parse(&value.to_bytes()), Some(value));
+  }
+}
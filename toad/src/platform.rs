@@ -1,19 +1,20 @@
 use core::fmt::Debug;
 
-use ::toad_msg::{Id, OptNumber, OptValue, OptionMap, Token, TryIntoBytes};
+use ::toad_msg::{Id, MessageOptions, OptNumber, OptValue, OptionMap, Token, TryIntoBytes};
+use embedded_time::duration::Milliseconds;
 use embedded_time::Instant;
 use naan::prelude::MonadOnce;
 use no_std_net::SocketAddr;
 #[cfg(feature = "alloc")]
 use std_alloc::vec::Vec;
-use toad_array::{AppendCopy, Array};
+use toad_array::{AppendCopy, Array, Indexed};
 
 use crate::config::Config;
 use crate::net::{Addrd, Socket};
 use crate::req::Req;
 use crate::resp::Resp;
 use crate::step::Step;
-use crate::time::Clock;
+use crate::time::{Clock, Millis};
 use crate::todo::String;
 
 /// Default [`PlatformError`] implementation
@@ -27,7 +28,7 @@ pub enum Error<Step, Socket> {
 }
 
 impl<Step, Socket> PlatformError<Step, Socket> for Error<Step, Socket>
-  where Step: core::fmt::Debug,
+  where Step: crate::step::Error,
         Socket: core::fmt::Debug
 {
   fn msg_to_bytes(e: ::toad_msg::to_bytes::MessageToBytesError) -> Self {
@@ -45,6 +46,14 @@ impl<Step, Socket> PlatformError<Step, Socket> for Error<Step, Socket>
   fn clock(e: embedded_time::clock::Error) -> Self {
     Self::Clock(e)
   }
+
+  fn is_timeout(&self) -> bool {
+    matches!(self, Self::Step(e) if e.is_timeout())
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    matches!(self, Self::Step(e) if e.is_unacknowledged())
+  }
 }
 
 /// Errors that may be encountered during the CoAP lifecycle
@@ -60,6 +69,18 @@ pub trait PlatformError<StepError, SocketError>: Sized + core::fmt::Debug {
 
   /// Convert a clock error to PlatformError
   fn clock(e: embedded_time::clock::Error) -> Self;
+
+  /// Was this converted from a step error that
+  /// [is a timeout](crate::step::Error::is_timeout)?
+  fn is_timeout(&self) -> bool {
+    false
+  }
+
+  /// Was this converted from a step error that
+  /// [is unacknowledged](crate::step::Error::is_unacknowledged)?
+  fn is_unacknowledged(&self) -> bool {
+    false
+  }
 }
 
 /// The runtime component of the `Platform` abstraction
@@ -94,11 +115,13 @@ pub trait Platform<Steps>
     self.socket()
         .poll()
         .map_err(Self::Error::socket)
-        .and_then(|recvd_dgram| {
+        .and_then(|recvd| {
+          let (recvd_dgram, recvd_dgram_dest) = recvd.unzip();
           self.clock()
               .try_now()
               .map_err(Self::Error::clock)
               .map(|time| Snapshot { recvd_dgram,
+                                     recvd_dgram_dest,
                                      config: self.config(),
                                      time })
         })
@@ -162,12 +185,69 @@ pub trait Platform<Steps>
     res
   }
 
+  /// Subscribe to a resource on a remote server.
+  ///
+  /// Sends `req` with an [Observe](toad_msg::opt::known::no_repeat::OBSERVE)
+  /// value of [register](toad_msg::opt::known::observe::Action::Register), and yields a
+  /// [`Sub`](crate::client::observe::Sub) handle that can be polled for notifications.
+  ///
+  /// The subscription is ended when the handle is [cancelled](crate::client::observe::Sub::cancel)
+  /// or dropped.
+  ///
+  /// ```no_run
+  /// use toad::net::{ipv4_socketaddr, Addrd};
+  /// use toad::platform::Platform;
+  /// use toad::req::Req;
+  /// use toad::std::{dtls, PlatformTypes as Std};
+  /// use toad::step::runtime::std::Runtime;
+  ///
+  /// let client = toad::std::Platform::<dtls::N, Runtime<dtls::N>>::try_new("127.0.0.1:4444",
+  ///                                                                     Default::default()).unwrap();
+  ///
+  /// let req = Req::<Std<dtls::N>>::get("temperature");
+  /// let server = ipv4_socketaddr([127, 0, 0, 1], 5683);
+  ///
+  /// let mut sub = nb::block!(client.observe(Addrd(req.clone(), server))).unwrap();
+  /// for _ in 0..10 {
+  ///   let notification = nb::block!(sub.poll()).unwrap();
+  ///   println!("{:?}", notification.data().payload_string());
+  /// }
+  ///
+  /// sub.cancel().unwrap();
+  /// ```
+  fn observe(&self,
+             mut req: Addrd<Req<Self::Types>>)
+             -> nb::Result<crate::client::observe::Sub<'_, Self, Steps>, Self::Error>
+    where Self: Sized
+  {
+    use ::toad_msg::opt::known::observe::Action::Register;
+    use embedded_time::Clock;
+
+    req.as_mut().msg_mut().set_observe(Register).ok();
+
+    let now = self.clock()
+                  .try_now()
+                  .map_err(Self::Error::clock)
+                  .map_err(nb::Error::Other)?;
+
+    let mut msg = req.map(self::toad_msg::Message::<Self::Types>::from);
+    let (id, token) = self.send_msg(msg.clone())?;
+    msg.as_mut().id = id;
+    msg.as_mut().token = token;
+
+    Ok(crate::client::observe::Sub::new(self, msg, now))
+  }
+
   /// `toad` may occasionally emit tracing and logs by invoking this method.
   ///
   /// It's completely up to the Platform to handle them meaningfully (e.g. `println!`)
   fn log(&self, level: log::Level, msg: String<1000>) -> Result<(), Self::Error>;
 
   /// Send a [`toad_msg::Message`]
+  ///
+  /// If a step marks the message with [`step::SUPPRESS`](crate::step::SUPPRESS)
+  /// in [`Step::before_message_sent`], the message will not be sent
+  /// and steps will not be notified via [`Step::on_message_sent`].
   fn send_msg(&self,
               mut addrd_msg: Addrd<self::toad_msg::Message<Self::Types>>)
               -> nb::Result<(Id, Token), Self::Error> {
@@ -176,35 +256,42 @@ pub trait Platform<Steps>
     let mut effs = <Self::Types as PlatformTypes>::Effects::default();
     let mut on_message_sent_effs = <Self::Types as PlatformTypes>::Effects::default();
 
-    self.snapshot()
-        .discard(|snapshot: &Snapshot<Self::Types>| {
-          self.steps()
-              .before_message_sent(snapshot, &mut effs, &mut addrd_msg)
-              .map_err(Self::Error::step)
-        })
-        .discard(|_: &Snapshot<Self::Types>| self.exec_many(effs).map_err(|(_, e)| e))
-        .and_then(|snapshot| {
-          addrd_msg.clone().fold(|msg, addr| {
-                             let (id, token) = (msg.id, msg.token);
-                             msg.try_into_bytes::<Dgram<Self::Types>>()
-                                .map_err(Self::Error::msg_to_bytes)
-                                .map(|bytes| (id, token, snapshot, Addrd(bytes, addr)))
-                           })
-        })
-        .map_err(nb::Error::Other)
-        .discard(|(_, _, _, addrd_bytes): &(_, _, _, Addrd<<<Self::Types as PlatformTypes>::Socket as Socket>::Dgram>)| {
-          self.socket()
-              .send(addrd_bytes.as_ref().map(|s| s.as_ref()))
-              .map_err(|e: nb::Error<_>| e.map(Self::Error::socket))
-        })
-        .discard(|(_, _, snapshot, _): &(_, _, Snapshot<<Self as Platform<Steps>>::Types>, _)| {
-          self.steps()
-              .on_message_sent(snapshot, &mut on_message_sent_effs, &addrd_msg)
-              .map_err(Self::Error::step)
-              .map_err(nb::Error::Other)
-        })
-        .discard(|_: &(_, _, _, _)| self.exec_many(on_message_sent_effs).map_err(|(_, e)| e).map_err(nb::Error::Other))
-        .map(|(id, token, _, _)| (id, token))
+    let snapshot = self.snapshot()
+                       .discard(|snapshot: &Snapshot<Self::Types>| {
+                         self.steps()
+                             .before_message_sent(snapshot, &mut effs, &mut addrd_msg)
+                             .map_err(Self::Error::step)
+                       })
+                       .discard(|_: &Snapshot<Self::Types>| {
+                         self.exec_many(effs).map_err(|(_, e)| e)
+                       })
+                       .map_err(nb::Error::Other)?;
+
+    if addrd_msg.data().get(crate::step::SUPPRESS).is_some() {
+      return Ok((addrd_msg.data().id, addrd_msg.data().token));
+    }
+
+    addrd_msg.clone()
+             .fold(|msg, addr| {
+               let (id, token) = (msg.id, msg.token);
+               msg.try_into_bytes::<Dgram<Self::Types>>()
+                  .map_err(Self::Error::msg_to_bytes)
+                  .map(|bytes| (id, token, snapshot, Addrd(bytes, addr)))
+             })
+             .map_err(nb::Error::Other)
+             .discard(|(_, _, _, addrd_bytes): &(_, _, _, Addrd<<<Self::Types as PlatformTypes>::Socket as Socket>::Dgram>)| {
+               self.socket()
+                   .send(addrd_bytes.as_ref().map(|s| s.as_ref()))
+                   .map_err(|e: nb::Error<_>| e.map(Self::Error::socket))
+             })
+             .discard(|(_, _, snapshot, _): &(_, _, Snapshot<<Self as Platform<Steps>>::Types>, _)| {
+               self.steps()
+                   .on_message_sent(snapshot, &mut on_message_sent_effs, &addrd_msg)
+                   .map_err(Self::Error::step)
+                   .map_err(nb::Error::Other)
+             })
+             .discard(|_: &(_, _, _, _)| self.exec_many(on_message_sent_effs).map_err(|(_, e)| e).map_err(nb::Error::Other))
+             .map(|(id, token, _, _)| (id, token))
   }
 
   /// Execute an [`Effect`]
@@ -232,16 +319,52 @@ pub trait Platform<Steps>
              | Ok(()) => nb::block!(self.exec_1(&eff)).map_err(|e| {
                            let mut effs: <Self::Types as PlatformTypes>::Effects =
                              Default::default();
-                           effs.push(eff);
+                           effs.append(eff);
                            (effs, e)
                          }),
              | Err((mut effs, e)) => {
-               effs.push(eff);
+               effs.append(eff);
                Err((effs, e))
              },
            })
   }
 
+  /// How long the platform can go without being polled if no datagrams
+  /// are received, capped at `until` (if provided).
+  ///
+  /// This is the time until the earliest [`Step::next_deadline`]
+  /// (or `until`, whichever comes first), and `None` if there is
+  /// nothing to do until a datagram arrives.
+  fn poll_timeout(&self,
+                  until: Option<Instant<<Self::Types as PlatformTypes>::Clock>>)
+                  -> Result<Option<Millis>, Self::Error> {
+    use embedded_time::Clock;
+
+    let now = self.clock().try_now().map_err(Self::Error::clock)?;
+    let deadline = crate::step::earliest(self.steps().next_deadline(self.config()), until);
+
+    Ok(deadline.map(|at| {
+                 at.checked_duration_since(&now)
+                   .and_then(|d| Millis::try_from(d).ok())
+                   .unwrap_or(Milliseconds(0))
+               }))
+  }
+
+  /// Block until a datagram may have been received, `Steps` need to be polled
+  /// (see [`Platform::poll_timeout`]) or `until` has passed; whichever comes first.
+  ///
+  /// Blocking consumers of the platform should invoke this when
+  /// [`Platform::poll_req`] or [`Platform::poll_resp`] would block,
+  /// rather than polling in a busy loop.
+  fn wait(&self,
+          until: Option<Instant<<Self::Types as PlatformTypes>::Clock>>)
+          -> Result<(), Self::Error> {
+    let timeout = self.poll_timeout(until)?;
+    self.socket()
+        .wait_recv_ready(timeout)
+        .map_err(Self::Error::socket)
+  }
+
   /// Copy of runtime behavior [`Config`] to be used
   ///
   /// Typically this will be a field access (`self.config`)
@@ -307,6 +430,12 @@ pub struct Snapshot<P: PlatformTypes> {
   /// A UDP datagram received from somewhere
   pub recvd_dgram: Option<Addrd<<P::Socket as Socket>::Dgram>>,
 
+  /// The address that `recvd_dgram` was sent to
+  ///
+  /// e.g. a multicast group address joined by the socket
+  /// (see [`Socket::recv`])
+  pub recvd_dgram_dest: Option<SocketAddr>,
+
   /// Runtime config, includes many useful timings
   pub config: Config,
 }
@@ -316,6 +445,7 @@ impl<P: PlatformTypes> core::fmt::Debug for Snapshot<P> {
     f.debug_struct("Snapshot")
      .field("time", &self.time)
      .field("recvd_dgram", &self.recvd_dgram)
+     .field("recvd_dgram_dest", &self.recvd_dgram_dest)
      .field("config", &self.config)
      .finish()
   }
@@ -325,6 +455,7 @@ impl<P: PlatformTypes> Clone for Snapshot<P> {
   fn clone(&self) -> Self {
     Self { time: self.time,
            recvd_dgram: self.recvd_dgram.clone(),
+           recvd_dgram_dest: self.recvd_dgram_dest,
            config: self.config }
   }
 }
@@ -1,5 +1,4 @@
 use naan::prelude::MonadOnce;
-use toad_array::Array;
 use toad_msg::{MessageOptions, OptNumber, OptValue};
 
 use super::{Method, Req};
@@ -92,6 +91,21 @@ impl<P> ReqBuilder<P>
     Self::new(Method::DELETE, path)
   }
 
+  /// Creates a FETCH request
+  pub fn fetch(path: impl AsRef<str>) -> Self {
+    Self::new(Method::FETCH, path)
+  }
+
+  /// Creates a PATCH request
+  pub fn patch(path: impl AsRef<str>) -> Self {
+    Self::new(Method::PATCH, path)
+  }
+
+  /// Creates an iPATCH request
+  pub fn ipatch(path: impl AsRef<str>) -> Self {
+    Self::new(Method::IPATCH, path)
+  }
+
   /// Set the value of a non-repeatable option.
   ///
   /// If the option has already been set, this will yield `Err(Error::OptionNotRepeatable)`.
@@ -32,11 +32,17 @@ impl core::fmt::Display for Method {
       | Code { class: 0,
                detail: 1, } => "GET".to_string(),
       | Code { class: 0,
-               detail: 2, } => "PUT".to_string(),
+               detail: 2, } => "POST".to_string(),
       | Code { class: 0,
-               detail: 3, } => "POST".to_string(),
+               detail: 3, } => "PUT".to_string(),
       | Code { class: 0,
                detail: 4, } => "DELETE".to_string(),
+      | Code { class: 0,
+               detail: 5, } => "FETCH".to_string(),
+      | Code { class: 0,
+               detail: 6, } => "PATCH".to_string(),
+      | Code { class: 0,
+               detail: 7, } => "iPATCH".to_string(),
       | c => c.to_string(),
     };
 
@@ -50,4 +56,7 @@ impl Method {
   code!(rfc7252("5.8.2") POST   = Method(0 . 02));
   code!(rfc7252("5.8.3") PUT    = Method(0 . 03));
   code!(rfc7252("5.8.4") DELETE = Method(0 . 04));
+  code!(rfc(8132, "2")   FETCH  = Method(0 . 05));
+  code!(rfc(8132, "3")   PATCH  = Method(0 . 06));
+  code!(rfc(8132, "3")   IPATCH = Method(0 . 07));
 }
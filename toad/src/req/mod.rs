@@ -184,6 +184,48 @@ impl<P: PlatformTypes> Req<P> {
     Self::new(Method::DELETE, path)
   }
 
+  /// Creates a new FETCH request
+  ///
+  /// FETCH is like GET, but with a payload describing
+  /// the representation being requested (e.g. a query or filter)
+  ///
+  /// ```
+  /// use toad::req::Req;
+  /// use toad::std::{dtls, PlatformTypes as Std};
+  ///
+  /// let mut req = Req::<Std<dtls::Y>>::fetch("/sensors");
+  /// req.set_payload("temp>20".bytes());
+  /// ```
+  pub fn fetch(path: impl AsRef<str>) -> Self {
+    Self::new(Method::FETCH, path)
+  }
+
+  /// Creates a new PATCH request
+  ///
+  /// ```
+  /// use toad::req::Req;
+  /// use toad::std::{dtls, PlatformTypes as Std};
+  ///
+  /// let mut req = Req::<Std<dtls::Y>>::patch("/users/john");
+  /// req.set_payload(r#"{"name": "Jon"}"#.bytes());
+  /// ```
+  pub fn patch(path: impl AsRef<str>) -> Self {
+    Self::new(Method::PATCH, path)
+  }
+
+  /// Creates a new iPATCH (idempotent PATCH) request
+  ///
+  /// ```
+  /// use toad::req::Req;
+  /// use toad::std::{dtls, PlatformTypes as Std};
+  ///
+  /// let mut req = Req::<Std<dtls::Y>>::ipatch("/users/john");
+  /// req.set_payload(r#"{"name": "Jon"}"#.bytes());
+  /// ```
+  pub fn ipatch(path: impl AsRef<str>) -> Self {
+    Self::new(Method::IPATCH, path)
+  }
+
   /// Add a payload to this request
   ///
   /// ```
@@ -3,11 +3,12 @@ pub use toad_msg::Code;
 use crate::code;
 
 // 2.xx
-code!(rfc7252("5.9.1.1") CREATED = 2 . 01);
-code!(rfc7252("5.9.1.2") DELETED = 2 . 02);
-code!(rfc7252("5.9.1.3") VALID   = 2 . 03);
-code!(rfc7252("5.9.1.4") CHANGED = 2 . 04);
-code!(rfc7252("5.9.1.5") CONTENT = 2 . 05);
+code!(rfc7252("5.9.1.1") CREATED  = 2 . 01);
+code!(rfc7252("5.9.1.2") DELETED  = 2 . 02);
+code!(rfc7252("5.9.1.3") VALID    = 2 . 03);
+code!(rfc7252("5.9.1.4") CHANGED  = 2 . 04);
+code!(rfc7252("5.9.1.5") CONTENT  = 2 . 05);
+code!(rfc(7959, "2.9.1") CONTINUE = 2 . 31);
 
 // 4.xx
 code!(rfc7252("5.9.2.1")  BAD_REQUEST                = 4 . 00);
@@ -17,9 +18,12 @@ code!(rfc7252("5.9.2.4")  FORBIDDEN                  = 4 . 03);
 code!(rfc7252("5.9.2.5")  NOT_FOUND                  = 4 . 04);
 code!(rfc7252("5.9.2.6")  METHOD_NOT_ALLOWED         = 4 . 05);
 code!(rfc7252("5.9.2.7")  NOT_ACCEPTABLE             = 4 . 06);
+code!(rfc(7959, "2.9.2")  REQUEST_ENTITY_INCOMPLETE  = 4 . 08);
+code!(rfc(8132, "3.4")    CONFLICT                   = 4 . 09);
 code!(rfc7252("5.9.2.8")  PRECONDITION_FAILED        = 4 . 12);
 code!(rfc7252("5.9.2.9")  REQUEST_ENTITY_TOO_LARGE   = 4 . 13);
 code!(rfc7252("5.9.2.10") UNSUPPORTED_CONTENT_FORMAT = 4 . 15);
+code!(rfc(8132, "3.4")    UNPROCESSABLE_ENTITY       = 4 . 22);
 
 // 5.xx
 code!(rfc7252("5.9.3.1") INTERNAL_SERVER_ERROR  =  5 . 00);
@@ -28,3 +32,4 @@ code!(rfc7252("5.9.3.3") BAD_GATEWAY            =  5 . 02);
 code!(rfc7252("5.9.3.4") SERVICE_UNAVAILABLE    =  5 . 03);
 code!(rfc7252("5.9.3.5") GATEWAY_TIMEOUT        =  5 . 04);
 code!(rfc7252("5.9.3.6") PROXYING_NOT_SUPPORTED =  5 . 05);
+code!(rfc(8768, "3")    HOP_LIMIT_REACHED      =  5 . 08);
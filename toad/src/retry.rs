@@ -100,6 +100,11 @@ impl<C> RetryTimer<C> where C: Clock
     }
   }
 
+  /// Get the number of attempts made so far (including the first)
+  pub fn attempts(&self) -> Attempts {
+    self.attempts
+  }
+
   /// Get the instant this retry timer was first attempted
   pub fn first_attempted_at(&self) -> Instant<C> {
     self.start
@@ -21,6 +21,7 @@ pub struct Respond<P>
   pub code: Code,
   pub payload: P::MessagePayload,
   pub etag: Option<P::MessageOptionBytes>,
+  pub location: Option<P::MessageOptionBytes>,
 }
 
 impl<P> Clone for Respond<P> where P: PlatformTypes
@@ -28,14 +29,18 @@ impl<P> Clone for Respond<P> where P: PlatformTypes
   fn clone(&self) -> Self {
     Respond { code: self.code,
               payload: self.payload.clone(),
-              etag: self.etag.clone() }
+              etag: self.etag.clone(),
+              location: self.location.clone() }
   }
 }
 
 impl<P> PartialEq for Respond<P> where P: PlatformTypes
 {
   fn eq(&self, other: &Self) -> bool {
-    self.code == other.code && self.payload == other.payload && self.etag == other.etag
+    self.code == other.code
+    && self.payload == other.payload
+    && self.etag == other.etag
+    && self.location == other.location
   }
 }
 
@@ -46,6 +51,7 @@ impl<P> core::fmt::Debug for Respond<P> where P: PlatformTypes
      .field("code", &self.code)
      .field("payload", &self.payload)
      .field("etag", &self.etag)
+     .field("location", &self.location)
      .finish()
   }
 }
@@ -472,17 +478,38 @@ impl<S, P, T, E> Ap<S, P, T, E>
   /// If this is [`Ap::respond`] or [`Ap::respond_hydrated`],
   /// set the `etag` option for the response before sending.
   pub fn etag(self, etag: P::MessageOptionBytes) -> Self {
+    self.modify_respond(|r| r.etag = Some(etag))
+  }
+
+  /// If this is [`Ap::respond`] or [`Ap::respond_hydrated`],
+  /// set the `Location-Path` options for the response before sending
+  /// (e.g. to tell the client where a resource created by a POST lives).
+  ///
+  /// `path` is split on `/` into path segments.
+  ///
+  /// ```
+  /// use toad::server::ap::*;
+  /// use toad::server::respond;
+  /// use toad::std::{dtls, PlatformTypes as Std};
+  ///
+  /// let ap: Ap<_, Std<dtls::Y>, (), ()> = respond::created(vec![]).location("things/1234");
+  /// assert_eq!(ap.try_unwrap_respond().unwrap().location,
+  ///            Some("things/1234".bytes().collect()));
+  /// ```
+  pub fn location(self, path: impl AsRef<str>) -> Self {
+    let path = path.as_ref().trim_start_matches('/').bytes().collect();
+    self.modify_respond(|r| r.location = Some(path))
+  }
+
+  fn modify_respond(self, f: impl FnOnce(&mut Respond<P>)) -> Self {
     match self.0 {
-      | ApInner::Respond(Respond { code, payload, .. }) => {
-        Ap::respond(Respond { code,
-                              payload,
-                              etag: Some(etag) }).coerce_state()
+      | ApInner::Respond(mut r) => {
+        f(&mut r);
+        Ap::respond(r).coerce_state()
       },
-      | ApInner::RespondHydrated(Respond { code, payload, .. }, req) => {
-        Ap::respond_hydrated(req,
-                             Respond { code,
-                                       payload,
-                                       etag: Some(etag) }).coerce_state()
+      | ApInner::RespondHydrated(mut r, req) => {
+        f(&mut r);
+        Ap::respond_hydrated(req, r).coerce_state()
       },
       | other => Self(other),
     }
@@ -632,14 +659,16 @@ mod tests {
     let respond = || {
       Ap::respond(Respond { code: code::CONTENT,
                             payload: "".into(),
-                            etag: None })
+                            etag: None,
+                            location: None })
     };
     let reject_hy = || Ap::reject_hydrated(Addrd(req(), addr));
     let respond_hy = || {
       Ap::respond_hydrated(Addrd(req(), addr),
                            Respond { code: code::CONTENT,
                                      payload: "".into(),
-                                     etag: None })
+                                     etag: None,
+                                     location: None })
     };
 
     macro_rules! case {
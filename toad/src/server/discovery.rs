@@ -0,0 +1,264 @@
+use core::fmt::{self, Display, Write};
+
+use toad_msg::link_format::{attr, Attr};
+use toad_msg::opt::known::repeat;
+use toad_msg::{Code, MessageOptions};
+use toad_writable::Writable;
+
+use crate::net::Addrd;
+use crate::platform::{Message, PlatformTypes};
+use crate::req::Req;
+use crate::resp::{code, Resp};
+use crate::todo::String;
+use crate::ContentFormat;
+
+/// A resource advertised by the server at `/.well-known/core`
+///
+/// See [`Run::maybe_discover`](super::Run::maybe_discover)
+///
+/// ```
+/// use toad::server::discovery::Resource;
+/// use toad::ContentFormat;
+///
+/// const RESOURCES: &[Resource<'static>] =
+///   &[Resource::new("sensors/temp").resource_type("temperature-c")
+///                                  .interface("sensor")
+///                                  .content_format(ContentFormat::Text)
+///                                  .observable(),
+///     Resource::new("sensors/light").resource_type("light-lux")
+///                                   .interface("sensor")];
+///
+/// assert!(RESOURCES[0].matches("rt=temperature*"));
+/// assert!(!RESOURCES[1].matches("obs"));
+/// assert_eq!(RESOURCES[0].to_string(),
+///            r#"</sensors/temp>;rt="temperature-c";if="sensor";ct=0;obs"#);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Resource<'a> {
+  path: &'a str,
+  rt: Option<&'a str>,
+  if_: Option<&'a str>,
+  ct: Option<ContentFormat>,
+  sz: Option<u32>,
+  title: Option<&'a str>,
+  obs: bool,
+}
+
+impl<'a> Resource<'a> {
+  /// Advertise the resource at `path`
+  pub const fn new(path: &'a str) -> Self {
+    Self { path,
+           rt: None,
+           if_: None,
+           ct: None,
+           sz: None,
+           title: None,
+           obs: false }
+  }
+
+  /// Set the resource type(s) (`rt`); a space-separated list of names
+  /// describing what the resource is, e.g. `"temperature-c"`
+  pub const fn resource_type(mut self, rt: &'a str) -> Self {
+    self.rt = Some(rt);
+    self
+  }
+
+  /// Set the interface description(s) (`if`); a space-separated list of names
+  /// describing how to interact with the resource, e.g. `"sensor"`
+  pub const fn interface(mut self, if_: &'a str) -> Self {
+    self.if_ = Some(if_);
+    self
+  }
+
+  /// Set the content format of the resource's representation (`ct`)
+  pub const fn content_format(mut self, ct: ContentFormat) -> Self {
+    self.ct = Some(ct);
+    self
+  }
+
+  /// Set the estimated maximum size of the resource's representation, in bytes (`sz`)
+  pub const fn size(mut self, sz: u32) -> Self {
+    self.sz = Some(sz);
+    self
+  }
+
+  /// Set a human-readable title for the resource (`title`)
+  pub const fn title(mut self, title: &'a str) -> Self {
+    self.title = Some(title);
+    self
+  }
+
+  /// Mark the resource as observable (`obs`)
+  pub const fn observable(mut self) -> Self {
+    self.obs = true;
+    self
+  }
+
+  /// The path of the resource, without a leading `/`
+  pub fn path(&self) -> &'a str {
+    self.path.trim_start_matches('/')
+  }
+
+  /// Does this resource satisfy the query filter `query`
+  /// (e.g. `rt=temperature-c`, `if=sensor`, `href=/sensors/*`)?
+  ///
+  /// See [`Attr::matches`] for how filter values are matched.
+  pub fn matches(&self, query: &str) -> bool {
+    let (name, value) = query.split_once('=').unwrap_or((query, ""));
+
+    if name == attr::HREF {
+      return Attr::new(name, self.path()).matches(value.trim_start_matches('/'));
+    }
+
+    self.with_attrs(|mut attrs| Iterator::any(&mut attrs, |a| a.name == name && a.matches(value)))
+  }
+
+  /// Invoke `f` with the target attributes of this resource
+  fn with_attrs<R>(&self, f: impl FnOnce(&mut dyn Iterator<Item = Attr<'_>>) -> R) -> R {
+    let ct = self.ct
+                 .as_ref()
+                 .map(|ct| String::<8>::fmt(format_args!("{}", u16::from(ct))));
+    let sz = self.sz.map(|sz| String::<16>::fmt(format_args!("{}", sz)));
+
+    let mut attrs = [self.rt.map(|rt| Attr::new(attr::RT, rt)),
+                     self.if_.map(|if_| Attr::new(attr::IF, if_)),
+                     ct.as_ref().map(|ct| Attr::new(attr::CT, ct.as_str())),
+                     sz.as_ref().map(|sz| Attr::new(attr::SZ, sz.as_str())),
+                     self.title.map(|title| Attr::new(attr::TITLE, title)),
+                     Some(Attr::flag(attr::OBS)).filter(|_| self.obs)].into_iter()
+                                                                      .flatten();
+    f(&mut attrs)
+  }
+}
+
+impl<'a> Display for Resource<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "</{}>", self.path())?;
+    self.with_attrs(|mut attrs| Iterator::try_for_each(&mut attrs, |a| write!(f, ";{}", a)))
+  }
+}
+
+/// Write `resources` as a link-format document
+pub(crate) fn write_links<'a, 'r: 'a, W>(w: &mut W,
+                                         resources: impl IntoIterator<Item = &'a Resource<'r>>)
+                                         -> fmt::Result
+  where W: Write
+{
+  resources.into_iter().enumerate().try_for_each(|(ix, r)| {
+                                     let sep = if ix == 0 { "" } else { "," };
+                                     write!(w, "{}{}", sep, r)
+                                   })
+}
+
+/// Is `req` a GET request for `/.well-known/core`?
+pub(super) fn is_well_known_core<P>(req: &Req<P>) -> bool
+  where P: PlatformTypes
+{
+  let path = req.msg()
+                .get(repeat::PATH)
+                .into_iter()
+                .flat_map(|segs| segs.iter())
+                .map(|seg| seg.0.as_ref())
+                .filter(|seg| !seg.is_empty());
+
+  req.msg().code == Code::GET && path.eq([b".well-known".as_ref(), b"core".as_ref()])
+}
+
+/// Respond to a request for `/.well-known/core` with a link-format
+/// document describing the `resources` matching the request's query
+pub(super) fn respond<P>(req: &Addrd<Req<P>>, resources: &[Resource<'_>]) -> Addrd<Message<P>>
+  where P: PlatformTypes
+{
+  let queries = || {
+    req.data()
+       .msg()
+       .get(repeat::QUERY)
+       .into_iter()
+       .flat_map(|qs| qs.iter())
+       .filter_map(|q| core::str::from_utf8(&q.0).ok())
+  };
+
+  let mut payload = Writable::from(P::MessagePayload::default());
+  write_links(&mut payload,
+              resources.iter().filter(|r| queries().all(|q| r.matches(q)))).ok();
+
+  let mut resp = Resp::non(req.data());
+  resp.set_code(code::CONTENT);
+  resp.msg_mut()
+      .set_content_format(toad_msg::ContentFormat::LinkFormat)
+      .ok();
+  resp.msg_mut().payload = toad_msg::Payload(payload.unwrap());
+
+  Addrd(resp.into(), req.addr())
+}
+
+#[cfg(test)]
+mod tests {
+  use toad_msg::Type;
+
+  use super::*;
+  use crate::server::{Error, Run};
+  use crate::test;
+
+  const RESOURCES: &[Resource<'static>] =
+    &[Resource::new("sensors").title("Sensor Index")
+                              .content_format(ContentFormat::LinkFormat),
+      Resource::new("sensors/temp").resource_type("temperature-c")
+                                   .interface("sensor")
+                                   .observable(),
+      Resource::new("sensors/light").resource_type("light-lux")
+                                    .interface("sensor")
+                                    .size(8)];
+
+  fn discover(path: &str, query: Option<&str>) -> Run<test::Platform, ()> {
+    let mut req = Req::<test::Platform>::get(path);
+    req.msg_mut().ty = Type::Non;
+    if let Some(q) = query {
+      req.msg_mut().add_query(q).unwrap();
+    }
+
+    Run::Unmatched(Addrd(req, test::x.x.x.x(80))).maybe_discover(RESOURCES)
+  }
+
+  fn payload(run: Run<test::Platform, ()>) -> std::string::String {
+    match run {
+      | Run::Matched(msg) => {
+        assert_eq!(msg.data().code, code::CONTENT);
+        assert_eq!(msg.data().content_format(),
+                   Some(toad_msg::ContentFormat::LinkFormat));
+        std::string::String::from_utf8(msg.data().payload.0.clone()).unwrap()
+      },
+      | other => panic!("{:?}", other),
+    }
+  }
+
+  #[test]
+  fn should_list_all_resources() {
+    assert_eq!(payload(discover(".well-known/core", None)),
+               [r#"</sensors>;ct=40;title="Sensor Index""#,
+                r#"</sensors/temp>;rt="temperature-c";if="sensor";obs"#,
+                r#"</sensors/light>;rt="light-lux";if="sensor";sz=8"#].join(","));
+  }
+
+  #[test]
+  fn should_filter_resources_by_query() {
+    assert_eq!(payload(discover(".well-known/core", Some("rt=temperature-c"))),
+               r#"</sensors/temp>;rt="temperature-c";if="sensor";obs"#);
+    assert_eq!(payload(discover(".well-known/core", Some("if=sensor"))).split(',')
+                                                                       .count(),
+               2);
+    assert_eq!(payload(discover(".well-known/core", Some("href=/sensors/l*"))),
+               r#"</sensors/light>;rt="light-lux";if="sensor";sz=8"#);
+    assert_eq!(payload(discover(".well-known/core", Some("ct=40"))),
+               r#"</sensors>;ct=40;title="Sensor Index""#);
+    assert_eq!(payload(discover(".well-known/core", Some("rt=humidity"))),
+               "");
+  }
+
+  #[test]
+  fn should_ignore_other_requests() {
+    assert!(matches!(discover("sensors", None), Run::Unmatched(_)));
+    assert!(matches!(Run::<test::Platform, ()>::Error(Error::Other(())).maybe_discover(RESOURCES),
+                     Run::Error(_)));
+  }
+}
@@ -15,7 +15,7 @@ pub fn is<P, T, E>(method: Method) -> impl Fn(Ap<Hydrated, P, T, E>) -> Ap<Hydra
 }
 
 /// Reject request if the code is not included in `methods`
-pub fn is_one_of<P, T, E>(methods: ArrayVec<[Method; 5]>)
+pub fn is_one_of<P, T, E>(methods: ArrayVec<[Method; 8]>)
                           -> impl Fn(Ap<Hydrated, P, T, E>) -> Ap<Hydrated, P, T, E>
   where P: PlatformTypes,
         E: core::fmt::Debug
@@ -82,3 +82,27 @@ pub fn delete<P, T, E>(ap: Ap<Hydrated, P, T, E>) -> Ap<Hydrated, P, T, E>
 {
   ap.pipe(is(Method::DELETE))
 }
+
+/// Reject non-FETCH requests
+pub fn fetch<P, T, E>(ap: Ap<Hydrated, P, T, E>) -> Ap<Hydrated, P, T, E>
+  where P: PlatformTypes,
+        E: core::fmt::Debug
+{
+  ap.pipe(is(Method::FETCH))
+}
+
+/// Reject non-PATCH requests
+pub fn patch<P, T, E>(ap: Ap<Hydrated, P, T, E>) -> Ap<Hydrated, P, T, E>
+  where P: PlatformTypes,
+        E: core::fmt::Debug
+{
+  ap.pipe(is(Method::PATCH))
+}
+
+/// Reject non-iPATCH requests
+pub fn ipatch<P, T, E>(ap: Ap<Hydrated, P, T, E>) -> Ap<Hydrated, P, T, E>
+  where P: PlatformTypes,
+        E: core::fmt::Debug
+{
+  ap.pipe(is(Method::IPATCH))
+}
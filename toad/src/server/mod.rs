@@ -1,12 +1,15 @@
 use core::fmt::Write;
 
 pub use ap::Ap;
-use toad_msg::MessageOptions;
+use embedded_time::Instant;
+use no_std_net::SocketAddr;
+use toad_array::Array;
+use toad_msg::{Code, Id, MessageOptions, Token, Type};
 
 use self::ap::state::{Complete, Hydrated};
 use self::ap::{ApInner, Hydrate, Respond};
 use crate::net::{Addrd, Socket};
-use crate::platform::{Message, Platform, PlatformTypes};
+use crate::platform::{Message, Platform, PlatformError, PlatformTypes};
 use crate::req::Req;
 use crate::resp::Resp;
 use crate::step::Step;
@@ -37,6 +40,18 @@ pub mod method;
 /// Respond to requests
 pub mod respond;
 
+/// Resource discovery ([RFC6690](https://www.rfc-editor.org/rfc/rfc6690))
+///
+/// Advertise resources in the CoRE Link Format at `/.well-known/core`
+/// with [`Run::maybe_discover`].
+pub mod discovery;
+
+/// Resource Directory ([RFC9176](https://www.rfc-editor.org/rfc/rfc9176))
+///
+/// Accept registrations from endpoints & let clients look up
+/// their resources with [`Run::maybe_rd`].
+pub mod rd;
+
 /// [`Run`] errors
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub enum Error<E> {
@@ -77,6 +92,8 @@ pub enum Run<P, E>
   Unmatched(Addrd<Req<P>>),
   /// Request has a response
   Matched(Addrd<Message<P>>),
+  /// Request will be responded to later with a [`Deferred`] handle
+  Deferred(Addrd<Req<P>>),
   /// An Error occurred
   Error(Error<E>),
 }
@@ -89,6 +106,7 @@ impl<P, E> PartialEq for Run<P, E>
     match (self, other) {
       | (Self::Unmatched(a), Self::Unmatched(b)) => a == b,
       | (Self::Matched(a), Self::Matched(b)) => a == b,
+      | (Self::Deferred(a), Self::Deferred(b)) => a == b,
       | (Self::Error(a), Self::Error(b)) => a == b,
       | _ => false,
     }
@@ -105,8 +123,11 @@ impl<P, E> Run<P, E>
       | ApInner::Err(e) => Self::Error(Error::Other(e)),
       | ApInner::RespondHydrated(Respond { code,
                                            payload,
-                                           etag, },
+                                           etag,
+                                           location, },
                                  Addrd(req, addr)) => {
+        // the Ack step will piggyback this on the ACK if `req` is CON,
+        // or send it CON if `req` has already been ACKed
         let mut resp = Resp::non(&req);
         resp.set_code(code);
         resp.set_payload(payload);
@@ -115,6 +136,15 @@ impl<P, E> Run<P, E>
           resp.msg_mut().add_etag(etag.as_ref()).ok();
         }
 
+        if let Some(location) = location {
+          core::str::from_utf8(location.as_ref()).unwrap_or_default()
+                                                 .split('/')
+                                                 .try_for_each(|seg| {
+                                                   resp.msg_mut().add_location_path(seg)
+                                                 })
+                                                 .ok();
+        }
+
         Self::Matched(Addrd(resp.into(), addr))
       },
       | ApInner::RejectHydrated(req) => Self::Unmatched(req),
@@ -134,10 +164,157 @@ impl<P, E> Run<P, E>
   {
     match self {
       | Run::Matched(m) => Run::Matched(m),
+      | Run::Deferred(req) => Run::Deferred(req),
       | Run::Error(e) => Run::Error(e),
       | Run::Unmatched(req) => Self::handle(f(Ap::ok_hydrated((), Hydrate::from_request(req)))),
     }
   }
+
+  /// Use a function to potentially respond to a request later
+  ///
+  /// The function is given a [`Deferred`] handle for the request, and
+  /// returns `true` if it will use the handle to respond later (e.g. by moving
+  /// it to another thread), or `false` to leave the request unmatched.
+  ///
+  /// Deferred CON requests that aren't responded to within
+  /// [`Msg.piggyback_deadline`](crate::config::Msg.piggyback_deadline)
+  /// are ACKed by the runtime (see [`step::ack`](crate::step::ack)), and the
+  /// response is then sent as a separate CON response, retried until the client ACKs it.
+  ///
+  /// ```no_run
+  /// use std::sync::Arc;
+  ///
+  /// use toad::server::{BlockingServer, Init};
+  /// use toad::step::runtime::std::Runtime;
+  ///
+  /// type Platform = toad::std::Platform<toad::std::dtls::N, Runtime<toad::std::dtls::N>>;
+  ///
+  /// let server = Arc::new(Platform::try_new("0.0.0.0:5683", Default::default()).unwrap());
+  ///
+  /// server.run(Init::none(), |run| {
+  ///         run.maybe_defer(|req, later| {
+  ///              if req.data().path() != Ok(Some("sensor")) {
+  ///                return false;
+  ///              }
+  ///
+  ///              let server = Arc::clone(&server);
+  ///              std::thread::spawn(move || {
+  ///                // consult the slow sensor bus, then:
+  ///                let reading = || "21.5".bytes().collect();
+  ///                nb::block!(later.respond(&*server, toad::resp::code::CONTENT, reading())).unwrap();
+  ///              });
+  ///              true
+  ///            })
+  ///       })
+  ///       .unwrap();
+  /// ```
+  pub fn maybe_defer<F>(self, mut f: F) -> Self
+    where F: FnMut(&Addrd<Req<P>>, Deferred) -> bool
+  {
+    match self {
+      | Run::Unmatched(req) if f(&req, Deferred::new(&req)) => Run::Deferred(req),
+      | other => other,
+    }
+  }
+
+  /// Respond to `GET /.well-known/core` with a CoRE Link Format
+  /// document listing `resources`.
+  ///
+  /// Query filters in the request (e.g. `?rt=temperature-c`) are respected;
+  /// only resources matching all of them are listed.
+  ///
+  /// ```
+  /// use toad::server::discovery::Resource;
+  /// use toad::server::{Error, Run};
+  /// use toad::std::{dtls, PlatformTypes as Std};
+  ///
+  /// let run: Run<Std<dtls::Y>, ()> = Run::Error(Error::Other(()));
+  /// run.maybe_discover(&[Resource::new("hello").resource_type("greeting"),
+  ///                      Resource::new("sensors/temp").observable()]);
+  /// ```
+  pub fn maybe_discover(self, resources: &[discovery::Resource<'_>]) -> Self {
+    match self {
+      | Run::Unmatched(req) if discovery::is_well_known_core(req.data()) => {
+        Run::Matched(discovery::respond(&req, resources))
+      },
+      | other => other,
+    }
+  }
+
+  /// Serve the Resource Directory `dir`, handling registrations
+  /// and lookups (see [`rd::Directory`]).
+  ///
+  /// Registrations whose lifetime passed before `now` are forgotten
+  /// before the request is handled.
+  pub fn maybe_rd<Regs>(self, dir: &mut rd::Directory<Regs>, now: Instant<P::Clock>) -> Self
+    where Regs: Array<Item = rd::Registration<P>>
+  {
+    if !matches!(self, Run::Unmatched(_)) {
+      return self;
+    }
+
+    dir.prune(now);
+    self.maybe(|ap| dir.register(ap, now))
+        .maybe(|ap| dir.update(ap, now))
+        .maybe(|ap| dir.remove(ap))
+        .maybe(|ap| dir.lookup_ep(ap))
+        .maybe(|ap| dir.lookup_res(ap))
+  }
+}
+
+/// A handle to a request that will be responded to later,
+/// created by [`Run::maybe_defer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deferred {
+  addr: SocketAddr,
+  token: Token,
+  ty: Type,
+}
+
+impl Deferred {
+  fn new<P>(req: &Addrd<Req<P>>) -> Self
+    where P: PlatformTypes
+  {
+    Self { addr: req.addr(),
+           token: req.data().msg().token,
+           ty: req.data().msg().ty }
+  }
+
+  /// The address of the client that sent the request
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// The token of the request
+  pub fn token(&self) -> Token {
+    self.token
+  }
+
+  /// Send the response to the request.
+  ///
+  /// Responses to NON requests are sent NON.
+  ///
+  /// Responses to CON requests are piggybacked on the ACK if the request
+  /// hasn't been ACKed yet, and are otherwise sent CON and retried by the
+  /// runtime until the client ACKs them (see [`step::ack`](crate::step::ack)).
+  pub fn respond<Pl, S>(self,
+                        platform: &Pl,
+                        code: Code,
+                        payload: <Pl::Types as PlatformTypes>::MessagePayload)
+                        -> nb::Result<(), Pl::Error>
+    where Pl: Platform<S>,
+          S: Step<Pl::Types, PollReq = Addrd<Req<Pl::Types>>, PollResp = Addrd<Resp<Pl::Types>>>
+  {
+    let ty = match self.ty {
+      | Type::Non => Type::Non,
+      | _ => Type::Con,
+    };
+
+    let mut msg = Message::<Pl::Types>::new(ty, code, Id(0), self.token);
+    msg.payload = toad_msg::Payload(payload);
+
+    platform.send_msg(Addrd(msg, self.addr)).map(|_| ())
+  }
 }
 
 /// Newtype wrapper of an initialization function
@@ -195,7 +372,20 @@ pub trait BlockingServer<S>: Sized + Platform<S>
     init.0.map(|mut f| f());
 
     loop {
-      let req = nb::block!(self.poll_req()).map_err(Error::Other)?;
+      let req = loop {
+        match self.poll_req() {
+          | Ok(req) => break req,
+          // a response we sent was never acknowledged; there's
+          // nothing left to do about it, so keep serving
+          | Err(nb::Error::Other(e)) if e.is_unacknowledged() => {
+            let mut msg = String::<1000>::default();
+            write!(&mut msg, "Gave up on an unacknowledged message: {:?}", e).ok();
+            self.log(log::Level::Warn, msg).map_err(Error::Other)?;
+          },
+          | Err(nb::Error::Other(e)) => return Err(Error::Other(e)),
+          | Err(nb::Error::WouldBlock) => self.wait(None).map_err(Error::Other)?,
+        }
+      };
       match handle_request(Run::Unmatched(req)) {
         | Run::Unmatched(req) => {
           let mut msg = String::<1000>::default();
@@ -218,6 +408,9 @@ Do you need a fallback?
         },
         | Run::Matched(rep) => nb::block!(self.send_msg(rep.clone())).map_err(Error::Other)
                                                                      .map(|_| ())?,
+        // the Ack step will send an empty ACK if `req` is CON
+        // and isn't responded to within the piggyback deadline
+        | Run::Deferred(_) => (),
         | Run::Error(e) => break Err(e),
       }
     }
@@ -245,4 +438,165 @@ mod tests {
               });
     }
   }
+
+  mod deferred {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use toad_msg::Type;
+
+    use crate::client::BlockingClient;
+    use crate::net::{Addrd, Socket};
+    use crate::platform::Platform as _;
+    use crate::req::Req;
+    use crate::resp::code;
+    use crate::server::{BlockingServer, Init};
+    use crate::std::{dtls, PlatformTypes as Std};
+    use crate::step::runtime::std::Runtime;
+
+    type Platform = crate::std::Platform<dtls::N, Runtime<dtls::N>>;
+
+    #[test]
+    fn respond_should_send_response_from_another_thread() {
+      let server = Arc::new(Platform::try_new("127.0.0.1:0", Default::default()).unwrap());
+      let client = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+      let server_addr = Socket::local_addr(server.socket());
+
+      let piggyback_deadline = server.config().msg.piggyback_deadline.0;
+
+      std::thread::spawn({
+        let server = Arc::clone(&server);
+        move || {
+          server.run(Init::none(), |run| {
+                  run.maybe_defer(|req, later| {
+                       let server = Arc::clone(&server);
+                       let delay = match req.data().path() {
+                         | Ok(Some("late")) => piggyback_deadline * 2,
+                         | _ => 0,
+                       };
+
+                       std::thread::spawn(move || {
+                         std::thread::sleep(Duration::from_millis(delay));
+                         nb::block!(later.respond(&*server,
+                                                  code::CONTENT,
+                                                  "hi!".bytes().collect())).unwrap();
+                       });
+                       true
+                     })
+                })
+                .unwrap();
+        }
+      });
+
+      let get = |path: &str, ty: Type| {
+        let mut req = Req::<Std<dtls::N>>::get(path);
+        req.msg_mut().ty = ty;
+
+        let resp = client.send_req(Addrd(req, server_addr)).unwrap();
+        assert_eq!(resp.data().payload_string(), Ok("hi!".to_string()));
+        resp.data().as_ref().ty
+      };
+
+      assert_eq!(get("now", Type::Con), Type::Ack);
+      assert_eq!(get("late", Type::Con), Type::Con);
+      assert_eq!(get("now", Type::Non), Type::Non);
+    }
+  }
+
+  mod unacknowledged {
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use embedded_time::duration::Milliseconds;
+    use toad_msg::{Id, Token, TryFromBytes, TryIntoBytes, Type};
+
+    use crate::config::Config;
+    use crate::net::Socket;
+    use crate::platform::{Message, Platform as _};
+    use crate::req::Req;
+    use crate::resp::code;
+    use crate::retry::{Attempts, Strategy};
+    use crate::server::{BlockingServer, Init};
+    use crate::std::{dtls, PlatformTypes as Std};
+    use crate::step::runtime::std::Runtime;
+
+    type Platform = crate::std::Platform<dtls::N, Runtime<dtls::N>>;
+
+    #[test]
+    fn run_should_keep_serving_after_a_response_goes_unacknowledged() {
+      let mut config = Config::default();
+      config.msg.piggyback_deadline = Milliseconds(10);
+      config.msg.con.unacked_retry_strategy = Strategy::Delay { min: Milliseconds(10),
+                                                                max: Milliseconds(10) };
+      config.msg.con.max_attempts = Attempts(2);
+
+      let server = Arc::new(Platform::try_new("127.0.0.1:0", config).unwrap());
+      let server_addr = Socket::local_addr(server.socket());
+
+      std::thread::spawn({
+        let server = Arc::clone(&server);
+        move || {
+          server.run(Init::none(), |run| {
+                  run.maybe_defer(|req, later| {
+                       let server = Arc::clone(&server);
+                       let delay = match req.data().path() {
+                         | Ok(Some("late")) => 20,
+                         | _ => 0,
+                       };
+
+                       std::thread::spawn(move || {
+                         std::thread::sleep(Duration::from_millis(delay));
+                         nb::block!(later.respond(&*server,
+                                                  code::CONTENT,
+                                                  "hi!".bytes().collect())).unwrap();
+                       });
+                       true
+                     })
+                })
+                .unwrap();
+        }
+      });
+
+      // a peer that never ACKs the CON responses it receives
+      let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+      peer.set_read_timeout(Some(Duration::from_millis(100)))
+          .unwrap();
+
+      let send = |path: &str, ty: Type, token: u8| {
+        let mut msg = Message::<Std<dtls::N>>::from(Req::<Std<dtls::N>>::get(path));
+        msg.ty = ty;
+        msg.id = Id(token as u16);
+        msg.token = Token::new(&[token]).unwrap();
+
+        let bytes = msg.try_into_bytes::<Vec<u8>>().unwrap();
+        peer.send_to(&bytes, server_addr.to_string()).unwrap();
+      };
+
+      let recv_until = |deadline: Instant| {
+        let mut recvd = vec![];
+        let mut buf = [0u8; 1152];
+        while Instant::now() < deadline {
+          if let Ok(n) = peer.recv(&mut buf) {
+            recvd.push(Message::<Std<dtls::N>>::try_from_bytes(&buf[..n]).unwrap());
+          }
+        }
+        recvd
+      };
+
+      send("late", Type::Con, 1);
+      let recvd = recv_until(Instant::now() + Duration::from_millis(500));
+      assert!(recvd.iter()
+                   .any(|m| m.ty == Type::Con && m.token == Token::new(&[1]).unwrap()));
+
+      // the server may notice that it gave up on the response
+      // only after handling the next request
+      for token in [2, 3] {
+        send("now", Type::Non, token);
+        let recvd = recv_until(Instant::now() + Duration::from_millis(500));
+        assert!(recvd.iter()
+                     .any(|m| m.ty == Type::Non && m.token == Token::new(&[token]).unwrap()));
+      }
+    }
+  }
 }
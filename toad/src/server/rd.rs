@@ -0,0 +1,651 @@
+use core::fmt::{self, Write};
+
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use toad_array::{AppendCopy, Array};
+use toad_msg::link_format::{self, attr, Attr, Link};
+use toad_msg::opt::known::repeat;
+use toad_msg::MessageOptions;
+use toad_writable::Writable;
+
+use super::ap::state::{Complete, CompleteWhenHydrated, Hydrated};
+use super::discovery::Resource;
+use super::{method, path, respond, Ap};
+use crate::client::rd::DEFAULT_LIFETIME_SECONDS;
+use crate::net::Addrd;
+use crate::platform::PlatformTypes;
+use crate::req::Req;
+use crate::resp::code;
+use crate::todo::String;
+use crate::ContentFormat;
+
+/// The interfaces of the Resource Directory, to be advertised at
+/// `/.well-known/core` with [`Run::maybe_discover`](super::Run::maybe_discover)
+pub const RESOURCES: &[Resource<'static>] =
+  &[Resource::new("rd").resource_type("core.rd")
+                       .content_format(ContentFormat::LinkFormat),
+    Resource::new("rd-lookup/ep").resource_type("core.rd-lookup-ep")
+                                 .content_format(ContentFormat::LinkFormat),
+    Resource::new("rd-lookup/res").resource_type("core.rd-lookup-res")
+                                  .content_format(ContentFormat::LinkFormat)];
+
+/// An endpoint registered with the [`Directory`]
+pub struct Registration<P>
+  where P: PlatformTypes
+{
+  id: u32,
+  ep: String<64>,
+  d: Option<String<64>>,
+  base: String<64>,
+  lt: u32,
+  links: P::MessagePayload,
+  updated_at: Instant<P::Clock>,
+}
+
+impl<P> core::fmt::Debug for Registration<P> where P: PlatformTypes
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Registration")
+     .field("id", &self.id)
+     .field("ep", &self.ep)
+     .field("d", &self.d)
+     .field("base", &self.base)
+     .field("lt", &self.lt)
+     .field("links", &self.links)
+     .field("updated_at", &self.updated_at)
+     .finish()
+  }
+}
+
+impl<P> Registration<P> where P: PlatformTypes
+{
+  /// The path of the registration resource, e.g. `rd/4`
+  pub fn location(&self) -> String<16> {
+    String::fmt(format_args!("rd/{}", self.id))
+  }
+
+  /// The endpoint name (`ep`)
+  pub fn endpoint(&self) -> &str {
+    self.ep.as_str()
+  }
+
+  /// The sector (`d`) the endpoint registered in
+  pub fn sector(&self) -> Option<&str> {
+    self.d.as_ref().map(|d| d.as_str())
+  }
+
+  /// The base URI (`base`) that the endpoint's links are relative to
+  ///
+  /// Defaults to `coap://` followed by the address the registration was sent from.
+  pub fn base(&self) -> &str {
+    self.base.as_str()
+  }
+
+  /// The lifetime of the registration (`lt`), in seconds
+  pub fn lifetime_seconds(&self) -> u32 {
+    self.lt
+  }
+
+  /// The link-format document describing the endpoint's resources
+  pub fn links(&self) -> &str {
+    core::str::from_utf8(&self.links).unwrap_or_default()
+  }
+
+  fn is_expired(&self, now: Instant<P::Clock>) -> bool {
+    now.checked_duration_since(&self.updated_at)
+       .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+       .map(|Milliseconds(ms)| ms >= self.lt as u64 * 1000)
+       .unwrap_or(false)
+  }
+
+  /// Does this endpoint satisfy the endpoint lookup filter `query`?
+  ///
+  /// Filters on attributes other than the endpoint's match
+  /// endpoints with at least one resource satisfying the filter.
+  fn matches(&self, query: &str) -> bool {
+    let (name, value) = query.split_once('=').unwrap_or((query, ""));
+    match name {
+      | attr::HREF => {
+        Attr::new(name, self.location().as_str()).matches(value.trim_start_matches('/'))
+      },
+      | _ => self.attr_matches(query).unwrap_or_else(|| {
+                                       link_format::parse(self.links()).filter_map(Result::ok)
+                                                                       .any(|l| l.matches(query))
+                                     }),
+    }
+  }
+
+  /// Does this endpoint satisfy the filter `query`?
+  ///
+  /// `None` if `query` does not filter on an endpoint attribute.
+  fn attr_matches(&self, query: &str) -> Option<bool> {
+    let (name, value) = query.split_once('=').unwrap_or((query, ""));
+    let matches = |v: &str| Attr::new(name, v).matches(value);
+
+    match name {
+      | "ep" => Some(matches(self.endpoint())),
+      | "d" => Some(self.sector().map(matches).unwrap_or(false)),
+      | "base" => Some(matches(self.base())),
+      | "lt" => Some(matches(String::<16>::fmt(format_args!("{}", self.lt)).as_str())),
+      | _ => None,
+    }
+  }
+
+  /// Write the endpoint as a link for `/rd-lookup/ep`
+  fn write_ep<W>(&self, w: &mut W) -> fmt::Result
+    where W: Write
+  {
+    write!(w,
+           "</{}>;ep=\"{}\"",
+           self.location().as_str(),
+           self.endpoint())?;
+    if let Some(d) = self.sector() {
+      write!(w, ";d=\"{}\"", d)?;
+    }
+    write!(w, ";base=\"{}\";lt={}", self.base(), self.lt)
+  }
+
+  /// Write a link to one of the endpoint's resources for `/rd-lookup/res`,
+  /// resolving its target against the endpoint's base URI
+  fn write_res<W>(&self, w: &mut W, link: Link<'_>) -> fmt::Result
+    where W: Write
+  {
+    if link.target().contains("://") {
+      return write!(w, "{}", link);
+    }
+
+    write!(w,
+           "<{}/{}>",
+           self.base(),
+           link.target().trim_start_matches('/'))?;
+    link.attrs().try_for_each(|a| write!(w, ";{}", a))?;
+    if link.attr(attr::ANCHOR).is_none() {
+      write!(w, ";anchor=\"{}\"", self.base())?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Registration parameters sent in the query of a registration
+/// or registration update request
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Params<'a> {
+  ep: Option<&'a str>,
+  d: Option<&'a str>,
+  base: Option<&'a str>,
+  lt: Option<u32>,
+}
+
+impl<'a> Params<'a> {
+  /// Parse the query of `req`, yielding `None` if any parameter is invalid
+  fn parse<P>(req: &'a Req<P>) -> Option<Self>
+    where P: PlatformTypes
+  {
+    fn fits(s: &str) -> Option<&str> {
+      Some(s).filter(|s| !s.is_empty() && s.len() <= 64)
+    }
+
+    queries(req).try_fold(Self::default(), |mut params, q| {
+                  match q.split_once('=') {
+                    | Some(("ep", ep)) => params.ep = Some(fits(ep)?),
+                    | Some(("d", d)) => params.d = Some(fits(d)?),
+                    | Some(("base", base)) => params.base = Some(fits(base)?),
+                    | Some(("lt", lt)) => params.lt = Some(lt.parse().ok().filter(|lt| *lt > 0)?),
+                    | _ => (),
+                  };
+                  Some(params)
+                })
+  }
+}
+
+fn queries<P>(req: &Req<P>) -> impl Iterator<Item = &str>
+  where P: PlatformTypes
+{
+  req.msg()
+     .get(repeat::QUERY)
+     .into_iter()
+     .flat_map(|qs| qs.iter())
+     .filter_map(|q| core::str::from_utf8(&q.0).ok())
+}
+
+/// A Resource Directory ([RFC9176](https://www.rfc-editor.org/rfc/rfc9176))
+///
+/// Serve it with [`Run::maybe_rd`](super::Run::maybe_rd), which handles:
+/// * `POST /rd?ep=..` - register an endpoint, responding with the location of the registration
+/// * `POST /rd/{id}` - refresh or update a registration
+/// * `DELETE /rd/{id}` - remove a registration
+/// * `GET /rd-lookup/ep` - list registered endpoints
+/// * `GET /rd-lookup/res` - list registered resources
+///
+/// Registrations are stored in `Regs`, and forgotten once their lifetime
+/// passes without being refreshed.
+///
+/// ```no_run
+/// use embedded_time::Clock;
+/// use toad::config::Config;
+/// use toad::platform::Platform;
+/// use toad::server::{rd, BlockingServer, Init};
+/// use toad::std::{dtls, PlatformTypes as Std};
+/// use toad::step::runtime::std::Runtime;
+///
+/// let server = toad::std::Platform::<dtls::N, Runtime<dtls::N>>::try_new("0.0.0.0:5683",
+///                                                                        Config::default()).unwrap();
+/// let mut dir = rd::Directory::<Vec<rd::Registration<Std<dtls::N>>>>::default();
+///
+/// server.run(Init::none(), |run| {
+///         let now = server.clock().try_now().unwrap();
+///         run.maybe_discover(rd::RESOURCES).maybe_rd(&mut dir, now)
+///       })
+///       .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Directory<Regs> {
+  regs: Regs,
+  next_id: u32,
+}
+
+impl<Regs> Default for Directory<Regs> where Regs: Default
+{
+  fn default() -> Self {
+    Self { regs: Regs::default(),
+           next_id: 0 }
+  }
+}
+
+impl<P, Regs> Directory<Regs>
+  where P: PlatformTypes,
+        Regs: Array<Item = Registration<P>>
+{
+  /// The current registrations, including any that have
+  /// expired since the last request was handled
+  pub fn registrations(&self) -> impl Iterator<Item = &Registration<P>> {
+    self.regs.iter()
+  }
+
+  /// Forget registrations whose lifetime has passed
+  pub fn prune(&mut self, now: Instant<P::Clock>) {
+    while let Some(ix) = self.regs.iter().position(|r| r.is_expired(now)) {
+      self.regs.remove(ix);
+    }
+  }
+
+  fn find(&mut self, id: u32) -> Option<&mut Registration<P>> {
+    self.regs.iter_mut().find(|r| r.id == id)
+  }
+
+  /// `POST /rd?ep=..`
+  pub(super) fn register<E>(&mut self,
+                            ap: Ap<Hydrated, P, (), E>,
+                            now: Instant<P::Clock>)
+                            -> Ap<Complete, P, (), E>
+    where E: core::fmt::Debug
+  {
+    ap.pipe(method::post)
+      .pipe(path::check::rest_equals("rd"))
+      .bind_hydrated(|(), req| self.registered(req, now))
+  }
+
+  fn registered<E>(&mut self,
+                   req: &Addrd<Req<P>>,
+                   now: Instant<P::Clock>)
+                   -> Ap<CompleteWhenHydrated, P, (), E>
+    where E: core::fmt::Debug
+  {
+    let (params, links) = match (Params::parse(req.data()), links(req.data())) {
+      | (Some(params @ Params { ep: Some(_), .. }), Ok(links)) => (params, links),
+      | (_, Err(code)) => return respond::respond(code, Default::default()),
+      | _ => return respond::respond(code::BAD_REQUEST, Default::default()),
+    };
+
+    let ep = String::from(params.ep.unwrap_or_default());
+    let d = params.d.map(String::from);
+    let base = params.base
+                     .map(String::from)
+                     .unwrap_or_else(|| String::fmt(format_args!("coap://{}", req.addr())));
+    let lt = params.lt.unwrap_or(DEFAULT_LIFETIME_SECONDS);
+
+    // registering again with the same endpoint name & sector replaces the old registration
+    let id = match self.regs.iter().position(|r| r.ep == ep && r.d == d) {
+      | Some(ix) => self.regs.remove(ix).map(|r| r.id).unwrap_or_default(),
+      | None if self.regs.is_full() => {
+        return respond::respond(code::SERVICE_UNAVAILABLE, Default::default())
+      },
+      | None => {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.next_id
+      },
+    };
+
+    let reg = Registration { id,
+                             ep,
+                             d,
+                             base,
+                             lt,
+                             links,
+                             updated_at: now };
+    let location = reg.location();
+    self.regs.append(reg);
+
+    respond::created(Default::default()).location(location)
+  }
+
+  /// `POST /rd/{id}`
+  pub(super) fn update<E>(&mut self,
+                          ap: Ap<Hydrated, P, (), E>,
+                          now: Instant<P::Clock>)
+                          -> Ap<Complete, P, (), E>
+    where E: core::fmt::Debug
+  {
+    ap.pipe(method::post)
+      .pipe(path::segment::check::next_equals("rd"))
+      .pipe(path::segment::param::u32)
+      .pipe(path::check::rest_equals(""))
+      .bind_hydrated(|((), id), req| self.updated(id, req, now))
+  }
+
+  fn updated<E>(&mut self,
+                id: u32,
+                req: &Addrd<Req<P>>,
+                now: Instant<P::Clock>)
+                -> Ap<CompleteWhenHydrated, P, (), E>
+    where E: core::fmt::Debug
+  {
+    let (params, links) = match (Params::parse(req.data()), links(req.data())) {
+      | (Some(params), Ok(links)) => (params, links),
+      | (_, Err(code)) => return respond::respond(code, Default::default()),
+      | (None, _) => return respond::respond(code::BAD_REQUEST, Default::default()),
+    };
+
+    let reg = match self.find(id) {
+      | Some(reg) => reg,
+      | None => return respond::not_found(Default::default()),
+    };
+
+    reg.updated_at = now;
+    reg.lt = params.lt.unwrap_or(reg.lt);
+    if let Some(base) = params.base {
+      reg.base = String::from(base);
+    }
+    if !links.is_empty() {
+      reg.links = links;
+    }
+
+    respond::respond(code::CHANGED, Default::default())
+  }
+
+  /// `DELETE /rd/{id}`
+  pub(super) fn remove<E>(&mut self, ap: Ap<Hydrated, P, (), E>) -> Ap<Complete, P, (), E>
+    where E: core::fmt::Debug
+  {
+    ap.pipe(method::delete)
+      .pipe(path::segment::check::next_equals("rd"))
+      .pipe(path::segment::param::u32)
+      .pipe(path::check::rest_equals(""))
+      .bind(|((), id)| match self.regs.iter().position(|r| r.id == id) {
+        | Some(ix) => {
+          self.regs.remove(ix);
+          respond::respond(code::DELETED, Default::default())
+        },
+        | None => respond::not_found(Default::default()),
+      })
+  }
+
+  /// `GET /rd-lookup/ep`
+  pub(super) fn lookup_ep<E>(&self, ap: Ap<Hydrated, P, (), E>) -> Ap<Complete, P, (), E>
+    where E: core::fmt::Debug
+  {
+    ap.pipe(method::get)
+      .pipe(path::check::rest_equals("rd-lookup/ep"))
+      .bind_hydrated(|(), req| {
+        let mut payload = Writable::from(P::MessagePayload::default());
+        self.regs
+            .iter()
+            .filter(|r| queries(req.data()).all(|q| r.matches(q)))
+            .enumerate()
+            .try_for_each(|(ix, r)| {
+              payload.write_str(if ix == 0 { "" } else { "," })?;
+              r.write_ep(&mut payload)
+            })
+            .ok();
+
+        respond::ok(payload.unwrap())
+      })
+  }
+
+  /// `GET /rd-lookup/res`
+  pub(super) fn lookup_res<E>(&self, ap: Ap<Hydrated, P, (), E>) -> Ap<Complete, P, (), E>
+    where E: core::fmt::Debug
+  {
+    ap.pipe(method::get)
+      .pipe(path::check::rest_equals("rd-lookup/res"))
+      .bind_hydrated(|(), req| {
+        let link_matches = |r: &Registration<P>, link: &Link<'_>| {
+          queries(req.data()).all(|q| r.attr_matches(q).unwrap_or_else(|| link.matches(q)))
+        };
+
+        let mut payload = Writable::from(P::MessagePayload::default());
+        self.regs
+            .iter()
+            .flat_map(|r| {
+              link_format::parse(r.links()).filter_map(Result::ok)
+                                           .map(move |l| (r, l))
+            })
+            .filter(|(r, link)| link_matches(r, link))
+            .enumerate()
+            .try_for_each(|(ix, (r, link))| {
+              payload.write_str(if ix == 0 { "" } else { "," })?;
+              r.write_res(&mut payload, link)
+            })
+            .ok();
+
+        respond::ok(payload.unwrap())
+      })
+  }
+}
+
+/// Copy the link-format payload of a registration request,
+/// yielding the code to reject the request with if it isn't link-format.
+fn links<P>(req: &Req<P>) -> Result<P::MessagePayload, toad_msg::Code>
+  where P: PlatformTypes
+{
+  match req.msg().content_format() {
+    | None | Some(toad_msg::ContentFormat::LinkFormat) => (),
+    | Some(_) => return Err(code::UNSUPPORTED_CONTENT_FORMAT),
+  }
+
+  req.payload_str().map_err(|_| code::BAD_REQUEST)?;
+
+  let mut links = P::MessagePayload::default();
+  links.append_copy(req.payload());
+  Ok(links)
+}
+
+#[cfg(test)]
+mod tests {
+  use toad_msg::Type;
+
+  use super::*;
+  use crate::req::Method;
+  use crate::server::{Error, Run};
+  use crate::test::{self, ClockMock};
+
+  type Dir = Directory<Vec<Registration<test::Platform>>>;
+
+  fn req(method: Method,
+         path: &str,
+         queries: &[&str],
+         payload: &str)
+         -> Addrd<Req<test::Platform>> {
+    let mut req = Req::<test::Platform>::new(method, path);
+    req.msg_mut().ty = Type::Non;
+    queries.iter()
+           .for_each(|q| req.msg_mut().add_query(*q).unwrap());
+    if !payload.is_empty() {
+      req.msg_mut()
+         .set_content_format(toad_msg::ContentFormat::LinkFormat)
+         .unwrap();
+      req.set_payload(payload);
+    }
+    Addrd(req, test::x.x.x.x(5683))
+  }
+
+  fn run(dir: &mut Dir,
+         req: Addrd<Req<test::Platform>>,
+         now_secs: u64)
+         -> (toad_msg::Code, String<64>, std::string::String) {
+    match Run::<test::Platform, ()>::Unmatched(req).maybe_rd(dir,
+                                                             ClockMock::instant(now_secs
+                                                                                * 1_000_000))
+    {
+      | Run::Matched(msg) => {
+        let mut location = String::<64>::default();
+        msg.data()
+           .get(repeat::LOCATION_PATH)
+           .into_iter()
+           .flat_map(|segs| segs.iter())
+           .enumerate()
+           .for_each(|(ix, seg)| {
+             write!(location,
+                    "{}{}",
+                    if ix == 0 { "" } else { "/" },
+                    core::str::from_utf8(&seg.0).unwrap()).ok();
+           });
+
+        (msg.data().code,
+         location,
+         std::string::String::from_utf8(msg.data().payload.0.clone()).unwrap())
+      },
+      | other => panic!("{:?}", other),
+    }
+  }
+
+  fn register(dir: &mut Dir, ep: &str, links: &str, now_secs: u64) -> String<64> {
+    let (code, location, _) = run(dir,
+                                  req(Method::POST,
+                                      "rd",
+                                      &[&format!("ep={}", ep), "lt=60", "d=warehouse"],
+                                      links),
+                                  now_secs);
+    assert_eq!(code, code::CREATED);
+    location
+  }
+
+  #[test]
+  fn should_register_and_expire_endpoints() {
+    let mut dir = Dir::default();
+    let location = register(&mut dir, "node-1", r#"</temp>;rt="temperature-c""#, 0);
+    assert_eq!(location.as_str(), "rd/1");
+
+    let reg = dir.registrations().next().unwrap();
+    assert_eq!(reg.endpoint(), "node-1");
+    assert_eq!(reg.sector(), Some("warehouse"));
+    assert_eq!(reg.base(), "coap://192.168.0.1:5683");
+    assert_eq!(reg.lifetime_seconds(), 60);
+
+    // registering again replaces the registration
+    let location = register(&mut dir, "node-1", r#"</light>"#, 10);
+    assert_eq!(location.as_str(), "rd/1");
+    assert_eq!(dir.registrations().count(), 1);
+
+    // refreshing resets the lifetime
+    let (code, _, _) = run(&mut dir, req(Method::POST, "rd/1", &[], ""), 50);
+    assert_eq!(code, code::CHANGED);
+    assert_eq!(dir.registrations().next().unwrap().links(), "</light>");
+
+    let (_, _, eps) = run(&mut dir, req(Method::GET, "rd-lookup/ep", &[], ""), 109);
+    assert_eq!(eps.split(',').count(), 1);
+
+    let (_, _, eps) = run(&mut dir, req(Method::GET, "rd-lookup/ep", &[], ""), 110);
+    assert_eq!(eps, "");
+    assert_eq!(run(&mut dir, req(Method::POST, "rd/1", &[], ""), 110).0,
+               code::NOT_FOUND);
+  }
+
+  #[test]
+  fn should_reject_invalid_registrations() {
+    let mut dir = Dir::default();
+    assert_eq!(run(&mut dir, req(Method::POST, "rd", &[], "</a>"), 0).0,
+               code::BAD_REQUEST);
+    assert_eq!(run(&mut dir,
+                   req(Method::POST, "rd", &["ep=a", "lt=soon"], "</a>"),
+                   0).0,
+               code::BAD_REQUEST);
+
+    let mut json = req(Method::POST, "rd", &["ep=a"], "{}");
+    json.as_mut()
+        .msg_mut()
+        .set_content_format(toad_msg::ContentFormat::Json)
+        .unwrap();
+    assert_eq!(run(&mut dir, json, 0).0, code::UNSUPPORTED_CONTENT_FORMAT);
+    assert_eq!(dir.registrations().count(), 0);
+  }
+
+  #[test]
+  fn should_remove_registrations() {
+    let mut dir = Dir::default();
+    register(&mut dir, "node-1", "</a>", 0);
+
+    assert_eq!(run(&mut dir, req(Method::DELETE, "rd/1", &[], ""), 0).0,
+               code::DELETED);
+    assert_eq!(run(&mut dir, req(Method::DELETE, "rd/1", &[], ""), 0).0,
+               code::NOT_FOUND);
+    assert_eq!(dir.registrations().count(), 0);
+  }
+
+  #[test]
+  fn should_lookup_endpoints() {
+    let mut dir = Dir::default();
+    register(&mut dir, "node-1", r#"</temp>;rt="temperature-c""#, 0);
+    register(&mut dir, "node-2", r#"</light>;rt="light-lux""#, 0);
+
+    let lookup = |dir: &mut Dir, q: &[&str]| run(dir, req(Method::GET, "rd-lookup/ep", q, ""), 0).2;
+
+    assert_eq!(lookup(&mut dir, &["ep=node-1"]),
+               r#"</rd/1>;ep="node-1";d="warehouse";base="coap://192.168.0.1:5683";lt=60"#);
+    assert_eq!(lookup(&mut dir, &["ep=node*"]).split(',').count(), 2);
+    assert_eq!(lookup(&mut dir, &["rt=light-lux"]),
+               r#"</rd/2>;ep="node-2";d="warehouse";base="coap://192.168.0.1:5683";lt=60"#);
+    assert_eq!(lookup(&mut dir, &["d=garage"]), "");
+  }
+
+  #[test]
+  fn should_lookup_resources() {
+    let mut dir = Dir::default();
+    register(&mut dir,
+             "node-1",
+             r#"</temp>;rt="temperature-c";obs,</light>;rt="light-lux""#,
+             0);
+    register(&mut dir, "node-2", r#"</temp>;rt="temperature-f""#, 0);
+
+    let lookup =
+      |dir: &mut Dir, q: &[&str]| run(dir, req(Method::GET, "rd-lookup/res", q, ""), 0).2;
+
+    assert_eq!(lookup(&mut dir, &["rt=light-lux"]),
+               r#"<coap://192.168.0.1:5683/light>;rt="light-lux";anchor="coap://192.168.0.1:5683""#);
+    assert_eq!(lookup(&mut dir, &["rt=temperature*"]).split(',').count(), 2);
+    assert_eq!(lookup(&mut dir, &["rt=temperature*", "ep=node-2"]),
+               r#"<coap://192.168.0.1:5683/temp>;rt="temperature-f";anchor="coap://192.168.0.1:5683""#);
+    assert_eq!(lookup(&mut dir, &["obs"]).split(',').count(), 1);
+    assert_eq!(lookup(&mut dir, &[]).split(',').count(), 3);
+  }
+
+  #[test]
+  fn should_ignore_other_requests() {
+    let mut dir = Dir::default();
+    let now = ClockMock::instant(0);
+
+    assert!(matches!(
+      Run::<test::Platform, ()>::Unmatched(req(Method::GET, "rd", &[], "")).maybe_rd(&mut dir, now),
+      Run::Unmatched(_)
+    ));
+    assert!(matches!(
+      Run::<test::Platform, ()>::Unmatched(req(Method::POST, "rd/1/2", &[], "")).maybe_rd(
+        &mut dir, now
+      ),
+      Run::Unmatched(_)
+    ));
+    assert!(matches!(Run::<test::Platform, ()>::Error(Error::Other(())).maybe_rd(&mut dir, now),
+                     Run::Error(_)));
+  }
+}
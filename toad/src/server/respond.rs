@@ -11,7 +11,19 @@ pub fn respond<P, E>(code: Code, payload: P::MessagePayload) -> Ap<CompleteWhenH
 {
   Ap::respond(Respond { code,
                         payload,
-                        etag: None })
+                        etag: None,
+                        location: None })
+}
+
+/// [`respond`] with 2.01 CREATED
+///
+/// Use [`Ap::location`] to tell the client where the
+/// new resource can be found.
+pub fn created<P, E>(payload: P::MessagePayload) -> Ap<CompleteWhenHydrated, P, (), E>
+  where P: PlatformTypes,
+        E: core::fmt::Debug
+{
+  respond(crate::resp::code::CREATED, payload)
 }
 
 /// [`respond`] with 2.05 CONTENT
@@ -4,6 +4,12 @@ use embedded_time::rate::Fraction;
 
 /// Networking! woohoo!
 pub mod net;
+
+/// [`tokio`](::tokio)-backed sockets
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
 use core::marker::PhantomData;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
@@ -56,6 +62,30 @@ pub mod dtls {
   }
 }
 
+/// Serve CoAP over a transport other than UDP with types
+///
+/// These can be used anywhere the [`dtls`] markers can.
+pub mod transport {
+  use super::dtls::sealed::Security;
+  use super::{TcpSocket, WsSocket};
+
+  /// ZST marker for serving CoAP over TCP (RFC8323) instead of UDP
+  #[derive(Debug, Clone, Copy)]
+  pub struct Tcp;
+
+  /// ZST marker for serving CoAP over WebSockets (RFC8323) instead of UDP
+  #[derive(Debug, Clone, Copy)]
+  pub struct Ws;
+
+  impl Security for Tcp {
+    type Socket = TcpSocket;
+  }
+
+  impl Security for Ws {
+    type Socket = WsSocket;
+  }
+}
+
 /// implementor of [`crate::platform::PlatformTypes`] for
 /// platforms that support `std`.
 #[derive(Clone, Copy, Debug)]
@@ -72,8 +102,31 @@ impl<Sec> crate::platform::PlatformTypes for PlatformTypes<Sec> where Sec: Secur
   type Effects = Vec<Effect<Self>>;
 }
 
+/// Step error that gave up retrying a message, kept recognizable by
+/// [`PlatformError::is_timeout`] & [`PlatformError::is_unacknowledged`]
+/// after being converted to an [`io::Error`]
+#[derive(Debug)]
+enum RetriesExhausted {
+  Timeout(std::string::String),
+  Unacknowledged(std::string::String),
+}
+
+impl std::fmt::Display for RetriesExhausted {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      | Self::Timeout(e) | Self::Unacknowledged(e) => f.write_str(e),
+    }
+  }
+}
+
+impl std::error::Error for RetriesExhausted {}
+
+fn retries_exhausted(e: &io::Error) -> Option<&RetriesExhausted> {
+  e.get_ref().and_then(|e| e.downcast_ref())
+}
+
 impl<StepError, SocketError> PlatformError<StepError, SocketError> for io::Error
-  where StepError: Debug,
+  where StepError: crate::step::Error,
         SocketError: Debug
 {
   fn msg_to_bytes(e: toad_msg::to_bytes::MessageToBytesError) -> Self {
@@ -81,7 +134,15 @@ impl<StepError, SocketError> PlatformError<StepError, SocketError> for io::Error
   }
 
   fn step(e: StepError) -> Self {
-    io::Error::new(io::ErrorKind::Other, format!("{:?}", e))
+    let msg = format!("{:?}", e);
+    if e.is_timeout() {
+      io::Error::new(io::ErrorKind::TimedOut, RetriesExhausted::Timeout(msg))
+    } else if e.is_unacknowledged() {
+      io::Error::new(io::ErrorKind::TimedOut,
+                     RetriesExhausted::Unacknowledged(msg))
+    } else {
+      io::Error::new(io::ErrorKind::Other, msg)
+    }
   }
 
   fn socket(e: SocketError) -> Self {
@@ -91,6 +152,15 @@ impl<StepError, SocketError> PlatformError<StepError, SocketError> for io::Error
   fn clock(e: embedded_time::clock::Error) -> Self {
     io::Error::new(io::ErrorKind::Other, format!("{:?}", e))
   }
+
+  fn is_timeout(&self) -> bool {
+    matches!(retries_exhausted(self), Some(RetriesExhausted::Timeout(_)))
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    matches!(retries_exhausted(self),
+             Some(RetriesExhausted::Unacknowledged(_)))
+  }
 }
 
 /// implementor of [`crate::platform::Platform`] for `std`
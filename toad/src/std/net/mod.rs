@@ -1,20 +1,113 @@
-use std::io;
-use std::net::UdpSocket;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::sync::Mutex;
+use std::time::Duration;
 
 use naan::prelude::{Monad, MonadOnce};
 use tinyvec::ArrayVec;
 
-use crate::net::{Addrd, Socket};
+use crate::net::{Addrd, Socket, StreamSocket};
+use crate::time::Millis;
 
 pub(super) mod convert;
 
+#[cfg(unix)]
+pub(crate) mod sys;
+
 /// [`UdpSocket`] secured by DTLS
 pub mod secure;
 pub use secure::{Error as SecureSocketError, SecureUdpSocket};
 
+/// CoAP over TCP
+pub mod tcp;
+pub use tcp::TcpSocket;
+
+/// CoAP over WebSockets
+pub mod ws;
+pub use ws::WsSocket;
+
+/// Messages received by a connection-oriented socket, converted to
+/// datagrams & waiting to be received by the runtime
+#[derive(Debug, Default)]
+struct Inbox(Mutex<VecDeque<Addrd<Vec<u8>>>>);
+
+impl Inbox {
+  fn is_empty(&self) -> bool {
+    self.0.lock().unwrap().is_empty()
+  }
+
+  fn push(&self, dgram: Addrd<Vec<u8>>) {
+    self.0.lock().unwrap().push_back(dgram);
+  }
+
+  fn extend(&self, dgrams: impl IntoIterator<Item = Addrd<Vec<u8>>>) {
+    self.0.lock().unwrap().extend(dgrams);
+  }
+
+  /// Copy the datagram at the front of the inbox into `buffer`,
+  /// removing it if `pop`.
+  ///
+  /// Datagrams are never truncated when popped; if `buffer` is too small
+  /// the datagram is discarded and an error yielded instead.
+  fn take(&self, buffer: &mut [u8], pop: bool) -> nb::Result<Addrd<usize>, io::Error> {
+    let mut inbox = self.0.lock().unwrap();
+    let (n, addr) = match inbox.front() {
+      | Some(Addrd(dgram, addr)) => (dgram.len(), *addr),
+      | None => return Err(nb::Error::WouldBlock),
+    };
+
+    if !pop {
+      let fits = n.min(buffer.len());
+      buffer[..fits].copy_from_slice(&inbox[0].data()[..fits]);
+      return Ok(Addrd(fits, addr));
+    }
+
+    let dgram = inbox.pop_front().unwrap();
+    match buffer.get_mut(..n) {
+      | Some(buffer) => {
+        buffer.copy_from_slice(dgram.data());
+        Ok(Addrd(n, addr))
+      },
+      | None => Err(nb::Error::Other(io::Error::new(io::ErrorKind::InvalidInput,
+                                                    format!("{n} byte datagram does not fit \
+                                                             in {} byte buffer",
+                                                            buffer.len())))),
+    }
+  }
+}
+
+/// Ask the OS to report the address each datagram received
+/// by `sock` was sent to (see [`recvd_dest`])
+pub(crate) fn enable_recv_dest(sock: &UdpSocket) -> io::Result<()> {
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  {
+    use std::os::unix::io::AsRawFd;
+
+    sys::enable_recv_dest(sock.as_raw_fd(), sock.local_addr()?.is_ipv6())
+  }
+
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  {
+    let _ = sock;
+    Ok(())
+  }
+}
+
+/// The address that a datagram received by a socket bound to `local` was sent to,
+/// given the destination IP reported by the OS (if any)
+pub(crate) fn recvd_dest(local: std::net::SocketAddr,
+                         ip: Option<std::net::IpAddr>)
+                         -> no_std_net::SocketAddr {
+  let dest = ip.map(|ip| std::net::SocketAddr::new(ip, local.port()))
+               .unwrap_or(local);
+  convert::std::SockAddr(dest).into()
+}
+
 impl Socket for UdpSocket {
   type Error = io::Error;
   type Dgram = ArrayVec<[u8; 1152]>;
+  type RecvReady = ();
 
   fn local_addr(&self) -> no_std_net::SocketAddr {
     convert::std::SockAddr(self.local_addr().unwrap()).into()
@@ -31,11 +124,22 @@ impl Socket for UdpSocket {
         .map_err(convert::io_to_nb)
   }
 
-  fn recv(&self, buffer: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error> {
+  fn recv(&self,
+          buffer: &mut [u8])
+          -> nb::Result<(Addrd<usize>, no_std_net::SocketAddr), Self::Error> {
     self.set_nonblocking(true).unwrap();
-    self.recv_from(buffer)
-        .map(|(n, addr)| Addrd(n, convert::std::SockAddr(addr).into()))
-        .map_err(convert::io_to_nb)
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let recvd = sys::recv_from_to(std::os::unix::io::AsRawFd::as_raw_fd(self), buffer);
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let recvd = self.recv_from(buffer).map(|(n, addr)| (n, addr, None));
+
+    recvd.and_then(|(n, addr, dest)| {
+           Ok((Addrd(n, convert::std::SockAddr(addr).into()),
+               recvd_dest(UdpSocket::local_addr(self)?, dest)))
+         })
+         .map_err(convert::io_to_nb)
   }
 
   fn bind_raw<A: no_std_net::ToSocketAddrs>(addr: A) -> Result<Self, Self::Error> {
@@ -45,6 +149,7 @@ impl Socket for UdpSocket {
                     .collect::<Vec<std::net::SocketAddr>>();
 
     UdpSocket::bind(addrs.as_slice()).discard(|s: &UdpSocket| Ok(s.set_nonblocking(true).unwrap()))
+                                     .discard(enable_recv_dest)
   }
 
   fn join_multicast(&self, addr: no_std_net::IpAddr) -> Result<(), Self::Error> {
@@ -67,4 +172,138 @@ impl Socket for UdpSocket {
   fn empty_dgram() -> Self::Dgram {
     ArrayVec::from([0u8; 1152])
   }
+
+  #[cfg(unix)]
+  fn wait_recv_ready(&self, timeout: Option<Millis>) -> Result<(), Self::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    sys::wait_readable(&[self.as_raw_fd()],
+                       timeout.map(|ms| Duration::from_millis(ms.0)))
+  }
+}
+
+// The byte stream under a `TcpSocket`, which frames the
+// messages sent & received over it
+impl StreamSocket for TcpStream {
+  type Error = io::Error;
+
+  fn connect<A: no_std_net::ToSocketAddrs>(addr: A) -> Result<Self, Self::Error> {
+    let addrs = addr.to_socket_addrs()
+                    .unwrap()
+                    .map(|no_std| convert::no_std::SockAddr(no_std).into())
+                    .collect::<Vec<std::net::SocketAddr>>();
+
+    TcpStream::connect(addrs.as_slice()).discard(|s: &TcpStream| {
+                                          s.set_nodelay(true)?;
+                                          s.set_nonblocking(true)
+                                        })
+  }
+
+  fn local_addr(&self) -> no_std_net::SocketAddr {
+    convert::std::SockAddr(TcpStream::local_addr(self).unwrap()).into()
+  }
+
+  fn peer_addr(&self) -> no_std_net::SocketAddr {
+    convert::std::SockAddr(TcpStream::peer_addr(self).unwrap()).into()
+  }
+
+  fn send(&self, bytes: &[u8]) -> nb::Result<usize, Self::Error> {
+    let mut sock = self;
+    sock.write(bytes).map_err(convert::io_to_nb)
+  }
+
+  fn recv(&self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+    let mut sock = self;
+    sock.read(buffer).map_err(convert::io_to_nb)
+  }
+
+  fn shutdown(&self) -> Result<(), Self::Error> {
+    TcpStream::shutdown(self, std::net::Shutdown::Both)
+  }
+
+  #[cfg(unix)]
+  fn wait_recv_ready(&self, timeout: Option<Millis>) -> Result<(), Self::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    sys::wait_readable(&[self.as_raw_fd()],
+                       timeout.map(|ms| Duration::from_millis(ms.0)))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::time::Instant;
+
+  use super::*;
+  use crate::net::ipv4_socketaddr;
+
+  #[test]
+  fn wait_recv_ready_should_return_on_timeout_or_dgram() {
+    let recv = <UdpSocket as Socket>::bind(ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let send = <UdpSocket as Socket>::bind(ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+
+    let start = Instant::now();
+    recv.wait_recv_ready(Some(Millis::new(20))).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert!(matches!(Socket::recv(&recv, &mut [0u8; 8]),
+                     Err(nb::Error::WouldBlock)));
+
+    Socket::send(&send, Addrd(&[1, 2, 3], Socket::local_addr(&recv))).unwrap();
+    recv.wait_recv_ready(None).unwrap();
+    assert_eq!(Socket::recv(&recv, &mut [0u8; 8]).unwrap().0.data(), &3);
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn recv_should_yield_address_dgram_was_sent_to() {
+    let recv = <UdpSocket as Socket>::bind(ipv4_socketaddr([0, 0, 0, 0], 0)).unwrap();
+    let send = <UdpSocket as Socket>::bind(ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let port = Socket::local_addr(&recv).port();
+
+    Socket::send(&send, Addrd(&[1, 2, 3], ipv4_socketaddr([127, 0, 0, 1], port))).unwrap();
+    recv.wait_recv_ready(None).unwrap();
+
+    let (Addrd(n, from), dest) = Socket::recv(&recv, &mut [0u8; 8]).unwrap();
+    assert_eq!(n, 3);
+    assert_eq!(from, Socket::local_addr(&send));
+    assert_eq!(dest, ipv4_socketaddr([127, 0, 0, 1], port));
+  }
+
+  #[test]
+  fn inbox_should_not_truncate_dgrams() {
+    let addr = ipv4_socketaddr([127, 0, 0, 1], 1234);
+    let inbox = Inbox::default();
+    inbox.extend([Addrd(vec![1, 2, 3], addr), Addrd(vec![4], addr)]);
+
+    let mut buf = [0u8; 2];
+    assert_eq!(inbox.take(&mut buf, false).unwrap(), Addrd(2, addr));
+    assert_eq!(buf, [1, 2]);
+    assert!(matches!(inbox.take(&mut buf, true), Err(nb::Error::Other(_))));
+
+    assert_eq!(inbox.take(&mut buf, true).unwrap(), Addrd(1, addr));
+    assert_eq!(buf[0], 4);
+    assert!(inbox.is_empty());
+  }
+
+  #[test]
+  fn tcp_stream_should_send_and_recv() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = convert::std::SockAddr(listener.local_addr().unwrap()).into();
+
+    let client = <TcpStream as StreamSocket>::connect(addr).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    server.set_nonblocking(true).unwrap();
+
+    assert_eq!(StreamSocket::peer_addr(&client), addr);
+    assert!(matches!(StreamSocket::recv(&server, &mut [0u8; 8]),
+                     Err(nb::Error::WouldBlock)));
+
+    assert_eq!(StreamSocket::send(&client, &[1, 2, 3]).unwrap(), 3);
+    server.wait_recv_ready(Some(Millis::new(1000))).unwrap();
+    assert_eq!(StreamSocket::recv(&server, &mut [0u8; 8]).unwrap(), 3);
+
+    StreamSocket::shutdown(&client).unwrap();
+    server.wait_recv_ready(Some(Millis::new(1000))).unwrap();
+    assert_eq!(StreamSocket::recv(&server, &mut [0u8; 8]).unwrap(), 0);
+  }
 }
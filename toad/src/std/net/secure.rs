@@ -17,11 +17,11 @@ use openssl::ssl::{ConnectConfiguration,
                    SslMethod,
                    SslMode};
 use tinyvec::ArrayVec;
-use toad_array::Array;
 
 use self::conn::{SecureUdpConn, SslStream};
 use super::convert::nb_to_io;
 use super::{convert, Addrd, Socket};
+use crate::time::Millis;
 use crate::todo::{self, NbResultExt, ResultExt2};
 
 /// Secure socket result
@@ -213,7 +213,7 @@ pub mod conn {
               Err(io::Error::from(io::ErrorKind::WouldBlock))
             }
           })
-          .map(|Addrd(n, _)| n)
+          .map(|(Addrd(n, _), _)| n)
     }
   }
 
@@ -491,6 +491,7 @@ impl SecureUdpSocket {
 impl Socket for SecureUdpSocket {
   type Error = Error;
   type Dgram = ArrayVec<[u8; 1152]>;
+  type RecvReady = ();
 
   fn local_addr(&self) -> no_std_net::SocketAddr {
     convert::std::SockAddr(self.sock.local_addr().unwrap()).into()
@@ -526,7 +527,9 @@ impl Socket for SecureUdpSocket {
                                          .perform_nb_err(|e| log::error!("{:?}", e))
   }
 
-  fn recv(&self, buffer: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error> {
+  fn recv(&self,
+          buffer: &mut [u8])
+          -> nb::Result<(Addrd<usize>, no_std_net::SocketAddr), Self::Error> {
     self.sock
         .peek_addr()
         .map_err(convert::nb_to_io)
@@ -541,6 +544,7 @@ impl Socket for SecureUdpSocket {
             | conn::SecureUdpConn::Establishing(_) => Err(self.restart_handshake(addr)),
           }
         })
+        .map(|recvd| (recvd, Socket::local_addr(self)))
         .map_err(Error::into_nb)
         .perform_nb_err(|e| log::error!("{:?}", e))
   }
@@ -567,6 +571,10 @@ impl Socket for SecureUdpSocket {
         .unwrap_or(Err(nb::Error::WouldBlock))
   }
 
+  fn wait_recv_ready(&self, timeout: Option<Millis>) -> Result<()> {
+    Socket::wait_recv_ready(self.sock.as_ref(), timeout).map_err(Error::from)
+  }
+
   /// Multicast and SSL are incompatible, so this always returns `Err(io::ErrorKind::Unsupported)`.
   fn join_multicast(&self, _: no_std_net::IpAddr) -> Result<()> {
     Err(io::Error::from(io::ErrorKind::Unsupported).into()).discard_err(|e: &Error| {
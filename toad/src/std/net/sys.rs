@@ -0,0 +1,139 @@
+//! Thin wrappers around OS socket APIs that `std` doesn't expose
+#![allow(unsafe_code)]
+
+use std::io;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::{mem, ptr};
+
+/// Block the current thread until at least one of `fds` is readable
+/// (or has hung up), or until `timeout` has elapsed
+/// (waiting indefinitely if `timeout` is `None`).
+///
+/// Being interrupted by a signal is treated as a spurious wakeup.
+#[cfg(unix)]
+pub(crate) fn wait_readable(fds: &[RawFd], timeout: Option<Duration>) -> io::Result<()> {
+  let mut fds = fds.iter()
+                   .map(|fd| libc::pollfd { fd: *fd,
+                                            events: libc::POLLIN,
+                                            revents: 0 })
+                   .collect::<Vec<_>>();
+  let timeout = timeout.map(|t| t.as_millis().min(i32::MAX as u128) as libc::c_int)
+                       .unwrap_or(-1);
+
+  // SAFETY: `fds` is a valid, exclusively borrowed array of `fds.len()` pollfds
+  match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } {
+    | -1 => match io::Error::last_os_error() {
+      | e if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+      | e => Err(e),
+    },
+    | _ => Ok(()),
+  }
+}
+
+/// Ask the OS to report the destination address of each datagram
+/// received by the UDP socket `fd` (see [`recv_from_to`])
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn enable_recv_dest(fd: RawFd, ipv6: bool) -> io::Result<()> {
+  let (level, name) = match ipv6 {
+    | true => (libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO),
+    | false => (libc::IPPROTO_IP, libc::IP_PKTINFO),
+  };
+  let on: libc::c_int = 1;
+
+  // SAFETY: `on` is a valid c_int that lives for the duration of the call
+  match unsafe {
+          libc::setsockopt(fd,
+                           level,
+                           name,
+                           &on as *const libc::c_int as *const libc::c_void,
+                           mem::size_of::<libc::c_int>() as libc::socklen_t)
+        } {
+    | -1 => Err(io::Error::last_os_error()),
+    | _ => Ok(()),
+  }
+}
+
+/// Receive a datagram on the UDP socket `fd` into `buf`,
+/// yielding the number of bytes read, the sender's address and
+/// the IP address the datagram was sent to.
+///
+/// The latter is only known if [`enable_recv_dest`] was invoked on the socket.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn recv_from_to(fd: RawFd,
+                           buf: &mut [u8])
+                           -> io::Result<(usize, SocketAddr, Option<IpAddr>)> {
+  // SAFETY: all-zero is a valid bit pattern for these C structs
+  let mut src: libc::sockaddr_storage = unsafe { mem::zeroed() };
+  let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+
+  // u64s so that the control messages are suitably aligned
+  let mut control = [0u64; 16];
+  let mut iov = libc::iovec { iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                              iov_len: buf.len() };
+
+  msg.msg_name = &mut src as *mut libc::sockaddr_storage as *mut libc::c_void;
+  msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+  msg.msg_iov = &mut iov;
+  msg.msg_iovlen = 1;
+  msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+  msg.msg_controllen = mem::size_of_val(&control) as _;
+
+  // SAFETY: every pointer in `msg` points to a live, exclusively borrowed
+  // buffer of the length stored alongside it
+  let n = match unsafe { libc::recvmsg(fd, &mut msg, 0) } {
+    | -1 => return Err(io::Error::last_os_error()),
+    | n => n as usize,
+  };
+
+  let mut dest = None;
+
+  // SAFETY: `msg.msg_control` holds `msg.msg_controllen` bytes of control messages
+  // written by the kernel, and the CMSG_* macros keep us within them
+  unsafe {
+    let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+    while !cmsg.is_null() {
+      let data = libc::CMSG_DATA(cmsg);
+      match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+        | (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+          let info = ptr::read_unaligned(data as *const libc::in_pktinfo);
+          dest = Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr))));
+        },
+        | (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+          let info = ptr::read_unaligned(data as *const libc::in6_pktinfo);
+          dest = Some(IpAddr::V6(Ipv6Addr::from(info.ipi6_addr.s6_addr)));
+        },
+        | _ => (),
+      }
+      cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+    }
+  }
+
+  Ok((n, sockaddr_to_std(&src)?, dest))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn sockaddr_to_std(addr: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+  // SAFETY: `ss_family` says which sockaddr struct `addr` holds,
+  // and sockaddr_storage is large & aligned enough for any of them
+  match addr.ss_family as libc::c_int {
+    | libc::AF_INET => {
+      let addr = unsafe { &*(addr as *const _ as *const libc::sockaddr_in) };
+      Ok(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                                          u16::from_be(addr.sin_port))))
+    },
+    | libc::AF_INET6 => {
+      let addr = unsafe { &*(addr as *const _ as *const libc::sockaddr_in6) };
+      Ok(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                                          u16::from_be(addr.sin6_port),
+                                          addr.sin6_flowinfo,
+                                          addr.sin6_scope_id)))
+    },
+    | family => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("unexpected address family {family}"))),
+  }
+}
@@ -0,0 +1,505 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+
+use naan::prelude::MonadOnce;
+use no_std_net::SocketAddr;
+use tinyvec::ArrayVec;
+use toad_msg::alloc::Message;
+use toad_msg::tcp::{frame_len, Reliable, Signal};
+use toad_msg::{Code, Id, Token, TryFromBytes, TryIntoBytes, Type};
+
+use super::{convert, Inbox};
+use crate::net::{Addrd, Socket, StreamSocket};
+use crate::time::Millis;
+
+/// The largest message we can receive, advertised in our CSM
+/// ([RFC8323 Section 5.3.1](https://www.rfc-editor.org/rfc/rfc8323#section-5.3.1))
+///
+/// Messages are converted to datagrams with a 4-byte header instead of a
+/// 2-byte one, so this leaves room for them to fit in a [`TcpSocket::Dgram`](Socket::Dgram).
+pub const MAX_MESSAGE_SIZE: u32 = 1150;
+
+/// The Max-Message-Size assumed for peers that haven't said otherwise
+/// ([RFC8323 Section 5.3.1](https://www.rfc-editor.org/rfc/rfc8323#section-5.3.1))
+const DEFAULT_MAX_MESSAGE_SIZE: u32 = 1152;
+
+fn invalid_data<E: core::fmt::Debug>(e: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+/// A connection with a peer
+#[derive(Debug)]
+struct Conn {
+  stream: TcpStream,
+  closed: bool,
+  /// The Max-Message-Size of the peer, once we've received its CSM
+  peer_max_message_size: Option<u32>,
+  inbound: Vec<u8>,
+  outbound: Vec<u8>,
+  next_id: u16,
+}
+
+impl Conn {
+  /// Wrap a connected (non-blocking) stream, sending our CSM
+  /// (both peers must send a CSM before anything else, see
+  /// [RFC8323 Section 5.3](https://www.rfc-editor.org/rfc/rfc8323#section-5.3))
+  fn new(stream: TcpStream) -> Self {
+    let mut conn = Self { stream,
+                          closed: false,
+                          peer_max_message_size: None,
+                          inbound: vec![],
+                          outbound: vec![],
+                          next_id: 0 };
+
+    conn.signal(Signal::Csm { max_message_size: Some(MAX_MESSAGE_SIZE),
+                              block_wise_transfer: true },
+                Token(Default::default()));
+    conn
+  }
+
+  /// Write as much of the outbound buffer to the stream as it will take
+  /// without blocking
+  fn flush(&mut self) {
+    while !self.outbound.is_empty() && !self.closed {
+      match StreamSocket::send(&self.stream, &self.outbound) {
+        | Ok(0) | Err(nb::Error::Other(_)) => self.closed = true,
+        | Ok(n) => drop(self.outbound.drain(..n)),
+        | Err(nb::Error::WouldBlock) => break,
+      }
+    }
+  }
+
+  fn send(&mut self, msg: Message) -> io::Result<()> {
+    let bytes: Vec<u8> = Reliable(msg).try_into_bytes().map_err(invalid_data)?;
+    self.outbound.extend(bytes);
+    self.flush();
+    Ok(())
+  }
+
+  fn signal(&mut self, signal: Signal<'_>, token: Token) {
+    if let Ok(msg) = signal.to_message(token) {
+      self.send(msg).ok();
+    }
+  }
+
+  /// Tell the peer we're closing the connection because of an error,
+  /// and close it
+  /// ([RFC8323 Section 5.6](https://www.rfc-editor.org/rfc/rfc8323#section-5.6))
+  fn abort(&mut self) {
+    self.signal(Signal::Abort { bad_csm_option: None },
+                Token(Default::default()));
+    self.close();
+  }
+
+  fn close(&mut self) {
+    StreamSocket::shutdown(&self.stream).ok();
+    self.closed = true;
+  }
+
+  /// Read all available bytes from the stream
+  fn fill(&mut self) {
+    let mut chunk = [0u8; 1024];
+    while !self.closed {
+      match StreamSocket::recv(&self.stream, &mut chunk) {
+        | Ok(0) | Err(nb::Error::Other(_)) => self.closed = true,
+        | Ok(n) => self.inbound.extend(&chunk[..n]),
+        | Err(nb::Error::WouldBlock) => break,
+      }
+    }
+  }
+
+  /// Process all complete messages in the inbound buffer, handling
+  /// signaling messages & yielding the others as CoAP datagrams.
+  ///
+  /// Messages larger than [`MAX_MESSAGE_SIZE`], messages that can't be parsed,
+  /// and anything other than a CSM as the first message all abort the connection.
+  fn drain_messages(&mut self) -> Vec<Vec<u8>> {
+    let mut dgrams = vec![];
+
+    while let Some(n) = frame_len(&self.inbound) {
+      if n > MAX_MESSAGE_SIZE as usize {
+        self.abort();
+        break;
+      }
+
+      if self.inbound.len() < n {
+        break;
+      }
+
+      let msg = Reliable::<Message>::try_from_bytes(&self.inbound[..n]).map(|Reliable(m)| m);
+      self.inbound.drain(..n);
+
+      let mut msg = match msg {
+        | Ok(msg) if self.peer_max_message_size.is_some() || msg.code == Code::CSM => msg,
+        | _ => {
+          self.abort();
+          break;
+        },
+      };
+
+      match Signal::from_message(&msg) {
+        | Some(Signal::Csm { max_message_size, .. }) => {
+          self.peer_max_message_size = Some(max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE));
+        },
+        | Some(Signal::Ping { custody }) => self.signal(Signal::Pong { custody }, msg.token),
+        | Some(Signal::Release { .. }) | Some(Signal::Abort { .. }) => {
+          self.close();
+          break;
+        },
+        | Some(Signal::Pong { .. }) => (),
+        // unrecognized signals are ignored (RFC8323 Section 5.1)
+        | None if msg.code.class == 7 => (),
+        | None => {
+          msg.ty = Type::Non;
+          msg.id = Id(self.next_id);
+          self.next_id = self.next_id.wrapping_add(1);
+
+          match msg.try_into_bytes() {
+            | Ok(dgram) => dgrams.push(dgram),
+            | Err(_) => {
+              self.abort();
+              break;
+            },
+          }
+        },
+      }
+    }
+
+    dgrams
+  }
+}
+
+/// A [`Socket`] speaking CoAP over TCP (`coap+tcp`)
+/// ([RFC8323 Section 3](https://www.rfc-editor.org/rfc/rfc8323#section-3))
+///
+/// Accepts connections from clients, and connects to servers
+/// the first time a message is sent to them. Each CoAP message is
+/// framed with [`Reliable`], and the CSM, Ping, Pong, Release & Abort
+/// signaling messages are handled by the socket.
+///
+/// Messages received are presented to the runtime as
+/// non-confirmable datagrams from the peer's address, so the same
+/// [`server::Run`](crate::server::Run) routes can serve UDP and TCP
+/// clients. The Type and Message ID of outbound messages are discarded,
+/// and empty messages (ACKs & RSTs) are not sent. Since the transport is
+/// reliable, confirmable messages are acknowledged as soon as they are sent.
+///
+/// Use with the std runtime via [`transport::Tcp`](crate::std::transport::Tcp).
+#[derive(Debug)]
+pub struct TcpSocket {
+  listener: TcpListener,
+  conns: Mutex<HashMap<SocketAddr, Conn>>,
+  inbox: Inbox,
+}
+
+impl TcpSocket {
+  /// Accept new connections, flush pending writes and read
+  /// any messages from existing connections
+  fn poll_conns(&self) {
+    let mut conns = self.conns.lock().unwrap();
+
+    while let Ok((stream, addr)) = self.listener.accept() {
+      if stream.set_nonblocking(true).is_ok() {
+        stream.set_nodelay(true).ok();
+        conns.insert(convert::std::SockAddr(addr).into(), Conn::new(stream));
+      }
+    }
+
+    conns.iter_mut().for_each(|(addr, conn)| {
+                      conn.flush();
+                      conn.fill();
+                      self.inbox.extend(conn.drain_messages()
+                                            .into_iter()
+                                            .map(|dgram| Addrd(dgram, *addr)));
+                    });
+
+    conns.retain(|_, conn| !conn.closed);
+  }
+
+  fn take(&self, buffer: &mut [u8], pop: bool) -> nb::Result<Addrd<usize>, io::Error> {
+    self.poll_conns();
+    self.inbox.take(buffer, pop)
+  }
+}
+
+impl Socket for TcpSocket {
+  type Error = io::Error;
+  type Dgram = ArrayVec<[u8; 1152]>;
+  type RecvReady = ();
+
+  fn local_addr(&self) -> SocketAddr {
+    convert::std::SockAddr(self.listener.local_addr().unwrap()).into()
+  }
+
+  fn empty_dgram() -> Self::Dgram {
+    ArrayVec::from([0u8; 1152])
+  }
+
+  fn bind_raw<A: no_std_net::ToSocketAddrs>(addr: A) -> Result<Self, Self::Error> {
+    let addrs = addr.to_socket_addrs()
+                    .unwrap()
+                    .map(|no_std| convert::no_std::SockAddr(no_std).into())
+                    .collect::<Vec<std::net::SocketAddr>>();
+
+    TcpListener::bind(addrs.as_slice()).discard(|l: &TcpListener| l.set_nonblocking(true))
+                                       .map(|listener| Self { listener,
+                                                              conns: Default::default(),
+                                                              inbox: Default::default() })
+  }
+
+  fn send(&self, msg: Addrd<&[u8]>) -> nb::Result<(), Self::Error> {
+    let dgram = Message::try_from_bytes(msg.data()).map_err(invalid_data)?;
+    if dgram.code == Code::new(0, 0) {
+      return Ok(());
+    }
+
+    // (re)connect to peers we don't have an open connection with
+    let mut conns = self.conns.lock().unwrap();
+    let conn = match conns.entry(msg.addr()) {
+      | Entry::Occupied(e) if !e.get().closed => e.into_mut(),
+      | Entry::Occupied(mut e) => {
+        e.insert(Conn::new(<TcpStream as StreamSocket>::connect(msg.addr())?));
+        e.into_mut()
+      },
+      | Entry::Vacant(e) => e.insert(Conn::new(<TcpStream as StreamSocket>::connect(msg.addr())?)),
+    };
+
+    let max = conn.peer_max_message_size
+                  .unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+    let (ty, id) = (dgram.ty, dgram.id);
+    let bytes: Vec<u8> = Reliable(dgram).try_into_bytes().map_err(invalid_data)?;
+    if bytes.len() > max as usize {
+      return Err(nb::Error::Other(io::Error::new(io::ErrorKind::InvalidInput,
+                                                 format!("message is larger than the \
+                                                          Max-Message-Size of {} ({})",
+                                                         msg.addr(),
+                                                         max))));
+    }
+
+    conn.outbound.extend(bytes);
+    conn.flush();
+
+    if ty == Type::Con {
+      let [a, b] = id.0.to_be_bytes();
+      self.inbox
+          .push(Addrd(vec![0b0110_0000, 0, a, b], msg.addr()));
+    }
+
+    Ok(())
+  }
+
+  fn recv(&self,
+          buffer: &mut [u8])
+          -> nb::Result<(Addrd<usize>, SocketAddr), Self::Error> {
+    self.take(buffer, true)
+        .map(|recvd| (recvd, Socket::local_addr(self)))
+  }
+
+  fn peek(&self, buffer: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error> {
+    self.take(buffer, false)
+  }
+
+  fn join_multicast(&self, _: no_std_net::IpAddr) -> Result<(), Self::Error> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "tcp does not support multicast"))
+  }
+
+  #[cfg(unix)]
+  fn wait_recv_ready(&self, timeout: Option<Millis>) -> Result<(), Self::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    if !self.inbox.is_empty() {
+      return Ok(());
+    }
+
+    let fds = core::iter::once(self.listener.as_raw_fd()).chain(self.conns
+                                                                    .lock()
+                                                                    .unwrap()
+                                                                    .values()
+                                                                    .map(|c| c.stream.as_raw_fd()))
+                                                         .collect::<Vec<_>>();
+
+    super::sys::wait_readable(&fds,
+                              timeout.map(|ms| std::time::Duration::from_millis(ms.0)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{Read, Write};
+
+  use toad_msg::MessageOptions;
+
+  use super::*;
+  use crate::net::ipv4_socketaddr;
+
+  fn frame(msg: Message) -> Vec<u8> {
+    Reliable(msg).try_into_bytes().unwrap()
+  }
+
+  fn signal(signal: Signal<'_>) -> Vec<u8> {
+    frame(signal.to_message(Token(Default::default())).unwrap())
+  }
+
+  fn read_msg(stream: &mut TcpStream) -> Option<Message> {
+    let mut buf = vec![];
+    let mut byte = [0u8];
+    while frame_len(&buf).map(|n| n > buf.len()).unwrap_or(true) {
+      match stream.read(&mut byte).unwrap() {
+        | 0 => return None,
+        | _ => buf.push(byte[0]),
+      }
+    }
+
+    Some(Reliable::<Message>::try_from_bytes(&buf).unwrap().0)
+  }
+
+  fn recv(sock: &TcpSocket) -> Addrd<Vec<u8>> {
+    let mut buf = [0u8; 1152];
+    loop {
+      match sock.recv(&mut buf) {
+        | Ok((Addrd(n, addr), _)) => break Addrd(buf[..n].to_vec(), addr),
+        | Err(nb::Error::WouldBlock) => sock.wait_recv_ready(Some(Millis::new(100))).unwrap(),
+        | Err(e) => panic!("{:?}", e),
+      }
+    }
+  }
+
+  fn connect(sock: &TcpSocket) -> TcpStream {
+    let addr: std::net::SocketAddr = convert::no_std::SockAddr(sock.local_addr()).into();
+    let mut client = TcpStream::connect(addr).unwrap();
+    sock.wait_recv_ready(Some(Millis::new(1000))).unwrap();
+    assert!(matches!(sock.recv(&mut [0u8; 8]), Err(nb::Error::WouldBlock)));
+
+    let csm = read_msg(&mut client).unwrap();
+    assert_eq!(Signal::from_message(&csm),
+               Some(Signal::Csm { max_message_size: Some(MAX_MESSAGE_SIZE),
+                                  block_wise_transfer: true }));
+    client
+  }
+
+  #[test]
+  fn socket_should_frame_messages_and_handle_signals() {
+    let sock = TcpSocket::bind_raw(ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let mut client = connect(&sock);
+    let client_addr: SocketAddr = convert::std::SockAddr(client.local_addr().unwrap()).into();
+
+    let mut get = Message::new(Type::Con, Code::GET, Id(0), Token::new(&[0xAB]).unwrap());
+    get.set_path("hello").unwrap();
+
+    client.write_all(&signal(Signal::Csm { max_message_size: None,
+                                           block_wise_transfer: false }))
+          .unwrap();
+    client.write_all(&signal(Signal::Ping { custody: false }))
+          .unwrap();
+    client.write_all(&frame(get.clone())).unwrap();
+
+    let dgram = recv(&sock);
+    assert_eq!(dgram.addr(), client_addr);
+
+    let req = Message::try_from_bytes(dgram.data()).unwrap();
+    assert_eq!(req.ty, Type::Non);
+    assert_eq!(req.code, Code::GET);
+    assert_eq!(req.token, get.token);
+    assert_eq!(req.path_string().unwrap(), "hello");
+
+    let pong = read_msg(&mut client).unwrap();
+    assert_eq!(Signal::from_message(&pong),
+               Some(Signal::Pong { custody: false }));
+
+    // Empty ACKs are not sent, and CON responses are acknowledged immediately
+    let mut resp = Message::new(Type::Con, Code::new(2, 5), Id(7), get.token);
+    resp.payload = toad_msg::Payload(b"hi".to_vec());
+    sock.send(Addrd(&req.ack(req.id).try_into_bytes::<Vec<u8>>().unwrap(),
+                    client_addr))
+        .unwrap();
+    sock.send(Addrd(&resp.clone().try_into_bytes::<Vec<u8>>().unwrap(),
+                    client_addr))
+        .unwrap();
+
+    let sent = read_msg(&mut client).unwrap();
+    assert_eq!(sent.code, resp.code);
+    assert_eq!(sent.payload, resp.payload);
+
+    let ack = Message::try_from_bytes(recv(&sock).data()).unwrap();
+    assert_eq!((ack.ty, ack.code, ack.id),
+               (Type::Ack, Code::new(0, 0), Id(7)));
+  }
+
+  #[test]
+  fn socket_should_abort_when_first_message_is_not_csm() {
+    let sock = TcpSocket::bind_raw(ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let mut client = connect(&sock);
+
+    client.write_all(&frame(Message::new(Type::Non, Code::GET, Id(0), Token(Default::default()))))
+          .unwrap();
+    while !sock.conns.lock().unwrap().is_empty() {
+      sock.wait_recv_ready(Some(Millis::new(100))).unwrap();
+      assert!(matches!(sock.recv(&mut [0u8; 8]), Err(nb::Error::WouldBlock)));
+    }
+
+    let abort = read_msg(&mut client).unwrap();
+    assert_eq!(Signal::from_message(&abort),
+               Some(Signal::Abort { bad_csm_option: None }));
+    assert_eq!(read_msg(&mut client), None);
+  }
+
+  #[test]
+  fn socket_should_abort_when_message_is_too_large() {
+    let sock = TcpSocket::bind_raw(ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let mut client = connect(&sock);
+
+    let mut big = Message::new(Type::Non, Code::POST, Id(0), Token(Default::default()));
+    big.payload = toad_msg::Payload(vec![0; MAX_MESSAGE_SIZE as usize]);
+
+    client.write_all(&signal(Signal::Csm { max_message_size: None,
+                                           block_wise_transfer: false }))
+          .unwrap();
+    client.write_all(&frame(big)).unwrap();
+    while !sock.conns.lock().unwrap().is_empty() {
+      sock.wait_recv_ready(Some(Millis::new(100))).unwrap();
+      assert!(matches!(sock.recv(&mut [0u8; 8]), Err(nb::Error::WouldBlock)));
+    }
+
+    let abort = read_msg(&mut client).unwrap();
+    assert_eq!(abort.code, Code::ABORT);
+    assert_eq!(read_msg(&mut client), None);
+  }
+
+  #[tokio::test]
+  async fn platform_should_serve_and_send_requests_over_tcp() {
+    use crate::future::AsyncPlatform;
+    use crate::platform::Platform as _;
+    use crate::req::Req;
+    use crate::resp::{code, Resp};
+    use crate::std::{transport, PlatformTypes as Std};
+    use crate::step::runtime::std::Runtime;
+
+    type Platform = crate::std::Platform<transport::Tcp, Runtime<transport::Tcp>>;
+
+    let server = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+    let client = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+    let server_addr = Socket::local_addr(server.socket());
+
+    let serve = async {
+      let req = server.next_req().await.unwrap();
+      assert_eq!(req.data().path(), Ok(Some("hello")));
+
+      let mut resp = Resp::for_request(req.data()).unwrap();
+      resp.set_code(code::CONTENT);
+      resp.set_payload("hi!".bytes());
+      server.send_msg_async(Addrd(resp.into(), req.addr()))
+            .await
+            .unwrap();
+    };
+
+    let req = Req::<Std<transport::Tcp>>::get("hello");
+    let (_, resp) = tokio::join!(serve, client.send_req(Addrd(req, server_addr)));
+
+    let resp = resp.unwrap();
+    assert_eq!(resp.data().code(), code::CONTENT);
+    assert_eq!(resp.data().payload_string(), Ok("hi!".to_string()));
+  }
+}
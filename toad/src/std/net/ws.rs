@@ -0,0 +1,799 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+
+use naan::prelude::MonadOnce;
+use no_std_net::SocketAddr;
+use tinyvec::ArrayVec;
+
+use super::{convert, Inbox};
+use crate::net::{Addrd, Socket};
+use crate::time::Millis;
+
+/// The GUID appended to `Sec-WebSocket-Key` when computing `Sec-WebSocket-Accept`
+/// ([RFC6455 Section 1.3](https://www.rfc-editor.org/rfc/rfc6455#section-1.3))
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The WebSocket subprotocol negotiated for CoAP
+/// ([RFC8323 Section 4.1](https://www.rfc-editor.org/rfc/rfc8323#section-4.1))
+pub const SUBPROTOCOL: &str = "coap";
+
+/// The largest opening handshake we will accept from a client, in bytes
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+
+/// The largest CoAP message we can receive, advertised in our CSM
+/// ([RFC8323 Section 5.3.1](https://www.rfc-editor.org/rfc/rfc8323#section-5.3.1))
+///
+/// Messages are converted to datagrams with a 4-byte header instead of a
+/// 2-byte one, so this leaves room for them to fit in a [`WsSocket::Dgram`](Socket::Dgram).
+pub const MAX_MESSAGE_SIZE: usize = 1150;
+
+mod opcode {
+  pub(super) const CONTINUATION: u8 = 0x0;
+  pub(super) const BINARY: u8 = 0x2;
+  pub(super) const CLOSE: u8 = 0x8;
+  pub(super) const PING: u8 = 0x9;
+  pub(super) const PONG: u8 = 0xA;
+}
+
+/// Status codes sent in close frames
+/// ([RFC6455 Section 7.4.1](https://www.rfc-editor.org/rfc/rfc6455#section-7.4.1))
+mod status {
+  pub(super) const NORMAL: u16 = 1000;
+  pub(super) const PROTOCOL_ERROR: u16 = 1002;
+  pub(super) const TOO_BIG: u16 = 1009;
+}
+
+mod signal {
+  pub(super) const CSM: u8 = 0xE1;
+  pub(super) const PING: u8 = 0xE2;
+  pub(super) const PONG: u8 = 0xE3;
+  pub(super) const RELEASE: u8 = 0xE4;
+  pub(super) const ABORT: u8 = 0xE5;
+
+  /// Our CSM, advertising a Max-Message-Size option of [`super::MAX_MESSAGE_SIZE`]
+  pub(super) fn csm() -> Vec<u8> {
+    let [a, b] = (super::MAX_MESSAGE_SIZE as u16).to_be_bytes();
+    vec![0, CSM, 0x22, a, b]
+  }
+}
+
+/// Compute the `Sec-WebSocket-Accept` header for a client's `Sec-WebSocket-Key`
+fn accept_key(key: &str) -> std::string::String {
+  let mut bytes = key.trim().as_bytes().to_vec();
+  bytes.extend_from_slice(WS_GUID.as_bytes());
+  openssl::base64::encode_block(&openssl::sha::sha1(&bytes))
+}
+
+/// Parse a complete HTTP upgrade request, yielding
+/// the response that should be written to the client and
+/// whether the upgrade was successful.
+fn handshake(req: &str) -> (std::string::String, bool) {
+  let mut lines = req.split("\r\n");
+  let is_get = lines.next()
+                    .map(|l| l.starts_with("GET "))
+                    .unwrap_or_default();
+
+  let header = |name: &str| {
+    req.split("\r\n")
+       .skip(1)
+       .filter_map(|l| l.split_once(':'))
+       .find(|(k, _)| k.trim().eq_ignore_ascii_case(name))
+       .map(|(_, v)| v.trim())
+  };
+
+  let upgrade = header("upgrade").map(|v| v.eq_ignore_ascii_case("websocket"))
+                                 .unwrap_or_default();
+  let coap =
+    header("sec-websocket-protocol").map(|v| v.split(',').any(|p| p.trim() == SUBPROTOCOL))
+                                    .unwrap_or_default();
+
+  match header("sec-websocket-key") {
+    | Some(key) if is_get && upgrade && coap => {
+      (format!("HTTP/1.1 101 Switching Protocols\r\n\
+                Upgrade: websocket\r\n\
+                Connection: Upgrade\r\n\
+                Sec-WebSocket-Accept: {}\r\n\
+                Sec-WebSocket-Protocol: {}\r\n\r\n",
+               accept_key(key),
+               SUBPROTOCOL),
+       true)
+    },
+    | _ => ("HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n".into(), false),
+  }
+}
+
+/// A single WebSocket frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+  fin: bool,
+  opcode: u8,
+  payload: Vec<u8>,
+}
+
+/// Reasons a frame received from a client was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameError {
+  /// The payload was longer than [`MAX_MESSAGE_SIZE`]
+  TooBig,
+  /// The frame wasn't masked, as frames sent by clients must be
+  /// ([RFC6455 Section 5.1](https://www.rfc-editor.org/rfc/rfc6455#section-5.1))
+  Unmasked,
+}
+
+impl FrameError {
+  fn status(&self) -> u16 {
+    match self {
+      | Self::TooBig => status::TOO_BIG,
+      | Self::Unmasked => status::PROTOCOL_ERROR,
+    }
+  }
+}
+
+impl Frame {
+  /// Try to parse a frame sent by a client from the start of `bytes`,
+  /// yielding the frame and the number of bytes it occupied.
+  ///
+  /// Returns `Ok(None)` if `bytes` does not yet contain a complete frame.
+  fn parse(bytes: &[u8]) -> Result<Option<(Self, usize)>, FrameError> {
+    let (b0, b1) = match (bytes.first(), bytes.get(1)) {
+      | (Some(b0), Some(b1)) => (*b0, *b1),
+      | _ => return Ok(None),
+    };
+
+    if b1 & 0x80 == 0 {
+      return Err(FrameError::Unmasked);
+    }
+
+    let (len, at) = match b1 & 0x7F {
+      | 126 => match bytes.get(2..4) {
+        | Some(len) => (u16::from_be_bytes([len[0], len[1]]) as u64, 4),
+        | None => return Ok(None),
+      },
+      | 127 => match bytes.get(2..10) {
+        | Some(len) => (u64::from_be_bytes(len.try_into().unwrap()), 10),
+        | None => return Ok(None),
+      },
+      | n => (n as u64, 2),
+    };
+
+    if len > MAX_MESSAGE_SIZE as u64 {
+      return Err(FrameError::TooBig);
+    }
+
+    let len = len as usize;
+    let (mask, payload) = match (bytes.get(at..at + 4), bytes.get(at + 4..at + 4 + len)) {
+      | (Some(mask), Some(payload)) => (mask, payload),
+      | _ => return Ok(None),
+    };
+
+    let payload = payload.iter()
+                         .enumerate()
+                         .map(|(ix, b)| b ^ mask[ix % 4])
+                         .collect();
+
+    Ok(Some((Self { fin: b0 & 0x80 != 0,
+                    opcode: b0 & 0x0F,
+                    payload },
+             at + 4 + len)))
+  }
+
+  /// Serialize an unmasked frame (as frames sent by servers must be)
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = vec![(if self.fin { 0x80 } else { 0 }) | self.opcode];
+
+    match self.payload.len() {
+      | n if n < 126 => bytes.push(n as u8),
+      | n if n <= u16::MAX as usize => {
+        bytes.push(126);
+        bytes.extend((n as u16).to_be_bytes());
+      },
+      | n => {
+        bytes.push(127);
+        bytes.extend((n as u64).to_be_bytes());
+      },
+    }
+
+    bytes.extend(&self.payload);
+    bytes
+  }
+}
+
+/// Convert a CoAP message as carried over WebSockets into a CoAP datagram,
+/// marking it as non-confirmable (since the transport is reliable)
+/// and assigning it `id`.
+///
+/// Over WebSockets the Len nibble is always zero, and there is no
+/// Version, Type or Message ID
+/// ([RFC8323 Section 4.2](https://www.rfc-editor.org/rfc/rfc8323#section-4.2))
+fn ws_to_dgram(ws: &[u8], id: u16) -> Option<Vec<u8>> {
+  let tkl = ws.first()? & 0x0F;
+  let code = *ws.get(1)?;
+
+  if ws.len() < 2 + tkl as usize {
+    return None;
+  }
+
+  let mut dgram = vec![0b0101_0000 | tkl, code];
+  dgram.extend(id.to_be_bytes());
+  dgram.extend(&ws[2..]);
+  Some(dgram)
+}
+
+/// Convert a CoAP datagram into a CoAP message as carried over WebSockets.
+///
+/// Empty messages (e.g. ACKs & RSTs) are meaningless over a reliable transport,
+/// and yield `None`.
+fn dgram_to_ws(dgram: &[u8]) -> Option<Vec<u8>> {
+  let tkl = dgram.first()? & 0x0F;
+  let code = *dgram.get(1)?;
+
+  if code == 0 || dgram.len() < 4 + tkl as usize {
+    return None;
+  }
+
+  let mut ws = vec![tkl, code];
+  ws.extend(&dgram[4..]);
+  Some(ws)
+}
+
+/// A WebSocket connection with a client
+#[derive(Debug)]
+struct Conn {
+  stream: TcpStream,
+  open: bool,
+  closed: bool,
+  inbound: Vec<u8>,
+  outbound: Vec<u8>,
+  fragments: Vec<u8>,
+  next_id: u16,
+}
+
+impl Conn {
+  fn new(stream: TcpStream) -> Self {
+    Self { stream,
+           open: false,
+           closed: false,
+           inbound: vec![],
+           outbound: vec![],
+           fragments: vec![],
+           next_id: 0 }
+  }
+
+  /// Write as much of the outbound buffer to the stream as it will take
+  /// without blocking
+  fn flush(&mut self) {
+    while !self.outbound.is_empty() {
+      match self.stream.write(&self.outbound) {
+        | Ok(0) => self.closed = true,
+        | Ok(n) => drop(self.outbound.drain(..n)),
+        | Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+        | Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+        | Err(_) => self.closed = true,
+      }
+
+      if self.closed {
+        break;
+      }
+    }
+  }
+
+  fn write(&mut self, bytes: &[u8]) {
+    self.outbound.extend(bytes);
+    self.flush();
+  }
+
+  fn write_frame(&mut self, opcode: u8, payload: Vec<u8>) {
+    self.write(&Frame { fin: true,
+                        opcode,
+                        payload }.to_bytes())
+  }
+
+  /// Send a close frame with `status` (writing as much of it as the stream will
+  /// take without blocking) and close the connection
+  fn close(&mut self, status: u16) {
+    self.write_frame(opcode::CLOSE, status.to_be_bytes().to_vec());
+    self.stream.shutdown(std::net::Shutdown::Both).ok();
+    self.closed = true;
+  }
+
+  /// Read all available bytes from the stream
+  fn fill(&mut self) {
+    let mut chunk = [0u8; 1024];
+    while !self.closed {
+      match self.stream.read(&mut chunk) {
+        | Ok(0) => self.closed = true,
+        | Ok(n) => self.inbound.extend(&chunk[..n]),
+        | Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+        | Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+        | Err(_) => self.closed = true,
+      }
+    }
+  }
+
+  /// Perform the opening handshake, if the client's request has been fully received
+  ///
+  /// Handshakes longer than [`MAX_HANDSHAKE_SIZE`] close the connection.
+  fn try_open(&mut self) {
+    let end = match self.inbound.windows(4).position(|w| w == b"\r\n\r\n") {
+      | Some(ix) if ix + 4 <= MAX_HANDSHAKE_SIZE => ix + 4,
+      | None if self.inbound.len() < MAX_HANDSHAKE_SIZE => return,
+      | _ => {
+        self.stream.shutdown(std::net::Shutdown::Both).ok();
+        self.closed = true;
+        return;
+      },
+    };
+
+    let (resp, ok) = handshake(&std::string::String::from_utf8_lossy(&self.inbound[..end]));
+    self.inbound.drain(..end);
+    self.write(resp.as_bytes());
+
+    if !ok {
+      self.stream.shutdown(std::net::Shutdown::Both).ok();
+      self.closed = true;
+      return;
+    }
+
+    self.open = true;
+
+    // Both peers must send a CSM before anything else
+    // (RFC8323 Section 5.3)
+    self.write_frame(opcode::BINARY, signal::csm());
+  }
+
+  /// Process all complete frames in the buffer, yielding
+  /// the CoAP messages (converted to datagrams) that were received.
+  ///
+  /// Unmasked frames and messages longer than [`MAX_MESSAGE_SIZE`]
+  /// close the connection.
+  fn drain_messages(&mut self) -> Vec<Vec<u8>> {
+    let mut msgs = vec![];
+
+    while !self.closed {
+      let (frame, n) = match Frame::parse(&self.inbound) {
+        | Ok(Some(frame)) => frame,
+        | Ok(None) => break,
+        | Err(e) => {
+          self.close(e.status());
+          break;
+        },
+      };
+      self.inbound.drain(..n);
+
+      match frame.opcode {
+        | opcode::BINARY | opcode::CONTINUATION => {
+          if self.fragments.len() + frame.payload.len() > MAX_MESSAGE_SIZE {
+            self.close(status::TOO_BIG);
+            break;
+          }
+
+          self.fragments.extend(frame.payload);
+          if !frame.fin {
+            continue;
+          }
+
+          let ws = core::mem::take(&mut self.fragments);
+          match ws.get(1).copied() {
+            | Some(signal::PING) => {
+              let mut pong = ws.clone();
+              pong[1] = signal::PONG;
+              pong.truncate(2 + (pong[0] & 0x0F) as usize);
+              self.write_frame(opcode::BINARY, pong);
+            },
+            | Some(signal::RELEASE) | Some(signal::ABORT) => self.close(status::NORMAL),
+            | Some(code) if code >> 5 == 7 => (),
+            | _ => {
+              if let Some(dgram) = ws_to_dgram(&ws, self.next_id) {
+                self.next_id = self.next_id.wrapping_add(1);
+                msgs.push(dgram);
+              }
+            },
+          }
+        },
+        | opcode::PING => self.write_frame(opcode::PONG, frame.payload),
+        | opcode::PONG => (),
+        | opcode::CLOSE => self.close(status::NORMAL),
+        // text & unknown frames are not part of the coap subprotocol
+        | _ => self.close(status::PROTOCOL_ERROR),
+      }
+    }
+
+    msgs
+  }
+}
+
+/// A [`Socket`] serving CoAP over WebSockets (`coap+ws`)
+/// ([RFC8323 Section 4](https://www.rfc-editor.org/rfc/rfc8323#section-4))
+///
+/// Accepts WebSocket connections negotiating the `coap` subprotocol,
+/// and carries each CoAP message in a binary WebSocket message.
+///
+/// Messages received from clients are presented to the runtime as
+/// non-confirmable datagrams from the client's address, so the same
+/// [`server::Run`](crate::server::Run) routes can serve UDP and WebSocket
+/// clients; the Type and Message ID of outbound messages are discarded,
+/// and empty messages (ACKs & RSTs) are not sent.
+///
+/// Use with the std runtime via [`transport::Ws`](crate::std::transport::Ws).
+#[derive(Debug)]
+pub struct WsSocket {
+  listener: TcpListener,
+  conns: Mutex<HashMap<SocketAddr, Conn>>,
+  inbox: Inbox,
+}
+
+impl WsSocket {
+  /// Accept new connections, flush pending writes and read
+  /// any frames from existing connections
+  fn poll_conns(&self) {
+    let mut conns = self.conns.lock().unwrap();
+
+    while let Ok((stream, addr)) = self.listener.accept() {
+      if stream.set_nonblocking(true).is_ok() {
+        stream.set_nodelay(true).ok();
+        conns.insert(convert::std::SockAddr(addr).into(), Conn::new(stream));
+      }
+    }
+
+    conns.iter_mut().for_each(|(addr, conn)| {
+                      conn.flush();
+                      conn.fill();
+
+                      if !conn.open {
+                        conn.try_open();
+                      }
+
+                      if conn.open {
+                        self.inbox.extend(conn.drain_messages()
+                                              .into_iter()
+                                              .map(|dgram| Addrd(dgram, *addr)));
+                      }
+                    });
+
+    conns.retain(|_, conn| !conn.closed);
+  }
+
+  fn take(&self, buffer: &mut [u8], pop: bool) -> nb::Result<Addrd<usize>, io::Error> {
+    self.poll_conns();
+    self.inbox.take(buffer, pop)
+  }
+}
+
+impl Socket for WsSocket {
+  type Error = io::Error;
+  type Dgram = ArrayVec<[u8; 1152]>;
+  type RecvReady = ();
+
+  fn local_addr(&self) -> SocketAddr {
+    convert::std::SockAddr(self.listener.local_addr().unwrap()).into()
+  }
+
+  fn empty_dgram() -> Self::Dgram {
+    ArrayVec::from([0u8; 1152])
+  }
+
+  fn bind_raw<A: no_std_net::ToSocketAddrs>(addr: A) -> Result<Self, Self::Error> {
+    let addrs = addr.to_socket_addrs()
+                    .unwrap()
+                    .map(|no_std| convert::no_std::SockAddr(no_std).into())
+                    .collect::<Vec<std::net::SocketAddr>>();
+
+    TcpListener::bind(addrs.as_slice()).discard(|l: &TcpListener| l.set_nonblocking(true))
+                                       .map(|listener| Self { listener,
+                                                              conns: Default::default(),
+                                                              inbox: Default::default() })
+  }
+
+  fn send(&self, msg: Addrd<&[u8]>) -> nb::Result<(), Self::Error> {
+    let ws = match dgram_to_ws(msg.data()) {
+      | Some(ws) => ws,
+      | None => return Ok(()),
+    };
+
+    let mut conns = self.conns.lock().unwrap();
+    let conn = conns.get_mut(&msg.addr())
+                    .filter(|conn| conn.open && !conn.closed)
+                    .ok_or_else(|| {
+                      io::Error::new(io::ErrorKind::NotConnected,
+                                     format!("no websocket connection with {}", msg.addr()))
+                    })?;
+
+    conn.write_frame(opcode::BINARY, ws);
+    Ok(())
+  }
+
+  fn recv(&self,
+          buffer: &mut [u8])
+          -> nb::Result<(Addrd<usize>, SocketAddr), Self::Error> {
+    self.take(buffer, true)
+        .map(|recvd| (recvd, Socket::local_addr(self)))
+  }
+
+  fn peek(&self, buffer: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error> {
+    self.take(buffer, false)
+  }
+
+  fn join_multicast(&self, _: no_std_net::IpAddr) -> Result<(), Self::Error> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       "websockets do not support multicast"))
+  }
+
+  #[cfg(unix)]
+  fn wait_recv_ready(&self, timeout: Option<Millis>) -> Result<(), Self::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    if !self.inbox.is_empty() {
+      return Ok(());
+    }
+
+    let fds = core::iter::once(self.listener.as_raw_fd()).chain(self.conns
+                                                                    .lock()
+                                                                    .unwrap()
+                                                                    .values()
+                                                                    .map(|c| c.stream.as_raw_fd()))
+                                                         .collect::<Vec<_>>();
+
+    super::sys::wait_readable(&fds,
+                              timeout.map(|ms| std::time::Duration::from_millis(ms.0)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use toad_msg::{Code, TryFromBytes, Type};
+
+  use super::*;
+  use crate::platform;
+
+  fn client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    client_fragment(true, opcode, payload)
+  }
+
+  fn client_fragment(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [1u8, 2, 3, 4];
+    let mut bytes = vec![if fin { 0x80 } else { 0 } | opcode];
+    match payload.len() {
+      | n if n < 126 => bytes.push(0x80 | n as u8),
+      | n => {
+        bytes.push(0x80 | 126);
+        bytes.extend((n as u16).to_be_bytes());
+      },
+    }
+    bytes.extend(mask);
+    bytes.extend(payload.iter().enumerate().map(|(ix, b)| b ^ mask[ix % 4]));
+    bytes
+  }
+
+  /// Read an (unmasked) frame sent by the server
+  fn read_frame(stream: &mut TcpStream) -> Frame {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).unwrap();
+    assert_eq!(head[1] & 0x80, 0);
+
+    let len = match head[1] {
+      | 126 => {
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).unwrap();
+        u16::from_be_bytes(len) as usize
+      },
+      | n => n as usize,
+    };
+
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).unwrap();
+    Frame { fin: head[0] & 0x80 != 0,
+            opcode: head[0] & 0x0F,
+            payload }
+  }
+
+  fn send_handshake(stream: &mut TcpStream) {
+    stream.write_all(b"GET /.well-known/coap HTTP/1.1\r\n\
+                       Upgrade: websocket\r\n\
+                       Connection: Upgrade\r\n\
+                       Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                       Sec-WebSocket-Protocol: coap\r\n\
+                       Sec-WebSocket-Version: 13\r\n\r\n")
+          .unwrap();
+  }
+
+  /// Connect to `sock` and perform the opening handshake, yielding
+  /// the client's stream and address
+  fn connect(sock: &WsSocket) -> (TcpStream, SocketAddr) {
+    let addr: std::net::SocketAddr = convert::no_std::SockAddr(sock.local_addr()).into();
+    let mut client = TcpStream::connect(addr).unwrap();
+    let client_addr = convert::std::SockAddr(client.local_addr().unwrap()).into();
+    send_handshake(&mut client);
+
+    while sock.conns.lock().unwrap().values().all(|c| !c.open) {
+      sock.wait_recv_ready(Some(crate::time::Millis::new(100)))
+          .unwrap();
+      assert!(matches!(sock.recv(&mut [0u8; 64]), Err(nb::Error::WouldBlock)));
+    }
+
+    let mut resp = vec![];
+    while !resp.ends_with(b"\r\n\r\n") {
+      let mut byte = [0u8];
+      client.read_exact(&mut byte).unwrap();
+      resp.push(byte[0]);
+    }
+    assert!(resp.starts_with(b"HTTP/1.1 101"));
+    assert_eq!(read_frame(&mut client).payload, signal::csm());
+
+    (client, client_addr)
+  }
+
+  /// Assert that `sock` closes its connection with `client`,
+  /// sending a close frame with `status`
+  fn assert_closed(sock: &WsSocket, client: &mut TcpStream, status: u16) {
+    while !sock.conns.lock().unwrap().is_empty() {
+      sock.wait_recv_ready(Some(crate::time::Millis::new(100)))
+          .unwrap();
+      assert!(matches!(sock.recv(&mut [0u8; 64]), Err(nb::Error::WouldBlock)));
+    }
+
+    let close = read_frame(client);
+    assert_eq!(close.opcode, opcode::CLOSE);
+    assert_eq!(close.payload, status.to_be_bytes().to_vec());
+    assert_eq!(client.read(&mut [0u8; 8]).unwrap(), 0);
+  }
+
+  #[test]
+  fn accept_key_should_match_rfc6455_example() {
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+               "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+  }
+
+  #[test]
+  fn handshake_should_require_coap_subprotocol() {
+    let req = |proto: &str| {
+      format!("GET /.well-known/coap HTTP/1.1\r\n\
+               Host: localhost\r\n\
+               Upgrade: websocket\r\n\
+               Connection: Upgrade\r\n\
+               Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+               Sec-WebSocket-Protocol: {}\r\n\
+               Sec-WebSocket-Version: 13\r\n\r\n",
+              proto)
+    };
+
+    let (resp, ok) = handshake(&req("coap"));
+    assert!(ok);
+    assert!(resp.starts_with("HTTP/1.1 101"));
+    assert!(resp.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    assert!(resp.contains("Sec-WebSocket-Protocol: coap\r\n"));
+
+    let (resp, ok) = handshake(&req("mqtt"));
+    assert!(!ok);
+    assert!(resp.starts_with("HTTP/1.1 400"));
+  }
+
+  #[test]
+  fn frames_should_round_trip() {
+    let (frame, n) = Frame::parse(&client_frame(opcode::BINARY, &[1, 2, 3])).unwrap()
+                                                                            .unwrap();
+    assert_eq!(frame.payload, vec![1, 2, 3]);
+    assert_eq!(n, 9);
+    assert_eq!(frame.to_bytes(), vec![0x82, 3, 1, 2, 3]);
+
+    let bytes = client_frame(opcode::BINARY, &[1, 2, 3]);
+    assert_eq!(Frame::parse(&bytes[..8]), Ok(None));
+
+    let long = Frame { fin: true,
+                       opcode: opcode::BINARY,
+                       payload: vec![1; 300] };
+    assert_eq!(&long.to_bytes()[..4], &[0x82, 126, 1, 44]);
+  }
+
+  #[test]
+  fn parse_should_reject_unmasked_and_oversized_frames() {
+    assert_eq!(Frame::parse(&[0x82, 3, 1, 2, 3]), Err(FrameError::Unmasked));
+
+    let len = (MAX_MESSAGE_SIZE as u16 + 1).to_be_bytes();
+    assert_eq!(Frame::parse(&[0x82, 0x80 | 126, len[0], len[1]]),
+               Err(FrameError::TooBig));
+    assert_eq!(Frame::parse(&[0x82, 0x80 | 127, 0xFF, 0, 0, 0, 0, 0, 0, 0]),
+               Err(FrameError::TooBig));
+  }
+
+  #[test]
+  fn messages_should_convert_to_and_from_dgrams() {
+    // GET with token [0xAB] and payload [1]
+    let ws = [0x01, 0x01, 0xAB, 0xFF, 1];
+    let dgram = ws_to_dgram(&ws, 3).unwrap();
+    assert_eq!(dgram, vec![0x51, 0x01, 0, 3, 0xAB, 0xFF, 1]);
+    assert_eq!(dgram_to_ws(&dgram).unwrap(), ws.to_vec());
+
+    // Empty ACK
+    assert_eq!(dgram_to_ws(&[0x60, 0x00, 0, 3]), None);
+    assert_eq!(ws_to_dgram(&[0x02, 0x01, 0xAB], 0), None);
+  }
+
+  #[test]
+  fn socket_should_serve_coap_over_websockets() {
+    let sock = WsSocket::bind_raw(crate::net::ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let (mut client, client_addr) = connect(&sock);
+    let mut buf = [0u8; 64];
+
+    // client CSM is ignored, PING is answered
+    client.write_all(&client_frame(opcode::BINARY, &[0, signal::CSM]))
+          .unwrap();
+    client.write_all(&client_frame(opcode::BINARY, &[0x01, signal::PING, 9]))
+          .unwrap();
+    client.write_all(&client_frame(opcode::BINARY, &[0x01, 0x01, 0xAB, 0xFF, 1]))
+          .unwrap();
+
+    let dgram = loop {
+      match sock.peek(&mut buf) {
+        | Ok(Addrd(n, addr)) => {
+          assert_eq!(addr, client_addr);
+          break buf[..n].to_vec();
+        },
+        | Err(nb::Error::WouldBlock) => sock.wait_recv_ready(None).unwrap(),
+        | Err(e) => panic!("{:?}", e),
+      }
+    };
+    assert_eq!(sock.recv(&mut buf).unwrap(),
+               (Addrd(dgram.len(), client_addr), Socket::local_addr(&sock)));
+
+    let msg =
+      platform::Message::<crate::std::PlatformTypes<crate::std::transport::Ws>>::try_from_bytes(dgram)
+        .unwrap();
+    assert_eq!(msg.code, Code::GET);
+    assert_eq!(msg.ty, Type::Non);
+    assert_eq!(msg.payload.0, vec![1]);
+
+    assert_eq!(read_frame(&mut client).payload, vec![0x01, signal::PONG, 9]);
+
+    // piggybacked ACK response is sent without type or id
+    sock.send(Addrd(&[0x61, 0x45, 0, 0, 0xAB, 0xFF, 2], client_addr))
+        .unwrap();
+    assert_eq!(read_frame(&mut client).payload,
+               vec![0x01, 0x45, 0xAB, 0xFF, 2]);
+  }
+
+  #[test]
+  fn socket_should_close_on_unmasked_frames() {
+    let sock = WsSocket::bind_raw(crate::net::ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let (mut client, _) = connect(&sock);
+
+    client.write_all(&[0x82, 2, 0, signal::CSM]).unwrap();
+    assert_closed(&sock, &mut client, status::PROTOCOL_ERROR);
+  }
+
+  #[test]
+  fn socket_should_close_on_oversized_messages() {
+    let sock = WsSocket::bind_raw(crate::net::ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let (mut client, _) = connect(&sock);
+
+    let half = vec![0u8; MAX_MESSAGE_SIZE / 2 + 1];
+    client.write_all(&client_fragment(false, opcode::BINARY, &half))
+          .unwrap();
+    client.write_all(&client_fragment(true, opcode::CONTINUATION, &half))
+          .unwrap();
+    assert_closed(&sock, &mut client, status::TOO_BIG);
+  }
+
+  #[test]
+  fn socket_should_close_on_oversized_handshakes() {
+    let sock = WsSocket::bind_raw(crate::net::ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let addr: std::net::SocketAddr = convert::no_std::SockAddr(sock.local_addr()).into();
+    let mut client = TcpStream::connect(addr).unwrap();
+
+    client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    client.write_all(&[b'a'; MAX_HANDSHAKE_SIZE]).unwrap();
+
+    client.set_read_timeout(Some(std::time::Duration::from_millis(10)))
+          .unwrap();
+    loop {
+      assert!(matches!(sock.recv(&mut [0u8; 64]), Err(nb::Error::WouldBlock)));
+      match client.read(&mut [0u8; 64]) {
+        | Ok(0) => break,
+        | Ok(_) => panic!("handshake should not be answered"),
+        | Err(_) => continue,
+      }
+    }
+    assert!(sock.conns.lock().unwrap().is_empty());
+  }
+}
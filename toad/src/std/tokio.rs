@@ -0,0 +1,333 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use ::tokio::time::{Instant, Sleep};
+use tinyvec::ArrayVec;
+
+use super::dtls::sealed::Security;
+use super::net::{convert, enable_recv_dest, recvd_dest};
+use crate::net::{Addrd, Socket};
+use crate::time::Millis;
+
+/// ZST marker for using a [`UdpSocket`] driven by tokio (without DTLS)
+///
+/// ```no_run
+/// use toad::future::AsyncPlatform;
+/// use toad::std::tokio::{Platform, N};
+/// use toad::step::runtime::std::Runtime;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///   let server = Platform::<Runtime<N>>::try_new("0.0.0.0:5683", Default::default()).unwrap();
+///
+///   loop {
+///     let req = server.next_req().await.unwrap();
+///     println!("{:?}", req.data().path());
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct N;
+
+impl Security for N {
+  type Socket = UdpSocket;
+}
+
+/// [`PlatformTypes`](super::PlatformTypes) using a tokio [`UdpSocket`]
+pub type PlatformTypes = super::PlatformTypes<N>;
+
+/// [`Platform`](super::Platform) using a tokio [`UdpSocket`]
+///
+/// This must be created from within a tokio runtime
+/// with IO and time enabled.
+pub type Platform<Steps> = super::Platform<N, Steps>;
+
+/// A [`tokio::net::UdpSocket`](::tokio::net::UdpSocket) that wakes
+/// tasks waiting on it when a datagram arrives
+/// (or when the platform next needs to be polled, whichever comes first).
+#[derive(Debug)]
+pub struct UdpSocket {
+  sock: Arc<::tokio::net::UdpSocket>,
+  // tokio will not attempt to send until the reactor has seen the socket
+  // become writable, which would make sends block (without waking anyone)
+  // when the runtime hasn't been driven yet.
+  send: std::net::UdpSocket,
+  // a datagram received by one task may be buffered by `Steps` for another
+  // (e.g. a response to someone else's request), so every waiting task is
+  // woken whenever a datagram is received.
+  recvd: AtomicUsize,
+  waiting: Mutex<Vec<Waker>>,
+}
+
+/// [`Socket::RecvReady`] for the tokio [`UdpSocket`]
+///
+/// tokio's `UdpSocket::poll_recv_ready` only remembers the waker
+/// it was most recently polled with, so each waiting task instead
+/// holds its own `readable()` future and timer, along with how many
+/// datagrams the socket had received when the task last checked.
+#[derive(Default)]
+pub struct RecvReady {
+  recvd: Option<usize>,
+  readable: Option<Pin<Box<dyn Future<Output = io::Result<()>> + Send>>>,
+  timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl core::fmt::Debug for RecvReady {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("RecvReady")
+     .field("recvd", &self.recvd)
+     .field("readable", &self.readable.as_ref().map(|_| ()))
+     .field("timer", &self.timer)
+     .finish()
+  }
+}
+
+impl UdpSocket {
+  /// Wrap a tokio socket
+  ///
+  /// This must be invoked from within a tokio runtime with time enabled.
+  pub fn new(sock: ::tokio::net::UdpSocket) -> io::Result<Self> {
+    let sock = sock.into_std()?;
+    let send = sock.try_clone()?;
+
+    Ok(Self { sock: Arc::new(::tokio::net::UdpSocket::from_std(sock)?),
+              send,
+              recvd: AtomicUsize::new(0),
+              waiting: Mutex::new(Vec::new()) })
+  }
+
+  /// Get the wrapped tokio socket
+  pub fn inner(&self) -> &::tokio::net::UdpSocket {
+    &self.sock
+  }
+}
+
+impl Socket for UdpSocket {
+  type Error = io::Error;
+  type Dgram = ArrayVec<[u8; 1152]>;
+  type RecvReady = RecvReady;
+
+  fn local_addr(&self) -> no_std_net::SocketAddr {
+    convert::std::SockAddr(self.sock.local_addr().unwrap()).into()
+  }
+
+  fn send(&self, msg: Addrd<&[u8]>) -> nb::Result<(), Self::Error> {
+    self.send
+        .send_to(msg.data(),
+                 std::net::SocketAddr::from(convert::no_std::SockAddr(msg.addr())))
+        .map(|_| ())
+        .map_err(convert::io_to_nb)
+  }
+
+  fn recv(&self,
+          buffer: &mut [u8])
+          -> nb::Result<(Addrd<usize>, no_std_net::SocketAddr), Self::Error> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let recvd = {
+      use std::os::unix::io::AsRawFd;
+
+      self.sock.try_io(::tokio::io::Interest::READABLE, || {
+                 crate::std::net::sys::recv_from_to(self.sock.as_raw_fd(), buffer)
+               })
+    };
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let recvd = self.sock
+                    .try_recv_from(buffer)
+                    .map(|(n, addr)| (n, addr, None));
+
+    let (n, addr, dest) = recvd.map_err(convert::io_to_nb)?;
+    let dest = recvd_dest(self.sock.local_addr()?, dest);
+
+    self.recvd.fetch_add(1, Ordering::SeqCst);
+    self.waiting.lock().unwrap().drain(..).for_each(Waker::wake);
+
+    Ok((Addrd(n, convert::std::SockAddr(addr).into()), dest))
+  }
+
+  fn peek(&self, buffer: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error> {
+    self.sock
+        .try_peek_from(buffer)
+        .map(|(n, addr)| Addrd(n, convert::std::SockAddr(addr).into()))
+        .map_err(convert::io_to_nb)
+  }
+
+  fn bind_raw<A: no_std_net::ToSocketAddrs>(addr: A) -> Result<Self, Self::Error> {
+    let addrs = addr.to_socket_addrs()
+                    .unwrap()
+                    .map(|no_std| convert::no_std::SockAddr(no_std).into())
+                    .collect::<Vec<std::net::SocketAddr>>();
+
+    let sock = std::net::UdpSocket::bind(addrs.as_slice())?;
+    sock.set_nonblocking(true)?;
+    enable_recv_dest(&sock)?;
+    ::tokio::net::UdpSocket::from_std(sock).and_then(Self::new)
+  }
+
+  fn join_multicast(&self, addr: no_std_net::IpAddr) -> Result<(), Self::Error> {
+    match convert::std::Ip::from(convert::no_std::Ip(addr)).0 {
+      | std::net::IpAddr::V4(addr) => self.sock
+                                          .join_multicast_v4(addr, std::net::Ipv4Addr::UNSPECIFIED),
+      | std::net::IpAddr::V6(addr) => self.sock.join_multicast_v6(&addr, 0),
+    }
+  }
+
+  fn empty_dgram() -> Self::Dgram {
+    ArrayVec::from([0u8; 1152])
+  }
+
+  fn poll_recv_ready(&self,
+                     ready: &mut RecvReady,
+                     cx: &mut Context<'_>,
+                     timeout: Option<Millis>)
+                     -> Poll<Result<(), Self::Error>> {
+    // register before checking `recvd`, so that a datagram received
+    // between the check and us returning `Pending` still wakes us
+    {
+      let mut waiting = self.waiting.lock().unwrap();
+      if !waiting.iter().any(|w| w.will_wake(cx.waker())) {
+        waiting.push(cx.waker().clone());
+      }
+    }
+
+    let recvd = self.recvd.load(Ordering::SeqCst);
+    if ready.recvd.replace(recvd) != Some(recvd) {
+      return Poll::Ready(Ok(()));
+    }
+
+    let sock = &self.sock;
+    let readable = ready.readable.get_or_insert_with(|| {
+                                   let sock = Arc::clone(sock);
+                                   Box::pin(async move { sock.readable().await })
+                                 });
+
+    if let Poll::Ready(r) = readable.as_mut().poll(cx) {
+      ready.readable = None;
+      return Poll::Ready(r);
+    }
+
+    match timeout {
+      | None => {
+        ready.timer = None;
+        Poll::Pending
+      },
+      | Some(timeout) => {
+        let deadline = Instant::now() + Duration::from_millis(timeout.0);
+        let timer = ready.timer
+                         .get_or_insert_with(|| Box::pin(::tokio::time::sleep_until(deadline)));
+        timer.as_mut().reset(deadline);
+        timer.as_mut().poll(cx).map(Ok)
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use embedded_time::duration::Milliseconds;
+
+  use super::*;
+  use crate::config::Config;
+  use crate::future::AsyncPlatform;
+  use crate::platform::Platform as _;
+  use crate::req::Req;
+  use crate::resp::{code, Resp};
+  use crate::retry::Strategy;
+  use crate::step::runtime::std::Runtime;
+
+  type Platform = super::Platform<Runtime<N>>;
+
+  #[::tokio::test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  async fn recv_should_yield_address_dgram_was_sent_to() {
+    use crate::net::ipv4_socketaddr;
+
+    let recv = UdpSocket::bind(ipv4_socketaddr([0, 0, 0, 0], 0)).unwrap();
+    let send = UdpSocket::bind(ipv4_socketaddr([127, 0, 0, 1], 0)).unwrap();
+    let port = recv.local_addr().port();
+
+    send.send(Addrd(&[1, 2, 3], ipv4_socketaddr([127, 0, 0, 1], port)))
+        .unwrap();
+    recv.inner().readable().await.unwrap();
+
+    let (Addrd(n, from), dest) = recv.recv(&mut [0u8; 8]).unwrap();
+    assert_eq!(n, 3);
+    assert_eq!(from, send.local_addr());
+    assert_eq!(dest, ipv4_socketaddr([127, 0, 0, 1], port));
+  }
+
+  #[::tokio::test]
+  async fn send_req_should_resolve_to_response() {
+    let server = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+    let client = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+    let server_addr = server.socket().local_addr();
+
+    let serve = async {
+      let req = server.next_req().await.unwrap();
+
+      let mut resp = Resp::non(req.data());
+      resp.set_code(code::CONTENT);
+      resp.set_payload("hi!".bytes());
+      server.send_msg_async(Addrd(resp.into(), req.addr()))
+            .await
+            .unwrap();
+    };
+
+    let req = Req::<PlatformTypes>::get("hello");
+    let (_, resp) = ::tokio::join!(serve, client.send_req(Addrd(req, server_addr)));
+
+    assert_eq!(resp.unwrap().data().payload_string(), Ok("hi!".to_string()));
+  }
+
+  #[::tokio::test]
+  async fn tasks_waiting_on_the_same_socket_should_all_be_woken() {
+    let server = Platform::try_new("127.0.0.1:0", Default::default()).unwrap();
+    // don't let retransmissions wake a task that was forgotten about
+    let mut config = Config::default();
+    config.msg.con.unacked_retry_strategy = Strategy::Delay { min: Milliseconds(10_000),
+                                                              max: Milliseconds(10_000) };
+
+    let client = Arc::new(Platform::try_new("127.0.0.1:0", config).unwrap());
+    let server_addr = server.socket().local_addr();
+
+    let get = |path: &'static str| {
+      let client = Arc::clone(&client);
+      ::tokio::spawn(async move {
+        let req = Req::<PlatformTypes>::get(path);
+        client.send_req(Addrd(req, server_addr))
+              .await
+              .unwrap()
+              .data()
+              .payload_string()
+              .unwrap()
+      })
+    };
+
+    // tokens are derived from the time a request is sent (in milliseconds),
+    // so make sure the requests don't share one.
+    let a = get("a");
+    ::tokio::time::sleep(Duration::from_millis(2)).await;
+    let b = get("b");
+
+    for _ in 0..2 {
+      let req = server.next_req().await.unwrap();
+
+      let mut resp = Resp::non(req.data());
+      resp.set_code(code::CONTENT);
+      resp.set_payload(req.data().path().unwrap().unwrap().bytes());
+      server.send_msg_async(Addrd(resp.into(), req.addr()))
+            .await
+            .unwrap();
+    }
+
+    let both = async { (a.await.unwrap(), b.await.unwrap()) };
+    let (a, b) = ::tokio::time::timeout(Duration::from_secs(1), both).await
+                                                                     .unwrap();
+    assert_eq!((a.as_str(), b.as_str()), ("a", "b"));
+  }
+}
@@ -1,36 +1,172 @@
-use toad_array::Array;
-use toad_msg::{CodeKind, Type};
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use no_std_net::SocketAddr;
+use toad_array::{Array, Indexed};
+use toad_msg::{Code, CodeKind, Id, Token, Type};
+use toad_stem::Stem;
 
-use super::{exec_inner_step, Step, StepOutput};
+use super::{exec_inner_step, log, Step, StepOutput};
+use crate::config::Config;
 use crate::net::Addrd;
-use crate::platform::{Effect, PlatformTypes};
+use crate::platform::{self, Effect, PlatformTypes, Snapshot};
 use crate::req::Req;
 use crate::resp::Resp;
 
+/// A CON request that we've yielded to the application
+/// and haven't responded to yet
+pub struct Exchange<P>
+  where P: PlatformTypes
+{
+  addr: SocketAddr,
+  id: Id,
+  token: Token,
+  acked: bool,
+  received_at: Instant<P::Clock>,
+}
+
+impl<P> core::fmt::Debug for Exchange<P> where P: PlatformTypes
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Exchange")
+     .field("addr", &self.addr)
+     .field("id", &self.id)
+     .field("token", &self.token)
+     .field("acked", &self.acked)
+     .field("received_at", &self.received_at)
+     .finish()
+  }
+}
+
+impl<P> Exchange<P> where P: PlatformTypes
+{
+  /// Whether we've sent an empty ACK for this request,
+  /// meaning the response must be sent separately
+  pub fn acked(&self) -> bool {
+    self.acked
+  }
+
+  fn elapsed_millis(&self, now: Instant<P::Clock>) -> u64 {
+    now.checked_duration_since(&self.received_at)
+       .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+       .map(|Milliseconds(ms)| ms)
+       .unwrap_or(0)
+  }
+
+  fn is_expired(&self, now: Instant<P::Clock>, config: Config) -> bool {
+    self.elapsed_millis(now) >= config.exchange_lifetime_millis()
+  }
+
+  /// When we'll need to send an empty ACK for this request
+  /// if the application hasn't responded to it
+  fn ack_at(&self, config: Config) -> Option<Instant<P::Clock>> {
+    Some(self.received_at + config.msg.piggyback_deadline).filter(|_| !self.acked)
+  }
+
+  fn should_ack(&self, now: Instant<P::Clock>, config: Config) -> bool {
+    !self.acked && self.elapsed_millis(now) >= config.msg.piggyback_deadline.0
+  }
+}
+
 /// ACK incoming Confirmable messages
 ///
 /// See the [module documentation](crate::step::ack) for more
-#[derive(Debug, Clone, Copy)]
-pub struct Ack<S>(S);
+#[derive(Debug)]
+pub struct Ack<S, Exchanges> {
+  inner: S,
+  exchanges: Stem<Exchanges>,
+}
 
-impl<S: Default> Default for Ack<S> {
+impl<S: Default, Exchanges: Default> Default for Ack<S, Exchanges> {
   fn default() -> Self {
-    Ack(Default::default())
+    Ack { inner: Default::default(),
+          exchanges: Default::default() }
   }
 }
 
-impl<S> Ack<S> {
+impl<S, Exchanges: Default> Ack<S, Exchanges> {
   /// Create a new Ack step
   pub fn new(s: S) -> Self {
-    Self(s)
+    Self { inner: s,
+           exchanges: Default::default() }
+  }
+}
+
+impl<S, Exchanges> Ack<S, Exchanges> {
+  fn empty_ack<P>(id: Id) -> platform::Message<P>
+    where P: PlatformTypes
+  {
+    platform::Message::<P>::new(Type::Ack, Code::EMPTY, id, Token(Default::default()))
+  }
+
+  /// Send empty ACKs for requests that the application
+  /// hasn't responded to within the piggyback deadline,
+  /// and forget requests older than the exchange lifetime.
+  fn ack_late<P>(effs: &mut P::Effects, exchanges: &mut Exchanges, snap: &Snapshot<P>)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    while let Some(ix) = exchanges.iter()
+                                  .position(|e| e.is_expired(snap.time, snap.config))
+    {
+      exchanges.remove(ix);
+    }
+
+    exchanges.iter_mut()
+             .filter(|e| e.should_ack(snap.time, snap.config))
+             .for_each(|e| {
+               log!(Ack::ack_late,
+                    effs,
+                    log::Level::Trace,
+                    "No response to {:?} from {} within {}ms, sending empty ACK",
+                    e.token,
+                    e.addr,
+                    snap.config.msg.piggyback_deadline.0);
+               e.acked = true;
+               effs.append(Effect::Send(Addrd(Self::empty_ack::<P>(e.id), e.addr)));
+             });
+  }
+
+  /// Remember a CON request yielded to the application,
+  /// evicting the oldest one if we can't remember any more.
+  ///
+  /// If the evicted request hasn't been ACKed yet, it's ACKed now
+  /// since we won't be able to piggyback its response.
+  fn track<P>(effs: &mut P::Effects,
+              exchanges: &mut Exchanges,
+              now: Instant<P::Clock>,
+              req: &Addrd<Req<P>>)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    if exchanges.is_full() {
+      if let Some(e) = exchanges.iter().next().filter(|e| !e.acked) {
+        log!(Ack::track,
+             effs,
+             log::Level::Warn,
+             "Too many unanswered requests, sending empty ACK for {:?} from {}",
+             e.token,
+             e.addr);
+        effs.append(Effect::Send(Addrd(Self::empty_ack::<P>(e.id), e.addr)));
+      }
+
+      exchanges.remove(0);
+    }
+
+    exchanges.append(Exchange { addr: req.addr(),
+                                id: req.data().msg().id,
+                                token: req.data().msg().token,
+                                acked: false,
+                                received_at: now });
   }
 }
 
 type InnerPollReq<P> = Addrd<Req<P>>;
 type InnerPollResp<P> = Addrd<Resp<P>>;
 
-impl<Inner: Step<P, PollReq = InnerPollReq<P>, PollResp = InnerPollResp<P>>, P: PlatformTypes>
-  Step<P> for Ack<Inner>
+impl<Inner, Exchanges, P> Step<P> for Ack<Inner, Exchanges>
+  where Inner: Step<P, PollReq = InnerPollReq<P>, PollResp = InnerPollResp<P>>,
+        Exchanges: Default + Array<Item = Exchange<P>>,
+        P: PlatformTypes
 {
   type PollReq = Addrd<Req<P>>;
   type PollResp = Addrd<Resp<P>>;
@@ -38,19 +174,30 @@ impl<Inner: Step<P, PollReq = InnerPollReq<P>, PollResp = InnerPollResp<P>>, P:
   type Inner = Inner;
 
   fn inner(&self) -> &Inner {
-    &self.0
+    &self.inner
   }
 
   fn poll_req(&self,
-              snap: &crate::platform::Snapshot<P>,
+              snap: &Snapshot<P>,
               effects: &mut <P as PlatformTypes>::Effects)
               -> StepOutput<Self::PollReq, Inner::Error> {
-    match exec_inner_step!(self.0.poll_req(snap, effects), core::convert::identity) {
+    let out = self.inner.poll_req(snap, effects);
+    self.exchanges
+        .map_mut(|es| Self::ack_late::<P>(effects, es, snap));
+
+    match exec_inner_step!(out, core::convert::identity) {
       | Some(req)
         if req.data().as_ref().ty == Type::Con
            && req.data().as_ref().code.kind() == CodeKind::Request =>
       {
-        effects.push(Effect::Send(Addrd(Resp::ack(req.as_ref().data()).into(), req.addr())));
+        if snap.config.msg.piggyback_deadline.0 == 0 {
+          let ack = Self::empty_ack::<P>(req.data().msg().id);
+          effects.append(Effect::Send(Addrd(ack, req.addr())));
+        } else {
+          self.exchanges
+              .map_mut(|es| Self::track::<P>(effects, es, snap.time, &req));
+        }
+
         Some(Ok(req))
       },
       | Some(req) => Some(Ok(req)),
@@ -59,29 +206,109 @@ impl<Inner: Step<P, PollReq = InnerPollReq<P>, PollResp = InnerPollResp<P>>, P:
   }
 
   fn poll_resp(&self,
-               snap: &crate::platform::Snapshot<P>,
+               snap: &Snapshot<P>,
                effects: &mut <P as PlatformTypes>::Effects,
                token: toad_msg::Token,
                addr: no_std_net::SocketAddr)
                -> StepOutput<Self::PollResp, Inner::Error> {
-    exec_inner_step!(self.0.poll_resp(snap, effects, token, addr),
-                     core::convert::identity).map(Ok)
+    let out = self.inner.poll_resp(snap, effects, token, addr);
+    self.exchanges
+        .map_mut(|es| Self::ack_late::<P>(effects, es, snap));
+
+    match exec_inner_step!(out, core::convert::identity) {
+      | Some(resp)
+        if resp.data().as_ref().ty == Type::Con
+           && resp.data().as_ref().code.kind() == CodeKind::Response =>
+      {
+        let ack = Self::empty_ack::<P>(resp.data().as_ref().id);
+        effects.append(Effect::Send(Addrd(ack, resp.addr())));
+        Some(Ok(resp))
+      },
+      | Some(resp) => Some(Ok(resp)),
+      | None => None,
+    }
+  }
+
+  fn before_message_sent(&self,
+                         snap: &Snapshot<P>,
+                         effs: &mut P::Effects,
+                         msg: &mut Addrd<platform::Message<P>>)
+                         -> Result<(), Self::Error> {
+    self.inner.before_message_sent(snap, effs, msg)?;
+
+    if msg.data().ty == Type::Ack && msg.data().code.kind() == CodeKind::Empty {
+      // the application ACKed a request itself (e.g. to respond later)
+      let (addr, id) = (msg.addr(), msg.data().id);
+      self.exchanges.map_mut(|es| {
+                      es.iter_mut()
+                        .filter(|e| e.addr == addr && e.id == id)
+                        .for_each(|e| e.acked = true)
+                    });
+      return Ok(());
+    }
+
+    if msg.data().code.kind() != CodeKind::Response {
+      return Ok(());
+    }
+
+    let (addr, token) = (msg.addr(), msg.data().token);
+    let exchange = self.exchanges.map_mut(|es| {
+                                   es.iter()
+                                     .position(|e| e.addr == addr && e.token == token)
+                                     .and_then(|ix| es.remove(ix))
+                                 });
+
+    match exchange {
+      | Some(Exchange { acked: false, id, .. }) => {
+        let m = msg.as_mut();
+        m.ty = Type::Ack;
+        m.id = id;
+      },
+      | Some(Exchange { acked: true, .. }) if msg.data().ty == Type::Non => {
+        msg.as_mut().ty = Type::Con;
+      },
+      | _ => (),
+    }
+
+    Ok(())
+  }
+
+  fn next_deadline(&self, config: Config) -> Option<Instant<P::Clock>> {
+    let ack_at = self.exchanges
+                     .map_ref(|es| es.iter().filter_map(|e| e.ack_at(config)).min());
+    super::earliest(self.inner.next_deadline(config), ack_at)
   }
 }
 
 #[cfg(test)]
 mod test {
+  use embedded_time::duration::Milliseconds;
   use toad_msg::{Code, Type};
 
   use super::super::test;
-  use super::{Ack, Effect, Step};
+  use super::{Effect, Exchange, Step};
   use crate::net::Addrd;
   use crate::platform;
   use crate::req::Req;
   use crate::resp::Resp;
+  use crate::test::ClockMock;
 
   type InnerPollReq = super::InnerPollReq<crate::test::Platform>;
   type InnerPollResp = super::InnerPollResp<crate::test::Platform>;
+  type Ack<S> = super::Ack<S, Vec<Exchange<crate::test::Platform>>>;
+
+  fn empty_ack() -> platform::Message<crate::test::Platform> {
+    platform::Message::<crate::test::Platform>::new(Type::Ack,
+                                                    Code::EMPTY,
+                                                    toad_msg::Id(1),
+                                                    toad_msg::Token(Default::default()))
+  }
+
+  fn non_resp() -> Addrd<platform::Message<crate::test::Platform>> {
+    let mut resp = Resp::non(&test_msg(Type::Con, Code::GET).0 .0);
+    resp.msg_mut().id = toad_msg::Id(2);
+    Addrd(resp.into(), crate::test::dummy_addr())
+  }
 
   fn test_msg(ty: Type,
               code: Code)
@@ -151,21 +378,70 @@ mod test {
   test::test_step!(
       GIVEN Ack::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
       WHEN inner_yields_con_request [
-        (inner.poll_req => { Some(Ok(test_msg(Type::Con, Code::new(0, 01)).0)) })
+        (inner.poll_req => { Some(Ok(test_msg(Type::Con, Code::GET).0)) })
+      ]
+      THEN poll_req_should_wait_for_response [
+        (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(test_msg(Type::Con, Code::GET).0))) }),
+        (effects == { vec![] }),
+        (before_message_sent(_, _, non_resp()) should be ok with { |msg| {
+          assert_eq!(msg.data().ty, Type::Ack);
+          assert_eq!(msg.data().id, toad_msg::Id(1));
+        }})
+      ]
+  );
+
+  test::test_step!(
+      GIVEN Ack::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+      WHEN inner_yields_con_request_and_no_piggyback_deadline [
+        (inner.poll_req => { Some(Ok(test_msg(Type::Con, Code::GET).0)) }),
+        (snapshot = {{
+          let mut snap = crate::test::snapshot();
+          snap.config.msg.piggyback_deadline = Milliseconds(0);
+          snap
+        }})
       ]
       THEN poll_req_should_ack [
-        (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(test_msg(Type::Con, Code::new(0, 01)).0))) }),
-        (effects == {
-          vec![
-            Effect::Send(
-              Addrd(
-                Resp::ack(&test_msg(Type::Con, Code::new(1, 01)).0.0)
-                  .into(),
-                crate::test::dummy_addr()
-              )
-            )
-          ]
-        })
+        (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(test_msg(Type::Con, Code::GET).0))) }),
+        (effects == { vec![Effect::Send(Addrd(empty_ack(), crate::test::dummy_addr()))] })
+      ]
+  );
+
+  test::test_step!(
+      GIVEN Ack::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+      WHEN con_request_not_responded_to_within_deadline [
+        (inner.poll_req => { Some(Ok(test_msg(Type::Con, Code::GET).0)) }),
+        ({|step: &Ack<Dummy>| step.poll_req(&crate::test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+        (inner.poll_req => { None }),
+        (snapshot = {{
+          let mut snap = crate::test::snapshot();
+          snap.time = ClockMock::instant(snap.config.msg.piggyback_deadline.0 * 1000);
+          snap
+        }})
+      ]
+      THEN empty_ack_should_be_sent_and_response_sent_con [
+        (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+        (effects should satisfy { |effs| {
+          assert!(effs.contains(&Effect::Send(Addrd(empty_ack(), crate::test::dummy_addr()))));
+        }}),
+        (before_message_sent(_, _, non_resp()) should be ok with { |msg| {
+          assert_eq!(msg.data().ty, Type::Con);
+          assert_eq!(msg.data().id, toad_msg::Id(2));
+        }})
+      ]
+  );
+
+  test::test_step!(
+      GIVEN Ack::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+      WHEN application_acks_con_request [
+        (inner.poll_req => { Some(Ok(test_msg(Type::Con, Code::GET).0)) }),
+        ({|step: &Ack<Dummy>| step.poll_req(&crate::test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+        ({|step: &Ack<Dummy>| step.before_message_sent(&crate::test::snapshot(), &mut vec![], &mut Addrd(empty_ack(), crate::test::dummy_addr())).unwrap()})
+      ]
+      THEN response_should_be_sent_con [
+        (before_message_sent(_, _, non_resp()) should be ok with { |msg| {
+          assert_eq!(msg.data().ty, Type::Con);
+          assert_eq!(msg.data().id, toad_msg::Id(2));
+        }})
       ]
   );
 
@@ -179,4 +455,37 @@ mod test {
         (effects == { vec![] })
       ]
   );
+
+  test::test_step!(
+      GIVEN Ack::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+      WHEN inner_yields_con_response [
+        (inner.poll_resp => { Some(Ok(test_msg(Type::Con, Code::new(2, 5)).1)) })
+      ]
+      THEN poll_resp_should_ack [
+        (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Ok(test_msg(Type::Con, Code::new(2, 5)).1))) }),
+        (effects == {
+          vec![
+            Effect::Send(Addrd(empty_ack(), crate::test::dummy_addr()))
+          ]
+        })
+      ]
+  );
+
+  #[test]
+  fn next_deadline_should_be_piggyback_deadline_of_unanswered_requests() {
+    type Mock = crate::test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = Ack::<Mock>::default();
+    let snap = crate::test::snapshot();
+    assert_eq!(s.next_deadline(snap.config), None);
+
+    s.inner()
+     .set_poll_req(|_, _, _| Some(Ok(test_msg(Type::Con, Code::GET).0)));
+    s.poll_req(&snap, &mut vec![]).unwrap().unwrap();
+    assert_eq!(s.next_deadline(snap.config),
+               Some(snap.time + snap.config.msg.piggyback_deadline));
+
+    s.before_message_sent(&snap, &mut vec![], &mut non_resp())
+     .unwrap();
+    assert_eq!(s.next_deadline(snap.config), None);
+  }
 }
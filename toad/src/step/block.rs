@@ -0,0 +1,1555 @@
+use core::fmt::Write;
+
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use no_std_net::SocketAddr;
+use toad_array::{AppendCopy, Array, Indexed};
+use toad_len::Len;
+use toad_msg::{CacheKey, CodeKind, DefaultCacheKey, Id, MessageOptions, Payload, Token, Type};
+use toad_stem::Stem;
+
+use super::{exec_inner_step, log, Step, StepOutput, SUPPRESS};
+use crate::config::Config;
+use crate::net::Addrd;
+use crate::platform::toad_msg::opt::SetError;
+use crate::platform::{self, Effect, PlatformTypes, Snapshot};
+use crate::req::Req;
+use crate::resp::{code, Resp};
+use crate::time::Clock;
+use crate::todo::String;
+
+/// A request that we've yielded to the application,
+/// and (once the application responds) the full response
+/// that is being served to the client in blocks.
+pub struct Transfer<P>
+  where P: PlatformTypes
+{
+  addr: SocketAddr,
+  token: Token,
+  cache_key: u64,
+  size: u16,
+  num: u32,
+  block1: Option<toad_msg::block::Block>,
+  resp: Option<platform::Message<P>>,
+  last_touched: Instant<P::Clock>,
+}
+
+impl<P> core::fmt::Debug for Transfer<P> where P: PlatformTypes
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Transfer")
+     .field("addr", &self.addr)
+     .field("token", &self.token)
+     .field("cache_key", &self.cache_key)
+     .field("size", &self.size)
+     .field("num", &self.num)
+     .field("block1", &self.block1)
+     .field("resp", &self.resp)
+     .field("last_touched", &self.last_touched)
+     .finish()
+  }
+}
+
+impl<P> Transfer<P> where P: PlatformTypes
+{
+  /// The address of the client we're transferring to
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Token of the most recent request in this transfer
+  pub fn token(&self) -> Token {
+    self.token
+  }
+
+  /// Cache key of the request(s) in this transfer.
+  ///
+  /// Requests for different blocks of the same response
+  /// will have the same cache key, since the
+  /// [Block2](toad_msg::opt::known::no_repeat::BLOCK2)
+  /// option does not contribute to it.
+  pub fn cache_key(&self) -> u64 {
+    self.cache_key
+  }
+
+  /// The full response being transferred, if the application
+  /// has responded yet.
+  pub fn resp(&self) -> Option<&platform::Message<P>> {
+    self.resp.as_ref()
+  }
+
+  fn is_expired(&self, now: Instant<P::Clock>, config: Config) -> bool {
+    expired(self.last_touched, now, config)
+  }
+}
+
+/// A request body being received from a client in blocks
+pub struct Assembly<P>
+  where P: PlatformTypes
+{
+  addr: SocketAddr,
+  cache_key: u64,
+  payload: P::MessagePayload,
+  last_touched: Instant<P::Clock>,
+}
+
+impl<P> core::fmt::Debug for Assembly<P> where P: PlatformTypes
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Assembly")
+     .field("addr", &self.addr)
+     .field("cache_key", &self.cache_key)
+     .field("payload", &self.payload)
+     .field("last_touched", &self.last_touched)
+     .finish()
+  }
+}
+
+impl<P> Assembly<P> where P: PlatformTypes
+{
+  /// The address of the client sending us the request body
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Cache key of the requests carrying the blocks of the body
+  ///
+  /// Since the [Block1](toad_msg::opt::known::no_repeat::BLOCK1)
+  /// option does not contribute to the cache key, all requests in the
+  /// transfer will have the same cache key.
+  pub fn cache_key(&self) -> u64 {
+    self.cache_key
+  }
+
+  /// The blocks of the request body received so far
+  pub fn payload(&self) -> &[u8] {
+    &self.payload
+  }
+
+  fn is_expired(&self, now: Instant<P::Clock>, config: Config) -> bool {
+    expired(self.last_touched, now, config)
+  }
+}
+
+/// A response being received from a server in blocks
+pub struct Fetch<P>
+  where P: PlatformTypes
+{
+  addr: SocketAddr,
+  req: platform::Message<P>,
+  resp: Option<platform::Message<P>>,
+  last_touched: Instant<P::Clock>,
+}
+
+impl<P> core::fmt::Debug for Fetch<P> where P: PlatformTypes
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Fetch")
+     .field("addr", &self.addr)
+     .field("req", &self.req)
+     .field("resp", &self.resp)
+     .field("last_touched", &self.last_touched)
+     .finish()
+  }
+}
+
+impl<P> Fetch<P> where P: PlatformTypes
+{
+  /// The address of the server we sent the request to
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Token of the request, re-used when asking for subsequent blocks
+  pub fn token(&self) -> Token {
+    self.req.token
+  }
+
+  /// The request that was sent, used as a template when asking
+  /// for subsequent blocks
+  pub fn req(&self) -> &platform::Message<P> {
+    &self.req
+  }
+
+  /// The blocks of the response received so far
+  pub fn payload(&self) -> &[u8] {
+    self.resp.as_ref().map(|r| &r.payload.0[..]).unwrap_or(&[])
+  }
+
+  fn is_expired(&self, now: Instant<P::Clock>, config: Config) -> bool {
+    expired(self.last_touched, now, config)
+  }
+}
+
+fn expired<C>(last_touched: Instant<C>, now: Instant<C>, config: Config) -> bool
+  where C: Clock
+{
+  now.checked_duration_since(&last_touched)
+     .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+     .map(|Milliseconds(ms)| ms >= config.exchange_lifetime_millis())
+     .unwrap_or(false)
+}
+
+/// Errors that can be encountered when transferring messages in blocks
+#[derive(Clone, PartialEq, Eq)]
+pub enum Error<E> {
+  /// The inner step failed.
+  ///
+  /// This variant's Debug representation is completely
+  /// replaced by the inner type E's debug representation
+  Inner(E),
+  /// While fetching a response in blocks, a block arrived with
+  /// a different [ETag](toad_msg::opt::known::repeat::ETAG) than
+  /// the blocks before it, meaning the resource changed mid-transfer.
+  ///
+  /// The blocks received so far are discarded; the request may
+  /// be sent again to fetch the new representation.
+  EtagMismatch(Addrd<Token>),
+}
+
+impl<E> From<E> for Error<E> {
+  fn from(e: E) -> Self {
+    Error::Inner(e)
+  }
+}
+
+impl<E: core::fmt::Debug> core::fmt::Debug for Error<E> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match self {
+      | Self::EtagMismatch(t) => f.debug_tuple("EtagMismatch").field(t).finish(),
+      | Self::Inner(e) => e.fmt(f),
+    }
+  }
+}
+
+impl<E: super::Error> super::Error for Error<E> {
+  fn is_timeout(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_timeout())
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_unacknowledged())
+  }
+}
+
+/// Step responsible for sending responses that are too big to fit
+/// in a single message as a sequence of Block2 blocks, for
+/// reassembling request bodies received as a sequence of Block1 blocks,
+/// and for fetching the rest of responses that we receive in Block2 blocks.
+///
+/// For more information, see the [module documentation](crate::step::block).
+#[derive(Debug)]
+pub struct Block<S, Transfers, Assemblies, Fetches> {
+  inner: S,
+  transfers: Stem<Transfers>,
+  assemblies: Stem<Assemblies>,
+  fetches: Stem<Fetches>,
+}
+
+impl<S, Transfers, Assemblies, Fetches> Default for Block<S, Transfers, Assemblies, Fetches>
+  where S: Default,
+        Transfers: Default,
+        Assemblies: Default,
+        Fetches: Default
+{
+  fn default() -> Self {
+    Self { inner: S::default(),
+           transfers: Stem::new(Transfers::default()),
+           assemblies: Stem::new(Assemblies::default()),
+           fetches: Stem::new(Fetches::default()) }
+  }
+}
+
+impl<S, Transfers, Assemblies, Fetches> Block<S, Transfers, Assemblies, Fetches> {
+  fn cache_key<P>(msg: &platform::Message<P>) -> u64
+    where P: PlatformTypes
+  {
+    DefaultCacheKey::new().cache_key(msg)
+  }
+
+  /// The block size that should be used when responding to `req`;
+  /// the smaller of the size the client asked for and [`Msg.block_size`](crate::config::Msg.block_size)
+  fn block_size<P>(config: &Config, req: &platform::Message<P>) -> u16
+    where P: PlatformTypes
+  {
+    req.block2()
+       .map(|b| b.size())
+       .unwrap_or(config.block_size())
+       .min(config.block_size())
+  }
+
+  /// Copy block `num` of `full` into a new message.
+  ///
+  /// Yields `None` if `num` is beyond the end of the payload,
+  /// and errors if the block's Block2 option couldn't be set.
+  fn block_of<P>(full: &platform::Message<P>,
+                 size: u16,
+                 num: u32)
+                 -> Result<Option<platform::Message<P>>, SetError<P>>
+    where P: PlatformTypes
+  {
+    let len = full.payload.0.len();
+    let start = size as usize * num as usize;
+
+    if start >= len && num > 0 {
+      return Ok(None);
+    }
+
+    let end = (start + size as usize).min(len);
+
+    let mut msg = full.clone();
+    msg.payload = Payload(full.payload.0[start..end].iter().copied().collect());
+    msg.set_block2(size, num, end < len)?;
+
+    // Size2 is only a hint, so the block is still usable without it
+    msg.set_size2(len as u64).ok();
+
+    Ok(Some(msg))
+  }
+
+  /// If `msg` is a response too big for the block size the client asked for
+  /// (or [`Msg.block_size`](crate::config::Msg.block_size)), replace it with the block
+  /// the client asked for and store it so that the rest may be served.
+  fn slice<P>(&self,
+              snap: &Snapshot<P>,
+              effs: &mut P::Effects,
+              msg: &mut Addrd<platform::Message<P>>)
+    where P: PlatformTypes,
+          Transfers: Array<Item = Transfer<P>>
+  {
+    if msg.data().code.kind() != CodeKind::Response || msg.data().block2().is_some() {
+      return;
+    }
+
+    let (addr, token) = (msg.addr(), msg.data().token);
+
+    self.transfers.map_mut(|ts| {
+                    let ix =
+                      ts.iter()
+                        .position(|t| t.addr == addr && t.token == token && t.resp.is_none());
+
+                    let (size, num) = ix.map(|ix| (ts[ix].size, ts[ix].num))
+                                        .unwrap_or((snap.config.block_size(), 0));
+
+                    // echo the final Block1 option of a request body we reassembled
+                    if let Some(b) = ix.and_then(|ix| ts[ix].block1) {
+                      msg.data_mut().set_block1(b.size(), b.num(), false).ok();
+                    }
+
+                    if num == 0 && msg.data().payload.0.len() <= size as usize {
+                      if let Some(ix) = ix {
+                        ts.remove(ix);
+                      }
+                      return;
+                    }
+
+                    let full = msg.data().clone();
+                    match Self::block_of::<P>(&full, size, num) {
+                      | Ok(Some(block)) => *msg.data_mut() = block,
+                      | Ok(None) => {
+                        msg.data_mut().code = code::BAD_OPTION;
+                        msg.data_mut().payload = Payload(Default::default());
+                      },
+                      | Err(_) => {
+                        log!(Block::slice,
+                             effs,
+                             log::Level::Error,
+                             "{}b response to {} is too big for one message, and Block2 couldn't be added to it",
+                             full.payload.0.len(),
+                             addr);
+                        msg.data_mut().code = code::INTERNAL_SERVER_ERROR;
+                        msg.data_mut().payload = Payload(Default::default());
+                        if let Some(ix) = ix {
+                          ts.remove(ix);
+                        }
+                        return;
+                      },
+                    }
+
+                    let mut log_msg = String::<1000>::default();
+                    write!(log_msg,
+                           "{}b response to {} is too big for one message, sending {:?}",
+                           full.payload.0.len(),
+                           addr,
+                           msg.data().block2()).ok();
+                    effs.append(Effect::Log(log::Level::Debug, log_msg));
+
+                    // if we didn't remember the request, we can't recognize requests
+                    // for the rest of this response; they'll be yielded to the application,
+                    // and the response to the first of them will be stored.
+                    if let Some(ix) = ix {
+                      ts[ix].resp = Some(full);
+                      ts[ix].last_touched = snap.time;
+                    }
+                  });
+  }
+
+  fn prune<P>(effs: &mut P::Effects,
+              transfers: &mut Transfers,
+              now: Instant<P::Clock>,
+              config: Config)
+    where P: PlatformTypes,
+          Transfers: Array<Item = Transfer<P>>
+  {
+    while let Some(ix) = transfers.iter().position(|t| t.is_expired(now, config)) {
+      let t = transfers.remove(ix).unwrap();
+      log!(Block::prune,
+           effs,
+           log::Level::Debug,
+           "Transfer to {} of {:?} expired",
+           t.addr,
+           t.token);
+    }
+  }
+
+  /// Remember that we yielded `req` to the application, so that we may
+  /// later respond with the correct block size.
+  fn track<P>(effs: &mut P::Effects, transfers: &mut Transfers, t: Transfer<P>)
+    where P: PlatformTypes,
+          Transfers: Array<Item = Transfer<P>>
+  {
+    if let Some(ix) = transfers.iter()
+                               .position(|o| o.addr == t.addr && o.cache_key == t.cache_key)
+    {
+      transfers.remove(ix);
+    }
+
+    if transfers.is_full() {
+      // Make room by forgetting the transfer that was touched least recently
+      let oldest = transfers.iter()
+                            .enumerate()
+                            .min_by_key(|(_, t)| t.last_touched)
+                            .map(|(ix, _)| ix);
+
+      if let Some(ix) = oldest {
+        let old = transfers.remove(ix).unwrap();
+        log!(Block::track,
+             effs,
+             log::Level::Warn,
+             "Transfer buffer reached capacity of {}. Forgetting transfer to {} of {:?} to make room",
+             Transfers::CAPACITY.unwrap_or(usize::MAX),
+             old.addr,
+             old.token);
+      }
+    }
+
+    transfers.append(t);
+  }
+
+  /// Create a response to `req` with no payload
+  fn respond_empty<P>(req: &Addrd<Req<P>>, code: toad_msg::Code) -> Addrd<platform::Message<P>>
+    where P: PlatformTypes
+  {
+    let mut resp = Resp::for_request(req.data()).unwrap_or_else(|| Resp::non(req.data()));
+    resp.set_code(code);
+    Addrd(resp.into(), req.addr())
+  }
+
+  fn prune_assemblies<P>(effs: &mut P::Effects,
+                         assemblies: &mut Assemblies,
+                         now: Instant<P::Clock>,
+                         config: Config)
+    where P: PlatformTypes,
+          Assemblies: Array<Item = Assembly<P>>
+  {
+    while let Some(ix) = assemblies.iter().position(|a| a.is_expired(now, config)) {
+      let a = assemblies.remove(ix).unwrap();
+      log!(Block::prune_assemblies,
+           effs,
+           log::Level::Debug,
+           "Gave up waiting for the rest of {}b request body from {}",
+           a.payload.len(),
+           a.addr);
+    }
+  }
+
+  /// If `req` carries a block of a request body, store it.
+  ///
+  /// Yields `Ok` with the request (with the complete body) when there is nothing more to wait for,
+  /// or `Err` with a response that should be sent to the client in place of yielding the request.
+  fn assemble<P>(effs: &mut P::Effects,
+                 assemblies: &mut Assemblies,
+                 now: Instant<P::Clock>,
+                 config: &Config,
+                 mut req: Addrd<Req<P>>)
+                 -> Result<Addrd<Req<P>>, Addrd<platform::Message<P>>>
+    where P: PlatformTypes,
+          Assemblies: Array<Item = Assembly<P>>
+  {
+    let (addr, msg) = (req.addr(), req.data().msg());
+    let block = match msg.block1() {
+      | Some(b) => b,
+      | None => return Ok(req),
+    };
+
+    let key = Self::cache_key::<P>(msg);
+    let find = |assemblies: &Assemblies| {
+      assemblies.iter()
+                .position(|a| a.addr == addr && a.cache_key == key)
+    };
+
+    if block.num() == 0 {
+      if let Some(ix) = find(assemblies) {
+        assemblies.remove(ix);
+      }
+
+      if !block.more() {
+        return Ok(req);
+      }
+
+      if assemblies.is_full() {
+        let oldest = assemblies.iter()
+                               .enumerate()
+                               .min_by_key(|(_, a)| a.last_touched)
+                               .map(|(ix, _)| ix);
+
+        if let Some(ix) = oldest {
+          let old = assemblies.remove(ix).unwrap();
+          log!(Block::assemble,
+               effs,
+               log::Level::Warn,
+               "Request body buffer reached capacity of {}. Forgetting {}b request body from {} to make room",
+               Assemblies::CAPACITY.unwrap_or(usize::MAX),
+               old.payload.len(),
+               old.addr);
+        }
+      }
+
+      assemblies.append(Assembly { addr,
+                                 cache_key: key,
+                                 payload: Default::default(),
+                                 last_touched: now });
+    }
+
+    let ix = match find(assemblies) {
+      | Some(ix) => ix,
+      | None => {
+        log!(Block::assemble,
+             effs,
+             log::Level::Debug,
+             "{} sent {:?} of a request body we aren't receiving",
+             addr,
+             block);
+        return Err(Self::respond_empty(&req, code::REQUEST_ENTITY_INCOMPLETE));
+      },
+    };
+
+    let offset = block.num() as usize * block.size() as usize;
+    let received = assemblies[ix].payload.len();
+    let len = msg.payload.0.len();
+
+    let continue_ = || {
+      let mut resp = Self::respond_empty(&req, code::CONTINUE);
+      let size = block.size().min(config.block_size());
+      resp.data_mut().set_block1(size, block.num(), true).ok();
+      resp
+    };
+
+    if block.more() && offset < received && offset + len <= received {
+      // we've already seen this block; the client probably didn't receive our response.
+      return Err(continue_());
+    }
+
+    if offset != received {
+      assemblies.remove(ix);
+      log!(Block::assemble,
+           effs,
+           log::Level::Debug,
+           "{} sent {:?} (starting at byte {}) after only {} bytes were received",
+           addr,
+           block,
+           offset,
+           received);
+      return Err(Self::respond_empty(&req, code::REQUEST_ENTITY_INCOMPLETE));
+    }
+
+    // a fixed-capacity payload can't hold more than its capacity,
+    // regardless of how much we're willing to buffer
+    let max = (config.msg.max_request_body_bytes_per_peer as usize)
+                .min(<P::MessagePayload as Len>::CAPACITY.unwrap_or(usize::MAX));
+    let buffered_for_peer: usize = assemblies.iter()
+                                             .filter(|a| a.addr == addr)
+                                             .map(|a| a.payload.len())
+                                             .sum();
+    let too_big =
+      buffered_for_peer + len > max || msg.size1().map(|s| s > max as u64).unwrap_or(false);
+
+    if too_big {
+      assemblies.remove(ix);
+      log!(Block::assemble,
+           effs,
+           log::Level::Warn,
+           "Request body from {} exceeds limit of {} bytes",
+           addr,
+           max);
+      let mut resp = Self::respond_empty(&req, code::REQUEST_ENTITY_TOO_LARGE);
+      resp.data_mut().set_size1(max as u64).ok();
+      return Err(resp);
+    }
+
+    let a = &mut assemblies[ix];
+    a.payload.append_copy(&msg.payload.0);
+    a.last_touched = now;
+
+    if block.more() {
+      return Err(continue_());
+    }
+
+    let a = assemblies.remove(ix).unwrap();
+    req.data_mut().msg_mut().payload = Payload(a.payload);
+    Ok(req)
+  }
+
+  /// If `req` asks for a block of a response we have buffered,
+  /// create a response containing that block.
+  fn serve<P>(transfers: &mut Transfers,
+              now: Instant<P::Clock>,
+              config: &Config,
+              req: &Addrd<Req<P>>)
+              -> Option<Addrd<platform::Message<P>>>
+    where P: PlatformTypes,
+          Transfers: Array<Item = Transfer<P>>
+  {
+    let msg = req.data().msg();
+    let num = msg.block2().map(|b| b.num()).unwrap_or(0);
+
+    if num == 0 {
+      return None;
+    }
+
+    let key = Self::cache_key::<P>(msg);
+    let size = Self::block_size::<P>(config, msg);
+
+    let t = transfers.iter_mut()
+                     .find(|t| t.addr == req.addr() && t.cache_key == key && t.resp.is_some())?;
+    t.token = msg.token;
+    t.last_touched = now;
+
+    let block = match Self::block_of::<P>(t.resp.as_ref().unwrap(), size, num) {
+      | Ok(Some(mut block)) => {
+        block.remove(toad_msg::opt::known::no_repeat::BLOCK1);
+        block.token = msg.token;
+        match msg.ty {
+          | Type::Con => {
+            block.ty = Type::Ack;
+            block.id = msg.id;
+          },
+          | _ => {
+            block.ty = Type::Non;
+            block.id = Id(0);
+          },
+        };
+        block
+      },
+      | Ok(None) => {
+        let mut resp = Resp::for_request(req.data()).unwrap_or_else(|| Resp::non(req.data()));
+        resp.set_code(code::BAD_OPTION);
+        resp.into()
+      },
+      | Err(_) => {
+        let mut resp = Resp::for_request(req.data()).unwrap_or_else(|| Resp::non(req.data()));
+        resp.set_code(code::INTERNAL_SERVER_ERROR);
+        resp.into()
+      },
+    };
+
+    Some(Addrd(block, req.addr()))
+  }
+
+  fn prune_fetches<P>(effs: &mut P::Effects,
+                      fetches: &mut Fetches,
+                      now: Instant<P::Clock>,
+                      config: Config)
+    where P: PlatformTypes,
+          Fetches: Array<Item = Fetch<P>>
+  {
+    while let Some(ix) = fetches.iter().position(|f| f.is_expired(now, config)) {
+      let f = fetches.remove(ix).unwrap();
+      if f.resp.is_some() {
+        log!(Block::prune_fetches,
+             effs,
+             log::Level::Debug,
+             "Gave up waiting for the rest of the response to {:?} from {}",
+             f.req.token,
+             f.addr);
+      }
+    }
+  }
+
+  /// Remember a request that we sent, so that if the response
+  /// comes back in blocks we can ask for the rest of them.
+  fn track_fetch<P>(effs: &mut P::Effects,
+                    fetches: &mut Fetches,
+                    now: Instant<P::Clock>,
+                    msg: &Addrd<platform::Message<P>>)
+    where P: PlatformTypes,
+          Fetches: Array<Item = Fetch<P>>
+  {
+    let (addr, req) = (msg.addr(), msg.data());
+
+    // requests for blocks after the first continue a fetch rather than starting one
+    if req.code.kind() != CodeKind::Request || req.block2().map(|b| b.num() > 0).unwrap_or(false) {
+      return;
+    }
+
+    if let Some(ix) = fetches.iter()
+                             .position(|f| f.addr == addr && f.req.token == req.token)
+    {
+      if fetches[ix].req.id == req.id {
+        // retransmission of a request we're already tracking
+        return;
+      }
+
+      fetches.remove(ix);
+    }
+
+    if fetches.is_full() {
+      let oldest = fetches.iter()
+                          .enumerate()
+                          .min_by_key(|(_, f)| f.last_touched)
+                          .map(|(ix, _)| ix);
+
+      if let Some(ix) = oldest {
+        let old = fetches.remove(ix).unwrap();
+        log!(Block::track_fetch,
+             effs,
+             log::Level::Debug,
+             "Fetch buffer reached capacity of {}. Forgetting request {:?} to {} to make room",
+             Fetches::CAPACITY.unwrap_or(usize::MAX),
+             old.req.token,
+             old.addr);
+      }
+    }
+
+    fetches.append(Fetch { addr,
+                         req: req.clone(),
+                         resp: None,
+                         last_touched: now });
+  }
+
+  fn same_etag<P>(a: &platform::Message<P>, b: &platform::Message<P>) -> bool
+    where P: PlatformTypes
+  {
+    match (a.etags(), b.etags()) {
+      | (None, None) => true,
+      | (Some(a), Some(b)) => a.iter().map(|v| &v.0).eq(b.iter().map(|v| &v.0)),
+      | _ => false,
+    }
+  }
+
+  /// If `resp` carries a block of a response to a request we sent, store it.
+  ///
+  /// Yields `Ok(Some)` with the response (with the complete payload) when there is
+  /// nothing more to fetch, or `Ok(None)` when the next block has been requested.
+  fn fetch<P, E>(effs: &mut P::Effects,
+                 fetches: &mut Fetches,
+                 now: Instant<P::Clock>,
+                 mut resp: Addrd<Resp<P>>)
+                 -> Result<Option<Addrd<Resp<P>>>, Error<E>>
+    where P: PlatformTypes,
+          Fetches: Array<Item = Fetch<P>>
+  {
+    let (addr, msg) = (resp.addr(), resp.data().msg());
+
+    if msg.code.kind() != CodeKind::Response {
+      return Ok(Some(resp));
+    }
+
+    let ix = match fetches.iter()
+                          .position(|f| f.addr == addr && f.req.token == msg.token)
+    {
+      | Some(ix) => ix,
+      | None => return Ok(Some(resp)),
+    };
+
+    let block = match msg.block2() {
+      | Some(b) => b,
+      | None => {
+        fetches.remove(ix);
+        return Ok(Some(resp));
+      },
+    };
+
+    let f = &mut fetches[ix];
+    let offset = block.num() as usize * block.size() as usize;
+    let received = f.payload().len();
+
+    if offset < received {
+      // we've already seen this block; probably a retransmission
+      return Ok(None);
+    }
+
+    if offset != received {
+      fetches.remove(ix);
+      log!(Block::fetch,
+           effs,
+           log::Level::Warn,
+           "{} sent {:?} (starting at byte {}) after only {} bytes were received",
+           addr,
+           block,
+           offset,
+           received);
+      return Ok(Some(resp));
+    }
+
+    match f.resp.as_mut() {
+      | Some(first) if !Self::same_etag::<P>(first, msg) => {
+        fetches.remove(ix);
+        log!(Block::fetch,
+             effs,
+             log::Level::Warn,
+             "ETag of response to {:?} from {} changed after {} bytes were received",
+             msg.token,
+             addr,
+             received);
+        return Err(Error::EtagMismatch(Addrd(msg.token, addr)));
+      },
+      | Some(first) => first.payload.0.append_copy(&msg.payload.0),
+      | None => f.resp = Some(msg.clone()),
+    }
+
+    f.last_touched = now;
+
+    if block.more() {
+      let mut next = f.req.clone();
+      next.id = Id(0);
+      next.payload = Payload(Default::default());
+      next.remove(toad_msg::opt::known::no_repeat::BLOCK1);
+      if next.set_block2(block.size(), block.num() + 1, false)
+             .is_err()
+      {
+        fetches.remove(ix);
+        log!(Block::fetch,
+             effs,
+             log::Level::Warn,
+             "Couldn't ask {} for block {} of response to {:?}; yielding the block we got as-is",
+             addr,
+             block.num() + 1,
+             msg.token);
+        return Ok(Some(resp));
+      }
+
+      log!(Block::fetch,
+           effs,
+           log::Level::Trace,
+           "Received {:?} of response to {:?} from {}, requesting the next block",
+           block,
+           msg.token,
+           addr);
+      effs.append(Effect::Send(Addrd(next, addr)));
+      return Ok(None);
+    }
+
+    let f = fetches.remove(ix).unwrap();
+    let msg = resp.data_mut().msg_mut();
+    msg.payload = f.resp.unwrap().payload;
+    msg.remove(toad_msg::opt::known::no_repeat::BLOCK2);
+    Ok(Some(resp))
+  }
+}
+
+impl<P, E, S, Transfers, Assemblies, Fetches> Step<P> for Block<S, Transfers, Assemblies, Fetches>
+  where P: PlatformTypes,
+        E: super::Error,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>, Error = E>,
+        Transfers: Array<Item = Transfer<P>>,
+        Assemblies: Array<Item = Assembly<P>>,
+        Fetches: Array<Item = Fetch<P>>
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = Error<E>;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut P::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    let req = exec_inner_step!(self.inner.poll_req(snap, effects), Error::Inner);
+
+    self.transfers
+        .map_mut(|ts| Self::prune(effects, ts, snap.time, snap.config));
+    self.assemblies
+        .map_mut(|asm| Self::prune_assemblies(effects, asm, snap.time, snap.config));
+
+    let req = match req {
+      | Some(req) if req.data().msg().code.kind() == CodeKind::Request => req,
+      | Some(req) => return Some(Ok(req)),
+      | None => return None,
+    };
+
+    let block1 = req.data().msg().block1();
+
+    let mut req = Some(req);
+    let req = match self.assemblies.map_mut(|asm| {
+                                     Self::assemble(effects,
+                                                    asm,
+                                                    snap.time,
+                                                    &snap.config,
+                                                    Option::take(&mut req).unwrap())
+                                   }) {
+      | Ok(req) => req,
+      | Err(resp) => {
+        effects.append(Effect::Send(resp));
+        return None;
+      },
+    };
+
+    match self.transfers
+              .map_mut(|ts| Self::serve(ts, snap.time, &snap.config, &req))
+    {
+      | Some(block) => {
+        log!(Block::poll_req,
+             effects,
+             log::Level::Trace,
+             "Serving {:?} to {}",
+             block.data().block2(),
+             block.addr());
+        effects.append(Effect::Send(block));
+        None
+      },
+      | None => {
+        let msg = req.data().msg();
+
+        // responses to requests that didn't ask for a block (or send a request body
+        // in blocks) are sliced using `block_size` without needing to remember the request
+        if msg.block2().is_some() || block1.is_some() {
+          let t = Transfer { addr: req.addr(),
+                             token: msg.token,
+                             cache_key: Self::cache_key::<P>(msg),
+                             size: Self::block_size::<P>(&snap.config, msg),
+                             num: msg.block2().map(|b| b.num()).unwrap_or(0),
+                             block1,
+                             resp: None,
+                             last_touched: snap.time };
+          let mut t = Some(t);
+          self.transfers
+              .map_mut(|ts| Self::track(effects, ts, Option::take(&mut t).unwrap()));
+        }
+
+        Some(Ok(req))
+      },
+    }
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut P::Effects,
+               token: Token,
+               addr: SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    let resp = exec_inner_step!(self.inner.poll_resp(snap, effects, token, addr),
+                                Error::Inner);
+
+    self.fetches
+        .map_mut(|fs| Self::prune_fetches(effects, fs, snap.time, snap.config));
+
+    let mut resp = match resp {
+      | Some(resp) => Some(resp),
+      | None => return None,
+    };
+    match self.fetches
+              .map_mut(|fs| Self::fetch(effects, fs, snap.time, Option::take(&mut resp).unwrap()))
+    {
+      | Ok(Some(resp)) => Some(Ok(resp)),
+      | Ok(None) => None,
+      | Err(e) => Some(Err(nb::Error::Other(e))),
+    }
+  }
+
+  fn before_message_sent(&self,
+                         snap: &Snapshot<P>,
+                         effs: &mut P::Effects,
+                         msg: &mut Addrd<platform::Message<P>>)
+                         -> Result<(), Self::Error> {
+    // Responses are sliced & requests copied before the inner steps see them,
+    // since protecting them with OSCORE encrypts the payload & Block options
+    // (RFC8613 section 4.1.3.4.2)
+    let req = (msg.data().code.kind() == CodeKind::Request).then(|| msg.data().clone());
+    self.slice(snap, effs, msg);
+
+    self.inner
+        .before_message_sent(snap, effs, msg)
+        .map_err(Error::Inner)?;
+
+    if let Some(mut req) = req.filter(|_| msg.data().get(SUPPRESS).is_none()) {
+      // the id & token may have been provisioned by the inner steps
+      req.id = msg.data().id;
+      req.token = msg.data().token;
+      let req = Addrd(req, msg.addr());
+      self.fetches
+          .map_mut(|fs| Self::track_fetch(effs, fs, snap.time, &req));
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use embedded_time::duration::Microseconds;
+  use tinyvec::array_vec;
+
+  use super::*;
+  use crate::step::test::test_step;
+  use crate::test::{self, ClockMock};
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type Transfers = Vec<Transfer<test::Platform>>;
+  type Assemblies = Vec<Assembly<test::Platform>>;
+  type Fetches = Vec<Fetch<test::Platform>>;
+  type Block<S> = super::Block<S, Transfers, Assemblies, Fetches>;
+
+  fn req(ty: Type, block2: Option<(u16, u32)>) -> InnerPollReq {
+    let mut msg = test::msg!({ty} {toad_msg::Code::GET} x.x.x.x:80).unwrap();
+    msg.id = Id(2);
+    msg.token = Token(array_vec!(2));
+    msg.set_path("firmware").ok();
+    if let Some((size, num)) = block2 {
+      msg.set_block2(size, num, false).ok();
+    }
+
+    Addrd(Req::from(msg), test::x.x.x.x(80))
+  }
+
+  fn resp(payload_len: usize) -> Addrd<test::Message> {
+    let mut msg = test::msg!(NON {2 . 5} x.x.x.x:80).unwrap();
+    msg.token = Token(array_vec!(2));
+    msg.payload = Payload((0..payload_len).map(|n| n as u8).collect());
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn upload(ty: Type, num: u32, more: bool, payload: core::ops::Range<usize>) -> InnerPollReq {
+    let mut msg = test::msg!({ty} {toad_msg::Code::PUT} x.x.x.x:80).unwrap();
+    msg.id = Id(3 + num as u16);
+    msg.token = Token(array_vec!(3));
+    msg.set_path("logs").ok();
+    msg.set_block1(64, num, more).ok();
+    msg.payload = Payload(payload.map(|n| n as u8).collect());
+
+    Addrd(Req::from(msg), test::x.x.x.x(80))
+  }
+
+  fn get() -> Addrd<test::Message> {
+    let mut msg = test::msg!(CON GET x.x.x.x:80).unwrap();
+    msg.id = Id(4);
+    msg.token = Token(array_vec!(4));
+    msg.set_path("firmware").ok();
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn block(num: u32, more: bool, etag: u8) -> InnerPollResp {
+    let mut msg = test::msg!(ACK {2 . 5} x.x.x.x:80).unwrap();
+    msg.id = Id(4 + num as u16);
+    msg.token = Token(array_vec!(4));
+    msg.set_block2(64, num, more).ok();
+    msg.add_etag([etag]).ok();
+    msg.payload = Payload((num as usize * 64..num as usize * 64 + 64).map(|n| n as u8)
+                                                                     .collect());
+
+    Addrd(Resp::from(msg), test::x.x.x.x(80))
+  }
+
+  fn sent(effs: &[test::Effect]) -> Vec<Addrd<test::Message>> {
+    effs.iter()
+        .filter_map(|e| match e {
+          | Effect::Send(m) => Some(m.clone()),
+          | _ => None,
+        })
+        .collect()
+  }
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(Error::Inner(()))))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(Error::Inner(()))))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_blocks [
+      (inner.poll_req => { Some(Err(nb::Error::WouldBlock)) }),
+      (inner.poll_resp => { Some(Err(nb::Error::WouldBlock)) })
+    ]
+    THEN this_should_block [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::WouldBlock))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::WouldBlock))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN small_response_sent [
+      (inner.poll_req => { Some(Ok(req(Type::Con, None))) }),
+      ({|step: &Block<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      ({|step: &Block<Dummy>| assert!(step.transfers.map_ref(|ts| ts.is_empty()))})
+    ]
+    THEN response_should_be_unchanged [
+      (before_message_sent(_, _, resp(100)) should be ok with { |msg| {
+        assert_eq!(msg, resp(100));
+        assert_eq!(msg.data().block2(), None);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN large_response_sent [
+      (inner.poll_req => { Some(Ok(req(Type::Con, None))) }),
+      ({|step: &Block<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()})
+    ]
+    THEN response_should_contain_first_block [
+      (before_message_sent(_, _, resp(2500)) should be ok with { |msg| {
+        assert_eq!(msg.data().payload.0, resp(2500).data().payload.0[0..1024].to_vec());
+        assert_eq!(msg.data().block2(), Some(toad_msg::block::Block::new(1024, 0, true)));
+        assert_eq!(msg.data().size2(), Some(2500));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_asks_for_small_blocks [
+      (inner.poll_req => { Some(Ok(req(Type::Con, Some((64, 0))))) }),
+      ({|step: &Block<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()})
+    ]
+    THEN response_should_use_requested_size [
+      (before_message_sent(_, _, resp(100)) should be ok with { |msg| {
+        assert_eq!(msg.data().payload.0.len(), 64);
+        assert_eq!(msg.data().block2(), Some(toad_msg::block::Block::new(64, 0, true)));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_asks_for_next_block [
+      (inner.poll_req => { Some(Ok(req(Type::Con, Some((1024, 0))))) }),
+      ({|step: &Block<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      ({|step: &Block<Dummy>| step.before_message_sent(&test::snapshot(), &mut vec![], &mut resp(2500)).unwrap()}),
+      (inner.poll_req => { Some(Ok(req(Type::Con, Some((1024, 2))))) })
+    ]
+    THEN last_block_should_be_sent_from_buffer [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+
+        let block = sent[0].data();
+        assert_eq!(block.ty, Type::Ack);
+        assert_eq!(block.id, Id(2));
+        assert_eq!(block.token, Token(array_vec!(2)));
+        assert_eq!(block.payload.0, resp(2500).data().payload.0[2048..2500].to_vec());
+        assert_eq!(block.block2(), Some(toad_msg::block::Block::new(1024, 2, false)));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_asks_for_next_block_of_response_to_request_without_block2 [
+      (inner.poll_req => { Some(Ok(req(Type::Con, None))) }),
+      ({|step: &Block<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      ({|step: &Block<Dummy>| step.before_message_sent(&test::snapshot(), &mut vec![], &mut resp(2500)).unwrap()}),
+      ({|step: &Block<Dummy>| assert!(step.transfers.map_ref(|ts| ts.is_empty()))}),
+      (inner.poll_req => { Some(Ok(req(Type::Con, Some((1024, 1))))) })
+    ]
+    THEN request_should_be_yielded_and_response_stored [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req(Type::Con, Some((1024, 1)))))) }),
+      (before_message_sent(_, _, resp(2500)) should be ok with { |msg| {
+        assert_eq!(msg.data().payload.0, resp(2500).data().payload.0[1024..2048].to_vec());
+        assert_eq!(msg.data().block2(), Some(toad_msg::block::Block::new(1024, 1, true)));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_asks_for_block_out_of_range [
+      (inner.poll_req => { Some(Ok(req(Type::Non, Some((1024, 0))))) }),
+      ({|step: &Block<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      ({|step: &Block<Dummy>| step.before_message_sent(&test::snapshot(), &mut vec![], &mut resp(2500)).unwrap()}),
+      (inner.poll_req => { Some(Ok(req(Type::Non, Some((1024, 3))))) })
+    ]
+    THEN bad_option_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().ty, Type::Non);
+        assert_eq!(sent[0].data().code, code::BAD_OPTION);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_asks_for_block_of_unknown_response [
+      (inner.poll_req => { Some(Ok(req(Type::Con, Some((1024, 1))))) })
+    ]
+    THEN request_should_be_yielded [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req(Type::Con, Some((1024, 1)))))) }),
+      (before_message_sent(_, _, resp(2500)) should be ok with { |msg| {
+        assert_eq!(msg.data().payload.0, resp(2500).data().payload.0[1024..2048].to_vec());
+        assert_eq!(msg.data().block2(), Some(toad_msg::block::Block::new(1024, 1, true)));
+      }})
+    ]
+  );
+
+  #[test]
+  fn prune_should_forget_transfers_older_than_exchange_lifetime() {
+    type Step = Block<()>;
+
+    let cfg = Config::default();
+    let exchange_lifetime_micros = cfg.exchange_lifetime_millis() * 1_000;
+
+    // This test assumes that the clock considers 1 "tick" to be 1 microsecond.
+    assert_eq!(Microseconds::try_from(ClockMock::instant(1).duration_since_epoch()),
+               Ok(Microseconds(1u64)));
+
+    let transfer = |n: u8, time: u64| Transfer::<test::Platform> { addr: test::dummy_addr(),
+                                                                   token: Token::new(&[n]).unwrap(),
+                                                                   cache_key: n as u64,
+                                                                   size: 1024,
+                                                                   num: 0,
+                                                                   block1: None,
+                                                                   resp: None,
+                                                                   last_touched:
+                                                                     ClockMock::instant(time) };
+
+    let mut transfers: Transfers = vec![transfer(1, 0), transfer(2, 2_000)];
+
+    Step::prune(&mut vec![],
+                &mut transfers,
+                ClockMock::instant(exchange_lifetime_micros + 1_000),
+                cfg);
+
+    assert_eq!(transfers.iter().map(|t| t.token()).collect::<Vec<_>>(),
+               vec![Token(array_vec!(2))]);
+  }
+
+  #[test]
+  fn track_should_replace_transfer_of_same_resource() {
+    type Step = Block<()>;
+
+    let transfer = |n: u8, key: u64| Transfer::<test::Platform> { addr: test::dummy_addr(),
+                                                                  token: Token::new(&[n]).unwrap(),
+                                                                  cache_key: key,
+                                                                  size: 1024,
+                                                                  num: 0,
+                                                                  block1: None,
+                                                                  resp: None,
+                                                                  last_touched:
+                                                                    ClockMock::instant(0) };
+
+    let mut transfers: Transfers = vec![transfer(1, 1), transfer(2, 2)];
+    Step::track(&mut vec![], &mut transfers, transfer(3, 1));
+
+    assert_eq!(transfers.iter().map(|t| t.token()).collect::<Vec<_>>(),
+               vec![Token(array_vec!(2)), Token(array_vec!(3))]);
+  }
+
+  #[test]
+  fn block_size_should_be_rounded_to_one_block2_can_represent() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = Block::<Mock>::default();
+    s.inner()
+     .set_poll_req(|_, _, _| Some(Ok(req(Type::Con, None))));
+
+    let mut snap = test::snapshot();
+    snap.config.msg.block_size = 100;
+    s.poll_req(&snap, &mut vec![]).unwrap().unwrap();
+
+    let mut msg = resp(250);
+    s.before_message_sent(&snap, &mut vec![], &mut msg).unwrap();
+    assert_eq!(msg.data().payload.0.len(), 64);
+    assert_eq!(msg.data().block2(),
+               Some(toad_msg::block::Block::new(64, 0, true)));
+  }
+
+  #[test]
+  fn responses_should_be_sliced_before_inner_steps_protect_them() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = Block::<Mock>::default();
+    s.inner()
+     .set_poll_req(|_, _, _| Some(Ok(req(Type::Con, None))));
+    s.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap();
+
+    // like the Oscore step, encrypt the payload & Block2 option
+    s.inner().set_before_message_sent(|_, _, _, msg| {
+               assert_eq!(msg.data().payload.0.len(), 1024);
+               assert_eq!(msg.data().block2(),
+                          Some(toad_msg::block::Block::new(1024, 0, true)));
+               msg.as_mut().remove(toad_msg::opt::known::no_repeat::BLOCK2);
+               msg.as_mut().payload = Payload(vec![0; 1040]);
+               Ok(())
+             });
+
+    let mut msg = resp(2500);
+    s.before_message_sent(&test::snapshot(), &mut vec![], &mut msg)
+     .unwrap();
+    assert_eq!(msg.data().payload.0.len(), 1040);
+    assert_eq!(msg.data().block2(), None);
+  }
+
+  #[test]
+  fn next_block_should_be_requested_with_request_before_inner_steps_protect_it() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = Block::<Mock>::default();
+
+    // like the Oscore & ProvisionTokens steps, encrypt the options & provision a token
+    s.inner().set_before_message_sent(|_, _, _, msg| {
+               msg.as_mut().remove(toad_msg::opt::known::repeat::PATH);
+               msg.as_mut().token = Token(array_vec!(4));
+               Ok(())
+             });
+
+    let mut msg = get();
+    msg.as_mut().token = Token(Default::default());
+    s.before_message_sent(&test::snapshot(), &mut vec![], &mut msg)
+     .unwrap();
+
+    s.inner()
+     .set_poll_resp(|_, _, _, _, _| Some(Ok(block(0, true, 1))));
+    let mut effs = vec![];
+    assert!(s.poll_resp(&test::snapshot(),
+                        &mut effs,
+                        Token(array_vec!(4)),
+                        test::x.x.x.x(80))
+             .is_none());
+
+    let sent = sent(&effs);
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].data().token, Token(array_vec!(4)));
+    assert_eq!(sent[0].data().get(toad_msg::opt::known::repeat::PATH),
+               get().data().get(toad_msg::opt::known::repeat::PATH));
+  }
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN first_block_of_request_body_received [
+      (inner.poll_req => { Some(Ok(upload(Type::Con, 0, true, 0..64))) })
+    ]
+    THEN continue_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().ty, Type::Ack);
+        assert_eq!(sent[0].data().id, Id(3));
+        assert_eq!(sent[0].data().code, code::CONTINUE);
+        assert_eq!(sent[0].data().block1(), Some(toad_msg::block::Block::new(64, 0, true)));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN last_block_of_request_body_received [
+      (inner.poll_req => { Some(Ok(upload(Type::Con, 0, true, 0..64))) }),
+      ({|step: &Block<Dummy>| assert!(step.poll_req(&test::snapshot(), &mut vec![]).is_none())}),
+      (inner.poll_req => { Some(Ok(upload(Type::Con, 1, true, 64..128))) }),
+      ({|step: &Block<Dummy>| assert!(step.poll_req(&test::snapshot(), &mut vec![]).is_none())}),
+      (inner.poll_req => { Some(Ok(upload(Type::Con, 2, false, 128..150))) })
+    ]
+    THEN request_should_be_yielded_with_entire_body [
+      (poll_req(_, _) should satisfy { |out| {
+        let req = out.unwrap().unwrap();
+        assert_eq!(req.data().payload(), &(0..150).map(|n| n as u8).collect::<Vec<_>>());
+      }}),
+      (effects should satisfy { |effs| assert!(sent(effs).is_empty()) }),
+      (before_message_sent(_, _, test::msg!(NON {2 . 4} x.x.x.x:80 with |m: &mut test::Message| m.token = Token(array_vec!(3)))) should be ok with { |msg| {
+        assert_eq!(msg.data().block1(), Some(toad_msg::block::Block::new(64, 2, false)));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_body_block_skipped [
+      (inner.poll_req => { Some(Ok(upload(Type::Non, 0, true, 0..64))) }),
+      ({|step: &Block<Dummy>| assert!(step.poll_req(&test::snapshot(), &mut vec![]).is_none())}),
+      (inner.poll_req => { Some(Ok(upload(Type::Non, 2, true, 128..192))) })
+    ]
+    THEN request_entity_incomplete_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().ty, Type::Non);
+        assert_eq!(sent[0].data().code, code::REQUEST_ENTITY_INCOMPLETE);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_body_block_received_without_first_block [
+      (inner.poll_req => { Some(Ok(upload(Type::Con, 1, true, 64..128))) })
+    ]
+    THEN request_entity_incomplete_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().code, code::REQUEST_ENTITY_INCOMPLETE);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_body_exceeds_limit [
+      (snapshot = {{
+        let mut snap = test::snapshot();
+        snap.config.msg.max_request_body_bytes_per_peer = 100;
+        snap
+      }}),
+      (inner.poll_req => { Some(Ok(upload(Type::Con, 0, true, 0..64))) }),
+      ({|step: &Block<Dummy>| {
+        let mut snap = test::snapshot();
+        snap.config.msg.max_request_body_bytes_per_peer = 100;
+        assert!(step.poll_req(&snap, &mut vec![]).is_none())
+      }}),
+      (inner.poll_req => { Some(Ok(upload(Type::Con, 1, true, 64..128))) })
+    ]
+    THEN request_entity_too_large_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().code, code::REQUEST_ENTITY_TOO_LARGE);
+        assert_eq!(sent[0].data().size1(), Some(100));
+      }})
+    ]
+  );
+
+  #[test]
+  fn assemble_should_reject_bodies_larger_than_payload_capacity() {
+    use tinyvec::ArrayVec;
+    use toad_msg::{OptNumber, OptValue};
+
+    #[derive(Debug, Clone, Copy)]
+    struct P;
+    impl PlatformTypes for P {
+      type MessagePayload = ArrayVec<[u8; 100]>;
+      type MessageOptionBytes = ArrayVec<[u8; 16]>;
+      type MessageOptionMapOptionValues = ArrayVec<[OptValue<Self::MessageOptionBytes>; 2]>;
+      type MessageOptions = ArrayVec<[(OptNumber, Self::MessageOptionMapOptionValues); 4]>;
+      type Clock = ClockMock;
+      type Socket = test::SockMock;
+      type Effects = Vec<Effect<Self>>;
+    }
+
+    type Block = super::Block<(), Vec<Transfer<P>>, Vec<Assembly<P>>, Vec<Fetch<P>>>;
+
+    let upload = |num: u32, more: bool, payload: core::ops::Range<usize>| {
+      let mut msg = platform::Message::<P>::new(Type::Con,
+                                                toad_msg::Code::new(0, 3),
+                                                Id(3 + num as u16),
+                                                Token(array_vec!(3)));
+      msg.set_block1(64, num, more).ok();
+      msg.payload = Payload(payload.map(|n| n as u8).collect());
+      Addrd(Req::from(msg), test::x.x.x.x(80))
+    };
+
+    let (mut effs, mut assemblies) = (vec![], vec![]);
+    let (now, config) = (ClockMock::instant(0), Config::default());
+
+    assert!(Block::assemble::<P>(&mut effs,
+                                 &mut assemblies,
+                                 now,
+                                 &config,
+                                 upload(0, true, 0..64)).is_err());
+
+    let resp = Block::assemble::<P>(&mut effs,
+                                    &mut assemblies,
+                                    now,
+                                    &config,
+                                    upload(1, true, 64..128)).unwrap_err();
+    assert_eq!(resp.data().code, code::REQUEST_ENTITY_TOO_LARGE);
+    assert_eq!(resp.data().size1(), Some(100));
+    assert!(assemblies.is_empty());
+  }
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN first_block_of_response_received [
+      ({|step: &Block<Dummy>| step.before_message_sent(&test::snapshot(), &mut vec![], &mut get()).unwrap()}),
+      (inner.poll_resp => { Some(Ok(block(0, true, 1))) })
+    ]
+    THEN next_block_should_be_requested [
+      (poll_resp(_, _, _, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+
+        let next = sent[0].data();
+        assert_eq!(next.ty, Type::Con);
+        assert_eq!(next.code, toad_msg::Code::GET);
+        assert_eq!(next.id, Id(0));
+        assert_eq!(next.token, Token(array_vec!(4)));
+        assert_eq!(next.block2(), Some(toad_msg::block::Block::new(64, 1, false)));
+        assert_eq!(next.get(toad_msg::opt::known::repeat::PATH), get().data().get(toad_msg::opt::known::repeat::PATH));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN last_block_of_response_received [
+      ({|step: &Block<Dummy>| step.before_message_sent(&test::snapshot(), &mut vec![], &mut get()).unwrap()}),
+      (inner.poll_resp => { Some(Ok(block(0, true, 1))) }),
+      ({|step: &Block<Dummy>| assert!(step.poll_resp(&test::snapshot(), &mut vec![], Token(array_vec!(4)), test::x.x.x.x(80)).is_none())}),
+      (inner.poll_resp => { Some(Ok(block(1, false, 1))) })
+    ]
+    THEN complete_response_should_be_yielded [
+      (poll_resp(_, _, _, _) should satisfy { |out| {
+        let resp = out.unwrap().unwrap();
+        assert_eq!(resp.data().msg().payload.0, (0..128).map(|n| n as u8).collect::<Vec<_>>());
+        assert_eq!(resp.data().msg().block2(), None);
+        assert_eq!(resp.data().msg().token, Token(array_vec!(4)));
+      }}),
+      (effects == { vec![] })
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN etag_changes_between_blocks [
+      ({|step: &Block<Dummy>| step.before_message_sent(&test::snapshot(), &mut vec![], &mut get()).unwrap()}),
+      (inner.poll_resp => { Some(Ok(block(0, true, 1))) }),
+      ({|step: &Block<Dummy>| assert!(step.poll_resp(&test::snapshot(), &mut vec![], Token(array_vec!(4)), test::x.x.x.x(80)).is_none())}),
+      (inner.poll_resp => { Some(Ok(block(1, false, 2))) })
+    ]
+    THEN etag_mismatch_should_be_yielded [
+      (poll_resp(_, _, _, _) should satisfy { |out| {
+        assert_eq!(out, Some(Err(nb::Error::Other(Error::EtagMismatch(Addrd(Token(array_vec!(4)), test::x.x.x.x(80)))))));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Block::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN block_of_response_to_unknown_request_received [
+      (inner.poll_resp => { Some(Ok(block(0, true, 1))) })
+    ]
+    THEN block_should_be_yielded [
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Ok(block(0, true, 1)))) }),
+      (effects == { vec![] })
+    ]
+  );
+}
@@ -1,14 +1,13 @@
 use core::fmt::Write;
 
 use no_std_net::SocketAddr;
-use toad_array::Array;
+use toad_array::Indexed;
 use toad_len::Len;
 use toad_map::Map;
 use toad_msg::{Token, Type};
 use toad_stem::Stem;
 
 use super::{Step, StepOutput};
-use crate::exec_inner_step;
 use crate::net::Addrd;
 use crate::platform::{Effect, PlatformTypes};
 use crate::req::Req;
@@ -78,7 +77,15 @@ impl<E: core::fmt::Debug> core::fmt::Debug for Error<E> {
   }
 }
 
-impl<E: super::Error> super::Error for Error<E> {}
+impl<E: super::Error> super::Error for Error<E> {
+  fn is_timeout(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_timeout())
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_unacknowledged())
+  }
+}
 
 impl<P: PlatformTypes,
       B: Map<(SocketAddr, Token, Type), Addrd<Resp<P>>>,
@@ -110,8 +117,7 @@ impl<P: PlatformTypes,
                token: toad_msg::Token,
                addr: no_std_net::SocketAddr)
                -> StepOutput<Self::PollResp, Self::Error> {
-    let resp = exec_inner_step!(self.inner.poll_resp(snap, effects, token, addr),
-                                Error::Inner);
+    let resp = self.inner.poll_resp(snap, effects, token, addr);
 
     if self.buffer.map_ref(Len::is_full) {
       return Some(Err(nb::Error::Other(Error::BufferResponsesFull)));
@@ -120,29 +126,38 @@ impl<P: PlatformTypes,
     let try_remove_from_buffer =
       |ty: Type| self.buffer.map_mut(|buf| buf.remove(&(addr, token, ty)));
 
+    let try_remove_any_from_buffer = || {
+      try_remove_from_buffer(Type::Ack).or_else(|| try_remove_from_buffer(Type::Con))
+                                       .or_else(|| try_remove_from_buffer(Type::Non))
+                                       .or_else(|| try_remove_from_buffer(Type::Reset))
+    };
+
     let is_what_we_polled_for =
       |resp: &Addrd<Resp<_>>| resp.addr() == addr && resp.data().as_ref().token == token;
 
     match resp {
-      | Some(resp) if is_what_we_polled_for(&resp) => Some(Ok(resp)),
-      | Some(resp) => {
+      | Some(Ok(resp)) if is_what_we_polled_for(&resp) => Some(Ok(resp)),
+      | Some(Ok(resp)) => {
         let mut msg = String::<1000>::default();
         write!(&mut msg,
                "polled for response to {:?}, got response with token {:?}",
                token,
                resp.data().token()).ok();
-        effects.push(Effect::Log(log::Level::Info, msg));
+        effects.append(Effect::Log(log::Level::Info, msg));
         self.store(resp);
 
-        match try_remove_from_buffer(Type::Ack).or_else(|| try_remove_from_buffer(Type::Con))
-                                               .or_else(|| try_remove_from_buffer(Type::Non))
-                                               .or_else(|| try_remove_from_buffer(Type::Reset))
-        {
+        match try_remove_any_from_buffer() {
           | Some(resp) => Some(Ok(resp)),
           | None => Some(Err(nb::Error::WouldBlock)),
         }
       },
-      | None => None,
+      | Some(Err(nb::Error::Other(e))) => Some(Err(nb::Error::Other(Error::Inner(e)))),
+      // the response may have been buffered while someone else
+      // was polling for a response to their request
+      | blocked @ (None | Some(Err(nb::Error::WouldBlock))) => match try_remove_any_from_buffer() {
+        | Some(resp) => Some(Ok(resp)),
+        | None => blocked.map(|_| Err(nb::Error::WouldBlock)),
+      },
     }
   }
 }
@@ -266,7 +281,7 @@ mod test {
         poll_resp(
           _,
           _,
-          Token(array_vec!([u8; 8] => 2)),
+          Token(array_vec!(2)),
           crate::test::dummy_addr_2()
         ) should satisfy {
           // CACHED: ACK Token(1) Id(1) dummy_addr
@@ -277,7 +292,7 @@ mod test {
         poll_resp(
           _,
           _,
-          Token(array_vec!([u8; 8] => 2)),
+          Token(array_vec!(2)),
           crate::test::dummy_addr_2()
         ) should satisfy {
           // CACHED: ACK Token(2) Id(2) dummy_addr
@@ -288,7 +303,7 @@ mod test {
         poll_resp(
           _,
           _,
-          Token(array_vec!([u8; 8] => 2)),
+          Token(array_vec!(2)),
           crate::test::dummy_addr_2()
         ) should satisfy {
           // CACHED: ACK Token(1) Id(1) dummy_addr_2
@@ -299,7 +314,7 @@ mod test {
         poll_resp(
           _,
           _,
-          Token(array_vec!([u8; 8] => 2)),
+          Token(array_vec!(2)),
           crate::test::dummy_addr_2()
         ) should satisfy {
           // POPPED: ACK Token(2) Id(2) dummy_addr_2
@@ -310,7 +325,7 @@ mod test {
         poll_resp(
           _,
           _,
-          toad_msg::Token(array_vec!([u8; 8] => 1)),
+          toad_msg::Token(array_vec!(1)),
           crate::test::dummy_addr()
         ) should satisfy {
           // POPPED: ACK Token(1) Id(1) dummy_addr
@@ -321,7 +336,7 @@ mod test {
         poll_resp(
           _,
           _,
-          Token(array_vec!([u8; 8] => 2)),
+          Token(array_vec!(2)),
           crate::test::dummy_addr()
         ) should satisfy {
           // POPPED: ACK Token(2) Id(2) dummy_addr
@@ -334,7 +349,7 @@ mod test {
         poll_resp(
          _,
           _,
-          Token(array_vec!([u8; 8] => 3)),
+          Token(array_vec!(3)),
           crate::test::dummy_addr_2()
         ) should satisfy {
           |out| {
@@ -349,7 +364,7 @@ mod test {
         poll_resp(
           _,
           _,
-          Token(array_vec!([u8; 8] => 3)),
+          Token(array_vec!(3)),
           crate::test::dummy_addr_2()
         ) should satisfy {
           |out| {
@@ -362,4 +377,70 @@ mod test {
       )
     ]
   );
+
+  test_step!(
+    GIVEN BufferResponses::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_yields_response_then_blocks [
+      (inner.poll_resp = {
+        |_, _, _, _| {
+          use toad_msg::*;
+
+          static mut CALL: u8 = 1;
+
+          let call = CALL;
+          CALL += 1;
+
+          if call > 1 {
+            return Some(Err(nb::Error::WouldBlock));
+          }
+
+          let msg = platform::Message::<P> {
+            ver: Default::default(),
+            token: Token(array_vec!(1)),
+            ty: Type::Non,
+            code: Code::new(2, 5),
+            id: Id(1),
+            opts: Default::default(),
+            payload: Payload(vec![]),
+          };
+
+          Some(Ok(Addrd(msg.into(), crate::test::dummy_addr())))
+        }
+      })
+    ]
+    THEN this_should_yield_buffered_response_when_inner_blocks [
+      (
+        poll_resp(
+          _,
+          _,
+          Token(array_vec!(2)),
+          crate::test::dummy_addr()
+        ) should satisfy {
+          // CACHED: NON Token(1) Id(1) dummy_addr
+          |out| assert_eq!(out, Some(Err(nb::Error::WouldBlock)))
+        }
+      ),
+      (
+        poll_resp(
+          _,
+          _,
+          Token(array_vec!(1)),
+          crate::test::dummy_addr()
+        ) should satisfy {
+          // POPPED: NON Token(1) Id(1) dummy_addr
+          |out| assert_eq!(out.expect("a").expect("a").data().as_ref().id, Id(1))
+        }
+      ),
+      (
+        poll_resp(
+          _,
+          _,
+          Token(array_vec!(1)),
+          crate::test::dummy_addr()
+        ) should satisfy {
+          |out| assert_eq!(out, Some(Err(nb::Error::WouldBlock)))
+        }
+      )
+    ]
+  );
 }
@@ -0,0 +1,328 @@
+use core::fmt::Write;
+use core::marker::PhantomData;
+
+use toad_array::Indexed;
+use toad_map::Map;
+use toad_msg::opt::known::{no_repeat, repeat};
+use toad_msg::opt::OptionMustBeProcessed;
+use toad_msg::{Code, CodeKind, OptNumber, Token, Type};
+
+use super::{exec_inner_step, log, Step, StepOutput};
+use crate::net::Addrd;
+use crate::platform::{self, Effect, PlatformTypes, Snapshot};
+use crate::req::Req;
+use crate::resp::{code, Resp};
+use crate::todo::String;
+
+/// The set of options understood by the application
+///
+/// Critical options outside this set will cause requests to be
+/// rejected with 4.02 Bad Option, and CON & NON responses to be rejected
+/// with RESET.
+///
+/// A default implementation is provided by [`StandardOptions`].
+///
+/// ```
+/// use toad::step::check_options::{KnownOptions, StandardOptions};
+/// use toad_msg::OptNumber;
+///
+/// #[derive(Debug, Default)]
+/// struct MyOptions;
+///
+/// impl KnownOptions for MyOptions {
+///   fn is_known(n: OptNumber) -> bool {
+///     n == OptNumber(65001) || StandardOptions::is_known(n)
+///   }
+/// }
+///
+/// assert!(MyOptions::is_known(OptNumber(65001)));
+/// assert!(!StandardOptions::is_known(OptNumber(65001)));
+/// ```
+pub trait KnownOptions: Default + core::fmt::Debug {
+  /// Does the application understand this option?
+  fn is_known(n: OptNumber) -> bool;
+}
+
+/// Options defined by [RFC7252](https://www.rfc-editor.org/rfc/rfc7252#section-5.10),
+/// [RFC7641 (Observe)](https://www.rfc-editor.org/rfc/rfc7641),
+/// [RFC7959 (Block)](https://www.rfc-editor.org/rfc/rfc7959) and
+/// [RFC8613 (OSCORE)](https://www.rfc-editor.org/rfc/rfc8613)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StandardOptions;
+
+impl KnownOptions for StandardOptions {
+  fn is_known(n: OptNumber) -> bool {
+    [repeat::IF_MATCH,
+     no_repeat::HOST,
+     repeat::ETAG,
+     no_repeat::IF_NONE_MATCH,
+     no_repeat::OBSERVE,
+     no_repeat::PORT,
+     repeat::LOCATION_PATH,
+     repeat::PATH,
+     no_repeat::CONTENT_FORMAT,
+     no_repeat::MAX_AGE,
+     repeat::QUERY,
+     no_repeat::ACCEPT,
+     repeat::LOCATION_QUERY,
+     no_repeat::BLOCK2,
+     no_repeat::BLOCK1,
+     no_repeat::SIZE2,
+     no_repeat::PROXY_URI,
+     no_repeat::PROXY_SCHEME,
+     no_repeat::SIZE1,
+     no_repeat::OSCORE].contains(&n)
+  }
+}
+
+/// See [the module documentation](self)
+#[derive(Debug)]
+pub struct CheckOptions<S, Known> {
+  inner: S,
+  __known: PhantomData<Known>,
+}
+
+impl<S, Known> Default for CheckOptions<S, Known> where S: Default
+{
+  fn default() -> Self {
+    CheckOptions { inner: S::default(),
+                   __known: PhantomData }
+  }
+}
+
+impl<S, Known> CheckOptions<S, Known> where Known: KnownOptions
+{
+  /// Find the first critical option in `msg` that we don't understand
+  fn unknown_critical<P>(msg: &platform::Message<P>) -> Option<OptNumber>
+    where P: PlatformTypes
+  {
+    msg.opts
+       .iter()
+       .map(|(n, _)| *n)
+       .find(|n| n.must_be_processed() == OptionMustBeProcessed::Yes && !Known::is_known(*n))
+  }
+
+  fn bad_option<P>(req: &Addrd<Req<P>>, n: OptNumber) -> Addrd<platform::Message<P>>
+    where P: PlatformTypes
+  {
+    let mut diagnostic = String::<1000>::default();
+    write!(diagnostic, "Unrecognized critical option {}", n.0).ok();
+
+    let mut resp = Resp::for_request(req.data()).unwrap_or_else(|| Resp::non(req.data()));
+    resp.set_code(code::BAD_OPTION);
+    resp.set_payload(diagnostic.as_bytes().iter().copied());
+
+    Addrd(resp.into(), req.addr())
+  }
+}
+
+impl<P, S, Known> Step<P> for CheckOptions<S, Known>
+  where P: PlatformTypes,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>>,
+        Known: KnownOptions
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = S::Error;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut <P as PlatformTypes>::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    let req = exec_inner_step!(self.inner.poll_req(snap, effects), core::convert::identity)?;
+
+    let msg = req.data().msg();
+    match Self::unknown_critical::<P>(msg) {
+      | Some(n)
+        if msg.code.kind() == CodeKind::Request && matches!(msg.ty, Type::Con | Type::Non) =>
+      {
+        log!(CheckOptions::poll_req,
+             effects,
+             log::Level::Debug,
+             "Rejecting {:?} from {}: unrecognized critical option {}",
+             req.data().msg().token,
+             req.addr(),
+             n.0);
+        effects.append(Effect::Send(Self::bad_option(&req, n)));
+        None
+      },
+      | _ => Some(Ok(req)),
+    }
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut <P as PlatformTypes>::Effects,
+               token: Token,
+               addr: no_std_net::SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    let resp = exec_inner_step!(self.inner.poll_resp(snap, effects, token, addr),
+                                core::convert::identity)?;
+
+    match Self::unknown_critical::<P>(resp.data().msg()) {
+      | Some(n) => {
+        log!(CheckOptions::poll_resp,
+             effects,
+             log::Level::Warn,
+             "Rejecting response {:?} from {}: unrecognized critical option {}",
+             resp.data().msg().token,
+             resp.addr(),
+             n.0);
+
+        // rejecting an ACK means silently ignoring it
+        if matches!(resp.data().msg().ty, Type::Con | Type::Non) {
+          let rst = platform::Message::<P>::new(Type::Reset,
+                                                Code::EMPTY,
+                                                resp.data().msg().id,
+                                                Token(Default::default()));
+          effects.append(Effect::Send(Addrd(rst, resp.addr())));
+        }
+
+        None
+      },
+      | None => Some(Ok(resp)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use toad_msg::{Id, MessageOptions, OptValue};
+
+  use super::*;
+  use crate::step::test::test_step;
+  use crate::test;
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type CheckOptions<S> = super::CheckOptions<S, StandardOptions>;
+
+  fn msg(ty: Type, code: Code, opt: Option<u32>) -> Addrd<test::Message> {
+    let mut msg = test::msg!({ty} {code} x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    if let Some(n) = opt {
+      msg.set(OptNumber(n), OptValue(Default::default())).ok();
+    }
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn req(ty: Type, opt: Option<u32>) -> InnerPollReq {
+    msg(ty, Code::GET, opt).map(Req::from)
+  }
+
+  fn resp(ty: Type, opt: Option<u32>) -> InnerPollResp {
+    msg(ty, code::CONTENT, opt).map(Resp::from)
+  }
+
+  fn sent(effs: &[test::Effect]) -> Vec<Addrd<test::Message>> {
+    effs.iter()
+        .filter_map(|e| match e {
+          | Effect::Send(m) => Some(m.clone()),
+          | _ => None,
+        })
+        .collect()
+  }
+
+  test_step!(
+    GIVEN CheckOptions::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN CheckOptions::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_has_known_and_elective_options [
+      (inner.poll_req => { Some(Ok(req(Type::Con, Some(11)))) }),
+      (inner.poll_resp => { Some(Ok(resp(Type::Con, Some(65000)))) })
+    ]
+    THEN messages_should_be_yielded [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req(Type::Con, Some(11))))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Ok(resp(Type::Con, Some(65000))))) }),
+      (effects == { vec![] })
+    ]
+  );
+
+  test_step!(
+    GIVEN CheckOptions::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN con_request_has_unknown_critical_option [
+      (inner.poll_req => { Some(Ok(req(Type::Con, Some(65001)))) })
+    ]
+    THEN bad_option_should_be_piggybacked [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().ty, Type::Ack);
+        assert_eq!(sent[0].data().id, Id(1));
+        assert_eq!(sent[0].data().code, code::BAD_OPTION);
+        assert_eq!(sent[0].data().payload.0, b"Unrecognized critical option 65001".to_vec());
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN CheckOptions::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN non_request_has_unknown_critical_option [
+      (inner.poll_req => { Some(Ok(req(Type::Non, Some(65001)))) })
+    ]
+    THEN bad_option_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().ty, Type::Non);
+        assert_eq!(sent[0].data().code, code::BAD_OPTION);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN CheckOptions::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN con_response_has_unknown_critical_option [
+      (inner.poll_resp => { Some(Ok(resp(Type::Con, Some(65001)))) })
+    ]
+    THEN response_should_be_reset [
+      (poll_resp(_, _, _, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().ty, Type::Reset);
+        assert_eq!(sent[0].data().id, Id(1));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN CheckOptions::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN ack_has_unknown_critical_option [
+      (inner.poll_resp => { Some(Ok(resp(Type::Ack, Some(65001)))) })
+    ]
+    THEN response_should_be_ignored [
+      (poll_resp(_, _, _, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| assert!(sent(effs).is_empty()) })
+    ]
+  );
+
+  test_step!(
+    GIVEN CheckOptions::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN response_with_unknown_critical_option_is_polled_as_request [
+      (inner.poll_req => { Some(Ok(resp(Type::Con, Some(65001)).map(|r| Req::from(r.msg().clone())))) })
+    ]
+    THEN it_should_not_get_bad_option [
+      (poll_req(_, _) should satisfy { |out| assert!(matches!(out, Some(Ok(_)))) }),
+      (effects should satisfy { |effs| assert!(sent(effs).is_empty()) })
+    ]
+  );
+}
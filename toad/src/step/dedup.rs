@@ -0,0 +1,343 @@
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use no_std_net::SocketAddr;
+use toad_array::{Array, Indexed};
+use toad_msg::{CodeKind, Id, Token, Type};
+use toad_stem::Stem;
+
+use super::{exec_inner_step, log, Step, StepOutput};
+use crate::config::Config;
+use crate::net::Addrd;
+use crate::platform::{self, Effect, PlatformTypes, Snapshot};
+use crate::req::Req;
+use crate::resp::Resp;
+
+/// A request that we've received, and the reply
+/// we sent to it (once one has been sent)
+pub struct Exchange<P>
+  where P: PlatformTypes
+{
+  addr: SocketAddr,
+  id: Id,
+  token: Token,
+  ty: Type,
+  reply: Option<platform::Message<P>>,
+  received_at: Instant<P::Clock>,
+}
+
+impl<P> core::fmt::Debug for Exchange<P> where P: PlatformTypes
+{
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Exchange")
+     .field("addr", &self.addr)
+     .field("id", &self.id)
+     .field("token", &self.token)
+     .field("ty", &self.ty)
+     .field("reply", &self.reply)
+     .field("received_at", &self.received_at)
+     .finish()
+  }
+}
+
+impl<P> Exchange<P> where P: PlatformTypes
+{
+  /// The address of the client that sent the request
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// The Id of the request
+  pub fn id(&self) -> Id {
+    self.id
+  }
+
+  /// The reply we sent to the request, if we have replied.
+  ///
+  /// For CON requests this is the ACK (piggybacked response or empty ACK),
+  /// for NON requests this is the response.
+  pub fn reply(&self) -> Option<&platform::Message<P>> {
+    self.reply.as_ref()
+  }
+
+  fn is_expired(&self, now: Instant<P::Clock>, config: Config) -> bool {
+    now.checked_duration_since(&self.received_at)
+       .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+       .map(|Milliseconds(ms)| ms >= config.exchange_lifetime_millis())
+       .unwrap_or(false)
+  }
+
+  /// Is `msg` our reply to this request?
+  fn is_replied_to_by(&self, msg: &Addrd<platform::Message<P>>) -> bool {
+    let m = msg.data();
+    msg.addr() == self.addr
+    && match self.ty {
+      | Type::Con => matches!(m.ty, Type::Ack | Type::Reset) && m.id == self.id,
+      | _ => m.code.kind() == CodeKind::Response && m.token == self.token,
+    }
+  }
+}
+
+/// See [the module documentation](self)
+#[derive(Debug)]
+pub struct Dedup<S, Exchanges> {
+  inner: S,
+  exchanges: Stem<Exchanges>,
+}
+
+impl<S, Exchanges> Default for Dedup<S, Exchanges>
+  where S: Default,
+        Exchanges: Default
+{
+  fn default() -> Self {
+    Dedup { inner: S::default(),
+            exchanges: Stem::new(Exchanges::default()) }
+  }
+}
+
+impl<S, Exchanges> Dedup<S, Exchanges> {
+  fn prune<P>(exchanges: &mut Exchanges, now: Instant<P::Clock>, config: Config)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    while let Some(ix) = exchanges.iter().position(|e| e.is_expired(now, config)) {
+      exchanges.remove(ix);
+    }
+  }
+
+  /// Remember a request we're yielding to the application,
+  /// evicting the oldest one if we can't remember any more.
+  fn remember<P>(exchanges: &mut Exchanges, now: Instant<P::Clock>, req: &Addrd<Req<P>>)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    if exchanges.is_full() {
+      exchanges.remove(0);
+    }
+
+    let msg = req.data().msg();
+    exchanges.append(Exchange { addr: req.addr(),
+                              id: msg.id,
+                              token: msg.token,
+                              ty: msg.ty,
+                              reply: None,
+                              received_at: now });
+  }
+}
+
+impl<P, S, Exchanges> Step<P> for Dedup<S, Exchanges>
+  where P: PlatformTypes,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>>,
+        Exchanges: Default + Array<Item = Exchange<P>>
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = S::Error;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut <P as PlatformTypes>::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    self.exchanges
+        .map_mut(|es| Self::prune::<P>(es, snap.time, snap.config));
+
+    let req = exec_inner_step!(self.inner.poll_req(snap, effects), core::convert::identity)?;
+
+    let (addr, id) = (req.addr(), req.data().msg().id);
+    let dupe = self.exchanges.map_ref(|es| {
+                               es.iter()
+                                 .find(|e| e.addr == addr && e.id == id)
+                                 .map(|e| e.reply.clone())
+                             });
+
+    match dupe {
+      | Some(Some(reply)) => {
+        log!(Dedup::poll_req,
+             effects,
+             log::Level::Debug,
+             "Replaying reply to duplicate {:?} from {}",
+             id,
+             addr);
+        effects.append(Effect::Send(Addrd(reply, addr)));
+        None
+      },
+      | Some(None) => {
+        log!(Dedup::poll_req,
+             effects,
+             log::Level::Debug,
+             "Ignoring duplicate {:?} from {}; we haven't replied to it yet",
+             id,
+             addr);
+        None
+      },
+      | None => {
+        self.exchanges
+            .map_mut(|es| Self::remember::<P>(es, snap.time, &req));
+        Some(Ok(req))
+      },
+    }
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut <P as PlatformTypes>::Effects,
+               token: Token,
+               addr: SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    self.inner.poll_resp(snap, effects, token, addr)
+  }
+
+  fn on_message_sent(&self,
+                     snap: &Snapshot<P>,
+                     effs: &mut P::Effects,
+                     msg: &Addrd<platform::Message<P>>)
+                     -> Result<(), Self::Error> {
+    self.inner.on_message_sent(snap, effs, msg)?;
+
+    self.exchanges.map_mut(|es| {
+                    if let Some(e) = es.iter_mut().find(|e| e.is_replied_to_by(msg)) {
+                      e.reply = Some(msg.data().clone());
+                    }
+                  });
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tinyvec::array_vec;
+
+  use super::*;
+  use crate::step::test::test_step;
+  use crate::test::{self, ClockMock};
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type Dedup<S> = super::Dedup<S, Vec<Exchange<test::Platform>>>;
+
+  fn req(ty: Type) -> InnerPollReq {
+    let mut msg = test::msg!({ty} {toad_msg::Code::POST} x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    msg.token = Token(array_vec!(1));
+
+    Addrd(Req::from(msg), test::x.x.x.x(80))
+  }
+
+  fn piggybacked() -> Addrd<test::Message> {
+    let mut msg = test::msg!(ACK {2 . 4} x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    msg.token = Token(array_vec!(1));
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn non_resp() -> Addrd<test::Message> {
+    let mut msg = test::msg!(NON {2 . 4} x.x.x.x:80).unwrap();
+    msg.id = Id(2);
+    msg.token = Token(array_vec!(1));
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn sent(effs: &[test::Effect]) -> Vec<Addrd<test::Message>> {
+    effs.iter()
+        .filter_map(|e| match e {
+          | Effect::Send(m) => Some(m.clone()),
+          | _ => None,
+        })
+        .collect()
+  }
+
+  test_step!(
+    GIVEN Dedup::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Dedup::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN new_request_received [
+      (inner.poll_req => { Some(Ok(req(Type::Con))) })
+    ]
+    THEN request_should_be_yielded [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req(Type::Con)))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Dedup::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN duplicate_received_before_reply [
+      (inner.poll_req => { Some(Ok(req(Type::Con))) }),
+      ({|step: &Dedup<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()})
+    ]
+    THEN duplicate_should_be_ignored [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| assert!(sent(effs).is_empty()) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Dedup::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN duplicate_con_received_after_reply [
+      (inner.poll_req => { Some(Ok(req(Type::Con))) }),
+      ({|step: &Dedup<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      ({|step: &Dedup<Dummy>| step.on_message_sent(&test::snapshot(), &mut vec![], &piggybacked()).unwrap()})
+    ]
+    THEN reply_should_be_replayed [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| assert_eq!(sent(effs), vec![piggybacked()]) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Dedup::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN duplicate_non_received_after_reply [
+      (inner.poll_req => { Some(Ok(req(Type::Non))) }),
+      ({|step: &Dedup<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      ({|step: &Dedup<Dummy>| step.on_message_sent(&test::snapshot(), &mut vec![], &non_resp()).unwrap()})
+    ]
+    THEN reply_should_be_replayed [
+      (poll_req(_, _) should satisfy { |out| assert!(out.is_none()) }),
+      (effects should satisfy { |effs| assert_eq!(sent(effs), vec![non_resp()]) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Dedup::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN same_id_received_from_another_client [
+      (inner.poll_req => { Some(Ok(req(Type::Con))) }),
+      ({|step: &Dedup<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      (inner.poll_req => { Some(Ok(req(Type::Con).with_addr(test::x.x.x.x(81)))) })
+    ]
+    THEN request_should_be_yielded [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req(Type::Con).with_addr(test::x.x.x.x(81))))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Dedup::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN same_id_received_after_exchange_lifetime [
+      (inner.poll_req => { Some(Ok(req(Type::Con))) }),
+      ({|step: &Dedup<Dummy>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()}),
+      (snapshot = {{
+        let mut snap = test::snapshot();
+        snap.time = ClockMock::instant(snap.config.exchange_lifetime_millis() * 1000);
+        snap
+      }})
+    ]
+    THEN request_should_be_yielded [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req(Type::Con)))) })
+    ]
+  );
+}
@@ -0,0 +1,733 @@
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use no_std_net::SocketAddr;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use toad_array::{Array, Indexed};
+use toad_msg::opt::known::no_repeat::{ECHO, OSCORE};
+use toad_msg::{CodeKind, Id, MessageOptions, OptValue, Token, TryIntoBytes};
+use toad_stem::Stem;
+
+use super::{exec_inner_step, log, Step, StepOutput, SUPPRESS};
+use crate::config::Config;
+use crate::net::{Addrd, Socket};
+use crate::platform::{self, Effect, PlatformTypes, Snapshot};
+use crate::req::{Method, Req};
+use crate::resp::{code, Resp};
+use crate::time::{Clock, Stamped};
+
+/// An Echo value issued by this step
+pub type Value = [u8; 8];
+
+/// What we know about a peer that sent us requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerState {
+  /// We challenged the peer with this Echo value,
+  /// and are waiting for it to be repeated back to us
+  Challenged(Value),
+  /// The peer repeated an Echo value we sent it,
+  /// proving that it can receive datagrams at its address
+  Verified,
+}
+
+/// A peer's address & state, [`Stamped`] with the instant it was challenged or verified
+pub type Peer<P> = Stamped<<P as PlatformTypes>::Clock, Addrd<PeerState>>;
+
+/// The address & token of a request we received and the size of the datagram
+/// it arrived in, [`Stamped`] with the instant it was received
+pub type Received<P> = Stamped<<P as PlatformTypes>::Clock, (Addrd<Token>, usize)>;
+
+/// A request we sent, [`Stamped`] with the instant it was sent
+pub type Sent<P> = Stamped<<P as PlatformTypes>::Clock, Addrd<platform::Message<P>>>;
+
+/// See [the module documentation](self)
+#[derive(Debug)]
+pub struct Echo<S, Peers, Receiveds, Sents> {
+  inner: S,
+  rand: Stem<Option<ChaCha8Rng>>,
+  peers: Stem<Peers>,
+  received: Stem<Receiveds>,
+  sent: Stem<Sents>,
+}
+
+impl<S, Peers, Receiveds, Sents> Default for Echo<S, Peers, Receiveds, Sents>
+  where S: Default,
+        Peers: Default,
+        Receiveds: Default,
+        Sents: Default
+{
+  fn default() -> Self {
+    Echo { inner: S::default(),
+           rand: Stem::new(None),
+           peers: Stem::new(Peers::default()),
+           received: Stem::new(Receiveds::default()),
+           sent: Stem::new(Sents::default()) }
+  }
+}
+
+/// Get a seed for the Echo value generator from the operating system
+#[cfg(feature = "std")]
+fn entropy() -> Option<[u8; 32]> {
+  let mut seed = [0u8; 32];
+  openssl::rand::rand_bytes(&mut seed).ok().map(|_| seed)
+}
+
+/// There is no source of entropy without `std`; see [`Echo::seed`]
+#[cfg(not(feature = "std"))]
+fn entropy() -> Option<[u8; 32]> {
+  None
+}
+
+fn expired<C>(at: Instant<C>, now: Instant<C>, lifetime_millis: u64) -> bool
+  where C: Clock
+{
+  now.checked_duration_since(&at)
+     .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+     .map(|Milliseconds(ms)| ms >= lifetime_millis)
+     .unwrap_or(false)
+}
+
+fn prune<C, T, A>(items: &mut A, now: Instant<C>, lifetime_millis: u64)
+  where C: Clock,
+        A: Array<Item = Stamped<C, T>>
+{
+  while let Some(ix) = items.iter()
+                            .position(|Stamped(_, at)| expired(*at, now, lifetime_millis))
+  {
+    items.remove(ix);
+  }
+}
+
+/// Push `item`, evicting the oldest item if we can't remember any more
+fn remember<A>(items: &mut A, item: <A as Array>::Item)
+  where A: Array
+{
+  if items.is_full() {
+    items.remove(0);
+  }
+
+  items.append(item);
+}
+
+impl<S, Peers, Receiveds, Sents> Echo<S, Peers, Receiveds, Sents> {
+  fn prune<P>(&self, now: Instant<P::Clock>, config: Config)
+    where P: PlatformTypes,
+          Peers: Array<Item = Peer<P>>,
+          Receiveds: Array<Item = Received<P>>,
+          Sents: Array<Item = Sent<P>>
+  {
+    let Milliseconds(echo_lifetime) = config.msg.echo_lifetime;
+    self.peers.map_mut(|p| prune(p, now, echo_lifetime));
+    self.received
+        .map_mut(|r| prune(r, now, config.exchange_lifetime_millis()));
+    self.sent
+        .map_mut(|s| prune(s, now, config.exchange_lifetime_millis()));
+  }
+
+  /// Seed the generator used for Echo values with 32 bytes of entropy
+  ///
+  /// With the `std` feature, the generator is seeded from the operating system's
+  /// random number generator the first time it's needed. Without `std` there is no
+  /// source of entropy, and peers can't be challenged until this is called.
+  pub fn seed(&self, seed: [u8; 32]) {
+    self.rand
+        .map_mut(|rand| *rand = Some(ChaCha8Rng::from_seed(seed)));
+  }
+
+  /// Generate a new Echo value, or `None` if the generator
+  /// hasn't been seeded & there is no source of entropy to seed it with
+  fn generate(&self) -> Option<Value> {
+    self.rand.map_mut(|rand| {
+               if rand.is_none() {
+                 *rand = entropy().map(ChaCha8Rng::from_seed);
+               }
+
+               rand.as_mut().map(|rand| {
+                              let mut value = Value::default();
+                              rand.fill_bytes(&mut value);
+                              value
+                            })
+             })
+  }
+
+  /// Get the Echo value a peer should repeat back to us,
+  /// reusing the one we already challenged it with if there is one.
+  fn challenge<P>(&self, now: Instant<P::Clock>, addr: SocketAddr) -> Option<Value>
+    where P: PlatformTypes,
+          Peers: Array<Item = Peer<P>>
+  {
+    let existing =
+      self.peers.map_ref(|ps| {
+                  ps.iter().find_map(|Stamped(p, _)| match p.data() {
+                             | PeerState::Challenged(v) if p.addr() == addr => Some(*v),
+                             | _ => None,
+                           })
+                });
+
+    existing.or_else(|| {
+              let value = self.generate()?;
+              self.peers.map_mut(|ps| {
+                          remember(ps, Stamped(Addrd(PeerState::Challenged(value), addr), now))
+                        });
+              Some(value)
+            })
+  }
+
+  /// Must `req` be challenged before it is handled?
+  ///
+  /// With [`Msg.challenge_non_idempotent_requests`](crate::config::Msg.challenge_non_idempotent_requests),
+  /// requests that aren't idempotent are challenged before the application sees them
+  /// when they're from unverified peers, because challenging the response instead
+  /// would run the handler again when the request is repeated with the Echo value.
+  fn must_challenge_early<P>(&self, config: Config, req: &Addrd<Req<P>>) -> bool
+    where P: PlatformTypes,
+          Peers: Array<Item = Peer<P>>
+  {
+    config.msg.challenge_non_idempotent_requests
+    && matches!(req.data().method(), Method::POST | Method::PATCH)
+    && req.data().msg().get(OSCORE).is_none()
+    && !self.is_verified::<P>(req.addr())
+  }
+
+  /// A `4.01 Unauthorized` response to `req` asking it to repeat `value`
+  fn unauthorized<P>(req: &Addrd<Req<P>>, value: Value) -> Addrd<platform::Message<P>>
+    where P: PlatformTypes
+  {
+    let mut resp = Resp::for_request(req.data()).unwrap_or_else(|| Resp::non(req.data()));
+    resp.set_code(code::UNAUTHORIZED);
+    resp.msg_mut().set_echo(value).ok();
+
+    Addrd(resp.into(), req.addr())
+  }
+
+  /// If `echo` is the value we challenged `addr` with, mark the peer verified
+  fn verify<P>(&self, now: Instant<P::Clock>, addr: SocketAddr, echo: &[u8]) -> bool
+    where P: PlatformTypes,
+          Peers: Array<Item = Peer<P>>
+  {
+    self.peers.map_mut(|ps| {
+                match ps.iter_mut().find(|Stamped(p, _)| {
+                                     p.addr() == addr
+                                     && matches!(p.data(), PeerState::Challenged(v) if v == echo)
+                                   }) {
+                  | Some(peer) => {
+                    *peer = Stamped(Addrd(PeerState::Verified, addr), now);
+                    true
+                  },
+                  | None => false,
+                }
+              })
+  }
+
+  fn is_verified<P>(&self, addr: SocketAddr) -> bool
+    where P: PlatformTypes,
+          Peers: Array<Item = Peer<P>>
+  {
+    self.peers.map_ref(|ps| {
+                ps.iter()
+                  .any(|Stamped(p, _)| p.addr() == addr && *p.data() == PeerState::Verified)
+              })
+  }
+}
+
+impl<P, S, Peers, Receiveds, Sents> Step<P> for Echo<S, Peers, Receiveds, Sents>
+  where P: PlatformTypes,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>>,
+        Peers: Default + Array<Item = Peer<P>>,
+        Receiveds: Default + Array<Item = Received<P>>,
+        Sents: Default + Array<Item = Sent<P>>
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = S::Error;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut <P as PlatformTypes>::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    self.prune::<P>(snap.time, snap.config);
+
+    let req = exec_inner_step!(self.inner.poll_req(snap, effects), core::convert::identity)?;
+
+    let size = snap.recvd_dgram
+                   .as_ref()
+                   .map(|dgram| dgram.data().as_ref().len())
+                   .unwrap_or(0);
+    self.received.map_mut(|r| {
+                   remember(r,
+                            Stamped((req.as_ref().map(|r| r.msg().token), size), snap.time))
+                 });
+
+    if let Some(echo) = req.data().msg().echo() {
+      if self.verify::<P>(snap.time, req.addr(), &echo.0) {
+        log!(Echo::poll_req,
+             effects,
+             log::Level::Debug,
+             "{} repeated our Echo value; no longer limiting responses to it",
+             req.addr());
+      }
+    }
+
+    if !self.must_challenge_early::<P>(snap.config, &req) {
+      return Some(Ok(req));
+    }
+
+    match self.challenge::<P>(snap.time, req.addr()) {
+      | Some(value) => {
+        log!(Echo::poll_req,
+             effects,
+             log::Level::Debug,
+             "{:?} request {:?} from unverified {}; challenging with Echo before handling it",
+             req.data().method(),
+             req.data().msg().token,
+             req.addr());
+        effects.append(Effect::Send(Self::unauthorized(&req, value)));
+      },
+      | None => log!(Echo::poll_req,
+                     effects,
+                     log::Level::Warn,
+                     "Dropping {:?} request {:?} from unverified {}; no entropy to generate an Echo value with",
+                     req.data().method(),
+                     req.data().msg().token,
+                     req.addr()),
+    }
+
+    None
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut <P as PlatformTypes>::Effects,
+               token: Token,
+               addr: SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    self.prune::<P>(snap.time, snap.config);
+
+    match self.inner.poll_resp(snap, effects, token, addr) {
+      | Some(Ok(resp)) if resp.data().code() == code::UNAUTHORIZED => {
+        let echo = match resp.data().msg().echo() {
+          | Some(echo) => echo.clone(),
+          | None => return Some(Ok(resp)),
+        };
+
+        // Only retry once per Echo value, so that a server
+        // that keeps rejecting our Echo doesn't make us loop forever
+        let key = resp.as_ref().map(|r| r.token());
+        let retry = self.sent.map_ref(|s| {
+                               s.iter()
+                                .find(|Stamped(req, _)| req.as_ref().map(|r| r.token) == key)
+                                .filter(|Stamped(req, _)| req.data().echo() != Some(&echo))
+                                .map(|Stamped(req, _)| req.clone())
+                             });
+
+        match retry {
+          | Some(mut req) => {
+            log!(Echo::poll_resp,
+                 effects,
+                 log::Level::Debug,
+                 "{} challenged request {:?} with an Echo value; retrying with it",
+                 req.addr(),
+                 req.data().token);
+
+            let msg = req.as_mut();
+            msg.id = Id(0);
+            msg.set(ECHO, echo).ok();
+            effects.append(Effect::Send(req));
+            None
+          },
+          | None => Some(Ok(resp)),
+        }
+      },
+      | other => other,
+    }
+  }
+
+  fn before_message_sent(&self,
+                         snap: &Snapshot<P>,
+                         effs: &mut <P as PlatformTypes>::Effects,
+                         msg: &mut Addrd<platform::Message<P>>)
+                         -> Result<(), Self::Error> {
+    // Requests are remembered before the inner steps see them, so that retrying
+    // one protected with OSCORE protects it again rather than replaying it.
+    // Requests that are already protected are retransmissions.
+    let req = (msg.data().code.kind() == CodeKind::Request && msg.data().get(OSCORE).is_none())
+              .then(|| msg.data().clone());
+
+    self.inner.before_message_sent(snap, effs, msg)?;
+
+    if let Some(mut req) = req.filter(|_| msg.data().get(SUPPRESS).is_none()) {
+      // the token may have been provisioned by the inner steps
+      req.token = msg.data().token;
+      let key = Addrd(req.token, msg.addr());
+      let mut req = Some(Addrd(req, msg.addr()));
+      self.sent.map_mut(|s| {
+                 if let Some(ix) =
+                   s.iter()
+                    .position(|Stamped(sent, _)| sent.as_ref().map(|r| r.token) == key)
+                 {
+                   s.remove(ix);
+                 }
+
+                 remember(s, Stamped(Option::take(&mut req).unwrap(), snap.time));
+               });
+    }
+
+    let factor = match snap.config.msg.amplification_factor {
+      | Some(factor) => factor as usize,
+      | None => return Ok(()),
+    };
+
+    // peers we share an OSCORE security context with are authenticated
+    if msg.data().code.kind() != CodeKind::Response
+       || msg.data().get(SUPPRESS).is_some()
+       || msg.data().get(OSCORE).is_some()
+       || self.is_verified::<P>(msg.addr())
+    {
+      return Ok(());
+    }
+
+    let key = msg.as_ref().map(|m| m.token);
+    let req_size = match self.received.map_ref(|r| {
+                                        r.iter()
+                                         .find(|Stamped((k, _), _)| *k == key)
+                                         .map(|Stamped((_, size), _)| *size)
+                                      }) {
+      | Some(size) => size,
+      | None => return Ok(()),
+    };
+
+    let resp_size = msg.data()
+                       .clone()
+                       .try_into_bytes::<<P::Socket as Socket>::Dgram>()
+                       .map(|bytes| bytes.len())
+                       .unwrap_or(usize::MAX);
+
+    if resp_size <= req_size.saturating_mul(factor) {
+      return Ok(());
+    }
+
+    let value = match self.challenge::<P>(snap.time, msg.addr()) {
+      | Some(value) => value,
+      | None => {
+        log!(Echo::before_message_sent,
+             effs,
+             log::Level::Warn,
+             "Dropping {:?} response of {} bytes to unverified {}; no entropy to generate an Echo value with",
+             msg.data().code,
+             resp_size,
+             msg.addr());
+        msg.as_mut()
+           .set(SUPPRESS, OptValue(Default::default()))
+           .ok();
+        return Ok(());
+      },
+    };
+
+    log!(Echo::before_message_sent,
+         effs,
+         log::Level::Debug,
+         "{:?} response of {} bytes to unverified {} exceeds {}x the {} byte request; challenging with Echo instead",
+         msg.data().code,
+         resp_size,
+         msg.addr(),
+         factor,
+         req_size);
+
+    let m = msg.as_mut();
+    m.code = code::UNAUTHORIZED;
+    m.opts = Default::default();
+    m.payload = toad_msg::Payload(Default::default());
+    m.set_echo(value).ok();
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tinyvec::array_vec;
+  use toad_msg::{Code, Type};
+
+  use super::*;
+  use crate::step::test::test_step;
+  use crate::test::{self, ClockMock};
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type Echo<S> = super::Echo<S,
+                             Vec<Peer<test::Platform>>,
+                             Vec<Received<test::Platform>>,
+                             Vec<Sent<test::Platform>>>;
+
+  /// A snapshot at `ms` milliseconds, having received a 10 byte request
+  fn snapshot(ms: u64) -> test::Snapshot {
+    test::Snapshot { time: ClockMock::instant(ms * 1000),
+                     recvd_dgram: Some(Addrd(tinyvec::ArrayVec::from_iter([0u8; 10]),
+                                             test::x.x.x.x(80))),
+                     recvd_dgram_dest: None,
+                     config: Default::default() }
+  }
+
+  fn req(echo: Option<Value>) -> Addrd<test::Message> {
+    let mut msg = test::msg!(CON GET x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    msg.token = Token(array_vec!(1));
+    if let Some(echo) = echo {
+      msg.set_echo(echo).ok();
+    }
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn post(echo: Option<Value>) -> Addrd<test::Message> {
+    let mut msg = req(echo);
+    msg.as_mut().code = Method::POST.code();
+    msg
+  }
+
+  fn resp(code: Code, payload_len: usize) -> Addrd<test::Message> {
+    let mut msg = test::msg!({Type::Ack} {code} x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    msg.token = Token(array_vec!(1));
+    msg.payload = toad_msg::Payload(vec![0; payload_len]);
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn challenge(echo: Value) -> Addrd<test::Message> {
+    let mut msg = resp(code::UNAUTHORIZED, 0);
+    msg.as_mut().set_echo(echo).ok();
+    msg
+  }
+
+  /// `msg` as it looks once the Oscore step has verified or protected it
+  fn protected(mut msg: Addrd<test::Message>) -> Addrd<test::Message> {
+    msg.as_mut().set(OSCORE, OptValue(vec![0x09, 0x14])).ok();
+    msg
+  }
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_from_unverified_peer_received [
+      (inner.poll_req => { Some(Ok(req(None).map(Req::from))) }),
+      ({|step: &Echo<Dummy>| step.poll_req(&snapshot(0), &mut vec![])})
+    ]
+    THEN small_responses_should_be_sent_and_large_ones_challenged [
+      (before_message_sent(_, _, resp(Code::new(2, 5), 2)) should be ok with { |msg| {
+        assert_eq!(msg.data().code, Code::new(2, 5));
+        assert_eq!(msg.data().get(ECHO), None);
+      }}),
+      (before_message_sent(_, _, resp(Code::new(2, 5), 100)) should be ok with { |msg| {
+        assert_eq!(msg.data().code, code::UNAUTHORIZED);
+        assert_eq!(msg.data().ty, Type::Ack);
+        assert_eq!(msg.data().get_first(ECHO).map(|v| v.0.len()), Some(8));
+        assert!(msg.data().payload.0.is_empty());
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN protected_request_from_unverified_peer_received [
+      (inner.poll_req => { Some(Ok(protected(post(None)).map(Req::from))) }),
+      (snapshot = {{
+        let mut snap = snapshot(0);
+        snap.config.msg.challenge_non_idempotent_requests = true;
+        snap
+      }})
+    ]
+    THEN protected_exchange_should_not_be_challenged [
+      (poll_req(_, _) should satisfy { |out| assert!(matches!(out, Some(Ok(_)))) }),
+      (before_message_sent(_, _, protected(resp(Code::new(2, 4), 100))) should be ok with { |msg| {
+        assert_eq!(msg.data().code, Code::new(2, 4));
+        assert_eq!(msg.data().get(ECHO), None);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN post_from_unverified_peer_received [
+      (inner.poll_req => { Some(Ok(post(None).map(Req::from))) })
+    ]
+    THEN request_should_be_handled [
+      (poll_req(_, _) should satisfy { |out| assert!(matches!(out, Some(Ok(_)))) }),
+      (effects should satisfy { |effs| {
+        assert!(!effs.iter().any(|e| matches!(e, Effect::Send(_))));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN post_from_unverified_peer_received_challenging_non_idempotent_requests [
+      (inner.poll_req => { Some(Ok(post(None).map(Req::from))) }),
+      (snapshot = {{
+        let mut snap = snapshot(0);
+        snap.config.msg.challenge_non_idempotent_requests = true;
+        snap
+      }})
+    ]
+    THEN request_should_be_challenged_before_it_is_handled [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let sent = effs.iter().find_map(|e| match e {
+          | Effect::Send(m) => Some(m.clone()),
+          | _ => None,
+        }).unwrap();
+        assert_eq!(sent.data().code, code::UNAUTHORIZED);
+        assert_eq!(sent.data().ty, Type::Ack);
+        assert_eq!(sent.data().id, Id(1));
+        assert_eq!(sent.data().echo().map(|v| v.0.len()), Some(8));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN post_repeating_echo_value_received [
+      (inner.poll_req => { Some(Ok(post(Some([1; 8])).map(Req::from))) }),
+      ({|step: &Echo<Dummy>| step.peers.map_mut(|ps| ps.push(Stamped(Addrd(PeerState::Challenged([1; 8]), test::x.x.x.x(80)), ClockMock::instant(0)))) }),
+      (snapshot = {{
+        let mut snap = snapshot(0);
+        snap.config.msg.challenge_non_idempotent_requests = true;
+        snap
+      }})
+    ]
+    THEN request_should_be_handled [
+      (poll_req(_, _) should satisfy { |out| assert!(matches!(out, Some(Ok(_)))) }),
+      (effects should satisfy { |effs| {
+        assert!(!effs.iter().any(|e| matches!(e, Effect::Send(_))));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN peer_repeats_echo_value [
+      (inner.poll_req => { Some(Ok(req(Some([1; 8])).map(Req::from))) }),
+      ({|step: &Echo<Dummy>| {
+        step.peers.map_mut(|ps| ps.push(Stamped(Addrd(PeerState::Challenged([1; 8]), test::x.x.x.x(80)), ClockMock::instant(0))));
+        step.poll_req(&snapshot(1_000), &mut vec![])
+      }})
+    ]
+    THEN large_responses_should_be_sent [
+      (before_message_sent(_, _, resp(Code::new(2, 5), 100)) should be ok with { |msg| {
+        assert_eq!(msg.data().code, Code::new(2, 5));
+        assert_eq!(msg.data().payload.0.len(), 100);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN peer_repeats_expired_echo_value [
+      (inner.poll_req => { Some(Ok(req(Some([1; 8])).map(Req::from))) }),
+      ({|step: &Echo<Dummy>| {
+        step.peers.map_mut(|ps| ps.push(Stamped(Addrd(PeerState::Challenged([1; 8]), test::x.x.x.x(80)), ClockMock::instant(0))));
+        step.poll_req(&snapshot(60_000), &mut vec![])
+      }}),
+      (snapshot = { snapshot(60_000) })
+    ]
+    THEN large_responses_should_be_challenged_with_new_value [
+      (before_message_sent(_, _, resp(Code::new(2, 5), 100)) should be ok with { |msg| {
+        assert_eq!(msg.data().code, code::UNAUTHORIZED);
+        assert_ne!(msg.data().get_first(ECHO), Some(&OptValue(vec![1; 8])));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_sent_and_challenged [
+      (inner.poll_resp => { Some(Ok(challenge([2; 8]).map(Resp::from))) }),
+      ({|step: &Echo<Dummy>| step.before_message_sent(&snapshot(0), &mut vec![], &mut req(None)).unwrap()})
+    ]
+    THEN request_should_be_retried_with_echo [
+      (poll_resp(_, _, Token(array_vec!(1)), test::x.x.x.x(80)) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let sent = effs.iter().find_map(|e| match e {
+          | Effect::Send(m) => Some(m.clone()),
+          | _ => None,
+        }).unwrap();
+        assert_eq!(sent.data().id, Id(0));
+        assert_eq!(sent.data().token, Token(array_vec!(1)));
+        assert_eq!(sent.data().get_first(ECHO), Some(&OptValue(vec![2; 8])));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Echo::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_with_echo_sent_and_challenged_again [
+      (inner.poll_resp => { Some(Ok(challenge([2; 8]).map(Resp::from))) }),
+      ({|step: &Echo<Dummy>| step.before_message_sent(&snapshot(0), &mut vec![], &mut req(Some([2; 8]))).unwrap()})
+    ]
+    THEN challenge_should_be_yielded [
+      (poll_resp(_, _, Token(array_vec!(1)), test::x.x.x.x(80)) should satisfy { |out| {
+        assert_eq!(out.unwrap().unwrap().data().code(), code::UNAUTHORIZED);
+      }}),
+      (effects should satisfy { |effs| {
+        assert!(!effs.iter().any(|e| matches!(e, Effect::Send(_))));
+      }})
+    ]
+  );
+
+  #[test]
+  fn challenged_request_should_be_retried_before_inner_steps_protect_it() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = Echo::<Mock>::default();
+
+    // like the Oscore step, encrypt the payload
+    s.inner().set_before_message_sent(|_, _, _, msg| {
+               msg.as_mut().payload = toad_msg::Payload(vec![0; 16]);
+               msg.as_mut().set(OSCORE, OptValue(vec![0x09, 0x14])).ok();
+               Ok(())
+             });
+
+    let mut msg = req(None);
+    msg.as_mut().payload = toad_msg::Payload("hello".bytes().collect());
+    s.before_message_sent(&snapshot(0), &mut vec![], &mut msg)
+     .unwrap();
+
+    // retransmissions of the protected request must not replace it
+    s.before_message_sent(&snapshot(0), &mut vec![], &mut msg)
+     .unwrap();
+
+    s.inner()
+     .set_poll_resp(|_, _, _, _, _| Some(Ok(protected(challenge([2; 8])).map(Resp::from))));
+    let mut effs = vec![];
+    assert!(s.poll_resp(&snapshot(0),
+                        &mut effs,
+                        Token(array_vec!(1)),
+                        test::x.x.x.x(80))
+             .is_none());
+
+    let sent = effs.iter()
+                   .find_map(|e| match e {
+                     | Effect::Send(m) => Some(m.clone()),
+                     | _ => None,
+                   })
+                   .unwrap();
+    assert_eq!(sent.data().get(OSCORE), None);
+    assert_eq!(sent.data().payload.0, "hello".bytes().collect::<Vec<_>>());
+    assert_eq!(sent.data().get_first(ECHO), Some(&OptValue(vec![2; 8])));
+  }
+}
@@ -1,15 +1,14 @@
 use core::fmt::Write;
 
 use naan::prelude::ResultExt;
-use toad_array::Array;
 use toad_len::Len;
 use toad_map::{InsertError, Map};
-use toad_msg::{Token, Type};
+use toad_msg::{CodeKind, MessageOptions, Token, Type};
 use toad_stem::Stem;
 
 use super::{log, Step, StepOutput};
 use crate::net::Addrd;
-use crate::platform::{Effect, PlatformTypes};
+use crate::platform::PlatformTypes;
 use crate::req::Req;
 use crate::resp::Resp;
 use crate::todo::String;
@@ -32,6 +31,20 @@ impl<S: Default, B: Default> Default for HandleAcks<S, B> {
   }
 }
 
+impl<S, B> HandleAcks<S, B> where B: Map<Addrd<Token>, bool>
+{
+  /// Is `msg` an empty ACK for a request we sent that suppresses all responses?
+  fn is_only_answer<P>(&self, msg: Addrd<&platform::Message<P>>) -> bool
+    where P: PlatformTypes
+  {
+    msg.data().ty == Type::Ack
+    && msg.data().code.kind() == CodeKind::Empty
+    && self.buffer
+           .map_ref(|buf| buf.get(&msg.map(|m| m.token)).copied())
+           .unwrap_or(false)
+  }
+}
+
 /// Errors that can be encountered when buffering responses
 #[derive(Clone, PartialEq, Eq)]
 pub enum Error<E> {
@@ -63,7 +76,15 @@ impl<E: core::fmt::Debug> core::fmt::Debug for Error<E> {
   }
 }
 
-impl<E: super::Error> super::Error for Error<E> {}
+impl<E: super::Error> super::Error for Error<E> {
+  fn is_timeout(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_timeout())
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_unacknowledged())
+  }
+}
 
 macro_rules! common {
   ($in:expr, $msg:expr, $effects:expr, $buffer:expr) => {{
@@ -111,7 +132,7 @@ macro_rules! common {
 }
 
 impl<P: PlatformTypes,
-      B: Map<Addrd<Token>, ()> + core::fmt::Debug,
+      B: Map<Addrd<Token>, bool> + core::fmt::Debug,
       E: super::Error,
       S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>, Error = E>> Step<P>
   for HandleAcks<S, B>
@@ -150,6 +171,15 @@ impl<P: PlatformTypes,
                                 Error::Inner);
 
     match resp {
+      | Some(resp) if self.is_only_answer::<P>(resp.as_ref().map(|r| r.as_ref())) => {
+        let (sender, token) = (resp.addr(), resp.data().token());
+        log!(HandleAcks,
+             effects,
+             log::Level::Trace,
+             "Got empty ACK from {sender} for {token:?}, which suppressed all responses");
+        self.buffer.map_mut(|buf| buf.remove(&Addrd(token, sender)));
+        Some(Ok(resp))
+      },
       | Some(resp) => {
         let msg = resp.as_ref().map(|r| r.as_ref());
         common!(resp, msg, effects, self.buffer)
@@ -167,9 +197,15 @@ impl<P: PlatformTypes,
         .on_message_sent(snap, effects, msg)
         .map_err(Error::Inner)?;
 
+    let suppresses_all = msg.data().code.kind() == CodeKind::Request
+                         && msg.data()
+                               .no_response()
+                               .map(|n| n.suppresses_all())
+                               .unwrap_or(false);
+
     match msg.data().ty {
       | Type::Con => self.buffer
-                         .map_mut(|buf| buf.insert(msg.as_ref().map(|m| m.token), ()))
+                         .map_mut(|buf| buf.insert(msg.as_ref().map(|m| m.token), suppresses_all))
                          .recover(|e| {
                            if matches!(e, InsertError::Exists(_)) {
                              Ok(())
@@ -197,7 +233,7 @@ mod test {
 
   type InnerPollReq = Addrd<Req<test::Platform>>;
   type InnerPollResp = Addrd<Resp<test::Platform>>;
-  type HandleAcks<S> = super::HandleAcks<S, BTreeMap<Addrd<Token>, ()>>;
+  type HandleAcks<S> = super::HandleAcks<S, BTreeMap<Addrd<Token>, bool>>;
 
   fn test_message(ty: Type) -> Addrd<test::Message> {
     use toad_msg::*;
@@ -373,4 +409,39 @@ mod test {
 
     assert_eq!(res, None);
   }
+
+  #[test]
+  fn when_empty_ack_for_request_suppressing_all_responses_received_it_should_be_returned() {
+    type Mock = test::MockStep<(), Addrd<Req<test::Platform>>, Addrd<Resp<test::Platform>>, ()>;
+
+    let sut = HandleAcks::<Mock>::default();
+    sut.inner().set_poll_resp(|_, _, _, token, _| {
+                 let mut msg = test::msg!(ACK {0 . 00} x.x.x.x:2222);
+                 Addrd::data_mut(&mut msg).token = token;
+                 Some(Ok(msg.map(Resp::from)))
+               });
+
+    let token = Token(array_vec![1, 2, 3, 4]);
+
+    let mut sent_req = test::msg!(CON POST x.x.x.x:2222);
+    let dest = sent_req.addr();
+    sent_req.as_mut().token = token;
+    sent_req.as_mut()
+            .set_no_response(toad_msg::NoResponse::all())
+            .ok();
+
+    let snap = test::snapshot();
+    let mut effs = Vec::<test::Effect>::new();
+
+    sut.on_message_sent(&snap, &mut effs, &sent_req).unwrap();
+
+    let ack = sut.poll_resp(&snap, &mut effs, token, dest)
+                 .unwrap()
+                 .unwrap();
+    assert_eq!(ack.data().msg().ty, Type::Ack);
+    assert_eq!(ack.data().token(), token);
+
+    // it's only yielded once
+    assert_eq!(sut.poll_resp(&snap, &mut effs, token, dest), None);
+  }
 }
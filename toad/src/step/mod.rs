@@ -1,6 +1,8 @@
 use ::toad_msg::Token;
+use embedded_time::Instant;
 use no_std_net::SocketAddr;
 
+use crate::config::Config;
 use crate::net::Addrd;
 use crate::platform::{self, PlatformTypes};
 
@@ -10,11 +12,22 @@ pub mod runtime {
   use naan::prelude::{HKT1, HKT2};
   use no_std_net::SocketAddr;
 
-  use super::ack::Ack;
   use super::parse::Parse;
   use super::provision_ids::{self, IdWithDefault, SocketAddrWithDefault};
   use super::provision_tokens::ProvisionTokens;
-  use super::{buffer_responses, handle_acks, observe, retry};
+  use super::{ack,
+              block,
+              buffer_responses,
+              check_options,
+              dedup,
+              echo,
+              handle_acks,
+              multicast,
+              no_response,
+              observe,
+              oscore,
+              proxy,
+              retry};
   use crate::net::Addrd;
   use crate::platform::{Message, PlatformTypes};
   use crate::req::Req;
@@ -30,7 +43,7 @@ pub mod runtime {
   type Clock<P> = <P as PlatformTypes>::Clock;
 
   #[allow(missing_docs)]
-  pub type HandleAcks<M, S> = handle_acks::HandleAcks<S, Map<M, Addrd<Token>, ()>>;
+  pub type HandleAcks<M, S> = handle_acks::HandleAcks<S, Map<M, Addrd<Token>, bool>>;
   #[allow(missing_docs)]
   pub type Retry<P, A, S> = retry::Retry<S, Array<A, (retry::State<Clock<P>>, Addrd<Message<P>>)>>;
   #[allow(missing_docs)]
@@ -45,24 +58,65 @@ pub mod runtime {
                                     SocketAddrWithDefault,
                                     Array<A, Stamped<Clock<P>, IdWithDefault>>>>;
   #[allow(missing_docs)]
+  pub type Block<P, A, S> =
+    block::Block<S,
+                 Array<A, block::Transfer<P>>,
+                 Array<A, block::Assembly<P>>,
+                 Array<A, block::Fetch<P>>>;
+  #[allow(missing_docs)]
+  pub type Ack<P, A, S> = ack::Ack<S, Array<A, ack::Exchange<P>>>;
+  #[allow(missing_docs)]
+  pub type NoResponse<P, A, S> = no_response::NoResponse<S, Array<A, no_response::Exchange<P>>>;
+  #[allow(missing_docs)]
+  pub type Echo<P, A, S> =
+    echo::Echo<S, Array<A, echo::Peer<P>>, Array<A, echo::Received<P>>, Array<A, echo::Sent<P>>>;
+  #[allow(missing_docs)]
+  pub type CheckOptions<S> = check_options::CheckOptions<S, check_options::StandardOptions>;
+  #[allow(missing_docs)]
+  pub type Dedup<P, A, S> = dedup::Dedup<S, Array<A, dedup::Exchange<P>>>;
+  #[allow(missing_docs)]
+  pub type Multicast<P, A, S> = multicast::Multicast<S,
+                                                     Array<A, multicast::Pending<P>>,
+                                                     Array<A, multicast::Responding<P>>>;
+  #[allow(missing_docs)]
+  pub type Oscore<P, A, K, S> =
+    oscore::Oscore<S, K, Array<A, crate::oscore::Context>, Array<A, oscore::Exchange<P>>>;
+  #[allow(missing_docs)]
+  pub type Proxy<P, A, S> = proxy::Proxy<S, Array<A, proxy::Exchange<P>>>;
+  #[allow(missing_docs)]
   pub type Observe<P, A, S> = observe::Observe<S,
                                                Array<A, observe::Sub<P>>,
                                                Array<A, Addrd<Req<P>>>,
                                                observe::SubHash_TypePathQueryAccept<P>>;
 
-  /// Parse -> ProvisionIds -> ProvisionTokens -> Ack -> Retry -> HandleAcks -> BufferResponses -> Observe
+  /// Parse -> CheckOptions -> Dedup -> Oscore -> Multicast -> ProvisionIds -> ProvisionTokens -> Block -> Ack -> Echo -> NoResponse -> Retry -> HandleAcks -> BufferResponses -> Proxy -> Observe
+  ///
+  /// Inner steps see messages first whether they're being received or sent,
+  /// so the steps after Oscore see received messages decrypted, and sent messages
+  /// encrypted once they've called their inner step. Multicast, Block, Echo & NoResponse
+  /// look at messages being sent before calling their inner step where they need the plaintext,
+  /// and Block comes after ProvisionIds & ProvisionTokens so that it can pair the requests
+  /// it sends with their token.
   #[rustfmt::skip]
-  pub type Runtime<P, Array, Map> =
+  pub type Runtime<P, Array, Map, Keys = crate::oscore::NoKeys> =
     Observe<P, Array,
+    Proxy<P, Array,
     BufferResponses<P, Map,
     HandleAcks<Map,
     Retry<P, Array,
-    Ack<
+    NoResponse<P, Array,
+    Echo<P, Array,
+    Ack<P, Array,
+    Block<P, Array,
     ProvisionTokens<
     ProvisionIds<P, Map, Array,
+    Multicast<P, Array,
+    Oscore<P, Array, Keys,
+    Dedup<P, Array,
+    CheckOptions<
     Parse<
     ()
-    >>>>>>>>;
+    >>>>>>>>>>>>>>>>;
 
   #[allow(missing_docs)]
   #[cfg(feature = "std")]
@@ -70,8 +124,178 @@ pub mod runtime {
     use crate::std::PlatformTypes;
 
     /// Default steps + step order pre-applied with `Vec` and `BTreeMap`
-    pub type Runtime<Dtls> =
-      super::Runtime<PlatformTypes<Dtls>, naan::hkt::Vec, naan::hkt::BTreeMap>;
+    pub type Runtime<Dtls, Keys = crate::oscore::NoKeys> =
+      super::Runtime<PlatformTypes<Dtls>, naan::hkt::Vec, naan::hkt::BTreeMap, Keys>;
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use no_std_net::{Ipv4Addr, SocketAddrV4};
+    use toad_msg::opt::known::no_repeat::{BLOCK2, OSCORE};
+    use toad_msg::opt::known::repeat::PATH;
+    use toad_msg::{Code, Id, MessageOptions, NoResponse, Type};
+
+    use super::*;
+    use crate::oscore::tests::{client_params, server_params};
+    use crate::oscore::{ContextId, Keyring, Params};
+    use crate::platform::Platform;
+    use crate::test;
+
+    type TestPlatform<Keys = crate::oscore::NoKeys> =
+      test::MockPlatform<Runtime<test::Platform, naan::hkt::Vec, naan::hkt::BTreeMap, Keys>>;
+
+    /// RFC8613 appendix C.1.1
+    #[derive(Debug, Default)]
+    struct ClientKeys;
+
+    impl Keyring for ClientKeys {
+      fn for_peer(&self, _: SocketAddr) -> Option<Params> {
+        Some(client_params())
+      }
+
+      fn for_kid(&self, _: &[u8], _: Option<&[u8]>) -> Option<Params> {
+        None
+      }
+
+      fn store_ssn(&self, _: &ContextId, _: u64) -> bool {
+        true
+      }
+    }
+
+    /// RFC8613 appendix C.1.2
+    #[derive(Debug, Default)]
+    struct ServerKeys;
+
+    impl Keyring for ServerKeys {
+      fn for_peer(&self, _: SocketAddr) -> Option<Params> {
+        None
+      }
+
+      fn for_kid(&self, kid: &[u8], _: Option<&[u8]>) -> Option<Params> {
+        Some(server_params()).filter(|p| p.recipient_id.as_slice() == kid)
+      }
+
+      fn store_ssn(&self, _: &ContextId, _: u64) -> bool {
+        true
+      }
+    }
+
+    /// Move the datagrams `from` sent to `to`'s socket, yielding the messages that were moved
+    fn deliver<A, B>(from: &test::MockPlatform<A>,
+                     to: &test::MockPlatform<B>)
+                     -> Vec<Addrd<test::Message>> {
+      let sent = from.sent();
+      from.sock.tx.lock().unwrap().clear();
+      sent.iter()
+          .filter(|msg| msg.addr() == to.sock.addr)
+          .for_each(|msg| to.recv(Addrd(msg.data().clone(), from.sock.addr)));
+      sent
+    }
+
+    fn get(ty: Type) -> Addrd<test::Message> {
+      let mut msg = test::msg!({ty} {Code::GET} x.x.x.x:80).unwrap();
+      msg.id = Id(1);
+      msg.token = Token(tinyvec::array_vec!(1));
+      Addrd(msg, test::x.x.x.x(80))
+    }
+
+    /// Receive `req`, and respond to it with `code`
+    fn respond(platform: &TestPlatform, req: Addrd<test::Message>, code: Code) {
+      platform.recv(req);
+
+      let req = nb::block!(platform.poll_req()).unwrap();
+      let mut resp = Resp::for_request(req.data()).unwrap();
+      resp.set_code(code);
+
+      platform.send_msg(req.as_ref().map(|_| resp.into()))
+              .unwrap();
+    }
+
+    #[test]
+    fn suppressed_response_should_not_be_sent() {
+      let platform = TestPlatform::new(Default::default());
+      let mut req = get(Type::Non);
+      req.as_mut()
+         .set_no_response(NoResponse::new().suppress_2xx())
+         .unwrap();
+
+      respond(&platform, req, Code::new(2, 5));
+      assert!(platform.sent().is_empty());
+    }
+
+    #[test]
+    fn error_response_to_multicast_request_should_not_be_sent() {
+      let mut platform = TestPlatform::new(Default::default());
+      platform.sock.addr = crate::multicast::all_coap_devices(5683);
+      platform.config.msg.multicast_response_leisure = embedded_time::duration::Milliseconds(0);
+
+      respond(&platform, get(Type::Non), Code::new(4, 4));
+      assert!(platform.sent().is_empty());
+    }
+
+    #[test]
+    fn protected_request_should_be_answered_with_protected_blocks() {
+      let mut client = TestPlatform::<ClientKeys>::new(Default::default());
+      client.sock.addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(192, 168, 0, 2), 5683));
+
+      let mut server = TestPlatform::<ServerKeys>::new(Default::default());
+      server.sock.addr = test::x.x.x.x(5683);
+      server.config.msg.block_size = 16;
+
+      let body = (0..40u8).collect::<Vec<_>>();
+
+      let mut req = test::msg!(CON GET x.x.x.x:5683).unwrap();
+      req.set_path("hello").unwrap();
+      let (_, token) = client.send_msg(Addrd(req, server.sock.addr)).unwrap();
+
+      let sent = deliver(&client, &server);
+      assert_eq!(sent.len(), 1);
+      assert_eq!(sent[0].data().code, crate::req::Method::POST.code());
+      assert!(sent[0].data().get(OSCORE).is_some());
+      assert_eq!(sent[0].data().get(PATH), None);
+
+      // Responses to requests that didn't ask for a block aren't stored,
+      // so the request for the second block is yielded too
+      let serve = || loop {
+        match server.poll_req() {
+          | Ok(req) => {
+            assert_eq!(req.data().path().unwrap(), Some("hello"));
+
+            let mut resp = Resp::for_request(req.data()).unwrap();
+            resp.set_code(Code::new(2, 5));
+            resp.set_payload(body.iter().copied());
+            server.send_msg(req.as_ref().map(|_| resp.into())).unwrap();
+          },
+          | Err(nb::Error::WouldBlock) => break,
+          | Err(e) => panic!("{:?}", e),
+        }
+      };
+
+      let resp = loop {
+        serve();
+
+        for sent in deliver(&server, &client) {
+          // the code & Block2 option of each block are protected
+          assert_eq!(sent.data().code, Code::new(2, 4));
+          assert!(sent.data().get(OSCORE).is_some());
+          assert_eq!(sent.data().get(BLOCK2), None);
+        }
+
+        match client.poll_resp(token, server.sock.addr) {
+          | Ok(resp) => break resp,
+          | Err(nb::Error::WouldBlock) => (),
+          | Err(e) => panic!("{:?}", e),
+        }
+
+        for sent in deliver(&client, &server) {
+          assert!(sent.data().get(OSCORE).is_some());
+          assert_eq!(sent.data().get(BLOCK2), None);
+        }
+      };
+
+      assert_eq!(resp.data().code(), Code::new(2, 5));
+      assert_eq!(resp.data().payload().copied().collect::<Vec<_>>(), body);
+    }
   }
 }
 
@@ -88,7 +312,22 @@ pub mod runtime {
 ///
 /// For outbound non-confirmable requests, uses the params in [`Config.msg.non`](crate::config::Non).
 ///
-/// Outbound non-confirmable responses and ACKs will never be retried.
+/// Outbound non-confirmable responses and ACKs will never be retried, nor will
+/// non-confirmable requests whose [No-Response](toad_msg::opt::known::no_repeat::NO_RESPONSE) option
+/// suppresses all responses.
+///
+/// Once a message has been retried as many times as allowed and the last
+/// attempt goes unanswered, this step gives up on it and reports the failure:
+///  * `poll_resp` for the token & address of a request we gave up on yields
+///    [`Error::Timeout`](retry::Error::Timeout)
+///  * `poll_req` yields [`Error::Unacknowledged`](retry::Error::Unacknowledged)
+///    for CON responses & notifications that were never acknowledged
+///
+/// [`BlockingClient`](crate::client::BlockingClient) reports the former as
+/// [`Error::RetriesExhausted`](crate::client::Error::RetriesExhausted), and
+/// [`BlockingServer`](crate::server::BlockingServer) logs the latter and keeps serving.
+/// Failures that haven't been reported when the buffer is full are forgotten
+/// (oldest first) to make room for new messages.
 ///
 /// Note that the bandwidth used for retrying will never significantly exceed
 /// [`probing_rate`](crate::config::Config.probing_rate), so retries may be delayed
@@ -115,6 +354,14 @@ pub mod retry;
 ///
 /// Based on [`cmp_requests`](observe::Observe::cmp_requests), equivalent requests will be combined.
 ///
+/// Every `2.xx` response sent to a subscriber is stamped with:
+/// * an [Observe](toad_msg::opt::known::no_repeat::OBSERVE) value taken from a 24-bit sequence number
+///   that increases with every notification sent to that subscriber (see [`observe::seq`]),
+///   so that clients may reorder notifications and detect stale ones
+/// * a [Max-Age](toad_msg::opt::known::no_repeat::MAX_AGE) of
+///   [`Msg.notification_max_age_seconds`](crate::config::Msg.notification_max_age_seconds),
+///   unless the response already has one, so that clients know when to re-register
+///
 /// # Example
 /// ### Given
 /// * a resource `<coap://server/temperature>`
@@ -132,6 +379,9 @@ pub mod retry;
 ///  - Request 2 `GET coap://server/temperature?above=23deg`
 ///
 /// The response to request 1 will be sent to clients A, B, and C. The response to request 2 will be sent to client D.
+///
+/// # Client
+/// To subscribe to resources on other servers, see [`Platform::observe`](crate::platform::Platform::observe).
 pub mod observe;
 
 /// # Assign message tokens to those with Token(0)
@@ -142,8 +392,13 @@ pub mod observe;
 /// None
 ///
 /// ## Behavior
-/// Whenever a request is sent with an Token of 0, the Token is replaced
-/// with a new Token that has not been used yet.
+/// Whenever a request is sent with an empty Token, the Token is replaced
+/// with a new Token that has not been used yet, [`token_len`](crate::config::Msg::token_len)
+/// bytes long.
+///
+/// Tokens set by the application are never replaced, so they may carry
+/// application state (e.g. a stateless proxy's routing state in an
+/// [RFC8974](https://www.rfc-editor.org/rfc/rfc8974) extended token).
 ///
 /// ## Transformation
 /// None
@@ -171,7 +426,8 @@ pub mod provision_ids;
 ///
 /// ## Internal State
 /// This step will store the tokens of all CONfirmable messages sent,
-/// removing them as they are acknowledged.
+/// and whether they're requests whose [No-Response](toad_msg::opt::known::no_repeat::NO_RESPONSE)
+/// option suppresses all responses, removing them as they are acknowledged.
 ///
 /// ## Behavior
 /// If an ACK is received by a client or server that does not match any
@@ -182,6 +438,10 @@ pub mod provision_ids;
 /// If an ACK is received by a client or server that does not match any
 /// pending CONfirmable messages, this step will cause further steps
 /// to ignore it by yielding None.
+///
+/// Empty ACKs are ignored the same way, unless they acknowledge a request
+/// suppressing all responses; that ACK is the only answer the request will get,
+/// so clients polling for a response to it get the ACK.
 pub mod handle_acks;
 
 /// # ACK incoming messages
@@ -189,16 +449,112 @@ pub mod handle_acks;
 /// * Server Flow ✓
 ///
 /// ## Internal State
-/// None
+/// Stores the address, Id and token of CON requests received by a server,
+/// until they are responded to or age out of the exchange lifetime.
 ///
 /// ## Behavior
-/// If a CON is received by a client or server,
-/// this step will reply with an ACK.
+/// If a CON request is received by a server and the application responds within
+/// [`Msg.piggyback_deadline`](crate::config::Msg.piggyback_deadline),
+/// the response is piggybacked on the ACK.
+///
+/// If the application takes longer than that (e.g. when deferring the response with
+/// [`Run::maybe_defer`](crate::server::Run::maybe_defer)), this step will reply with an
+/// empty ACK and the response will be sent separately as a CON response. The same goes
+/// for requests the application ACKs itself.
+///
+/// If too many requests are awaiting a response to remember them all,
+/// the oldest is ACKed and forgotten.
+///
+/// If a CON response (e.g. a separate response or Observe notification)
+/// is received by a client, this step will reply with an empty ACK.
 ///
 /// ## Transformation
-/// None
+/// Responses to CON requests are sent as ACKs with the request's Id if the
+/// request hasn't been ACKed yet, and as CON if it has.
 pub mod ack;
 
+/// # Honor the No-Response option
+/// * Client Flow ✓
+/// * Server Flow ✓
+///
+/// ## Internal State
+///  * Stores the address, token, Id & [`NO_RESPONSE`](toad_msg::opt::known::no_repeat::NO_RESPONSE) value of requests
+///    received and sent with the option, until they age out of the exchange lifetime
+///
+/// ## Behavior
+/// Per [RFC7967](https://www.rfc-editor.org/rfc/rfc7967), clients may tell servers
+/// which classes of response (2.xx, 4.xx, 5.xx) they aren't interested in.
+///  * Responses in a class the client asked to suppress are not sent.
+///    If the response would have been piggybacked on an ACK, an empty ACK is sent instead.
+///  * Responses to our own requests in a class we asked to suppress are dropped
+///    unless they're the response being polled for, so they aren't buffered forever.
+///
+/// ## Transformation
+/// Suppressed responses are marked with [`SUPPRESS`], or replaced with an empty ACK.
+///
+/// Empty ACKs for our own CON requests suppressing all responses are given the
+/// request's token (see [`HandleAcks`](handle_acks)), since empty messages don't have one.
+pub mod no_response;
+
+/// # Mitigate amplification & retry Echo challenges
+/// * Client Flow ✓
+/// * Server Flow ✓
+///
+/// ## Internal State
+///  * Stores the [`ECHO`](toad_msg::opt::known::no_repeat::ECHO) values sent to peers, and which peers have repeated them,
+///    until they age out of [`Msg.echo_lifetime`](crate::config::Msg.echo_lifetime)
+///  * Stores the address, token & datagram size of requests received,
+///    and the requests sent (before they're protected with [OSCORE](oscore)),
+///    until they age out of the exchange lifetime
+///
+/// ## Behavior
+/// Per [RFC9175](https://www.rfc-editor.org/rfc/rfc9175#section-2.4), a server should not
+/// send responses much larger than the request to a peer that hasn't proven it can receive
+/// datagrams at its address, so that it can't be used to flood a spoofed victim.
+///  * Responses more than [`Msg.amplification_factor`](crate::config::Msg.amplification_factor)
+///    times the size of the request are not sent to unverified peers. Instead, the peer is sent
+///    `4.01 Unauthorized` with an Echo value.
+///  * With [`Msg.challenge_non_idempotent_requests`](crate::config::Msg.challenge_non_idempotent_requests),
+///    POST & PATCH requests from unverified peers are answered with `4.01 Unauthorized` & an Echo
+///    value before they are handled, so that repeating the request with the Echo value doesn't
+///    run a non-idempotent handler twice.
+///  * A request repeating an unexpired Echo value we sent marks the peer as verified.
+///  * Exchanges protected with [OSCORE](oscore) are not challenged, since the peer is authenticated.
+///  * When a request we sent is answered with `4.01 Unauthorized` and an Echo value,
+///    the request is sent again with the Echo value (once per value) and the `4.01` is dropped.
+///
+/// ## Transformation
+/// Responses too large for an unverified peer are replaced with `4.01 Unauthorized` + Echo.
+///
+/// Echo values are generated with a CSPRNG seeded from the operating system
+/// (or with [`Echo::seed`](echo::Echo::seed) without the `std` feature).
+pub mod echo;
+
+/// # Forward requests to other servers as a proxy
+/// * Client Flow ✗
+/// * Server Flow ✓
+///
+/// ## Internal State
+///  * Stores the address & token of each request we forwarded, along with the
+///    target & token we forwarded it with, until they age out of the exchange lifetime
+///    (Observe registrations are kept alive by each notification relayed)
+///
+/// ## Behavior
+/// When [`Config.forward_proxy`](crate::config::Config.forward_proxy) is `true`, requests
+/// with a Proxy-Uri or Proxy-Scheme option ([RFC7252 Section 5.7.2](https://www.rfc-editor.org/rfc/rfc7252#section-5.7.2))
+/// are forwarded to their target instead of being yielded.
+///  * Only `coap` targets whose host is an IP address are supported; other requests
+///    are answered with `5.05 Proxying Not Supported`.
+///  * The [`HOP_LIMIT`](toad_msg::opt::known::no_repeat::HOP_LIMIT) option ([RFC8768](https://www.rfc-editor.org/rfc/rfc8768))
+///    is decremented (or set to [`DEFAULT_HOP_LIMIT`](proxy::DEFAULT_HOP_LIMIT) if absent).
+///    Requests that would be forwarded with a Hop-Limit of 0 are answered with `5.08 Hop Limit Reached`.
+///  * Responses from the target are relayed to the client with the client's token.
+///    CON responses from the target are ACKed.
+///
+/// ## Transformation
+/// Requests to be forwarded and responses to forwarded requests are not yielded.
+pub mod proxy;
+
 /// # Set standard options on outbound messages
 /// * Client Flow ✓
 /// * Server Flow ✓
@@ -235,6 +591,177 @@ pub mod set_standard_options;
 /// None
 pub mod buffer_responses;
 
+/// # Send & receive large payloads in blocks
+/// * Client Flow ✓
+/// * Server Flow ✓
+///
+/// ## Internal State
+///  * Stores the address, token and cache key of requests yielded to the application
+///    that ask for a block of the response (or carried a request body in blocks)
+///  * Stores responses too big to fit in a single block until the transfer completes
+///    or ages out of the exchange lifetime
+///  * Stores the blocks of request bodies received so far, until the last block
+///    is received or the transfer ages out of the exchange lifetime
+///  * Stores requests sent by the application (before they're protected with [OSCORE](oscore)),
+///    and the blocks of their responses received so far, until the last block is received
+///    or the request ages out of the exchange lifetime
+///
+/// ## Behavior
+/// ### Responses (Block2)
+///  * When a response is sent with a payload bigger than
+///    [`Msg.block_size`](crate::config::Msg.block_size) (or the block size
+///    the client asked for with [Block2](toad_msg::opt::known::no_repeat::BLOCK2)),
+///    the full response is stored and only the requested block is sent.
+///  * When a request is received asking for a later block of a stored response,
+///    this step will reply with that block and will not yield the request.
+///  * Responses to requests that didn't ask for a block are not stored, so the
+///    request for the next block is yielded; the response to it is stored and
+///    used to serve the rest of the blocks.
+///  * Requests asking for a block beyond the end of a stored response are
+///    responded to with 4.02 Bad Option.
+///
+/// ### Request bodies (Block1)
+///  * When a request is received with a [Block1](toad_msg::opt::known::no_repeat::BLOCK1)
+///    option indicating more blocks will follow, the payload is stored and the client
+///    is sent `2.31 Continue`.
+///  * When the last block is received, the request is yielded with the entire body
+///    as its payload, and the response to it will echo the final Block1 option.
+///  * Blocks received out of order (or without the first block) are responded to
+///    with `4.08 Request Entity Incomplete`.
+///  * Request bodies that would cause more than
+///    [`Msg.max_request_body_bytes_per_peer`](crate::config::Msg.max_request_body_bytes_per_peer)
+///    to be buffered for a client are responded to with `4.13 Request Entity Too Large`
+///    and a [Size1](toad_msg::opt::known::no_repeat::SIZE1) option indicating the limit.
+///
+/// ### Fetching responses (Block2)
+///  * When a response is received with a [Block2](toad_msg::opt::known::no_repeat::BLOCK2)
+///    option indicating more blocks will follow, the payload is stored and the next block
+///    is requested with a copy of the original request (same token & options).
+///  * When the last block is received, the response is yielded with the entire
+///    payload and without the Block2 option.
+///  * If the [ETag](toad_msg::opt::known::repeat::ETAG) of a block differs from the blocks
+///    before it, the blocks received so far are discarded and
+///    [`Error::EtagMismatch`](block::Error::EtagMismatch) is yielded.
+///
+/// ## Transformation
+///  * Outbound responses are sliced into blocks, and have the
+///    [Block2](toad_msg::opt::known::no_repeat::BLOCK2) and
+///    [Size2](toad_msg::opt::known::no_repeat::SIZE2) options set.
+///  * Requests for blocks served from the buffer are not yielded.
+///  * Requests carrying blocks of a request body are not yielded until the last
+///    block is received.
+///  * Responses carrying blocks of a response are not yielded until the last
+///    block is received.
+pub mod block;
+
+/// # Reject messages with critical options we don't understand
+/// * Client Flow ✓
+/// * Server Flow ✓
+///
+/// ## Internal State
+/// None
+///
+/// ## Behavior
+/// Per [RFC7252 Section 5.4.1](https://www.rfc-editor.org/rfc/rfc7252#section-5.4.1),
+/// unrecognized options of class "critical" must not be ignored. The options
+/// the application understands are defined by an implementation of
+/// [`KnownOptions`](check_options::KnownOptions), defaulting to
+/// [`StandardOptions`](check_options::StandardOptions).
+///
+///  * When a CON or NON request is received with an unrecognized critical option,
+///    this step will reply with `4.02 Bad Option` and a diagnostic payload naming the option.
+///  * When a CON or NON response is received with an unrecognized critical option,
+///    this step will reply with RESET.
+///  * ACKs with unrecognized critical options are logged and ignored.
+///
+/// Unrecognized elective options are left untouched.
+///
+/// ## Transformation
+/// Messages with unrecognized critical options are not yielded.
+pub mod check_options;
+
+/// # Detect duplicate requests & replay our replies to them
+/// * Client Flow ✗
+/// * Server Flow ✓
+///
+/// ## Internal State
+///  * Stores the address & Id of every request received, along with the
+///    reply we sent to it, until they age out of the exchange lifetime
+///
+/// ## Behavior
+/// Clients retransmit CON requests when our ACK is lost, and may
+/// retransmit NON requests. Per [RFC7252 Section 4.5](https://www.rfc-editor.org/rfc/rfc7252#section-4.5),
+/// these duplicates must not be processed again.
+///
+///  * When a request is received with the same Id as a request from the same client,
+///    and we've replied to the original, our reply is sent again.
+///    (For CON requests this is the ACK, for NON requests this is the response.)
+///  * When a duplicate is received and we haven't replied yet, it is ignored.
+///
+/// ## Transformation
+/// Duplicate requests are not yielded.
+pub mod dedup;
+
+/// # Protect messages end-to-end with OSCORE
+/// * Client Flow ✓
+/// * Server Flow ✓
+///
+/// ## Internal State
+///  * Stores the [security contexts](crate::oscore::Context) derived from the
+///    [`Keyring`](crate::oscore::Keyring), evicting the oldest when full once its replay window
+///    is stored with [`Keyring::store_replay_window`](crate::oscore::Keyring::store_replay_window)
+///  * Stores the address, token, kid & Partial IV of protected requests received and sent,
+///    until they age out of the exchange lifetime (Observe registrations are kept until evicted)
+///
+/// ## Behavior
+/// Per [RFC8613](https://www.rfc-editor.org/rfc/rfc8613), messages exchanged with a peer
+/// we share a security context with are encrypted with AES-CCM-16-64-128, leaving only the
+/// options proxies need (Uri-Host, Uri-Port, Observe, Proxy-Uri, Proxy-Scheme, No-Response)
+/// readable.
+///  * Requests to peers with [`Keyring::for_peer`](crate::oscore::Keyring::for_peer) params are protected,
+///    and their responses are verified. Unprotected success responses to protected requests are dropped.
+///  * Protected requests are verified against the security context identified by their kid.
+///    Requests that can't be verified, or whose Partial IV was already seen, are answered with
+///    `4.01 Unauthorized`, `4.00 Bad Request` or `4.02 Bad Option` and are not yielded.
+///  * Responses to protected requests are protected.
+///  * The sender sequence number is persisted with [`Keyring::store_ssn`](crate::oscore::Keyring::store_ssn)
+///    every [`Keyring::SSN_PERSIST_INTERVAL`](crate::oscore::Keyring::SSN_PERSIST_INTERVAL) messages,
+///    and messages are not protected if it can't be.
+///  * Requests needing a new security context when none can be evicted are answered with `4.01 Unauthorized`.
+///
+/// Steps outside this one see messages after they're verified & before they're protected,
+/// so with [`block`] outside it (as in [`Runtime`](runtime::Runtime)) large messages are sent
+/// in blocks that are each protected on their own
+/// ([RFC8613 section 4.1.3.4.2](https://www.rfc-editor.org/rfc/rfc8613#section-4.1.3.4.2)).
+///
+/// ## Transformation
+///  * Protected inbound messages are replaced with the message that was protected
+///    (keeping the OSCORE option, so handlers can tell it was protected).
+///  * Outbound messages that can't be protected are marked with [`SUPPRESS`].
+pub mod oscore;
+
+/// # Respond to multicast requests
+/// * Client Flow ✗
+/// * Server Flow ✓
+///
+/// ## Internal State
+///  * Stores requests received on a multicast address until they should be yielded
+///  * Stores the address & token of multicast requests, until they age out of the exchange lifetime
+///
+/// ## Behavior
+///  * Requests received on a multicast address (see [`Snapshot.recvd_dgram_dest`](crate::platform::Snapshot.recvd_dgram_dest))
+///    are yielded after a random delay between zero and
+///    [`Msg.multicast_response_leisure`](crate::config::Msg.multicast_response_leisure),
+///    so that every server in the group doesn't respond at the same moment.
+///  * Error responses (4.xx, 5.xx) to multicast requests are not sent, unless
+///    [`Msg.suppress_multicast_error_responses`](crate::config::Msg.suppress_multicast_error_responses)
+///    is `false`. ([RFC7252 Section 8.2](https://www.rfc-editor.org/rfc/rfc7252#section-8.2))
+///
+/// ## Transformation
+///  * Multicast requests are not yielded until their delay has passed.
+///  * Error responses to multicast requests are marked with [`SUPPRESS`].
+pub mod multicast;
+
 /// # Parse messages from dgrams
 /// * Client Flow ✓
 /// * Server Flow ✓
@@ -255,6 +782,18 @@ pub mod parse;
 /// ```
 pub type StepOutput<T, E> = Option<nb::Result<T, E>>;
 
+/// Option that steps may add to an outbound message in
+/// [`Step::before_message_sent`] to prevent it from being sent.
+///
+/// Messages with this option will not be sent by
+/// [`Platform::send_msg`](crate::platform::Platform::send_msg),
+/// and steps will not be notified of them via [`Step::on_message_sent`].
+///
+/// This is in the range of option numbers reserved for experimental use,
+/// is distinct from the numbers used by steps to mark messages they created
+/// (e.g. [`observe::opt::WAS_CREATED_BY_OBSERVE`]), and is never sent over the wire.
+pub const SUPPRESS: toad_msg::OptNumber = toad_msg::OptNumber(65002);
+
 /// Macro to execute inner steps,
 /// converting the `Option<nb::Result<T, E>>` to `Option<T>`
 /// by returning the inner step's Errors & WouldBlock
@@ -288,11 +827,11 @@ macro_rules! exec_inner_step {
 #[macro_export]
 macro_rules! log {
   ($at:path, $effs:expr, $lvl:expr, $($arg:tt)*) => {{
-    use toad_array::Array;
+    use toad_array::Indexed;
     type S = $crate::todo::String::<1000>;
     let msg = S::fmt(format_args!($($arg)*));
     let msg = S::fmt(format_args!("[{}] {}", stringify!($at), msg.as_str()));
-    $effs.push($crate::platform::Effect::Log($lvl, msg));
+    $effs.append($crate::platform::Effect::Log($lvl, msg));
   }};
 }
 
@@ -309,10 +848,32 @@ macro_rules! _try {
   }};
 }
 
-pub use {_try, exec_inner_step, log};
+pub use _try;
+pub use exec_inner_step;
+pub use log;
+
+/// Whichever of two (optional) deadlines comes first
+pub(crate) fn earliest<C>(a: Option<Instant<C>>, b: Option<Instant<C>>) -> Option<Instant<C>>
+  where C: crate::time::Clock
+{
+  match (a, b) {
+    | (Some(a), Some(b)) => Some(a.min(b)),
+    | (a, b) => a.or(b),
+  }
+}
 
 /// An error that can be returned by a [`Step`].
-pub trait Error: core::fmt::Debug {}
+pub trait Error: core::fmt::Debug {
+  /// Is this (or the inner error it wraps) [`retry::Error::Timeout`]?
+  fn is_timeout(&self) -> bool {
+    false
+  }
+
+  /// Is this (or the inner error it wraps) [`retry::Error::Unacknowledged`]?
+  fn is_unacknowledged(&self) -> bool {
+    false
+  }
+}
 
 impl Error for () {}
 
@@ -401,6 +962,22 @@ pub trait Step<P: PlatformTypes>: Default {
         .on_message_sent(snap, effects, msg)
         .map_err(Self::Error::from)
   }
+
+  /// The next instant at which this step must be polled,
+  /// regardless of whether any messages are received before then
+  /// (e.g. to retry a message or send an empty ACK).
+  ///
+  /// Platforms use this to sleep until a datagram arrives
+  /// or the deadline passes, rather than polling in a busy loop.
+  ///
+  /// # Gotchas
+  /// Make sure you include `self.inner().next_deadline`!
+  ///
+  /// # Default Implementation
+  /// The default implementation will just invoke `self.inner().next_deadline`
+  fn next_deadline(&self, config: Config) -> Option<Instant<P::Clock>> {
+    self.inner().next_deadline(config)
+  }
 }
 
 impl<P: PlatformTypes> Step<P> for () {
@@ -450,6 +1027,10 @@ impl<P: PlatformTypes> Step<P> for () {
                      -> Result<(), Self::Error> {
     Ok(())
   }
+
+  fn next_deadline(&self, _: Config) -> Option<Instant<P::Clock>> {
+    None
+  }
 }
 
 #[cfg(test)]
@@ -464,6 +1045,7 @@ pub mod test {
     platform::Snapshot { time: ClockMock::new().try_now().unwrap(),
                          recvd_dgram: Some(crate::net::Addrd(Default::default(),
                                                              crate::test::dummy_addr())),
+                         recvd_dgram_dest: None,
                          config: crate::config::Config::default() }
   }
 
@@ -914,5 +1496,7 @@ pub mod test {
     };
   }
 
-  pub use {dummy_step, test_step, test_step_when};
+  pub use dummy_step;
+  pub use test_step;
+  pub use test_step_when;
 }
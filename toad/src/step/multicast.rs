@@ -0,0 +1,381 @@
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use rand::{Rng, SeedableRng};
+use toad_array::Array;
+use toad_msg::{MessageOptions, OptValue, Token};
+use toad_stem::Stem;
+
+use super::{exec_inner_step, log, Step, StepOutput, SUPPRESS};
+use crate::config::Config;
+use crate::net::Addrd;
+use crate::platform::{self, PlatformTypes, Snapshot};
+use crate::req::Req;
+use crate::resp::Resp;
+use crate::time::{Clock, Millis, Stamped};
+
+/// A multicast request that will be yielded once
+/// its [`Stamped`] instant has passed
+pub type Pending<P> = Stamped<<P as PlatformTypes>::Clock, Addrd<Req<P>>>;
+
+/// The address & token of a multicast request that was yielded,
+/// [`Stamped`] with the instant it was received
+pub type Responding<P> = Stamped<<P as PlatformTypes>::Clock, Addrd<Token>>;
+
+/// See [the module documentation](self)
+#[derive(Debug)]
+pub struct Multicast<S, Pendings, Respondings> {
+  inner: S,
+  pending: Stem<Pendings>,
+  responding: Stem<Respondings>,
+}
+
+impl<S, Pendings, Respondings> Default for Multicast<S, Pendings, Respondings>
+  where S: Default,
+        Pendings: Default,
+        Respondings: Default
+{
+  fn default() -> Self {
+    Multicast { inner: S::default(),
+                pending: Stem::new(Pendings::default()),
+                responding: Stem::new(Respondings::default()) }
+  }
+}
+
+impl<S, Pendings, Respondings> Multicast<S, Pendings, Respondings> {
+  /// Pick a random delay between zero and `leisure`
+  fn delay<P>(now: Instant<P::Clock>, req: &Addrd<Req<P>>, leisure: Millis) -> Millis
+    where P: PlatformTypes
+  {
+    let Milliseconds(leisure) = leisure;
+    if leisure == 0 {
+      return Milliseconds(0);
+    }
+
+    let Milliseconds(ms) = Millis::try_from(now.duration_since_epoch()).unwrap_or(Milliseconds(0));
+    let mut rand = rand_chacha::ChaCha8Rng::seed_from_u64(ms ^ req.data().msg().id.0 as u64);
+
+    Milliseconds(rand.gen_range(0..=leisure))
+  }
+
+  fn prune<P>(responding: &mut Respondings, now: Instant<P::Clock>, config: Config)
+    where P: PlatformTypes,
+          Respondings: Array<Item = Responding<P>>
+  {
+    while let Some(ix) = responding.iter()
+                                   .position(|Stamped(_, at)| expired(*at, now, config))
+    {
+      responding.remove(ix);
+    }
+  }
+
+  /// Remember that we're responding to a multicast request,
+  /// evicting the oldest one if we can't remember any more.
+  fn remember<P>(responding: &mut Respondings, now: Instant<P::Clock>, req: &Addrd<Req<P>>)
+    where P: PlatformTypes,
+          Respondings: Array<Item = Responding<P>>
+  {
+    if responding.is_full() {
+      responding.remove(0);
+    }
+
+    responding.append(Stamped(req.as_ref().map(|r| r.msg().token), now));
+  }
+
+  /// Pop the first pending request that is ready to be yielded
+  fn ready<P>(pending: &mut Pendings, now: Instant<P::Clock>) -> Option<Addrd<Req<P>>>
+    where P: PlatformTypes,
+          Pendings: Array<Item = Pending<P>>
+  {
+    pending.iter()
+           .position(|Stamped(_, at)| *at <= now)
+           .and_then(|ix| pending.remove(ix))
+           .map(|Stamped(req, _)| req)
+  }
+}
+
+fn expired<C>(received_at: Instant<C>, now: Instant<C>, config: Config) -> bool
+  where C: Clock
+{
+  now.checked_duration_since(&received_at)
+     .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+     .map(|Milliseconds(ms)| ms >= config.exchange_lifetime_millis())
+     .unwrap_or(false)
+}
+
+impl<P, S, Pendings, Respondings> Step<P> for Multicast<S, Pendings, Respondings>
+  where P: PlatformTypes,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>>,
+        Pendings: Default + Array<Item = Pending<P>>,
+        Respondings: Default + Array<Item = Responding<P>>
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = S::Error;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut <P as PlatformTypes>::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    self.responding
+        .map_mut(|r| Self::prune::<P>(r, snap.time, snap.config));
+
+    let req = exec_inner_step!(run_anyway_when_would_block = true,
+                               self.inner.poll_req(snap, effects),
+                               core::convert::identity);
+
+    let multicast = snap.recvd_dgram_dest
+                        .map(|addr| addr.ip().is_multicast())
+                        .unwrap_or(false);
+
+    match req {
+      | Some(req) if multicast => {
+        self.responding
+            .map_mut(|r| Self::remember::<P>(r, snap.time, &req));
+
+        let delay = Self::delay::<P>(snap.time, &req, snap.config.msg.multicast_response_leisure);
+        if delay == Milliseconds(0u64) || self.pending.map_ref(|p| p.is_full()) {
+          return Some(Ok(req));
+        }
+
+        log!(Multicast::poll_req,
+             effects,
+             log::Level::Debug,
+             "Deferring multicast request {:?} from {} by {}ms",
+             req.data().msg().token,
+             req.addr(),
+             delay.0);
+
+        let at = snap.time + delay;
+        let mut req = Some(req);
+        self.pending
+            .map_mut(|p| p.append(Stamped(Option::take(&mut req).unwrap(), at)));
+
+        None
+      },
+      | Some(req) => Some(Ok(req)),
+      | None => self.pending
+                    .map_mut(|p| Self::ready::<P>(p, snap.time))
+                    .map(Ok),
+    }
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut <P as PlatformTypes>::Effects,
+               token: Token,
+               addr: no_std_net::SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    self.inner.poll_resp(snap, effects, token, addr)
+  }
+
+  fn before_message_sent(&self,
+                         snap: &Snapshot<P>,
+                         effs: &mut <P as PlatformTypes>::Effects,
+                         msg: &mut Addrd<platform::Message<P>>)
+                         -> Result<(), Self::Error> {
+    // Decided before the inner steps see the response, since
+    // protecting it with OSCORE replaces its code with 2.04 or 2.05
+    let is_error = matches!(msg.data().code.class, 4 | 5);
+    let key = msg.as_ref().map(|m| m.token);
+    let suppress = is_error
+                   && snap.config.msg.suppress_multicast_error_responses
+                   && self.responding
+                          .map_ref(|r| r.iter().any(|Stamped(k, _)| *k == key));
+
+    if suppress {
+      log!(Multicast::before_message_sent,
+           effs,
+           log::Level::Debug,
+           "Suppressing {:?} response to multicast request {:?} from {}",
+           msg.data().code,
+           msg.data().token,
+           msg.addr());
+      msg.as_mut()
+         .set(SUPPRESS, OptValue(Default::default()))
+         .ok();
+    }
+
+    self.inner.before_message_sent(snap, effs, msg)
+  }
+
+  fn next_deadline(&self, config: Config) -> Option<Instant<P::Clock>> {
+    let ready_at = self.pending
+                       .map_ref(|p| p.iter().map(|Stamped(_, at)| *at).min());
+    super::earliest(self.inner.next_deadline(config), ready_at)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tinyvec::array_vec;
+  use toad_msg::{Code, Id};
+
+  use super::*;
+  use crate::step::test::test_step;
+  use crate::test::{self, ClockMock};
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type Multicast<S> =
+    super::Multicast<S, Vec<Pending<test::Platform>>, Vec<Responding<test::Platform>>>;
+
+  fn snapshot(ms: u64, dest: Option<no_std_net::SocketAddr>) -> test::Snapshot {
+    test::Snapshot { time: ClockMock::instant(ms * 1000),
+                     recvd_dgram: None,
+                     recvd_dgram_dest: dest,
+                     config: Default::default() }
+  }
+
+  fn multicast_snapshot(ms: u64) -> test::Snapshot {
+    snapshot(ms, Some(crate::multicast::all_coap_devices(5683)))
+  }
+
+  fn req() -> InnerPollReq {
+    let mut msg = test::msg!(NON GET x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    msg.token = Token(array_vec!(1));
+
+    Addrd(Req::from(msg), test::x.x.x.x(80))
+  }
+
+  fn resp(code: Code) -> Addrd<test::Message> {
+    let mut msg = test::msg!({toad_msg::Type::Non} {code} x.x.x.x:80).unwrap();
+    msg.token = Token(array_vec!(1));
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  test_step!(
+    GIVEN Multicast::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Multicast::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN unicast_request_received [
+      (inner.poll_req => { Some(Ok(req())) }),
+      (snapshot = { snapshot(0, Some(test::dummy_addr())) })
+    ]
+    THEN request_should_be_yielded_immediately [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req()))) }),
+      (before_message_sent(_, _, resp(Code::new(4, 4))) should be ok with { |msg| {
+        assert_eq!(msg.data().get(SUPPRESS), None);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Multicast::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN multicast_request_received [
+      (inner.poll_req = { |snap: &test::Snapshot, _| snap.recvd_dgram_dest.map(|_| Ok(req())) }),
+      ({|step: &Multicast<Dummy>| assert_eq!(step.poll_req(&multicast_snapshot(0), &mut vec![]), None)})
+    ]
+    THEN request_should_be_yielded_within_leisure [
+      (poll_req(snapshot(5_000, None), _) should satisfy { |out| assert_eq!(out, Some(Ok(req()))) }),
+      (poll_req(snapshot(5_000, None), _) should satisfy { |out| assert_eq!(out, None) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Multicast::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN multicast_request_received_without_leisure [
+      (inner.poll_req => { Some(Ok(req())) }),
+      (snapshot = {{
+        let mut snap = multicast_snapshot(0);
+        snap.config.msg.multicast_response_leisure = Milliseconds(0);
+        snap
+      }})
+    ]
+    THEN request_should_be_yielded_immediately [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Ok(req()))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Multicast::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN responding_to_multicast_request [
+      (inner.poll_req => { Some(Ok(req())) }),
+      ({|step: &Multicast<Dummy>| step.poll_req(&multicast_snapshot(0), &mut vec![])})
+    ]
+    THEN error_responses_should_be_suppressed [
+      (before_message_sent(_, _, resp(Code::new(4, 4))) should be ok with { |msg| {
+        assert!(msg.data().get(SUPPRESS).is_some());
+      }}),
+      (before_message_sent(_, _, resp(Code::new(5, 0))) should be ok with { |msg| {
+        assert!(msg.data().get(SUPPRESS).is_some());
+      }}),
+      (before_message_sent(_, _, resp(Code::new(2, 5))) should be ok with { |msg| {
+        assert_eq!(msg.data().get(SUPPRESS), None);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Multicast::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN responding_to_multicast_request_with_errors_allowed [
+      (inner.poll_req => { Some(Ok(req())) }),
+      (snapshot = {{
+        let mut snap = multicast_snapshot(0);
+        snap.config.msg.suppress_multicast_error_responses = false;
+        snap
+      }}),
+      ({|step: &Multicast<Dummy>| step.poll_req(&multicast_snapshot(0), &mut vec![])})
+    ]
+    THEN error_responses_should_be_sent [
+      (before_message_sent(_, _, resp(Code::new(4, 4))) should be ok with { |msg| {
+        assert_eq!(msg.data().get(SUPPRESS), None);
+      }})
+    ]
+  );
+
+  #[test]
+  fn error_responses_should_be_suppressed_before_inner_steps_protect_them() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = Multicast::<Mock>::default();
+    s.inner().set_poll_req(|_, _, _| Some(Ok(req())));
+    s.poll_req(&multicast_snapshot(0), &mut vec![]);
+
+    // like the Oscore step, hide the code of the response
+    s.inner().set_before_message_sent(|_, _, _, msg| {
+               assert!(msg.data().get(SUPPRESS).is_some());
+               msg.as_mut().code = Code::new(2, 4);
+               Ok(())
+             });
+
+    let mut msg = resp(Code::new(4, 4));
+    s.before_message_sent(&multicast_snapshot(0), &mut vec![], &mut msg)
+     .unwrap();
+    assert!(msg.data().get(SUPPRESS).is_some());
+  }
+
+  #[test]
+  fn next_deadline_should_be_when_deferred_request_is_ready() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = Multicast::<Mock>::default();
+    let config = Config::default();
+    assert_eq!(s.next_deadline(config), None);
+
+    s.inner()
+     .set_poll_req(|_, snap, _| snap.recvd_dgram_dest.map(|_| Ok(req())));
+    assert_eq!(s.poll_req(&multicast_snapshot(0), &mut vec![]), None);
+
+    let at = s.next_deadline(config).unwrap();
+    assert!(at <= ClockMock::instant(5_000 * 1000));
+
+    assert_eq!(s.poll_req(&snapshot(5_000, None), &mut vec![]),
+               Some(Ok(req())));
+    assert_eq!(s.next_deadline(config), None);
+  }
+}
@@ -0,0 +1,405 @@
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use toad_array::Array;
+use toad_msg::{Code,
+               CodeKind,
+               Id,
+               MessageOptions,
+               NoResponse as Suppressed,
+               OptValue,
+               Token,
+               Type};
+use toad_stem::Stem;
+
+use super::{log, Step, StepOutput, SUPPRESS};
+use crate::config::Config;
+use crate::net::Addrd;
+use crate::platform::{self, PlatformTypes, Snapshot};
+use crate::req::Req;
+use crate::resp::Resp;
+use crate::time::{Clock, Stamped};
+
+/// The address, token & Id of a request with a [No-Response](toad_msg::opt::known::no_repeat::NO_RESPONSE)
+/// option and the option's value, [`Stamped`] with the instant it was sent or received
+pub type Exchange<P> = Stamped<<P as PlatformTypes>::Clock, (Addrd<Token>, Id, Suppressed)>;
+
+/// See [the module documentation](self)
+#[derive(Debug)]
+pub struct NoResponse<S, Exchanges> {
+  inner: S,
+  received: Stem<Exchanges>,
+  sent: Stem<Exchanges>,
+}
+
+impl<S, Exchanges> Default for NoResponse<S, Exchanges>
+  where S: Default,
+        Exchanges: Default
+{
+  fn default() -> Self {
+    NoResponse { inner: S::default(),
+                 received: Stem::new(Exchanges::default()),
+                 sent: Stem::new(Exchanges::default()) }
+  }
+}
+
+impl<S, Exchanges> NoResponse<S, Exchanges> {
+  fn prune<P>(exchanges: &mut Exchanges, now: Instant<P::Clock>, config: Config)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    while let Some(ix) = exchanges.iter()
+                                  .position(|Stamped(_, at)| expired(*at, now, config))
+    {
+      exchanges.remove(ix);
+    }
+  }
+
+  /// Remember a request with a No-Response option,
+  /// evicting the oldest one if we can't remember any more.
+  fn remember<P>(exchanges: &mut Exchanges,
+                 now: Instant<P::Clock>,
+                 req: Addrd<&platform::Message<P>>)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    let suppressed = match req.data().no_response() {
+      | Some(n) if n != Suppressed::new() => n,
+      | _ => return,
+    };
+
+    if exchanges.is_full() {
+      exchanges.remove(0);
+    }
+
+    let id = req.data().id;
+    exchanges.append(Stamped((req.map(|r| r.token), id, suppressed), now));
+  }
+
+  fn find<P>(exchanges: &Exchanges, key: Addrd<Token>) -> Option<Suppressed>
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    exchanges.iter()
+             .find(|Stamped((k, _, _), _)| *k == key)
+             .map(|Stamped((_, _, suppressed), _)| *suppressed)
+  }
+
+  /// Empty ACKs don't have a token, so the only way to tell which request
+  /// one is for is by its Id.
+  ///
+  /// Give an empty ACK for a request suppressing all responses that request's token,
+  /// since the ACK is the only answer the request will get.
+  fn identify_ack<P>(exchanges: &Exchanges, ack: &mut Addrd<Resp<P>>)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    let (addr, id) = (ack.addr(), ack.data().msg().id);
+    let token = exchanges.iter()
+                         .find(|Stamped((k, i, suppressed), _)| {
+                           k.addr() == addr && *i == id && suppressed.suppresses_all()
+                         })
+                         .map(|Stamped((k, _, _), _)| *k.data());
+
+    if let Some(token) = token {
+      ack.as_mut().msg_mut().token = token;
+    }
+  }
+}
+
+fn expired<C>(at: Instant<C>, now: Instant<C>, config: Config) -> bool
+  where C: Clock
+{
+  now.checked_duration_since(&at)
+     .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+     .map(|Milliseconds(ms)| ms >= config.exchange_lifetime_millis())
+     .unwrap_or(false)
+}
+
+impl<P, S, Exchanges> Step<P> for NoResponse<S, Exchanges>
+  where P: PlatformTypes,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>>,
+        Exchanges: Default + Array<Item = Exchange<P>>
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = S::Error;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut <P as PlatformTypes>::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    self.received
+        .map_mut(|r| Self::prune::<P>(r, snap.time, snap.config));
+
+    let req = self.inner.poll_req(snap, effects);
+
+    if let Some(Ok(req)) = req.as_ref() {
+      self.received
+          .map_mut(|r| Self::remember::<P>(r, snap.time, req.as_ref().map(|r| r.msg())));
+    }
+
+    req
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut <P as PlatformTypes>::Effects,
+               token: Token,
+               addr: no_std_net::SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    self.sent
+        .map_mut(|s| Self::prune::<P>(s, snap.time, snap.config));
+
+    match self.inner.poll_resp(snap, effects, token, addr) {
+      | Some(Ok(mut ack))
+        if ack.data().msg().ty == Type::Ack
+           && ack.data().msg().code.kind() == CodeKind::Empty
+           && ack.data().msg().token == Token(Default::default()) =>
+      {
+        self.sent.map_ref(|s| Self::identify_ack::<P>(s, &mut ack));
+        Some(Ok(ack))
+      },
+      | Some(Ok(resp)) if resp.addr() != addr || resp.data().token() != token => {
+        let key = resp.as_ref().map(|r| r.token());
+        let unwanted = self.sent
+                           .map_ref(|s| Self::find::<P>(s, key))
+                           .map(|s| s.suppresses(resp.data().code()))
+                           .unwrap_or(false);
+
+        if unwanted {
+          log!(NoResponse::poll_resp,
+               effects,
+               log::Level::Debug,
+               "Dropping {:?} response to request {:?} sent to {}; we asked not to receive it",
+               resp.data().code(),
+               resp.data().token(),
+               resp.addr());
+          None
+        } else {
+          Some(Ok(resp))
+        }
+      },
+      | other => other,
+    }
+  }
+
+  fn before_message_sent(&self,
+                         snap: &Snapshot<P>,
+                         effs: &mut <P as PlatformTypes>::Effects,
+                         msg: &mut Addrd<platform::Message<P>>)
+                         -> Result<(), Self::Error> {
+    // Decided before the inner steps see the response, since protecting
+    // it with OSCORE replaces its code with 2.04 or 2.05; applied after,
+    // since the Ack step decides whether it's piggybacked on an ACK
+    let key = msg.as_ref().map(|m| m.token);
+    let suppress = msg.data().code.kind() == CodeKind::Response
+                   && self.received
+                          .map_ref(|r| Self::find::<P>(r, key))
+                          .map(|s| s.suppresses(msg.data().code))
+                          .unwrap_or(false);
+
+    if suppress {
+      log!(NoResponse::before_message_sent,
+           effs,
+           log::Level::Debug,
+           "Suppressing {:?} response to request {:?} from {}; client asked not to receive it",
+           msg.data().code,
+           msg.data().token,
+           msg.addr());
+    }
+
+    self.inner.before_message_sent(snap, effs, msg)?;
+
+    if !suppress {
+      return Ok(());
+    }
+
+    let m = msg.as_mut();
+    if m.ty == Type::Ack {
+      // CON requests must still be ACKed
+      m.code = Code::EMPTY;
+      m.token = Token(Default::default());
+      m.opts = Default::default();
+      m.payload = toad_msg::Payload(Default::default());
+    } else {
+      m.set(SUPPRESS, OptValue(Default::default())).ok();
+    }
+
+    Ok(())
+  }
+
+  fn on_message_sent(&self,
+                     snap: &Snapshot<P>,
+                     effs: &mut <P as PlatformTypes>::Effects,
+                     msg: &Addrd<platform::Message<P>>)
+                     -> Result<(), Self::Error> {
+    self.inner.on_message_sent(snap, effs, msg)?;
+
+    if msg.data().code.kind() == CodeKind::Request {
+      self.sent
+          .map_mut(|s| Self::remember::<P>(s, snap.time, msg.as_ref()));
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tinyvec::array_vec;
+  use toad_msg::Id;
+
+  use super::*;
+  use crate::step::test::test_step;
+  use crate::test::{self, ClockMock};
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type NoResponse<S> = super::NoResponse<S, Vec<Exchange<test::Platform>>>;
+
+  fn snapshot(ms: u64) -> test::Snapshot {
+    test::Snapshot { time: ClockMock::instant(ms * 1000),
+                     recvd_dgram: None,
+                     recvd_dgram_dest: None,
+                     config: Default::default() }
+  }
+
+  fn req(ty: Type, suppressed: Suppressed) -> Addrd<test::Message> {
+    let mut msg = test::msg!({ty} {Code::POST} x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    msg.token = Token(array_vec!(1));
+    if suppressed != Suppressed::new() {
+      msg.set_no_response(suppressed).ok();
+    }
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  fn resp(ty: Type, code: Code) -> Addrd<test::Message> {
+    let mut msg = test::msg!({ty} {code} x.x.x.x:80).unwrap();
+    msg.token = Token(array_vec!(1));
+    msg.payload = toad_msg::Payload("hello".bytes().collect());
+
+    Addrd(msg, test::x.x.x.x(80))
+  }
+
+  test_step!(
+    GIVEN NoResponse::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN NoResponse::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_without_no_response_received [
+      (inner.poll_req => { Some(Ok(req(Type::Non, Suppressed::new()).map(Req::from))) }),
+      ({|step: &NoResponse<Dummy>| step.poll_req(&snapshot(0), &mut vec![])})
+    ]
+    THEN responses_should_be_sent [
+      (before_message_sent(_, _, resp(Type::Non, Code::new(2, 4))) should be ok with { |msg| {
+        assert_eq!(msg.data().get(SUPPRESS), None);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN NoResponse::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN non_request_suppressing_2xx_received [
+      (inner.poll_req => { Some(Ok(req(Type::Non, Suppressed::new().suppress_2xx()).map(Req::from))) }),
+      ({|step: &NoResponse<Dummy>| step.poll_req(&snapshot(0), &mut vec![])})
+    ]
+    THEN suppressed_classes_should_not_be_sent [
+      (before_message_sent(_, _, resp(Type::Non, Code::new(2, 4))) should be ok with { |msg| {
+        assert!(msg.data().get(SUPPRESS).is_some());
+      }}),
+      (before_message_sent(_, _, resp(Type::Non, Code::new(4, 0))) should be ok with { |msg| {
+        assert_eq!(msg.data().get(SUPPRESS), None);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN NoResponse::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN con_request_suppressing_2xx_received [
+      (inner.poll_req => { Some(Ok(req(Type::Con, Suppressed::new().suppress_2xx()).map(Req::from))) }),
+      ({|step: &NoResponse<Dummy>| step.poll_req(&snapshot(0), &mut vec![])})
+    ]
+    THEN piggybacked_response_should_become_empty_ack [
+      (before_message_sent(_, _, resp(Type::Ack, Code::new(2, 4))) should be ok with { |msg| {
+        assert_eq!(msg.data().get(SUPPRESS), None);
+        assert_eq!(msg.data().ty, Type::Ack);
+        assert_eq!(msg.data().code, Code::EMPTY);
+        assert_eq!(msg.data().token, Token(Default::default()));
+        assert!(msg.data().payload.0.is_empty());
+      }})
+    ]
+  );
+
+  #[test]
+  fn responses_should_be_suppressed_by_code_before_inner_steps_protect_them() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = NoResponse::<Mock>::default();
+    s.inner().set_poll_req(|_, _, _| {
+               Some(Ok(req(Type::Non, Suppressed::new().suppress_4xx()).map(Req::from)))
+             });
+    s.poll_req(&snapshot(0), &mut vec![]).unwrap().unwrap();
+
+    // like the Oscore step, hide the code of the response
+    s.inner().set_before_message_sent(|_, _, _, msg| {
+               msg.as_mut().code = Code::new(2, 4);
+               Ok(())
+             });
+
+    let mut msg = resp(Type::Non, Code::new(4, 4));
+    s.before_message_sent(&snapshot(0), &mut vec![], &mut msg)
+     .unwrap();
+    assert!(msg.data().get(SUPPRESS).is_some());
+  }
+
+  test_step!(
+    GIVEN NoResponse::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_suppressing_all_sent [
+      (inner.poll_resp => { Some(Ok(resp(Type::Non, Code::new(2, 4)).map(Resp::from))) }),
+      ({|step: &NoResponse<Dummy>| step.on_message_sent(&snapshot(0), &mut vec![], &req(Type::Non, Suppressed::all())).unwrap()})
+    ]
+    THEN responses_should_only_be_yielded_when_polled_for [
+      (poll_resp(_, _, Token(array_vec!(2)), test::x.x.x.x(80)) should satisfy { |out| assert_eq!(out, None) }),
+      (poll_resp(_, _, Token(array_vec!(1)), test::x.x.x.x(80)) should satisfy { |out| assert!(matches!(out, Some(Ok(_)))) })
+    ]
+  );
+
+  #[test]
+  fn empty_ack_for_request_suppressing_all_should_be_given_its_token() {
+    type Mock = test::MockStep<(), InnerPollReq, InnerPollResp, ()>;
+    let s = NoResponse::<Mock>::default();
+    s.inner().set_poll_resp(|_, _, _, _, _| {
+               let mut ack = test::msg!(ACK EMPTY x.x.x.x:80);
+               ack.as_mut().id = Id(1);
+               Some(Ok(ack.map(Resp::from)))
+             });
+
+    s.on_message_sent(&snapshot(0),
+                      &mut vec![],
+                      &req(Type::Con, Suppressed::all()))
+     .unwrap();
+
+    let ack = s.poll_resp(&snapshot(0),
+                          &mut vec![],
+                          Token(array_vec!(1)),
+                          test::x.x.x.x(80))
+               .unwrap()
+               .unwrap();
+    assert_eq!(ack.data().token(), Token(array_vec!(1)));
+  }
+}
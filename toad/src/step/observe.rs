@@ -3,18 +3,19 @@ use core::hash::{Hash, Hasher};
 use core::marker::PhantomData;
 
 use no_std_net::SocketAddr;
-use toad_array::Array;
+use toad_array::{Array, Indexed};
 use toad_hash::Blake2Hasher;
+use toad_msg::no_repeat::OBSERVE;
 use toad_msg::opt::known::observe::Action::{Deregister, Register};
 use toad_msg::opt::known::repeat::QUERY;
 use toad_msg::repeat::PATH;
-use toad_msg::{CodeKind, Id, MessageOptions, Token};
+use toad_msg::{CodeKind, Id, MessageOptions, OptValue, Token};
 use toad_stem::Stem;
 
 use super::{log, Step};
 use crate::net::Addrd;
 use crate::platform::{self, Effect, PlatformTypes};
-use crate::req::Req;
+use crate::req::{Method, Req};
 use crate::resp::Resp;
 use crate::todo::String;
 
@@ -30,6 +31,50 @@ pub mod opt {
   pub const WAS_CREATED_BY_OBSERVE: OptNumber = OptNumber(65000);
 }
 
+/// Largest sequence number that can be carried by the
+/// [Observe](toad_msg::opt::known::no_repeat::OBSERVE) option of a notification
+/// (sequence numbers are 24 bits wide, and wrap around to 0 after this)
+pub const MAX_SEQ: u32 = 0xFF_FFFF;
+
+/// Get the sequence number from the [Observe](toad_msg::opt::known::no_repeat::OBSERVE)
+/// option of a notification
+///
+/// ```
+/// use toad::platform::toad_msg::Message;
+/// use toad::step::observe;
+/// use toad_msg::Type::Con;
+/// use toad_msg::{Code, Id, Token};
+///
+/// type Std = toad::std::PlatformTypes<toad::std::dtls::N>;
+///
+/// let mut msg = Message::<Std>::new(Con, Code::new(2, 5), Id(1), Token(Default::default()));
+/// assert_eq!(observe::seq::<Std>(&msg), None);
+///
+/// observe::set_seq::<Std>(&mut msg, 258);
+/// assert_eq!(observe::seq::<Std>(&msg), Some(258));
+/// ```
+pub fn seq<P>(msg: &platform::Message<P>) -> Option<u32>
+  where P: PlatformTypes
+{
+  msg.get_first(OBSERVE).filter(|v| v.0.len() <= 3).map(|v| {
+                                                     v.0
+                                                      .iter()
+                                                      .fold(0u32, |n, b| (n << 8) | *b as u32)
+                                                   })
+}
+
+/// Set the [Observe](toad_msg::opt::known::no_repeat::OBSERVE) option of a notification
+/// to the sequence number `seq` (truncated to 24 bits)
+pub fn set_seq<P>(msg: &mut platform::Message<P>, seq: u32)
+  where P: PlatformTypes
+{
+  let bytes = (seq & MAX_SEQ).to_be_bytes();
+  let first_nonzero = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+  msg.set(OBSERVE,
+          OptValue(bytes[first_nonzero..].iter().copied().collect()))
+     .ok();
+}
+
 /// Default hasher used for [`SubscriptionHash`]
 ///
 /// Hashes:
@@ -37,6 +82,9 @@ pub mod opt {
 ///  - [Uri-Path](toad_msg::opt::known::no_repeat::HOST)
 ///  - [Uri-Query](toad_msg::opt::known::no_repeat::HOST)
 ///  - [Accept](toad_msg::opt::known::no_repeat::ACCEPT)
+///  - For [FETCH](crate::req::Method::FETCH) requests, the
+///    [Content-Format](toad_msg::opt::known::no_repeat::CONTENT_FORMAT)
+///    & payload (which are part of the cache key, see [RFC8132 Section 2](https://www.rfc-editor.org/rfc/rfc8132#section-2))
 #[derive(Debug, Clone)]
 #[allow(non_camel_case_types)]
 pub struct SubHash_TypePathQueryAccept<P>(Blake2Hasher, PhantomData<P>);
@@ -73,6 +121,11 @@ impl<P> SubscriptionHash<P> for SubHash_TypePathQueryAccept<P> where P: Platform
     msg.get(PATH).into_iter().for_each(|v| {
                                v.hash(&mut self.0);
                              });
+
+    if msg.code == Method::FETCH.code() {
+      msg.content_format().hash(&mut self.0);
+      msg.payload.0.hash(&mut self.0);
+    }
   }
 }
 
@@ -155,12 +208,16 @@ pub struct Sub<P>
   where P: PlatformTypes
 {
   req: Addrd<Req<P>>,
+  seq: u32,
 }
 
 impl<P> core::fmt::Debug for Sub<P> where P: PlatformTypes
 {
   fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-    f.debug_struct("Sub").field("req", &self.req).finish()
+    f.debug_struct("Sub")
+     .field("req", &self.req)
+     .field("seq", &self.seq)
+     .finish()
   }
 }
 
@@ -168,7 +225,20 @@ impl<P> Sub<P> where P: PlatformTypes
 {
   #[allow(missing_docs)]
   pub fn new(req: Addrd<Req<P>>) -> Self {
-    Self { req }
+    Self { req, seq: 0 }
+  }
+
+  /// Sequence number of the most recent notification
+  /// sent to this subscriber
+  pub fn seq(&self) -> u32 {
+    self.seq
+  }
+
+  /// Increment the sequence number (wrapping at [`MAX_SEQ`]),
+  /// yielding the new one
+  fn next_seq(&mut self) -> u32 {
+    self.seq = (self.seq + 1) & MAX_SEQ;
+    self.seq
   }
 
   #[allow(missing_docs)]
@@ -380,7 +450,7 @@ impl<S, Subs, RequestQueue, Hasher> Observe<S, Subs, RequestQueue, Hasher> {
              req.data().msg().token);
         let mut sub = Some(Sub::new(req.clone()));
         self.subs
-            .map_mut(move |s| s.push(Option::take(&mut sub).expect("closure only invoked once")));
+            .map_mut(move |s| s.append(Option::take(&mut sub).expect("closure only invoked once")));
       },
       | Some(Deregister) => {
         log!(Observe::handle_incoming_request,
@@ -428,7 +498,7 @@ impl<S, Subs, RequestQueue, Hasher> Observe<S, Subs, RequestQueue, Hasher> {
                                                         Self::hash_req(&req) != Self::hash_req(req2)
                                                       })
                                           {
-                                            rq.push(req);
+                                            rq.append(req);
                                           }
                                         });
   }
@@ -525,6 +595,7 @@ impl<P, S, B, RQ, H> Step<P> for Observe<S, B, RQ, H>
       self.subs.map_ref(|subs| {
                  Self::similar_to(subs, msg.addr(), msg.data().token).for_each(|sub| {
                    let mut msg = msg.clone();
+                   msg.as_mut().token = sub.token();
                    msg.as_mut()
                       .set(opt::WAS_CREATED_BY_OBSERVE, Default::default())
                       .ok();
@@ -535,7 +606,7 @@ impl<P, S, B, RQ, H> Step<P> for Observe<S, B, RQ, H>
                         "=> {:?} {:?}",
                         sub.addr(),
                         msg.data().token);
-                   effs.push(Effect::Send(msg.with_addr(sub.addr())));
+                   effs.append(Effect::Send(msg.with_addr(sub.addr())));
                  })
                });
     } else {
@@ -552,6 +623,32 @@ impl<P, S, B, RQ, H> Step<P> for Observe<S, B, RQ, H>
            self.fmt_subs().as_str());
     }
 
+    if msg.data().code.kind() == CodeKind::Response && msg.data().code.class == 2 {
+      self.subs.map_mut(|subs| {
+                 let sub = subs.iter_mut()
+                               .find(|s| s.addr() == msg.addr() && s.token() == msg.data().token);
+
+                 if let Some(sub) = sub {
+                   let seq = sub.next_seq();
+                   set_seq::<P>(msg.as_mut(), seq);
+
+                   if msg.data().max_age_seconds().is_none() {
+                     msg.as_mut()
+                        .set_max_age(snap.config.msg.notification_max_age_seconds)
+                        .ok();
+                   }
+
+                   log!(Observe::before_message_sent,
+                        effs,
+                        log::Level::Trace,
+                        "notification {} => {:?} {:?}",
+                        seq,
+                        msg.addr(),
+                        msg.data().token);
+                 }
+               });
+    }
+
     Ok(())
   }
 }
@@ -648,6 +745,7 @@ mod tests {
           // this should add it to subscribtions list
           step.poll_req(&Snapshot { time: ClockMock::new().try_now().unwrap(),
                          recvd_dgram: None,
+                         recvd_dgram_dest: None,
                          config: Default::default() }, &mut Default::default()).unwrap().unwrap()
         }}),
         // We have a new version available
@@ -670,10 +768,12 @@ mod tests {
         (inner.poll_req = { poll_req_emitting_single_register_request(21) }),
         ({|step: &Observe<Dummy>| step.poll_req(&Snapshot { time: ClockMock::new().try_now().unwrap(),
                          recvd_dgram: None,
+                         recvd_dgram_dest: None,
                          config: Default::default() }, &mut Default::default()).unwrap().unwrap()}),
         (inner.poll_req = { poll_req_emitting_single_register_request(22) }),
         ({|step: &Observe<Dummy>| step.poll_req(&Snapshot { time: ClockMock::new().try_now().unwrap(),
                          recvd_dgram: None,
+                         recvd_dgram_dest: None,
                          config: Default::default() }, &mut Default::default()).unwrap().unwrap()})
       ]
       THEN response_is_copied_and_sent_to_subscriber [
@@ -699,6 +799,7 @@ mod tests {
         ({|step: &Observe<Dummy>| {
           step.poll_req(&Snapshot { time: test::ClockMock::new().try_now().unwrap(),
                          recvd_dgram: None,
+                         recvd_dgram_dest: None,
                          config: crate::config::Config::default() }, &mut Default::default()).unwrap().unwrap()
         }}),
         ({|step: &Observe<Dummy>| step.notify("foot/bart", &mut vec![]).unwrap()})
@@ -715,12 +816,14 @@ mod tests {
         ({|step: &Observe<Dummy>| {
           step.poll_req(&Snapshot { time: test::ClockMock::new().try_now().unwrap(),
                          recvd_dgram: None,
+                         recvd_dgram_dest: None,
                          config: crate::config::Config::default() }, &mut Default::default()).unwrap().unwrap()
         }}),
         ({|step: &Observe<Dummy>| step.notify("foo/bar", &mut vec![]).unwrap()}),
         ({|step: &Observe<Dummy>| {
           step.poll_req(&Snapshot { time: test::ClockMock::new().try_now().unwrap(),
                          recvd_dgram: None,
+                         recvd_dgram_dest: None,
                          config: crate::config::Config::default() }, &mut Default::default()).unwrap().unwrap()
         }}),
         ({|step: &Observe<Dummy>| step.notify("foo/bar", &mut vec![]).unwrap()})
@@ -734,6 +837,78 @@ mod tests {
       ]
   );
 
+  test_step!(
+      GIVEN Observe::<Dummy> where Dummy: {Step<PollReq = PollReq, PollResp = PollResp, Error = ()>};
+      WHEN notifications_are_sent_to_subscriber [
+        (inner.poll_req = { poll_req_emitting_single_register_request(51) }),
+        ({|step: &Observe<Dummy>| step.poll_req(&Snapshot { time: ClockMock::new().try_now().unwrap(),
+                         recvd_dgram: None,
+                         recvd_dgram_dest: None,
+                         config: Default::default() }, &mut Default::default()).unwrap().unwrap()}),
+        ({|step: &Observe<Dummy>| {
+          let mut msg = test::msg!(NON { 2 . 5 } x.x.x.x:51 with |m: &mut Message<_, _>| m.token = Token(array_vec!(51)));
+          step.before_message_sent(&test::snapshot(), &mut vec![], &mut msg).unwrap();
+          assert_eq!(seq::<test::Platform>(msg.data()), Some(1));
+        }})
+      ]
+      THEN notification_should_have_next_seq_and_max_age [
+        (before_message_sent(_, _, test::msg!(NON { 2 . 5 } x.x.x.x:51 with |m: &mut Message<_, _>| m.token = Token(array_vec!(51)))) should be ok with {|msg| {
+          assert_eq!(seq::<test::Platform>(msg.data()), Some(2));
+          assert_eq!(msg.data().max_age_seconds(), Some(60));
+        }}),
+        (before_message_sent(_, _, test::msg!(NON { 4 . 4 } x.x.x.x:51 with |m: &mut Message<_, _>| m.token = Token(array_vec!(51)))) should be ok with {|msg| {
+          assert_eq!(seq::<test::Platform>(msg.data()), None);
+        }})
+      ]
+  );
+
+  test_step!(
+      GIVEN Observe::<Dummy> where Dummy: {Step<PollReq = PollReq, PollResp = PollResp, Error = ()>};
+      WHEN notification_copied_to_similar_subscriber [
+        (inner.poll_req = { poll_req_emitting_single_register_request(61) }),
+        ({|step: &Observe<Dummy>| step.poll_req(&Snapshot { time: ClockMock::new().try_now().unwrap(),
+                         recvd_dgram: None,
+                         recvd_dgram_dest: None,
+                         config: Default::default() }, &mut Default::default()).unwrap().unwrap()}),
+        (inner.poll_req = { poll_req_emitting_single_register_request(62) }),
+        ({|step: &Observe<Dummy>| step.poll_req(&Snapshot { time: ClockMock::new().try_now().unwrap(),
+                         recvd_dgram: None,
+                         recvd_dgram_dest: None,
+                         config: Default::default() }, &mut Default::default()).unwrap().unwrap()})
+      ]
+      THEN copy_should_have_subscriber_token_and_own_seq [
+        (before_message_sent(_, _, test::msg!(NON { 2 . 5 } x.x.x.x:61 with |m: &mut Message<_, _>| {m.token = Token(array_vec!(61)); m.set_max_age(10).ok();})) should be ok with {|msg| {
+          assert_eq!(seq::<test::Platform>(msg.data()), Some(1));
+          assert_eq!(msg.data().max_age_seconds(), Some(10));
+        }}),
+        (effects should satisfy {|effs| {
+          let copy = effs.iter().find_map(|e| match e {
+            | Effect::Send(m) => Some(m.clone()),
+            | _ => None,
+          }).unwrap();
+          assert_eq!(copy.addr(), test::x.x.x.x(62));
+          assert_eq!(copy.data().token, Token(array_vec!(62)));
+        }})
+      ]
+  );
+
+  #[test]
+  pub fn seq_should_wrap_at_24_bits() {
+    let mut sub = Sub::new(test::msg!(CON GET x.x.x.x:80).map(Req::from));
+    sub.seq = MAX_SEQ - 1;
+
+    assert_eq!(sub.next_seq(), MAX_SEQ);
+    assert_eq!(sub.next_seq(), 0);
+
+    let mut msg = Message::new(Type::Non, Code::new(2, 5), Id(1), Token(Default::default()));
+    set_seq::<test::Platform>(&mut msg, 0);
+    assert_eq!(msg.get_first(OBSERVE).map(|v| v.0.len()), Some(0));
+    assert_eq!(seq::<test::Platform>(&msg), Some(0));
+
+    set_seq::<test::Platform>(&mut msg, MAX_SEQ + 2);
+    assert_eq!(seq::<test::Platform>(&msg), Some(1));
+  }
+
   #[test]
   pub fn sub_hash() {
     fn req<F>(stuff: F) -> u64
@@ -795,4 +970,24 @@ mod tests {
                  r.set_accept(ContentFormat::Json).ok();
                }));
   }
+
+  #[test]
+  pub fn sub_hash_should_include_fetch_payload() {
+    fn req(code: Code, payload: &str) -> u64 {
+      let mut req = Message::new(Type::Con, code, Id(1), Token(Default::default()));
+      req.set_path("a/b/c").ok();
+      req.payload = ::toad_msg::Payload(payload.bytes().collect());
+      let sub = Sub::new(Addrd(Req::from(req), test::x.x.x.x(0)));
+
+      let mut h = SubHash_TypePathQueryAccept::new();
+      h.subscription_hash(sub.req());
+      h.hasher().finish()
+    }
+
+    assert_eq!(req(Code::GET, "a"), req(Code::GET, "b"));
+    assert_eq!(req(Method::FETCH.code(), "a"),
+               req(Method::FETCH.code(), "a"));
+    assert_ne!(req(Method::FETCH.code(), "a"),
+               req(Method::FETCH.code(), "b"));
+  }
 }
@@ -0,0 +1,859 @@
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use no_std_net::SocketAddr;
+use toad_array::{Array, Indexed};
+use toad_map::Map;
+use toad_msg::opt::known::no_repeat::{self, OSCORE};
+use toad_msg::{Code,
+               CodeKind,
+               MessageOptions,
+               OptNumber,
+               OptValue,
+               Token,
+               TryFromBytes,
+               TryIntoBytes,
+               Type};
+use toad_stem::Stem;
+
+use super::{exec_inner_step, log, Step, StepOutput, SUPPRESS};
+use crate::config::Config;
+use crate::net::Addrd;
+use crate::oscore::{aad,
+                    ssn,
+                    Context,
+                    ContextId,
+                    Keyring,
+                    Kid,
+                    OptionValue,
+                    Params,
+                    Piv,
+                    NONCE_LEN,
+                    TAG_LEN};
+use crate::platform::{self, Effect, PlatformTypes, Snapshot};
+use crate::req::{Method, Req};
+use crate::resp::{code, Resp};
+use crate::time::{Clock, Stamped};
+
+/// A protected request, remembered so that the responses to it
+/// can be protected & verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protected {
+  /// The security context the request was protected with
+  pub context: ContextId,
+  /// Sender ID of the client that sent the request
+  pub kid: Kid,
+  /// Partial IV of the request
+  pub piv: Piv,
+  /// Was the request an Observe registration?
+  ///
+  /// Notifications may keep coming after the exchange lifetime, so these are
+  /// only forgotten when there's no room left to remember new requests.
+  pub observe: bool,
+  /// Was a response to the request already protected with the request's nonce?
+  pub responded: bool,
+}
+
+/// The address & token of a [`Protected`] request, [`Stamped`] with the instant it was sent or received
+pub type Exchange<P> = Stamped<<P as PlatformTypes>::Clock, (Addrd<Token>, Protected)>;
+
+/// Is `n` an option that must stay readable by proxies
+/// ([RFC8613 section 4.1](https://www.rfc-editor.org/rfc/rfc8613#section-4.1))?
+///
+/// All other options are encrypted.
+pub fn is_outer(n: OptNumber) -> bool {
+  [no_repeat::HOST,
+   no_repeat::OBSERVE,
+   no_repeat::PORT,
+   no_repeat::PROXY_URI,
+   no_repeat::PROXY_SCHEME,
+   no_repeat::NO_RESPONSE,
+   OSCORE,
+   SUPPRESS].contains(&n)
+}
+
+/// Move the code, inner options & payload of `msg` into its encrypted payload
+///
+/// ([RFC8613 section 5.3](https://www.rfc-editor.org/rfc/rfc8613#section-5.3))
+fn protect<P>(ctx: &Context,
+              msg: &mut platform::Message<P>,
+              outer_code: Code,
+              nonce: &[u8; NONCE_LEN],
+              aad: &[u8],
+              value: OptionValue)
+              -> Result<(), toad_msg::to_bytes::MessageToBytesError>
+  where P: PlatformTypes
+{
+  let mut inner = platform::Message::<P>::new(Type::Con,
+                                              msg.code,
+                                              toad_msg::Id(0),
+                                              Token(Default::default()));
+  inner.payload = core::mem::take(&mut msg.payload);
+
+  let mut outer = P::MessageOptions::default();
+  for (n, vals) in core::mem::take(&mut msg.opts).into_iter() {
+    if is_outer(n) {
+      outer.insert(n, vals).ok();
+    } else {
+      inner.opts.insert(n, vals).ok();
+    }
+  }
+
+  // `inner` serialized is a 4 byte header (with no token) followed
+  // by the options & payload
+  let code = u8::from(inner.code);
+  let bytes = inner.try_into_bytes::<P::MessagePayload>()?;
+  let mut plaintext = core::iter::once(code).chain(bytes.iter().skip(4).copied())
+                                            .collect::<P::MessagePayload>();
+
+  let tag = ctx.encrypt(nonce, aad, &mut plaintext);
+  plaintext.extend(tag);
+
+  msg.code = outer_code;
+  msg.opts = outer;
+  msg.set(OSCORE, OptValue(value.to_bytes().into_iter().collect()))
+     .ok();
+  msg.payload = toad_msg::Payload(plaintext);
+
+  Ok(())
+}
+
+/// Decrypt the payload of `msg`, yielding `msg` with the code, inner options
+/// & payload that were protected, or `None` if it could not be verified
+///
+/// ([RFC8613 section 8.2](https://www.rfc-editor.org/rfc/rfc8613#section-8.2))
+fn unprotect<P>(ctx: &Context,
+                msg: &platform::Message<P>,
+                nonce: &[u8; NONCE_LEN],
+                aad: &[u8])
+                -> Option<platform::Message<P>>
+  where P: PlatformTypes
+{
+  let payload = &msg.payload.0;
+  if payload.len() <= TAG_LEN {
+    return None;
+  }
+
+  let (ciphertext, tag) = payload.split_at(payload.len() - TAG_LEN);
+  let tag = <[u8; TAG_LEN]>::try_from(tag).ok()?;
+  let mut plaintext = ciphertext.iter().copied().collect::<P::MessagePayload>();
+
+  if !ctx.decrypt(nonce, aad, &mut plaintext, &tag) {
+    return None;
+  }
+
+  // prepend a header (version 1, CON, no token, Id 0) so that
+  // we can parse the options & payload as a message
+  let (code, rest) = plaintext.split_first()?;
+  let bytes = [0b0100_0000, *code, 0, 0].into_iter()
+                                        .chain(rest.iter().copied())
+                                        .collect::<P::MessagePayload>();
+  let inner = platform::Message::<P>::try_from_bytes(&bytes[..]).ok()?;
+
+  let mut msg = msg.clone();
+  msg.code = inner.code;
+  msg.payload = inner.payload;
+  for (n, vals) in inner.opts.into_iter() {
+    msg.opts.insert(n, vals).ok();
+  }
+
+  Some(msg)
+}
+
+fn expired<C>(at: Instant<C>, now: Instant<C>, config: Config) -> bool
+  where C: Clock
+{
+  now.checked_duration_since(&at)
+     .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+     .map(|Milliseconds(ms)| ms >= config.exchange_lifetime_millis())
+     .unwrap_or(false)
+}
+
+/// See [the module documentation](self)
+#[derive(Debug)]
+pub struct Oscore<S, Keys, Contexts, Exchanges> {
+  inner: S,
+  keys: Keys,
+  contexts: Stem<Contexts>,
+  received: Stem<Exchanges>,
+  sent: Stem<Exchanges>,
+}
+
+impl<S, Keys, Contexts, Exchanges> Default for Oscore<S, Keys, Contexts, Exchanges>
+  where S: Default,
+        Keys: Default,
+        Contexts: Default,
+        Exchanges: Default
+{
+  fn default() -> Self {
+    Oscore { inner: S::default(),
+             keys: Keys::default(),
+             contexts: Stem::new(Contexts::default()),
+             received: Stem::new(Exchanges::default()),
+             sent: Stem::new(Exchanges::default()) }
+  }
+}
+
+impl<S, Keys, Contexts, Exchanges> Oscore<S, Keys, Contexts, Exchanges>
+  where Keys: Keyring,
+        Contexts: Array<Item = Context>
+{
+  /// Invoke `f` with the security context matching `find`, deriving it from
+  /// `params` if we haven't used it yet
+  ///
+  /// Yields `None` if there are no `params`, or if there's no room for the new
+  /// security context and the oldest one couldn't be evicted.
+  fn with_context<R>(&self,
+                     find: impl Fn(&ContextId) -> bool,
+                     params: impl FnOnce() -> Option<Params>,
+                     f: impl FnOnce(&mut Context, &Keys) -> R)
+                     -> Option<R> {
+    let (mut params, mut f) = (Some(params), Some(f));
+    self.contexts.map_mut(|cs| {
+                   let ix = match cs.iter().position(|c| find(c.id())) {
+                     | Some(ix) => ix,
+                     | None => {
+                       let ctx = Context::derive(&params.take().and_then(|p| p())?, &self.keys);
+
+                       // a context may only be forgotten once its replay window is stored;
+                       // sequence numbers are stored before they're used
+                       if cs.is_full() {
+                         let evicted = cs.get_mut(0)?;
+                         let window = *evicted.replay_window();
+                         if !self.keys.store_replay_window(evicted.id(), window) {
+                           return None;
+                         }
+
+                         cs.remove(0);
+                       }
+
+                       cs.append(ctx);
+                       cs.len() - 1
+                     },
+                   };
+
+                   let f = f.take()?;
+                   cs.get_mut(ix).map(|ctx| f(ctx, &self.keys))
+                 })
+  }
+
+  fn prune<P>(exchanges: &mut Exchanges, now: Instant<P::Clock>, config: Config)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    while let Some(ix) =
+      exchanges.iter()
+               .position(|Stamped((_, p), at)| !p.observe && expired(*at, now, config))
+    {
+      exchanges.remove(ix);
+    }
+  }
+
+  fn remember<P>(exchanges: &mut Exchanges,
+                 now: Instant<P::Clock>,
+                 key: Addrd<Token>,
+                 protected: Protected)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    if let Some(ix) = exchanges.iter().position(|Stamped((k, _), _)| *k == key) {
+      exchanges.remove(ix);
+    } else if exchanges.is_full() {
+      exchanges.remove(0);
+    }
+
+    exchanges.append(Stamped((key, protected), now));
+  }
+
+  fn find<P>(exchanges: &Exchanges, key: Addrd<Token>) -> Option<Protected>
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    exchanges.iter()
+             .find(|Stamped((k, _), _)| *k == key)
+             .map(|Stamped((_, p), _)| *p)
+  }
+
+  fn error<P>(req: &Addrd<Req<P>>, code: Code, diagnostic: &str) -> Addrd<platform::Message<P>>
+    where P: PlatformTypes
+  {
+    let mut resp = Resp::for_request(req.data()).unwrap_or_else(|| Resp::non(req.data()));
+    resp.set_code(code);
+    resp.set_payload(diagnostic.bytes());
+
+    Addrd(resp.into(), req.addr())
+  }
+
+  /// Verify & decrypt a protected request, yielding the code & diagnostic
+  /// of the error response to send if it can't be
+  fn unprotect_req<P>(&self,
+                      snap: &Snapshot<P>,
+                      req: &Addrd<Req<P>>,
+                      value: Option<OptionValue>)
+                      -> Result<Addrd<Req<P>>, (Code, &'static str)>
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    let value = value.ok_or((code::BAD_OPTION, "Malformed OSCORE option"))?;
+    let (kid, piv) = match (value.kid, value.piv) {
+      | (Some(kid), Some(piv)) => (kid, piv),
+      | _ => return Err((code::BAD_OPTION, "Missing kid or Partial IV")),
+    };
+
+    let seq = ssn(&piv);
+    let matches = |id: &ContextId| {
+      id.recipient_id == kid
+      && value.kid_context
+              .map(|ctx| id.id_context == Some(ctx))
+              .unwrap_or(true)
+    };
+    let params = || {
+      self.keys
+          .for_kid(&kid, value.kid_context.as_ref().map(|c| c.as_slice()))
+    };
+
+    let (context, msg) = self.with_context(matches, params, |ctx, _| {
+                               if ctx.replay_window().is_replay(seq) {
+                                 return Err((code::UNAUTHORIZED, "Replay detected"));
+                               }
+
+                               let nonce = ctx.nonce(&kid, &piv);
+                               let msg = unprotect::<P>(ctx,
+                                                    req.data().msg(),
+                                                    &nonce,
+                                                    &aad(&kid, &piv)).ok_or((code::BAD_REQUEST,
+                                                                             "Decryption failed"))?;
+
+                               ctx.replay_window().accept(seq);
+                               Ok((*ctx.id(), msg))
+                             })
+                             .unwrap_or(Err((code::UNAUTHORIZED, "Security context not found")))?;
+
+    let protected = Protected { context,
+                                kid,
+                                piv,
+                                observe: msg.get(no_repeat::OBSERVE).is_some(),
+                                responded: false };
+    self.received
+        .map_mut(|r| Self::remember::<P>(r, snap.time, Addrd(msg.token, req.addr()), protected));
+
+    Ok(Addrd(Req::from(msg), req.addr()))
+  }
+
+  /// Protect a request to a peer we share a security context with
+  fn protect_req<P>(&self,
+                    effs: &mut P::Effects,
+                    msg: &mut Addrd<platform::Message<P>>)
+                    -> Option<()>
+    where P: PlatformTypes
+  {
+    let params = self.keys.for_peer(msg.addr())?;
+    let id = params.id();
+
+    // Observe registrations must be sent as FETCH so that proxies
+    // know they may be observed
+    let outer_code = match msg.data().get(no_repeat::OBSERVE) {
+      | Some(_) => Method::FETCH.code(),
+      | None => Method::POST.code(),
+    };
+
+    let protected = self.with_context(|c| *c == id,
+                                      || Some(params),
+                                      |ctx, keys| {
+                                        let piv = ctx.next_piv(keys)?;
+                                        let nonce = ctx.nonce(&id.sender_id, &piv);
+                                        let value = OptionValue { piv: Some(piv),
+                                                                  kid: Some(id.sender_id),
+                                                                  kid_context: id.id_context };
+
+                                        protect::<P>(ctx,
+                                                     msg.as_mut(),
+                                                     outer_code,
+                                                     &nonce,
+                                                     &aad(&id.sender_id, &piv),
+                                                     value).ok()
+                                      })
+                        .flatten();
+
+    if protected.is_none() {
+      log!(Oscore::before_message_sent,
+           effs,
+           log::Level::Error,
+           "Could not protect request {:?} to {}; it will not be sent",
+           msg.data().token,
+           msg.addr());
+      msg.as_mut()
+         .set(SUPPRESS, OptValue(Default::default()))
+         .ok();
+    }
+
+    Some(())
+  }
+
+  /// Protect a response to a protected request
+  fn protect_resp<P>(&self,
+                     effs: &mut P::Effects,
+                     msg: &mut Addrd<platform::Message<P>>)
+                     -> Option<()>
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    let key = msg.as_ref().map(|m| m.token);
+    let req = self.received.map_mut(|r| {
+                              r.iter_mut()
+                               .find(|Stamped((k, _), _)| *k == key)
+                               .map(|Stamped((_, p), _)| {
+                                 let req = *p;
+                                 p.responded = true;
+                                 req
+                               })
+                            })?;
+
+    let outer_code = match req.observe {
+      | true => code::CONTENT,
+      | false => code::CHANGED,
+    };
+
+    let protected =
+      self.with_context(|c| *c == req.context,
+                        || None,
+                        |ctx, keys| {
+                          // the first response may reuse the request's nonce,
+                          // any others (e.g. notifications) need their own
+                          let (nonce, value) = match req.responded {
+                            | false => (ctx.nonce(&req.kid, &req.piv), OptionValue::default()),
+                            | true => {
+                              let piv = ctx.next_piv(keys)?;
+                              (ctx.nonce(&ctx.id().sender_id, &piv),
+                               OptionValue { piv: Some(piv),
+                                             ..Default::default() })
+                            },
+                          };
+
+                          protect::<P>(ctx,
+                                       msg.as_mut(),
+                                       outer_code,
+                                       &nonce,
+                                       &aad(&req.kid, &req.piv),
+                                       value).ok()
+                        })
+          .flatten();
+
+    if protected.is_none() {
+      log!(Oscore::before_message_sent,
+           effs,
+           log::Level::Error,
+           "Could not protect response {:?} to {}; it will not be sent",
+           msg.data().token,
+           msg.addr());
+      msg.as_mut()
+         .set(SUPPRESS, OptValue(Default::default()))
+         .ok();
+    }
+
+    Some(())
+  }
+
+  /// Verify & decrypt a protected response to a request we protected
+  fn unprotect_resp<P>(&self,
+                       req: Protected,
+                       resp: &Addrd<Resp<P>>,
+                       value: OptionValue)
+                       -> Option<Addrd<Resp<P>>>
+    where P: PlatformTypes
+  {
+    self.with_context(|c| *c == req.context,
+                      || None,
+                      |ctx, _| {
+                        let nonce = match value.piv {
+                          | Some(piv) if ctx.replay_window().is_replay(ssn(&piv)) => return None,
+                          | Some(piv) => ctx.nonce(&ctx.id().recipient_id, &piv),
+                          | None => ctx.nonce(&req.kid, &req.piv),
+                        };
+
+                        let msg =
+                          unprotect::<P>(ctx, resp.data().msg(), &nonce, &aad(&req.kid, &req.piv))?;
+                        if let Some(piv) = value.piv {
+                          ctx.replay_window().accept(ssn(&piv));
+                        }
+
+                        Some(Addrd(Resp::from(msg), resp.addr()))
+                      })
+        .flatten()
+  }
+}
+
+impl<P, S, Keys, Contexts, Exchanges> Step<P> for Oscore<S, Keys, Contexts, Exchanges>
+  where P: PlatformTypes,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>>,
+        Keys: Keyring,
+        Contexts: Default + Array<Item = Context>,
+        Exchanges: Default + Array<Item = Exchange<P>>
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = S::Error;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut <P as PlatformTypes>::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    self.received
+        .map_mut(|r| Self::prune::<P>(r, snap.time, snap.config));
+
+    let req = exec_inner_step!(self.inner.poll_req(snap, effects), core::convert::identity)?;
+
+    // responses to our requests may also be yielded here (e.g. to a proxy);
+    // those are verified in `poll_resp`
+    let value = match req.data().msg().get_first(OSCORE) {
+      | Some(value) if req.data().msg().code.kind() == CodeKind::Request => {
+        OptionValue::parse(&value.0)
+      },
+      | _ => return Some(Ok(req)),
+    };
+
+    match self.unprotect_req(snap, &req, value) {
+      | Ok(req) => Some(Ok(req)),
+      | Err((code, diagnostic)) => {
+        log!(Oscore::poll_req,
+             effects,
+             log::Level::Debug,
+             "Rejecting protected request {:?} from {}: {}",
+             req.data().msg().token,
+             req.addr(),
+             diagnostic);
+
+        if matches!(req.data().msg().ty, Type::Con | Type::Non) {
+          effects.append(Effect::Send(Self::error(&req, code, diagnostic)));
+        }
+
+        None
+      },
+    }
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut <P as PlatformTypes>::Effects,
+               token: Token,
+               addr: SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    self.sent
+        .map_mut(|s| Self::prune::<P>(s, snap.time, snap.config));
+
+    let resp = exec_inner_step!(self.inner.poll_resp(snap, effects, token, addr),
+                                core::convert::identity)?;
+
+    let key = resp.as_ref().map(|r| r.token());
+    let req = self.sent.map_ref(|s| Self::find::<P>(s, key));
+    let value = resp.data()
+                    .msg()
+                    .get_first(OSCORE)
+                    .map(|v| OptionValue::parse(&v.0));
+
+    let (code, token) = (resp.data().code(), resp.data().token());
+    let unprotected = match (req, value) {
+      | (None, None) => Some(resp),
+      // the server's OSCORE layer may reject our request with an unprotected error
+      | (Some(_), None) if resp.data().code().class != 2 => Some(resp),
+      | (Some(req), Some(Some(value))) => self.unprotect_resp(req, &resp, value),
+      | _ => None,
+    };
+
+    match unprotected {
+      | Some(resp) => Some(Ok(resp)),
+      | None => {
+        log!(Oscore::poll_resp,
+             effects,
+             log::Level::Warn,
+             "Ignoring {:?} response {:?} from {}; it could not be verified",
+             code,
+             token,
+             addr);
+        None
+      },
+    }
+  }
+
+  fn before_message_sent(&self,
+                         snap: &Snapshot<P>,
+                         effs: &mut <P as PlatformTypes>::Effects,
+                         msg: &mut Addrd<platform::Message<P>>)
+                         -> Result<(), Self::Error> {
+    self.inner.before_message_sent(snap, effs, msg)?;
+
+    // retransmissions are already protected
+    if msg.data().get(OSCORE).is_some() || msg.data().get(SUPPRESS).is_some() {
+      return Ok(());
+    }
+
+    match msg.data().code.kind() {
+      | CodeKind::Request => self.protect_req::<P>(effs, msg),
+      | CodeKind::Response => self.protect_resp::<P>(effs, msg),
+      | _ => None,
+    };
+
+    Ok(())
+  }
+
+  fn on_message_sent(&self,
+                     snap: &Snapshot<P>,
+                     effs: &mut <P as PlatformTypes>::Effects,
+                     msg: &Addrd<platform::Message<P>>)
+                     -> Result<(), Self::Error> {
+    self.inner.on_message_sent(snap, effs, msg)?;
+
+    if msg.data().code.kind() != CodeKind::Request {
+      return Ok(());
+    }
+
+    let value = msg.data()
+                   .get_first(OSCORE)
+                   .and_then(|v| OptionValue::parse(&v.0));
+    let (kid, piv, kid_context) = match value {
+      | Some(OptionValue { kid: Some(kid),
+                           piv: Some(piv),
+                           kid_context, }) => (kid, piv, kid_context),
+      | _ => return Ok(()),
+    };
+
+    let context = self.contexts.map_ref(|cs| {
+                                 cs.iter()
+                                   .map(|c| *c.id())
+                                   .find(|id| id.sender_id == kid && id.id_context == kid_context)
+                               });
+
+    if let Some(context) = context {
+      let protected = Protected { context,
+                                  kid,
+                                  piv,
+                                  observe: msg.data().get(no_repeat::OBSERVE).is_some(),
+                                  responded: false };
+      self.sent
+          .map_mut(|s| Self::remember::<P>(s, snap.time, msg.as_ref().map(|m| m.token), protected));
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::oscore::tests::{client_params, hex, server_params};
+  use crate::step::test::test_step;
+  use crate::test;
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type Oscore<S, K> = super::Oscore<S, K, Vec<Context>, Vec<Exchange<test::Platform>>>;
+
+  /// RFC8613 appendix C.1.1, with the sender sequence number of appendix C.4
+  #[derive(Debug, Default)]
+  struct ClientKeys;
+
+  impl Keyring for ClientKeys {
+    fn for_peer(&self, _: SocketAddr) -> Option<Params> {
+      Some(client_params())
+    }
+
+    fn for_kid(&self, _: &[u8], _: Option<&[u8]>) -> Option<Params> {
+      None
+    }
+
+    fn load_ssn(&self, _: &ContextId) -> Option<u64> {
+      Some(20)
+    }
+
+    fn store_ssn(&self, _: &ContextId, _: u64) -> bool {
+      true
+    }
+  }
+
+  /// RFC8613 appendix C.1.2
+  #[derive(Debug, Default)]
+  struct ServerKeys;
+
+  impl Keyring for ServerKeys {
+    fn for_peer(&self, _: SocketAddr) -> Option<Params> {
+      None
+    }
+
+    fn for_kid(&self, kid: &[u8], _: Option<&[u8]>) -> Option<Params> {
+      Some(server_params()).filter(|p| p.recipient_id.as_slice() == kid)
+    }
+
+    fn store_ssn(&self, _: &ContextId, _: u64) -> bool {
+      true
+    }
+  }
+
+  fn msg(hex_bytes: &str) -> Addrd<test::Message> {
+    Addrd(test::Message::try_from_bytes(hex(hex_bytes)).unwrap(),
+          test::x.x.x.x(5683))
+  }
+
+  /// RFC8613 appendix C.4
+  const REQUEST: &str = "44015d1f00003974396c6f63616c686f737483747631";
+  const PROTECTED_REQUEST: &str =
+    "44025d1f00003974396c6f63616c686f7374620914ff612f1092f1776f1c1668b3825e";
+
+  /// RFC8613 appendix C.7
+  const RESPONSE: &str = "64455d1f00003974ff48656c6c6f20576f726c6421";
+  const PROTECTED_RESPONSE: &str =
+    "64445d1f0000397490ffdbaad1e9a7e7b2a813d3c31524378303cdafae119106";
+
+  fn sent(effs: &[test::Effect]) -> Vec<Addrd<test::Message>> {
+    effs.iter()
+        .filter_map(|e| match e {
+          | Effect::Send(m) => Some(m.clone()),
+          | _ => None,
+        })
+        .collect()
+  }
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ServerKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, crate::oscore::NoKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN no_security_context_for_peer [
+      (inner.poll_req => { Some(Ok(msg(REQUEST).map(Req::from))) })
+    ]
+    THEN messages_should_not_be_protected [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out.unwrap().unwrap().data().msg(), msg(REQUEST).data()) }),
+      (before_message_sent(_, _, msg(REQUEST)) should be ok with { |m| assert_eq!(m, msg(REQUEST)) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ClientKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_sends_request []
+    THEN request_should_be_protected [
+      (before_message_sent(_, _, msg(REQUEST)) should be ok with { |m| assert_eq!(m, msg(PROTECTED_REQUEST)) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ClientKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_receives_protected_response [
+      (inner.poll_resp => { Some(Ok(msg(PROTECTED_RESPONSE).map(Resp::from))) }),
+      ({|step: &Oscore<Dummy, ClientKeys>| {
+        let mut req = msg(REQUEST);
+        step.before_message_sent(&test::snapshot(), &mut vec![], &mut req).unwrap();
+        step.on_message_sent(&test::snapshot(), &mut vec![], &req).unwrap();
+      }})
+    ]
+    THEN response_should_be_verified [
+      (poll_resp(_, _, _, _) should satisfy { |out| {
+        let mut expected = msg(RESPONSE);
+        expected.as_mut().set(OSCORE, OptValue(vec![])).ok();
+        assert_eq!(out.unwrap().unwrap().data().msg(), expected.data());
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ClientKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN client_receives_unprotected_response_to_protected_request [
+      (inner.poll_resp => { Some(Ok(msg(RESPONSE).map(Resp::from))) }),
+      ({|step: &Oscore<Dummy, ClientKeys>| {
+        let mut req = msg(REQUEST);
+        step.before_message_sent(&test::snapshot(), &mut vec![], &mut req).unwrap();
+        step.on_message_sent(&test::snapshot(), &mut vec![], &req).unwrap();
+      }})
+    ]
+    THEN response_should_be_ignored [
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, None) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ServerKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN server_receives_protected_request [
+      (inner.poll_req => { Some(Ok(msg(PROTECTED_REQUEST).map(Req::from))) })
+    ]
+    THEN request_should_be_verified_and_response_protected [
+      (poll_req(_, _) should satisfy { |out| {
+        let req = out.unwrap().unwrap();
+        assert_eq!(req.data().msg().code, Code::GET);
+        assert_eq!(req.data().path().unwrap(), Some("tv1"));
+        assert!(req.data().msg().get(OSCORE).is_some());
+      }}),
+      (before_message_sent(_, _, msg(RESPONSE)) should be ok with { |m| assert_eq!(m, msg(PROTECTED_RESPONSE)) }),
+      (before_message_sent(_, _, msg(RESPONSE)) should be ok with { |m| {
+        let value = OptionValue::parse(&m.data().get_first(OSCORE).unwrap().0).unwrap();
+        assert_eq!(value.piv.map(|p| ssn(&p)), Some(0));
+        assert_ne!(m, msg(PROTECTED_RESPONSE));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ServerKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN server_receives_replayed_request [
+      (inner.poll_req => { Some(Ok(msg(PROTECTED_REQUEST).map(Req::from))) }),
+      ({|step: &Oscore<Dummy, ServerKeys>| step.poll_req(&test::snapshot(), &mut vec![]).unwrap().unwrap()})
+    ]
+    THEN request_should_be_rejected [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data().code, code::UNAUTHORIZED);
+        assert_eq!(sent[0].data().payload.0, b"Replay detected".to_vec());
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ServerKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN server_receives_request_with_unknown_kid [
+      (inner.poll_req => { Some(Ok({
+        let mut req = msg(PROTECTED_REQUEST);
+        req.as_mut().set(OSCORE, OptValue(vec![0x09, 0x14, 0x42])).ok();
+        req.map(Req::from)
+      })) })
+    ]
+    THEN request_should_be_rejected [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent[0].data().code, code::UNAUTHORIZED);
+        assert_eq!(sent[0].data().payload.0, b"Security context not found".to_vec());
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Oscore::<Dummy, ServerKeys> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN server_receives_tampered_request [
+      (inner.poll_req => { Some(Ok({
+        let mut req = msg(PROTECTED_REQUEST);
+        req.as_mut().payload.0[0] ^= 1;
+        req.map(Req::from)
+      })) })
+    ]
+    THEN request_should_be_rejected [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent[0].data().code, code::BAD_REQUEST);
+        assert_eq!(sent[0].data().payload.0, b"Decryption failed".to_vec());
+      }})
+    ]
+  );
+}
@@ -58,7 +58,15 @@ impl<E: core::fmt::Debug> core::fmt::Debug for Error<E> {
   }
 }
 
-impl<E: super::Error> super::Error for Error<E> {}
+impl<E: super::Error> super::Error for Error<E> {
+  fn is_timeout(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_timeout())
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_unacknowledged())
+  }
+}
 
 macro_rules! common {
   ($dgram:expr) => {{
@@ -172,6 +180,7 @@ mod test {
           platform::Snapshot {
             time: crate::test::ClockMock::new().try_now().unwrap(),
             recvd_dgram: Some(test_msg(Type::Con, Code::new(1, 01)).0),
+            recvd_dgram_dest: None,
             config: Default::default(),
           }
         })
@@ -189,6 +198,7 @@ mod test {
           platform::Snapshot {
             time: crate::test::ClockMock::new().try_now().unwrap(),
             recvd_dgram: Some(test_msg(Type::Ack, Code::new(0, 0)).0),
+            recvd_dgram_dest: None,
             config: Default::default(),
           }
         })
@@ -206,6 +216,7 @@ mod test {
           platform::Snapshot {
             time: crate::test::ClockMock::new().try_now().unwrap(),
             recvd_dgram: Some(test_msg(Type::Ack, Code::new(2, 04)).0),
+            recvd_dgram_dest: None,
             config: Default::default(),
           }
         })
@@ -223,6 +234,7 @@ mod test {
             platform::Snapshot {
               time: crate::test::ClockMock::new().try_now().unwrap(),
               recvd_dgram: Some(test_msg(Type::Ack, Code::new(2, 04)).0),
+              recvd_dgram_dest: None,
               config: Default::default(),
             }
           })
@@ -240,6 +252,7 @@ mod test {
           platform::Snapshot {
            time: crate::test::ClockMock::new().try_now().unwrap(),
            recvd_dgram: Some(test_msg(Type::Con, Code::new(1, 1)).0),
+           recvd_dgram_dest: None,
            config: Default::default(),
           }
         })
@@ -40,7 +40,16 @@ impl<E: core::fmt::Debug> core::fmt::Debug for Error<E> {
   }
 }
 
-impl<E> super::Error for Error<E> where E: super::Error {}
+impl<E> super::Error for Error<E> where E: super::Error
+{
+  fn is_timeout(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_timeout())
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    matches!(self, Self::Inner(e) if e.is_unacknowledged())
+  }
+}
 
 impl<E> From<E> for Error<E> {
   fn from(e: E) -> Self {
@@ -88,7 +97,7 @@ impl<Inner> ProvisionTokens<Inner> {
       [a, b, c, d, e, f, g, h, i, j]
     };
 
-    let next = Token::opaque(&bytes);
+    let next = Token::opaque_with_len(&bytes, cfg.msg.token_len as usize);
     log!(ProvisionTokens::next,
          effs,
          log::Level::Debug,
@@ -118,14 +127,11 @@ impl<P, E: super::Error, Inner> Step<P> for ProvisionTokens<Inner>
                          -> Result<(), Self::Error> {
     self.inner.before_message_sent(snap, effs, msg)?;
 
-    let token = match (msg.data().code.kind(), msg.data().token) {
-      | (CodeKind::Request, t) if t == Token(Default::default()) => {
-        self.next(effs, snap.time, snap.config)?
-      },
-      | (_, t) => t,
-    };
-
-    msg.data_mut().token = token;
+    // Tokens set by the application are left untouched (without copying them),
+    // since they may be extended tokens carrying state (RFC8974)
+    if msg.data().code.kind() == CodeKind::Request && msg.data().token.0.is_empty() {
+      msg.data_mut().token = self.next(effs, snap.time, snap.config)?;
+    }
 
     Ok(())
   }
@@ -193,6 +199,7 @@ mod test {
       (before_message_sent(
           Snapshot { time: ClockMock::instant(0),
                      recvd_dgram: Some(Addrd(Default::default(), crate::test::dummy_addr())),
+                     recvd_dgram_dest: None,
                      config: Config::default() },
                      _,
           crate::test::msg!(CON GET x.x.x.x:80)
@@ -200,6 +207,48 @@ mod test {
     ]
   );
 
+  test_step!(
+    GIVEN ProvisionTokens::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN we_boutta_send_a_request_with_16_byte_tokens_configured [
+      (inner.before_message_sent = { |_, _, _| Ok(()) })
+    ]
+    THEN this_should_provision_a_token_up_to_max_len [
+      (before_message_sent(
+          Snapshot { time: ClockMock::instant(0),
+                     recvd_dgram: Some(Addrd(Default::default(), crate::test::dummy_addr())),
+                     recvd_dgram_dest: None,
+                     config: {
+                       let mut cfg = Config::default();
+                       cfg.msg.token_len = 16;
+                       cfg
+                     } },
+                     _,
+          crate::test::msg!(CON GET x.x.x.x:80)
+      ) should satisfy { |m| assert_eq!(m.data().token.as_bytes().len(), 16.min(Token::MAX_LEN)) })
+    ]
+  );
+
+  test_step!(
+    GIVEN ProvisionTokens::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN we_boutta_send_a_request_with_a_token [
+      (inner.before_message_sent = { |_, _, _| Ok(()) })
+    ]
+    THEN this_should_keep_its_token [
+      (before_message_sent(
+          Snapshot { time: ClockMock::instant(0),
+                     recvd_dgram: Some(Addrd(Default::default(), crate::test::dummy_addr())),
+                     recvd_dgram_dest: None,
+                     config: Config::default() },
+                     _,
+          {
+            let mut req = crate::test::msg!(CON GET x.x.x.x:80);
+            req.as_mut().token = Token(tinyvec::array_vec!(1, 2, 3));
+            req
+          }
+      ) should satisfy { |m| assert_eq!(m.data().token, Token(tinyvec::array_vec!(1, 2, 3))) })
+    ]
+  );
+
   test_step!(
     GIVEN ProvisionTokens::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
     WHEN we_boutta_send_a_response [
@@ -209,6 +258,7 @@ mod test {
       (before_message_sent(
           Snapshot { time: ClockMock::instant(0),
                      recvd_dgram: Some(Addrd(Default::default(), crate::test::dummy_addr())),
+                     recvd_dgram_dest: None,
                      config: Config::default() },
                      _,
           crate::test::msg!(CON {2 . 04} x.x.x.x:80)
@@ -0,0 +1,613 @@
+use core::str::FromStr;
+
+use embedded_time::duration::Milliseconds;
+use embedded_time::Instant;
+use no_std_net::{IpAddr, SocketAddr};
+use tinyvec::ArrayVec;
+use toad_array::{Array, Indexed};
+use toad_msg::opt::known::{no_repeat, repeat};
+use toad_msg::{Code, CodeKind, Id, MessageOptions, Token, Type};
+use toad_stem::Stem;
+
+use super::{exec_inner_step, log, Step, StepOutput};
+use crate::config::Config;
+use crate::net::Addrd;
+use crate::platform::{self, Effect, PlatformTypes, Snapshot};
+use crate::req::Req;
+use crate::resp::{code, Resp};
+use crate::time::{Clock, Millis, Stamped};
+
+/// The Hop-Limit we set on forwarded requests that didn't have one
+/// ([RFC8768 Section 3](https://www.rfc-editor.org/rfc/rfc8768#section-3))
+pub const DEFAULT_HOP_LIMIT: u8 = 16;
+
+/// The port targets are assumed to listen on when a URI doesn't specify one
+pub const DEFAULT_PORT: u16 = 5683;
+
+/// A request we forwarded on behalf of a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Forwarded {
+  /// The client that sent us the request & the token it used
+  pub client: Addrd<Token>,
+  /// The target we forwarded the request to & the token we used
+  pub upstream: Addrd<Token>,
+}
+
+/// A forwarded request, [`Stamped`] with the instant we last relayed a message for it
+pub type Exchange<P> = Stamped<<P as PlatformTypes>::Clock, Forwarded>;
+
+/// Why a request to be forwarded was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reject {
+  MalformedUri,
+  MissingHost,
+  UnsupportedScheme,
+  UnresolvableHost,
+  InvalidHopLimit,
+  HopLimitReached,
+}
+
+impl Reject {
+  fn code(&self) -> Code {
+    match self {
+      | Reject::MalformedUri | Reject::MissingHost => code::BAD_OPTION,
+      | Reject::UnsupportedScheme | Reject::UnresolvableHost => code::PROXYING_NOT_SUPPORTED,
+      | Reject::InvalidHopLimit => code::BAD_REQUEST,
+      | Reject::HopLimitReached => code::HOP_LIMIT_REACHED,
+    }
+  }
+
+  fn diagnostic(&self) -> &'static str {
+    match self {
+      | Reject::MalformedUri => "Malformed Proxy-Uri",
+      | Reject::MissingHost => "Proxy-Scheme requires Uri-Host",
+      | Reject::UnsupportedScheme => "Only coap:// targets can be proxied",
+      | Reject::UnresolvableHost => "Target host must be an IP address",
+      | Reject::InvalidHopLimit => "Hop-Limit must not be 0",
+      | Reject::HopLimitReached => "Hop-Limit reached",
+    }
+  }
+}
+
+/// The parts of a Proxy-Uri we care about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Target<'a> {
+  addr: SocketAddr,
+  path: &'a str,
+  query: Option<&'a str>,
+}
+
+fn check_scheme(scheme: &str) -> Result<(), Reject> {
+  if scheme.eq_ignore_ascii_case("coap") {
+    Ok(())
+  } else {
+    Err(Reject::UnsupportedScheme)
+  }
+}
+
+fn parse_host(host: &str) -> Result<IpAddr, Reject> {
+  let host = host.trim_start_matches('[').trim_end_matches(']');
+  match host {
+    | "" => Err(Reject::MalformedUri),
+    | host => IpAddr::from_str(host).map_err(|_| Reject::UnresolvableHost),
+  }
+}
+
+/// Parse the authority of a URI (`host`, `host:port`, `[ipv6]` or `[ipv6]:port`)
+fn parse_authority(authority: &str) -> Result<SocketAddr, Reject> {
+  let (host, port) = match authority.strip_prefix('[') {
+    | Some(rest) => {
+      let (host, port) = rest.split_once(']').ok_or(Reject::MalformedUri)?;
+      match port {
+        | "" => (host, None),
+        | port => (host, Some(port.strip_prefix(':').ok_or(Reject::MalformedUri)?)),
+      }
+    },
+    | None => match authority.split_once(':') {
+      | Some((host, port)) => (host, Some(port)),
+      | None => (authority, None),
+    },
+  };
+
+  let port = match port {
+    | Some(port) => u16::from_str(port).map_err(|_| Reject::MalformedUri)?,
+    | None => DEFAULT_PORT,
+  };
+
+  parse_host(host).map(|ip| SocketAddr::new(ip, port))
+}
+
+/// Parse an absolute `coap://` URI ([RFC7252 Section 6.4](https://www.rfc-editor.org/rfc/rfc7252#section-6.4))
+fn parse_uri(uri: &str) -> Result<Target<'_>, Reject> {
+  let (scheme, rest) = uri.split_once("://").ok_or(Reject::MalformedUri)?;
+  check_scheme(scheme)?;
+
+  let rest = rest.split('#').next().unwrap_or_default();
+  let (rest, query) = match rest.split_once('?') {
+    | Some((rest, query)) => (rest, Some(query)),
+    | None => (rest, None),
+  };
+  let (authority, path) = match rest.split_once('/') {
+    | Some((authority, path)) => (authority, path),
+    | None => (rest, ""),
+  };
+
+  parse_authority(authority).map(|addr| Target { addr, path, query })
+}
+
+/// See [the module documentation](self)
+#[derive(Debug)]
+pub struct Proxy<S, Exchanges> {
+  inner: S,
+  exchanges: Stem<Exchanges>,
+}
+
+impl<S, Exchanges> Default for Proxy<S, Exchanges>
+  where S: Default,
+        Exchanges: Default
+{
+  fn default() -> Self {
+    Proxy { inner: S::default(),
+            exchanges: Stem::new(Exchanges::default()) }
+  }
+}
+
+fn expired<C>(at: Instant<C>, now: Instant<C>, config: Config) -> bool
+  where C: Clock
+{
+  now.checked_duration_since(&at)
+     .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+     .map(|Milliseconds(ms)| ms >= config.exchange_lifetime_millis())
+     .unwrap_or(false)
+}
+
+impl<S, Exchanges> Proxy<S, Exchanges> {
+  fn prune<P>(exchanges: &mut Exchanges, now: Instant<P::Clock>, config: Config)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    while let Some(ix) = exchanges.iter()
+                                  .position(|Stamped(_, at)| expired(*at, now, config))
+    {
+      exchanges.remove(ix);
+    }
+  }
+
+  /// Remember a forwarded request, evicting the oldest one if we can't remember any more.
+  fn remember<P>(exchanges: &mut Exchanges, now: Instant<P::Clock>, forwarded: Forwarded)
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    if exchanges.is_full() {
+      exchanges.remove(0);
+    }
+
+    exchanges.append(Stamped(forwarded, now));
+  }
+
+  /// Generate the token to forward a client's request with,
+  /// unique to the client, its token and the instant it was forwarded.
+  fn upstream_token<P>(snap: &Snapshot<P>, client: Addrd<Token>) -> Token
+    where P: PlatformTypes
+  {
+    let now = Millis::try_from(snap.time.duration_since_epoch()).map(|Milliseconds(ms)| ms)
+                                                                .unwrap_or(0);
+
+    let mut bytes = ArrayVec::<[u8; 64]>::new();
+    bytes.extend_from_slice(&client.data().0);
+    match client.addr().ip() {
+      | IpAddr::V4(ip) => bytes.extend_from_slice(&ip.octets()),
+      | IpAddr::V6(ip) => bytes.extend_from_slice(&ip.octets()),
+    }
+    bytes.extend_from_slice(&client.addr().port().to_be_bytes());
+    bytes.extend_from_slice(&now.to_be_bytes());
+    bytes.extend_from_slice(&snap.config.msg.token_seed.to_be_bytes());
+
+    Token::opaque_with_len(&bytes, snap.config.msg.token_len as usize)
+  }
+
+  /// Build the request to forward to the target of a Proxy-Uri or Proxy-Scheme request
+  fn forward<P>(snap: &Snapshot<P>,
+                req: &Addrd<Req<P>>)
+                -> Result<Addrd<platform::Message<P>>, Reject>
+    where P: PlatformTypes
+  {
+    let src = req.data().msg();
+    let hops = match src.hop_limit() {
+      | None if src.get_first(no_repeat::HOP_LIMIT).is_some() => {
+        return Err(Reject::InvalidHopLimit)
+      },
+      | None => DEFAULT_HOP_LIMIT,
+      | Some(0) => return Err(Reject::InvalidHopLimit),
+      | Some(1) => return Err(Reject::HopLimitReached),
+      | Some(n) => n - 1,
+    };
+
+    let mut msg = src.clone();
+    msg.remove(no_repeat::PROXY_URI);
+    msg.remove(no_repeat::PROXY_SCHEME);
+
+    let addr = match src.proxy_uri().map_err(|_| Reject::MalformedUri)? {
+      | Some(uri) => {
+        let target = parse_uri(uri)?;
+
+        msg.remove(no_repeat::HOST);
+        msg.remove(no_repeat::PORT);
+        msg.remove(repeat::PATH);
+        msg.remove(repeat::QUERY);
+
+        if !target.path.is_empty() {
+          msg.set_path(target.path).ok();
+        }
+
+        target.query
+              .into_iter()
+              .flat_map(|q| q.split('&'))
+              .filter(|q| !q.is_empty())
+              .for_each(|q| {
+                msg.add_query(q).ok();
+              });
+
+        target.addr
+      },
+      | None => {
+        let scheme = src.proxy_scheme().map_err(|_| Reject::MalformedUri)?;
+        check_scheme(scheme.unwrap_or_default())?;
+
+        let host = src.host()
+                      .map_err(|_| Reject::MalformedUri)?
+                      .ok_or(Reject::MissingHost)?;
+        SocketAddr::new(parse_host(host)?, src.port().unwrap_or(DEFAULT_PORT))
+      },
+    };
+
+    msg.set_hop_limit(hops).ok();
+    msg.id = Id(0);
+    msg.token = Self::upstream_token(snap, req.as_ref().map(|r| r.msg().token));
+
+    Ok(Addrd(msg, addr))
+  }
+
+  fn error<P>(req: &Addrd<Req<P>>, reject: Reject) -> Addrd<platform::Message<P>>
+    where P: PlatformTypes
+  {
+    let mut resp = Resp::for_request(req.data()).unwrap_or_else(|| Resp::non(req.data()));
+    resp.set_code(reject.code());
+    resp.set_payload(reject.diagnostic().bytes());
+
+    Addrd(resp.into(), req.addr())
+  }
+
+  /// Relay a response from a target to the client whose request we forwarded to it
+  fn relay<P>(&self, snap: &Snapshot<P>, effects: &mut P::Effects, resp: &Addrd<Req<P>>) -> bool
+    where P: PlatformTypes,
+          Exchanges: Array<Item = Exchange<P>>
+  {
+    let msg = resp.data().msg();
+    let key = resp.as_ref().map(|r| r.msg().token);
+    let observing = msg.get(no_repeat::OBSERVE).is_some();
+
+    let client = self.exchanges.map_mut(|es| {
+                                 let ix = es.iter().position(|Stamped(f, _)| f.upstream == key)?;
+                                 let client = es[ix].0.client;
+
+                                 if observing {
+                                   es[ix].1 = snap.time;
+                                 } else {
+                                   es.remove(ix);
+                                 }
+
+                                 Some(client)
+                               });
+
+    let client = match client {
+      | Some(client) => client,
+      | None => return false,
+    };
+
+    if msg.ty == Type::Con {
+      let ack =
+        platform::Message::<P>::new(Type::Ack, Code::EMPTY, msg.id, Token(Default::default()));
+      effects.append(Effect::Send(Addrd(ack, resp.addr())));
+    }
+
+    log!(Proxy::poll_req,
+         effects,
+         log::Level::Debug,
+         "Relaying {:?} response from {} to {}",
+         msg.code,
+         resp.addr(),
+         client.addr());
+
+    // the Ack step will piggyback this on an ACK if the client's request is still unacknowledged
+    let mut relayed = msg.clone();
+    relayed.ty = Type::Non;
+    relayed.id = Id(0);
+    relayed.token = *client.data();
+    effects.append(Effect::Send(Addrd(relayed, client.addr())));
+
+    true
+  }
+}
+
+impl<P, S, Exchanges> Step<P> for Proxy<S, Exchanges>
+  where P: PlatformTypes,
+        S: Step<P, PollReq = Addrd<Req<P>>, PollResp = Addrd<Resp<P>>>,
+        Exchanges: Default + Array<Item = Exchange<P>>
+{
+  type PollReq = Addrd<Req<P>>;
+  type PollResp = Addrd<Resp<P>>;
+  type Error = S::Error;
+  type Inner = S;
+
+  fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  fn poll_req(&self,
+              snap: &Snapshot<P>,
+              effects: &mut <P as PlatformTypes>::Effects)
+              -> StepOutput<Self::PollReq, Self::Error> {
+    self.exchanges
+        .map_mut(|es| Self::prune::<P>(es, snap.time, snap.config));
+
+    let req = exec_inner_step!(self.inner.poll_req(snap, effects), core::convert::identity)?;
+    let msg = req.data().msg();
+
+    // responses to requests we forwarded are yielded here too
+    if msg.code.kind() == CodeKind::Response && self.relay(snap, effects, &req) {
+      return None;
+    }
+
+    if !snap.config.forward_proxy
+       || msg.code.kind() != CodeKind::Request
+       || (msg.get(no_repeat::PROXY_URI).is_none() && msg.get(no_repeat::PROXY_SCHEME).is_none())
+    {
+      return Some(Ok(req));
+    }
+
+    match Self::forward(snap, &req) {
+      | Ok(fwd) => {
+        log!(Proxy::poll_req,
+             effects,
+             log::Level::Debug,
+             "Forwarding {:?} request from {} to {}",
+             msg.code,
+             req.addr(),
+             fwd.addr());
+
+        let forwarded = Forwarded { client: req.as_ref().map(|r| r.msg().token),
+                                    upstream: fwd.as_ref().map(|m| m.token) };
+        self.exchanges
+            .map_mut(|es| Self::remember::<P>(es, snap.time, forwarded));
+        effects.append(Effect::Send(fwd));
+      },
+      | Err(reject) => {
+        log!(Proxy::poll_req,
+             effects,
+             log::Level::Debug,
+             "Not forwarding request from {}: {}",
+             req.addr(),
+             reject.diagnostic());
+
+        if matches!(msg.ty, Type::Con | Type::Non) {
+          effects.append(Effect::Send(Self::error(&req, reject)));
+        }
+      },
+    }
+
+    None
+  }
+
+  fn poll_resp(&self,
+               snap: &Snapshot<P>,
+               effects: &mut <P as PlatformTypes>::Effects,
+               token: Token,
+               addr: SocketAddr)
+               -> StepOutput<Self::PollResp, Self::Error> {
+    self.inner.poll_resp(snap, effects, token, addr)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tinyvec::array_vec;
+
+  use super::*;
+  use crate::step::test::test_step;
+  use crate::test::{self, ClockMock};
+
+  type InnerPollReq = Addrd<Req<test::Platform>>;
+  type InnerPollResp = Addrd<Resp<test::Platform>>;
+  type Proxy<S> = super::Proxy<S, Vec<Exchange<test::Platform>>>;
+
+  fn snapshot() -> test::Snapshot {
+    let mut snap = test::snapshot();
+    snap.config.forward_proxy = true;
+    snap
+  }
+
+  fn req(f: impl FnOnce(&mut test::Message)) -> InnerPollReq {
+    let mut msg = test::msg!(CON GET x.x.x.x:80).unwrap();
+    msg.id = Id(1);
+    msg.token = Token(array_vec!(1));
+    f(&mut msg);
+
+    Addrd(Req::from(msg), test::x.x.x.x(80))
+  }
+
+  fn sent(effs: &[test::Effect]) -> Vec<Addrd<test::Message>> {
+    effs.iter()
+        .filter_map(|e| match e {
+          | Effect::Send(m) => Some(m.clone()),
+          | _ => None,
+        })
+        .collect()
+  }
+
+  fn upstream() -> Addrd<Token> {
+    Addrd(Token(array_vec!(9, 9)), test::x.x.x.x(5683))
+  }
+
+  test_step!(
+    GIVEN Proxy::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN inner_errors [
+      (inner.poll_req => { Some(Err(nb::Error::Other(()))) }),
+      (inner.poll_resp => { Some(Err(nb::Error::Other(()))) })
+    ]
+    THEN this_should_error [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) }),
+      (poll_resp(_, _, _, _) should satisfy { |out| assert_eq!(out, Some(Err(nb::Error::Other(())))) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Proxy::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN proxy_request_received_and_forward_proxy_disabled [
+      (inner.poll_req => { Some(Ok(req(|m| m.set_proxy_uri("coap://192.168.0.1:5683/a").unwrap()))) })
+    ]
+    THEN request_should_be_yielded [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out.unwrap().unwrap().data().msg().proxy_uri(), Ok(Some("coap://192.168.0.1:5683/a"))) }),
+      (effects should satisfy { |effs| assert!(sent(effs).is_empty()) })
+    ]
+  );
+
+  test_step!(
+    GIVEN Proxy::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN proxy_uri_request_received [
+      (snapshot = { snapshot() }),
+      (inner.poll_req => { Some(Ok(req(|m| {
+        m.set_proxy_uri("coap://192.168.0.1:5683/a/b?c=1&d").unwrap();
+        m.set_hop_limit(3).unwrap();
+      }))) })
+    ]
+    THEN request_should_be_forwarded_to_target [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 1);
+
+        let fwd = &sent[0];
+        assert_eq!(fwd.addr(), test::x.x.x.x(5683));
+        assert_eq!(fwd.data().ty, Type::Con);
+        assert_eq!(fwd.data().id, Id(0));
+        assert_ne!(fwd.data().token, Token(array_vec!(1)));
+        assert_eq!(fwd.data().get(no_repeat::PROXY_URI), None);
+        assert_eq!(fwd.data().path_string().unwrap(), "a/b");
+        assert_eq!(fwd.data().query::<Vec<_>>().unwrap(), vec!["c=1", "d"]);
+        assert_eq!(fwd.data().hop_limit(), Some(2));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Proxy::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN proxy_scheme_request_received [
+      (snapshot = { snapshot() }),
+      (inner.poll_req => { Some(Ok(req(|m| {
+        m.set_proxy_scheme("coap").unwrap();
+        m.set_host("[::1]").unwrap();
+        m.set_port(1234).unwrap();
+        m.set_path("a").unwrap();
+      }))) })
+    ]
+    THEN request_should_be_forwarded_with_default_hop_limit [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let fwd = sent(effs).remove(0);
+        assert_eq!(fwd.addr(), "[::1]:1234".parse().unwrap());
+        assert_eq!(fwd.data().get(no_repeat::PROXY_SCHEME), None);
+        assert_eq!(fwd.data().path_string().unwrap(), "a");
+        assert_eq!(fwd.data().hop_limit(), Some(DEFAULT_HOP_LIMIT));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Proxy::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN request_received_with_hop_limit_1 [
+      (snapshot = { snapshot() }),
+      (inner.poll_req => { Some(Ok(req(|m| {
+        m.set_proxy_uri("coap://192.168.0.1/a").unwrap();
+        m.set_hop_limit(1).unwrap();
+      }))) })
+    ]
+    THEN hop_limit_reached_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let resp = sent(effs).remove(0);
+        assert_eq!(resp.addr(), test::x.x.x.x(80));
+        assert_eq!(resp.data().ty, Type::Ack);
+        assert_eq!(resp.data().code, code::HOP_LIMIT_REACHED);
+        assert_eq!(resp.data().token, Token(array_vec!(1)));
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Proxy::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN http_request_received [
+      (snapshot = { snapshot() }),
+      (inner.poll_req => { Some(Ok(req(|m| m.set_proxy_uri("http://192.168.0.1/a").unwrap()))) })
+    ]
+    THEN proxying_not_supported_should_be_sent [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        assert_eq!(sent(effs).remove(0).data().code, code::PROXYING_NOT_SUPPORTED);
+      }})
+    ]
+  );
+
+  test_step!(
+    GIVEN Proxy::<Dummy> where Dummy: {Step<PollReq = InnerPollReq, PollResp = InnerPollResp, Error = ()>};
+    WHEN response_to_forwarded_request_received [
+      (inner.poll_req => {{
+        let mut msg = test::msg!(CON {2 . 5} x.x.x.x:5683).unwrap();
+        msg.id = Id(7);
+        msg.token = upstream().unwrap();
+        Some(Ok(Addrd(Req::from(msg), upstream().addr())))
+      }}),
+      ({|step: &Proxy<Dummy>| {
+        step.exchanges.map_mut(|es| es.push(Stamped(Forwarded { client: Addrd(Token(array_vec!(1)), test::x.x.x.x(80)),
+                                                                upstream: upstream() },
+                                                    ClockMock::instant(0))));
+      }})
+    ]
+    THEN response_should_be_acked_and_relayed_to_client [
+      (poll_req(_, _) should satisfy { |out| assert_eq!(out, None) }),
+      (effects should satisfy { |effs| {
+        let sent = sent(effs);
+        assert_eq!(sent.len(), 2);
+
+        assert_eq!(sent[0].addr(), test::x.x.x.x(5683));
+        assert_eq!(sent[0].data().ty, Type::Ack);
+        assert_eq!(sent[0].data().code, Code::EMPTY);
+        assert_eq!(sent[0].data().id, Id(7));
+
+        assert_eq!(sent[1].addr(), test::x.x.x.x(80));
+        assert_eq!(sent[1].data().ty, Type::Non);
+        assert_eq!(sent[1].data().code, Code::new(2, 5));
+        assert_eq!(sent[1].data().token, Token(array_vec!(1)));
+      }})
+    ]
+  );
+
+  #[test]
+  fn parse_uri() {
+    assert_eq!(super::parse_uri("coap://192.168.0.1"),
+               Ok(Target { addr: test::x.x.x.x(DEFAULT_PORT),
+                           path: "",
+                           query: None }));
+    assert_eq!(super::parse_uri("COAP://[::1]:80/a?b#c"),
+               Ok(Target { addr: "[::1]:80".parse().unwrap(),
+                           path: "a",
+                           query: Some("b") }));
+    assert_eq!(super::parse_uri("coaps://192.168.0.1"),
+               Err(Reject::UnsupportedScheme));
+    assert_eq!(super::parse_uri("coap://example.com/a"),
+               Err(Reject::UnresolvableHost));
+    assert_eq!(super::parse_uri("coap://192.168.0.1:x"),
+               Err(Reject::MalformedUri));
+    assert_eq!(super::parse_uri("/a/b"), Err(Reject::MalformedUri));
+  }
+}
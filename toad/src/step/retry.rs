@@ -1,11 +1,11 @@
 use embedded_time::duration::Milliseconds;
 use embedded_time::Instant;
-use toad_array::Array;
-use toad_msg::{CodeKind, Token, Type};
+use toad_array::{Array, Indexed};
+use toad_msg::{CodeKind, MessageOptions, Token, Type};
 use toad_stem::Stem;
 use toad_string::{format, String};
 
-use super::{log, Step, StepOutput, _try};
+use super::{_try, log, Step, StepOutput};
 use crate::config::Config;
 use crate::net::Addrd;
 use crate::platform::{self, Effect, PlatformTypes, Snapshot};
@@ -61,8 +61,23 @@ pub trait Buf<P>
                     effects: &mut P::Effects)
                     -> Result<(), Error<E>> {
     self.iter_mut().for_each(|(state, msg)| {
+                     if let State::Exhausted(_) = state {
+                       return;
+                     }
+
                      let dbg = Self::debug(now, state, msg);
                      match state.timer().what_should_i_do(now) {
+                       | Ok(YouShould::Cry) if now >= state.retry_timer().next_attempt_at() => {
+                         log!(retry::Buf::attempt_all,
+                              effects,
+                              log::Level::Warn,
+                              "{} not {} after {} attempts (first attempt {}ms ago). giving up.",
+                              dbg.msg_short,
+                              dbg.msg_should_be,
+                              state.retry_timer().attempts().0,
+                              dbg.since_first_attempt);
+                         *state = State::Exhausted(*state.retry_timer());
+                       },
                        | Ok(YouShould::Retry) => {
                          log!(retry::Buf::attempt_all,
                               effects,
@@ -71,7 +86,7 @@ pub trait Buf<P>
                               dbg.msg_short,
                               dbg.msg_should_be,
                               dbg.since_last_attempt);
-                         effects.push(Effect::Send(msg.clone()));
+                         effects.append(Effect::Send(msg.clone()));
                        },
                        | _ => log!(retry::Buf::attempt_all,
                                    effects,
@@ -86,6 +101,59 @@ pub trait Buf<P>
     Ok(())
   }
 
+  /// Forget messages we gave up on that have aged out of the exchange lifetime
+  /// without anyone asking about them
+  fn prune_exhausted(&mut self, now: Instant<P::Clock>, config: Config) {
+    while let Some(ix) =
+      self.iter().position(|(state, _)| {
+                   matches!(state, State::Exhausted(_))
+                   && now.checked_duration_since(&state.retry_timer().first_attempted_at())
+                         .and_then(|d| Milliseconds::<u64>::try_from(d).ok())
+                         .map(|Milliseconds(ms)| ms >= config.exchange_lifetime_millis())
+                         .unwrap_or(false)
+                 })
+    {
+      self.remove(ix);
+    }
+  }
+
+  /// The earliest instant at which a message
+  /// will need to be retried (or given up on)
+  fn next_attempt_at(&self) -> Option<Instant<P::Clock>> {
+    self.iter()
+        .filter(|(state, _)| !matches!(state, State::Exhausted(_)))
+        .map(|(state, _)| state.retry_timer().next_attempt_at())
+        .min()
+  }
+
+  /// Remove & yield the first message we gave up on
+  /// that satisfies `f`
+  fn take_exhausted(&mut self,
+                    f: impl Fn(&Addrd<platform::Message<P>>) -> bool)
+                    -> Option<Addrd<platform::Message<P>>> {
+    self.iter()
+        .position(|(state, msg)| matches!(state, State::Exhausted(_)) && f(msg))
+        .and_then(|ix| self.remove(ix))
+        .map(|(_, msg)| msg)
+  }
+
+  /// Make room for a message that may still be delivered by forgetting
+  /// the oldest message we gave up on, without reporting the failure
+  fn forget_oldest_exhausted(&mut self, effects: &mut P::Effects) {
+    let ix = self.iter()
+                 .position(|(state, _)| matches!(state, State::Exhausted(_)));
+
+    if let Some((_, msg)) = ix.and_then(|ix| self.remove(ix)) {
+      log!(retry::Buf::forget_oldest_exhausted,
+           effects,
+           log::Level::Warn,
+           "retry buffer full; forgetting {:?} {:?} {:?} we gave up on before the failure was reported",
+           msg.data().ty,
+           msg.data().code,
+           msg.data().token);
+    }
+  }
+
   /// We saw a response and should remove all tracking of a token (if we have any)
   fn forget(&mut self, now: Instant<P::Clock>, effects: &mut P::Effects, token: Token) {
     match self.iter()
@@ -110,11 +178,20 @@ pub trait Buf<P>
 
   /// We saw an ACK and should transition the retry state for matching outbound
   /// CONs to the "acked" state
+  ///
+  /// CON responses & requests suppressing all responses are forgotten instead,
+  /// since nothing else is expected for them.
   fn mark_acked(&mut self, now: Instant<P::Clock>, effects: &mut P::Effects, token: Token) {
     let found = self.iter_mut().find(|(_, msg)| msg.data().token == token);
 
     match found {
-      | Some((_, msg)) if msg.data().code.kind() == CodeKind::Response => {
+      | Some((_, msg))
+        if msg.data().code.kind() == CodeKind::Response
+           || msg.data()
+                 .no_response()
+                 .map(|n| n.suppresses_all())
+                 .unwrap_or(false) =>
+      {
         self.forget(now, effects, token);
       },
       | Some((state, msg)) if matches!(state, State::ConPreAck { .. }) => {
@@ -208,6 +285,34 @@ pub trait Buf<P>
     }
   }
 
+  /// A new request re-using the token of a request we're retrying
+  /// (e.g. asking for the next block of a response) means the
+  /// server has already responded to the old one, so we should stop retrying it.
+  ///
+  /// Retransmissions of a stored request have the same Message ID, and are left alone.
+  fn forget_superseded(&mut self,
+                       now: Instant<P::Clock>,
+                       effects: &mut P::Effects,
+                       msg: &Addrd<platform::Message<P>>) {
+    let found = self.iter().position(|(_, stored)| {
+                             stored.addr() == msg.addr()
+                             && stored.data().code.kind() == CodeKind::Request
+                             && stored.data().token == msg.data().token
+                             && stored.data().id != msg.data().id
+                           });
+
+    if let Some(ix) = found {
+      let (state, stored) = self.remove(ix).unwrap();
+      let dbg = Self::debug(now, &state, &stored);
+      log!(retry::Buf::forget_superseded,
+           effects,
+           log::Level::Debug,
+           "{} superseded by a new request with the same token after waiting {}ms since last attempt",
+           dbg.msg_short,
+           dbg.since_last_attempt);
+    }
+  }
+
   /// Called when a message of any kind is sent,
   /// and may store it to be retried in the future
   fn store_retryables<E>(&mut self,
@@ -216,16 +321,24 @@ pub trait Buf<P>
                          msg: &Addrd<platform::Message<P>>,
                          config: Config)
                          -> Result<(), Error<E>> {
+    if msg.data().code.kind() == CodeKind::Request {
+      self.forget_superseded(now, effects, msg);
+    }
+
+    if matches!(msg.data().ty, Type::Con | Type::Non) && self.is_full() {
+      self.forget_oldest_exhausted(effects);
+    }
+
     match msg.data().ty {
       | Type::Con | Type::Non if self.is_full() => Err(Error::RetryBufferFull),
       | Type::Con => {
         let timer = RetryTimer::new(now,
                                     config.msg.con.unacked_retry_strategy,
                                     config.msg.con.max_attempts);
-        self.push((State::ConPreAck { timer,
-                                      post_ack_strategy: config.msg.con.acked_retry_strategy,
-                                      post_ack_max_attempts: config.msg.con.max_attempts },
-                   msg.clone()));
+        self.append((State::ConPreAck { timer,
+                                        post_ack_strategy: config.msg.con.acked_retry_strategy,
+                                        post_ack_max_attempts: config.msg.con.max_attempts },
+                     msg.clone()));
 
         log!(retry::Buf::store_retryables,
              effects,
@@ -235,7 +348,13 @@ pub trait Buf<P>
 
         Ok(())
       },
-      | Type::Non if msg.data().code.kind() == CodeKind::Request => {
+      | Type::Non
+        if msg.data().code.kind() == CodeKind::Request
+           && !msg.data()
+                  .no_response()
+                  .map(|n| n.suppresses_all())
+                  .unwrap_or(false) =>
+      {
         log!(retry::Buf::store_retryables,
              effects,
              log::Level::Trace,
@@ -244,7 +363,7 @@ pub trait Buf<P>
         let timer = RetryTimer::new(now,
                                     config.msg.non.retry_strategy,
                                     config.msg.non.max_attempts);
-        self.push((State::Just(timer), msg.clone()));
+        self.append((State::Just(timer), msg.clone()));
 
         Ok(())
       },
@@ -290,6 +409,14 @@ pub enum State<C>
     /// The max number of retry attempts for the post-ack state
     post_ack_max_attempts: Attempts,
   },
+  /// A message that was retried as many times as allowed
+  /// without being acknowledged or responded to.
+  ///
+  /// These are kept around so that the failure can be reported
+  /// (see [`Error::Timeout`] and [`Error::Unacknowledged`]),
+  /// unless the buffer is full and room is needed for another message,
+  /// and will never be retried again.
+  Exhausted(RetryTimer<C>),
 }
 
 impl<C> State<C> where C: Clock
@@ -297,7 +424,7 @@ impl<C> State<C> where C: Clock
   /// Gets the current in-use retry timer
   pub fn retry_timer(&self) -> &RetryTimer<C> {
     match self {
-      | Self::Just(r) => r,
+      | Self::Just(r) | Self::Exhausted(r) => r,
       | Self::ConPreAck { timer, .. } => timer,
     }
   }
@@ -309,6 +436,7 @@ impl<C> Clone for State<C> where C: Clock
   fn clone(&self) -> Self {
     match self {
       | Self::Just(t) => Self::Just(*t),
+      | Self::Exhausted(t) => Self::Exhausted(*t),
       | Self::ConPreAck { timer,
                           post_ack_strategy,
                           post_ack_max_attempts, } => {
@@ -338,7 +466,7 @@ impl<C> State<C> where C: Clock
 
   fn timer(&mut self) -> &mut RetryTimer<C> {
     match self {
-      | Self::Just(t) => t,
+      | Self::Just(t) | Self::Exhausted(t) => t,
       | Self::ConPreAck { timer, .. } => timer,
     }
   }
@@ -376,19 +504,52 @@ pub enum Error<E> {
   ///
   /// Only applicable to [`Retry`] that uses `ArrayVec` or
   /// similar heapless backing structure.
+  ///
+  /// Messages that were given up on are forgotten to make room
+  /// before this is returned.
   RetryBufferFull,
+  /// A request we sent was retried as many times as allowed by
+  /// [`Config.msg`](crate::config::Msg) without receiving a response.
+  ///
+  /// Yielded by `poll_resp` for the request's token & address.
+  Timeout(Addrd<Token>),
+  /// A CON response (or Observe notification) we sent was retried as many
+  /// times as allowed by [`Con.max_attempts`](crate::config::Con.max_attempts)
+  /// without being acknowledged.
+  ///
+  /// Yielded by `poll_req`.
+  Unacknowledged(Addrd<Token>),
 }
 
 impl<E: core::fmt::Debug> core::fmt::Debug for Error<E> {
   fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     match self {
       | Self::RetryBufferFull => f.debug_struct("RetryBufferFull").finish(),
+      | Self::Timeout(t) => f.debug_tuple("Timeout").field(t).finish(),
+      | Self::Unacknowledged(t) => f.debug_tuple("Unacknowledged").field(t).finish(),
       | Self::Inner(e) => e.fmt(f),
     }
   }
 }
 
-impl<E> super::Error for Error<E> where E: super::Error {}
+impl<E> super::Error for Error<E> where E: super::Error
+{
+  fn is_timeout(&self) -> bool {
+    match self {
+      | Self::Timeout(_) => true,
+      | Self::Inner(e) => e.is_timeout(),
+      | _ => false,
+    }
+  }
+
+  fn is_unacknowledged(&self) -> bool {
+    match self {
+      | Self::Unacknowledged(_) => true,
+      | Self::Inner(e) => e.is_unacknowledged(),
+      | _ => false,
+    }
+  }
+}
 
 impl<E> From<E> for Error<E> {
   fn from(e: E) -> Self {
@@ -421,10 +582,22 @@ impl<P, E, Inner, Buffer> Step<P> for Retry<Inner, Buffer>
     //  * ACKs          WILL NOT be retried
     //  * RESET         WILL NOT be retried
     _try!(Result; self.buf.map_mut(|b| b.attempt_all::<Inner::Error>(snap.time, effects)));
+    self.buf
+        .map_mut(|b| b.prune_exhausted(snap.time, snap.config));
 
     let req = self.inner
                   .poll_req(snap, effects)
                   .map(|r| r.map_err(|nb| nb.map(Error::Inner)));
+
+    if let None | Some(Err(nb::Error::WouldBlock)) = req {
+      let unacked =
+        self.buf
+            .map_mut(|b| b.take_exhausted(|msg| msg.data().code.kind() == CodeKind::Response));
+      if let Some(msg) = unacked {
+        return Some(Err(nb::Error::Other(Error::Unacknowledged(msg.map(|m| m.token)))));
+      }
+    }
+
     let req = _try!(Option<nb::Result>; req);
     _try!(Result; self.buf.map_mut(|b| b.maybe_seen_response::<Inner::Error>(snap.time, effects, req.as_ref().map(|r| r.as_ref()))));
     Some(Ok(req))
@@ -441,11 +614,27 @@ impl<P, E, Inner, Buffer> Step<P> for Retry<Inner, Buffer>
     //  * NON requests WILL     be retried
     //  * RESET        WILL NOT be retried
     _try!(Result; self.buf.map_mut(|b| b.attempt_all::<Inner::Error>(snap.time, effects)));
+    self.buf
+        .map_mut(|b| b.prune_exhausted(snap.time, snap.config));
 
     let resp =
       self.inner
           .poll_resp(snap, effects, token, addr)
           .map(|r| r.map_err(|nb| nb.map(Error::Inner)));
+
+    if let None | Some(Err(nb::Error::WouldBlock)) = resp {
+      let gave_up = self.buf.map_mut(|b| {
+                              b.take_exhausted(|msg| {
+                                 msg.addr() == addr
+                                 && msg.data().token == token
+                                 && msg.data().code.kind() == CodeKind::Request
+                               })
+                            });
+      if gave_up.is_some() {
+        return Some(Err(nb::Error::Other(Error::Timeout(Addrd(token, addr)))));
+      }
+    }
+
     let resp = _try!(Option<nb::Result>; resp);
     _try!(Result; self.buf.map_mut(|b| b.maybe_seen_response::<Inner::Error>(snap.time, effects, resp.as_ref().map(|r| r.as_ref()))));
     Some(Ok(resp))
@@ -460,6 +649,11 @@ impl<P, E, Inner, Buffer> Step<P> for Retry<Inner, Buffer>
     self.buf
         .map_mut(|b| b.store_retryables(snap.time, effects, msg, snap.config))
   }
+
+  fn next_deadline(&self, config: Config) -> Option<Instant<P::Clock>> {
+    super::earliest(self.inner.next_deadline(config),
+                    self.buf.map_ref(|b| b.next_attempt_at()))
+  }
 }
 
 #[cfg(test)]
@@ -480,6 +674,7 @@ mod tests {
   fn snap_time(config: Config, time: u64) -> test::Snapshot {
     test::Snapshot { config,
                      recvd_dgram: Some(Addrd(tinyvec::array_vec!(1), test::dummy_addr())),
+                     recvd_dgram_dest: None,
                      time: ClockMock::instant(time * 1000) }
   }
 
@@ -528,6 +723,157 @@ mod tests {
     ]
   );
 
+  #[test]
+  fn new_request_with_same_token_should_supersede_stored_request() {
+    type Buf = Vec<(State<ClockMock>, Addrd<platform::Message<P>>)>;
+
+    let cfg = config(200, 400);
+    let req = |id: u16| {
+      let mut req = test::msg!(CON GET x.x.x.x:1111);
+      req.as_mut().id = toad_msg::Id(id);
+      req.as_mut().token = Token(array_vec![1, 2, 3]);
+      req
+    };
+
+    let mut buf = Buf::default();
+    <Buf as super::Buf<P>>::store_retryables::<()>(&mut buf,
+                                                   ClockMock::instant(0),
+                                                   &mut vec![],
+                                                   &req(1),
+                                                   cfg).unwrap();
+    <Buf as super::Buf<P>>::store_retryables::<()>(&mut buf,
+                                                   ClockMock::instant(0),
+                                                   &mut vec![],
+                                                   &req(2),
+                                                   cfg).unwrap();
+
+    assert_eq!(buf.iter().map(|(_, m)| m.data().id).collect::<Vec<_>>(),
+               vec![toad_msg::Id(2)]);
+  }
+
+  #[test]
+  fn full_buffer_should_forget_exhausted_messages_to_make_room() {
+    type Item = (State<ClockMock>, Addrd<platform::Message<P>>);
+
+    /// A `Vec` that can hold 1 message, since
+    /// [`Addrd`] can't be stored in an `ArrayVec`
+    #[derive(Default)]
+    struct Buf(Vec<Item>);
+
+    impl toad_len::Len for Buf {
+      const CAPACITY: Option<usize> = Some(1);
+
+      fn len(&self) -> usize {
+        self.0.len()
+      }
+
+      fn is_full(&self) -> bool {
+        self.0.len() == 1
+      }
+    }
+
+    impl toad_array::Reserve for Buf {}
+
+    impl toad_array::Filled<Item> for Buf {
+      fn filled_using<F>(_: F) -> Option<Self>
+        where F: Fn() -> Item
+      {
+        None
+      }
+    }
+
+    impl toad_array::Trunc for Buf {
+      fn trunc(&mut self, len: usize) {
+        self.0.truncate(len)
+      }
+    }
+
+    impl Indexed<Item> for Buf {
+      fn insert(&mut self, ix: usize, t: Item) {
+        self.0.insert(ix, t)
+      }
+
+      fn remove(&mut self, ix: usize) -> Option<Item> {
+        Indexed::remove(&mut self.0, ix)
+      }
+    }
+
+    impl Extend<Item> for Buf {
+      fn extend<I: IntoIterator<Item = Item>>(&mut self, iter: I) {
+        self.0.extend(iter)
+      }
+    }
+
+    impl FromIterator<Item> for Buf {
+      fn from_iter<I: IntoIterator<Item = Item>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+      }
+    }
+
+    impl IntoIterator for Buf {
+      type Item = Item;
+      type IntoIter = std::vec::IntoIter<Item>;
+
+      fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+      }
+    }
+
+    impl core::ops::Deref for Buf {
+      type Target = [Item];
+
+      fn deref(&self) -> &[Item] {
+        &self.0
+      }
+    }
+
+    impl core::ops::DerefMut for Buf {
+      fn deref_mut(&mut self) -> &mut [Item] {
+        &mut self.0
+      }
+    }
+
+    impl Array for Buf {
+      type Item = Item;
+    }
+
+    let cfg = config(100, 100);
+    let req = |token: Token| {
+      let mut req = test::msg!(CON GET x.x.x.x:1111);
+      req.as_mut().token = token;
+      req
+    };
+
+    let mut buf = Buf::default();
+    <Buf as super::Buf<P>>::store_retryables::<()>(&mut buf,
+                                                   ClockMock::instant(0),
+                                                   &mut vec![],
+                                                   &req(Token(array_vec![1])),
+                                                   cfg).unwrap();
+    assert_eq!(<Buf as super::Buf<P>>::store_retryables::<()>(&mut buf,
+                                                              ClockMock::instant(0),
+                                                              &mut vec![],
+                                                              &req(Token(array_vec![2])),
+                                                              cfg),
+               Err(Error::RetryBufferFull));
+
+    // give up on the first request
+    (1..=10).for_each(|s| {
+              <Buf as super::Buf<P>>::attempt_all::<()>(&mut buf,
+                                                        ClockMock::instant(s * 1_000_000),
+                                                        &mut vec![]).unwrap()
+            });
+    assert!(matches!(buf[0].0, State::Exhausted(_)));
+
+    <Buf as super::Buf<P>>::store_retryables::<()>(&mut buf,
+                                                   ClockMock::instant(10_000_000),
+                                                   &mut vec![],
+                                                   &req(Token(array_vec![2])),
+                                                   cfg).unwrap();
+    assert_eq!(buf.iter().map(|(_, m)| m.data().token).collect::<Vec<_>>(),
+               vec![Token(array_vec![2])]);
+  }
+
   /*
    * | t      | what                                              |
    * | ------ | ------------------------------------------------- |
@@ -980,6 +1326,80 @@ mod tests {
     assert_eq!(sent!().len(), 0);
   }
 
+  /*
+   * | t      | what                                              |
+   * | ------ | ------------------------------------------------- |
+   * |     50 | NON request with No-Response: all sent            |
+   * | 10_000 | should not have retried                           |
+   */
+  #[test]
+  fn when_non_request_suppressing_all_responses_sent_retry_should_never_retry() {
+    type Mock = test::MockStep<(), Addrd<test::Req>, Addrd<test::Resp>, ()>;
+    let s = Retry::<Mock>::default();
+
+    let cfg = config(200, 200);
+    let mut effs = Vec::<test::Effect>::new();
+
+    let mut req = test::msg!(NON GET x.x.x.x:1111);
+    req.as_mut().token = Token(array_vec![1, 2, 3]);
+    req.as_mut()
+       .set_no_response(toad_msg::NoResponse::all())
+       .ok();
+
+    s.on_message_sent(&snap_time(cfg, 50), &mut effs, &req)
+     .unwrap();
+
+    s.poll_req(&snap_time(cfg, 10_000), &mut effs)
+     .ok_or(())
+     .unwrap_err();
+    assert!(!effs.iter().any(|e| matches!(e, Effect::Send(_))));
+  }
+
+  /*
+   * | t      | what                                              |
+   * | ------ | ------------------------------------------------- |
+   * |     50 | CON request with No-Response: all sent            |
+   * |    100 | got ACK, nothing else is coming                   |
+   * | 10_000 | should not have retried                           |
+   */
+  #[test]
+  fn when_con_request_suppressing_all_responses_acked_retry_should_never_retry() {
+    type Mock = test::MockStep<(), Addrd<test::Req>, Addrd<test::Resp>, ()>;
+    let s = Retry::<Mock>::default();
+    s.inner().set_poll_resp(|_, _, _, token, _| {
+               let mut ack = test::msg!(ACK EMPTY x.x.x.x:1111);
+               ack.as_mut().token = token;
+               Some(Ok(ack.map(Resp::from)))
+             });
+
+    let cfg = config(200, 200);
+    let mut effs = Vec::<test::Effect>::new();
+
+    let mut req = test::msg!(CON POST x.x.x.x:1111);
+    req.as_mut().token = Token(array_vec![1, 2, 3]);
+    req.as_mut()
+       .set_no_response(toad_msg::NoResponse::all())
+       .ok();
+
+    s.on_message_sent(&snap_time(cfg, 50), &mut effs, &req)
+     .unwrap();
+    s.poll_resp(&snap_time(cfg, 100),
+                &mut effs,
+                req.data().token,
+                req.addr())
+     .unwrap()
+     .unwrap();
+
+    s.inner().set_poll_resp(|_, _, _, _, _| None);
+    s.poll_resp(&snap_time(cfg, 10_000),
+                &mut effs,
+                req.data().token,
+                req.addr())
+     .ok_or(())
+     .unwrap_err();
+    assert!(!effs.iter().any(|e| matches!(e, Effect::Send(_))));
+  }
+
   /*
    * | t      | what                                              |
    * | ------ | ------------------------------------------------- |
@@ -1024,4 +1444,121 @@ mod tests {
      .unwrap_err();
     assert_eq!(sent!().len(), 0);
   }
+
+  /*
+   * | t      | what                                              |
+   * | ------ | ------------------------------------------------- |
+   * |     50 | CON request sent                                  |
+   * |    250 | resend (attempt 2)                                |
+   * |    450 | resend (attempt 3)                                |
+   * |    650 | resend (attempt 4)                                |
+   * |    850 | no ACK after last attempt, give up                |
+   */
+  #[test]
+  fn when_con_request_exhausts_attempts_poll_resp_should_time_out() {
+    type Mock = test::MockStep<(), Addrd<test::Req>, Addrd<test::Resp>, ()>;
+    let s = Retry::<Mock>::default();
+    s.inner().set_poll_resp(|_, _, _, _, _| None);
+
+    let cfg = config(200, 400);
+    let mut effs = Vec::<test::Effect>::new();
+
+    let mut req = test::msg!(CON GET x.x.x.x:1111);
+    req.as_mut().token = Token(array_vec![1, 2, 3]);
+
+    s.on_message_sent(&snap_time(cfg, 50), &mut effs, &req)
+     .unwrap();
+
+    for t in [250, 450, 650] {
+      s.poll_resp(&snap_time(cfg, t), &mut effs, req.data().token, req.addr())
+       .ok_or(())
+       .unwrap_err();
+    }
+
+    let other_token = Token(array_vec![4, 5, 6]);
+    s.poll_resp(&snap_time(cfg, 850), &mut effs, other_token, req.addr())
+     .ok_or(())
+     .unwrap_err();
+
+    assert_eq!(s.poll_resp(&snap_time(cfg, 850),
+                           &mut effs,
+                           req.data().token,
+                           req.addr()),
+               Some(Err(nb::Error::Other(Error::Timeout(req.as_ref().map(|m| m.token))))));
+
+    s.poll_resp(&snap_time(cfg, 10_000),
+                &mut effs,
+                req.data().token,
+                req.addr())
+     .ok_or(())
+     .unwrap_err();
+
+    let sent = effs.iter().filter(|e| matches!(e, Effect::Send(_))).count();
+    assert_eq!(sent, 3);
+  }
+
+  #[test]
+  fn next_deadline_should_be_next_retry_attempt() {
+    type Mock = test::MockStep<(), Addrd<test::Req>, Addrd<test::Resp>, ()>;
+    let s = Retry::<Mock>::default();
+    s.inner().set_poll_resp(|_, _, _, _, _| None);
+
+    let cfg = config(200, 400);
+    let mut effs = Vec::<test::Effect>::new();
+    assert_eq!(s.next_deadline(cfg), None);
+
+    let mut req = test::msg!(CON GET x.x.x.x:1111);
+    req.as_mut().token = Token(array_vec![1, 2, 3]);
+
+    s.on_message_sent(&snap_time(cfg, 50), &mut effs, &req)
+     .unwrap();
+    assert_eq!(s.next_deadline(cfg), Some(ClockMock::instant(250 * 1000)));
+
+    s.poll_resp(&snap_time(cfg, 250),
+                &mut effs,
+                req.data().token,
+                req.addr())
+     .ok_or(())
+     .unwrap_err();
+    assert_eq!(s.next_deadline(cfg), Some(ClockMock::instant(450 * 1000)));
+
+    for t in [450, 650, 850] {
+      s.poll_resp(&snap_time(cfg, t),
+                  &mut effs,
+                  Token(array_vec![4, 5, 6]),
+                  req.addr())
+       .ok_or(())
+       .unwrap_err();
+    }
+    assert_eq!(s.next_deadline(cfg), None);
+  }
+
+  #[test]
+  fn when_con_response_exhausts_attempts_poll_req_should_error() {
+    type Mock = test::MockStep<(), Addrd<test::Req>, Addrd<test::Resp>, ()>;
+    let s = Retry::<Mock>::default();
+    s.inner().set_poll_req(|_, _, _| None);
+
+    let cfg = config(200, 400);
+    let mut effs = Vec::<test::Effect>::new();
+
+    let mut resp = test::msg!(CON {2 . 5} x.x.x.x:1111);
+    resp.as_mut().token = Token(array_vec![1, 2, 3]);
+
+    s.on_message_sent(&snap_time(cfg, 50), &mut effs, &resp)
+     .unwrap();
+
+    for t in [250, 450, 650] {
+      s.poll_req(&snap_time(cfg, t), &mut effs)
+       .ok_or(())
+       .unwrap_err();
+    }
+
+    assert_eq!(s.poll_req(&snap_time(cfg, 850), &mut effs),
+               Some(Err(nb::Error::Other(Error::Unacknowledged(resp.as_ref().map(|m| m.token))))));
+
+    s.poll_req(&snap_time(cfg, 10_000), &mut effs)
+     .ok_or(())
+     .unwrap_err();
+  }
 }
@@ -6,6 +6,7 @@ use ::core::time::Duration;
 use ::std::sync::{Mutex, RwLock};
 use ::std::thread;
 use ::toad_msg::{TryFromBytes, TryIntoBytes};
+use config::Config;
 use embedded_time::rate::Fraction;
 use embedded_time::Instant;
 use net::*;
@@ -101,6 +102,7 @@ pub type Resp = crate::resp::Resp<Platform>;
 pub fn snapshot() -> Snapshot {
   Snapshot { config: Default::default(),
              time: ClockMock::instant(0),
+             recvd_dgram_dest: None,
              recvd_dgram: None }
 }
 
@@ -391,12 +393,15 @@ pub struct SockMock {
   pub rx: Arc<Mutex<Vec<Addrd<Vec<u8>>>>>,
   /// Outbound bytes to remote sockets. Address represents the destination
   pub tx: Arc<Mutex<Vec<Addrd<Vec<u8>>>>>,
+  /// The address the socket is bound to, which all inbound bytes were sent to
+  pub addr: SocketAddr,
 }
 
 impl SockMock {
   pub fn new() -> Self {
     Self { rx: Default::default(),
-           tx: Default::default() }
+           tx: Default::default(),
+           addr: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 5683)) }
   }
 
   pub fn send_msg<P: platform::PlatformTypes>(rx: &Arc<Mutex<Vec<Addrd<Vec<u8>>>>>,
@@ -430,12 +435,13 @@ impl SockMock {
 impl Socket for SockMock {
   type Error = Option<()>;
   type Dgram = ArrayVec<[u8; 1024]>;
+  type RecvReady = ();
 
   fn empty_dgram() -> Self::Dgram {
     ArrayVec::from([0u8; 1024])
   }
 
-  fn recv(&self, buf: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error> {
+  fn recv(&self, buf: &mut [u8]) -> nb::Result<(Addrd<usize>, SocketAddr), Self::Error> {
     let mut rx = self.rx.lock().unwrap();
 
     if rx.is_empty() {
@@ -449,7 +455,7 @@ impl Socket for SockMock {
          .enumerate()
          .for_each(|(ix, byte)| buf[ix] = *byte);
 
-    Ok(dgram.map(|bytes| bytes.len()))
+    Ok((dgram.map(|bytes| bytes.len()), self.local_addr()))
   }
 
   fn send(&self, buf: Addrd<&[u8]>) -> nb::Result<(), Self::Error> {
@@ -462,8 +468,13 @@ impl Socket for SockMock {
     todo!()
   }
 
-  fn bind_raw<A: no_std_net::ToSocketAddrs>(_: A) -> Result<Self, Self::Error> {
-    Ok(Self::new())
+  fn bind_raw<A: no_std_net::ToSocketAddrs>(addr: A) -> Result<Self, Self::Error> {
+    let addr = addr.to_socket_addrs()
+                   .ok()
+                   .and_then(|mut a| a.next())
+                   .ok_or(None)?;
+    Ok(Self { addr,
+              ..Self::new() })
   }
 
   fn peek(&self, _: &mut [u8]) -> nb::Result<Addrd<usize>, Self::Error> {
@@ -471,7 +482,70 @@ impl Socket for SockMock {
   }
 
   fn local_addr(&self) -> SocketAddr {
-    todo!()
+    self.addr
+  }
+}
+
+/// A [`platform::Platform`] running `Steps` over a [`SockMock`] and [`ClockMock`]
+pub struct MockPlatform<Steps> {
+  pub steps: Steps,
+  pub sock: SockMock,
+  pub clock: ClockMock,
+  pub config: Config,
+}
+
+impl<Steps> MockPlatform<Steps> {
+  pub fn new(steps: Steps) -> Self {
+    Self { steps,
+           sock: SockMock::new(),
+           clock: ClockMock::new(),
+           config: Default::default() }
+  }
+
+  /// Queue a message to be received by the platform
+  pub fn recv(&self, msg: Addrd<Message>) {
+    SockMock::send_msg::<Platform>(&self.sock.rx, msg);
+  }
+
+  /// Messages sent by the platform so far
+  pub fn sent(&self) -> Vec<Addrd<Message>> {
+    self.sock
+        .tx
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|dgram| {
+          dgram.as_ref()
+               .map(|bytes| Message::try_from_bytes(bytes.clone()).unwrap())
+        })
+        .collect()
+  }
+}
+
+impl<Steps> platform::Platform<Steps> for MockPlatform<Steps>
+  where Steps: step::Step<Platform, PollReq = Addrd<Req>, PollResp = Addrd<Resp>>
+{
+  type Types = Platform;
+  type Error = platform::Error<Steps::Error, Option<()>>;
+
+  fn log(&self, _: log::Level, _: crate::todo::String<1000>) -> Result<(), Self::Error> {
+    Ok(())
+  }
+
+  fn config(&self) -> Config {
+    self.config
+  }
+
+  fn steps(&self) -> &Steps {
+    &self.steps
+  }
+
+  fn socket(&self) -> &SockMock {
+    &self.sock
+  }
+
+  fn clock(&self) -> &ClockMock {
+    &self.clock
   }
 }
 